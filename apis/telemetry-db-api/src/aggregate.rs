@@ -0,0 +1,143 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use super::{Database, NUMERIC_FILTER, NUMERIC_VALUE};
+use crate::retention::RULE_FILTER;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Double, Nullable, Text};
+use std::cmp::Ordering;
+
+/// Function used to combine the entries within each bucket
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregate {
+    /// Smallest value
+    Min,
+    /// Largest value
+    Max,
+    /// Mean value
+    Avg,
+    /// Number of entries
+    Count,
+    /// Value of the most recent entry
+    Last,
+}
+
+/// The combined value of the entries for one parameter within one bucket
+#[derive(Clone, Debug, PartialEq, QueryableByName)]
+pub struct AggregateEntry {
+    /// Subsystem name
+    #[sql_type = "Text"]
+    pub subsystem: String,
+    /// Telemetry parameter
+    #[sql_type = "Text"]
+    pub parameter: String,
+    /// Start of the bucket
+    #[sql_type = "Double"]
+    pub timestamp: f64,
+    /// Aggregated value
+    #[sql_type = "Double"]
+    pub value: f64,
+}
+
+impl Database {
+    /// Combine entries over fixed-width time buckets
+    ///
+    /// Results are ordered by subsystem, parameter and then timestamp.
+    /// Entries with non-numeric values are only included when counting.
+    ///
+    /// # Arguments
+    /// `subsystem` - Only include entries from this subsystem
+    /// `parameters` - Only include entries for these parameters
+    /// `timestamp_ge` - Only include entries on or after this time
+    /// `timestamp_le` - Only include entries on or before this time
+    /// `bucket` - Width of each bucket. Must be positive
+    /// `function` - Function used to combine the entries in each bucket
+    pub fn aggregate(
+        &self,
+        subsystem: Option<&str>,
+        parameters: Option<&[String]>,
+        timestamp_ge: Option<f64>,
+        timestamp_le: Option<f64>,
+        bucket: f64,
+        function: Aggregate,
+    ) -> QueryResult<Vec<AggregateEntry>> {
+        // `Last` relies on SQLite taking bare columns from the row matching `MAX()`
+        let value = match function {
+            Aggregate::Min => "MIN(number)",
+            Aggregate::Max => "MAX(number)",
+            Aggregate::Avg => "AVG(number)",
+            Aggregate::Count => "CAST(COUNT(*) AS REAL)",
+            Aggregate::Last => "number",
+        };
+        let latest = match function {
+            Aggregate::Last => ", MAX(timestamp) AS latest",
+            _ => "",
+        };
+        let numeric = match function {
+            Aggregate::Count => String::new(),
+            _ => format!("AND {}", NUMERIC_FILTER),
+        };
+
+        let query = format!(
+            "SELECT subsystem, parameter, bucket AS timestamp, {value} AS value{latest} FROM \
+             (SELECT subsystem, parameter, timestamp, {number} AS number, \
+             CAST(timestamp / ?5 AS INTEGER) * ?5 AS bucket FROM telemetry \
+             WHERE {filter} AND (?3 IS NULL OR timestamp >= ?3) \
+             AND (?4 IS NULL OR timestamp <= ?4) {numeric}) \
+             GROUP BY subsystem, parameter, bucket ORDER BY subsystem, parameter, bucket",
+            value = value,
+            latest = latest,
+            number = NUMERIC_VALUE,
+            filter = RULE_FILTER,
+            numeric = numeric
+        );
+
+        // Each parameter is queried separately, since a list can't be bound to a single argument
+        let parameters: Vec<Option<&str>> = match parameters {
+            Some(parameters) => parameters
+                .iter()
+                .map(|param| Some(param.as_str()))
+                .collect(),
+            None => vec![None],
+        };
+
+        let mut entries = vec![];
+        for parameter in parameters {
+            entries.extend(
+                sql_query(query.as_str())
+                    .bind::<Nullable<Text>, _>(subsystem)
+                    .bind::<Nullable<Text>, _>(parameter)
+                    .bind::<Nullable<Double>, _>(timestamp_ge)
+                    .bind::<Nullable<Double>, _>(timestamp_le)
+                    .bind::<Double, _>(bucket)
+                    .load::<AggregateEntry>(&self.connection)?,
+            );
+        }
+
+        entries.sort_by(|a, b| {
+            (&a.subsystem, &a.parameter)
+                .cmp(&(&b.subsystem, &b.parameter))
+                .then(
+                    a.timestamp
+                        .partial_cmp(&b.timestamp)
+                        .unwrap_or(Ordering::Equal),
+                )
+        });
+
+        Ok(entries)
+    }
+}
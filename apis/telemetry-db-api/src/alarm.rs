@@ -0,0 +1,74 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use super::Database;
+use diesel::insert_into;
+use diesel::prelude::*;
+use diesel::sql_query;
+use serde_derive::{Deserialize, Serialize};
+
+table! {
+    alarms (timestamp, subsystem, parameter) {
+        timestamp -> Double,
+        subsystem -> Text,
+        parameter -> Text,
+        previous_state -> Text,
+        state -> Text,
+        value -> Double,
+    }
+}
+
+/// A change in the alarm state of a telemetry parameter
+#[derive(Clone, Debug, PartialEq, Queryable, Serialize, Deserialize, Insertable)]
+#[table_name = "alarms"]
+pub struct Alarm {
+    /// Timestamp of the entry which caused the change
+    pub timestamp: f64,
+    /// Subsystem name
+    pub subsystem: String,
+    /// Telemetry parameter
+    pub parameter: String,
+    /// State before the change
+    pub previous_state: String,
+    /// State after the change
+    pub state: String,
+    /// Value of the entry which caused the change
+    pub value: f64,
+}
+
+impl Database {
+    // Create the alarms table, if needed
+    pub(crate) fn setup_alarms(&self) -> QueryResult<usize> {
+        sql_query(
+            "CREATE TABLE IF NOT EXISTS alarms (
+            timestamp DOUBLE NOT NULL,
+            subsystem VARCHAR(255) NOT NULL,
+            parameter VARCHAR(255) NOT NULL,
+            previous_state VARCHAR(16) NOT NULL,
+            state VARCHAR(16) NOT NULL,
+            value DOUBLE NOT NULL,
+            PRIMARY KEY (timestamp, subsystem, parameter))",
+        )
+        .execute(&self.connection)
+    }
+
+    /// Record a change in a parameter's alarm state
+    pub fn insert_alarm(&self, alarm: &Alarm) -> QueryResult<usize> {
+        insert_into(alarms::table)
+            .values(alarm)
+            .execute(&self.connection)
+    }
+}
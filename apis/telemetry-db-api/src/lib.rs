@@ -20,18 +20,30 @@
 #[macro_use]
 extern crate diesel;
 
+pub mod aggregate;
+pub use crate::aggregate::*;
+pub mod alarm;
+pub use crate::alarm::*;
 pub mod models;
 pub use crate::models::*;
+pub mod retention;
+pub use crate::retention::*;
 
 use diesel::dsl::sql;
 use diesel::insert_into;
 use diesel::prelude::*;
 use diesel::sql_query;
-use diesel::sql_types::Bool;
+use diesel::sql_types::{Bool, Text};
 use diesel::sqlite::SqliteConnection;
 use diesel::*;
 use log::{error, info};
 
+/// Current system time, in fractional seconds
+pub fn systime() -> f64 {
+    let time = time::now_utc().to_timespec();
+    time.sec as f64 + (f64::from(time.nsec) / 1_000_000_000.0)
+}
+
 pub struct Database {
     pub connection: SqliteConnection,
 }
@@ -56,11 +68,11 @@ impl Database {
         }
     }
 
-    /// Check if database has correct table and creates table if needed
+    /// Check if database has correct tables and creates tables if needed
     ///
     /// # Panics
     ///
-    /// Will `panic!` if fails to locate and/or create telemetry table
+    /// Will `panic!` if fails to locate and/or create telemetry or alarms tables
     pub fn setup(&self) {
         match select(sql::<Bool>(
             "EXISTS \
@@ -84,6 +96,10 @@ impl Database {
                     subsystem VARCHAR(255) NOT NULL,
                     parameter VARCHAR(255) NOT NULL,
                     value VARCHAR(255) NOT NULL,
+                    value_type VARCHAR(8),
+                    int_value BIGINT,
+                    real_value DOUBLE,
+                    blob_value BLOB,
                     PRIMARY KEY (timestamp, subsystem, parameter))",
                 )
                 .execute(&self.connection)
@@ -96,36 +112,81 @@ impl Database {
                 }
             }
         };
+
+        if let Err(err) = self.add_typed_columns() {
+            error!("Error adding typed value columns: {:?}", err);
+            panic!("Error adding typed value columns: {:?}", err)
+        }
+
+        if let Err(err) = self.setup_alarms() {
+            error!("Error creating alarms table: {:?}", err);
+            panic!("Error creating alarms table: {:?}", err)
+        }
+    }
+
+    // Databases created before values were typed only have the `value` column.
+    // The new columns are added without touching the existing entries, whose types
+    // are instead inferred when they're read
+    fn add_typed_columns(&self) -> QueryResult<()> {
+        #[derive(QueryableByName)]
+        struct Column {
+            #[sql_type = "Text"]
+            name: String,
+        }
+
+        let columns = sql_query("PRAGMA table_info(telemetry)").load::<Column>(&self.connection)?;
+        if columns.iter().any(|column| column.name == "value_type") {
+            return Ok(());
+        }
+
+        info!("Adding typed value columns to telemetry table");
+        self.connection.transaction(|| {
+            for column in &[
+                "value_type VARCHAR(8)",
+                "int_value BIGINT",
+                "real_value DOUBLE",
+                "blob_value BLOB",
+            ] {
+                sql_query(format!("ALTER TABLE telemetry ADD COLUMN {}", column))
+                    .execute(&self.connection)?;
+            }
+            Ok(())
+        })
     }
 
+    /// Insert a new entry
+    ///
+    /// # Arguments
+    /// `timestamp` - Time of the entry
+    /// `subsystem` - Subsystem name
+    /// `parameter` - Telemetry parameter
+    /// `value` - String form of the value. Blobs must be given as hex strings
+    /// `value_type` - Type of the value. Inferred from `value` if `None`
     pub fn insert<'a>(
         &self,
         timestamp: f64,
         subsystem: &'a str,
         parameter: &'a str,
         value: &'a str,
+        value_type: Option<ValueType>,
     ) -> QueryResult<usize> {
-        let new_entry = Entry {
-            timestamp,
-            subsystem: String::from(subsystem),
-            parameter: String::from(parameter),
-            value: String::from(value),
-        };
+        let new_entry = Entry::new(timestamp, subsystem, parameter, value, value_type)
+            .map_err(|err| diesel::result::Error::SerializationError(Box::new(err)))?;
 
         insert_into(telemetry::table)
             .values(&new_entry)
             .execute(&self.connection)
     }
 
+    /// Insert a new entry, timestamped with the current system time
     pub fn insert_systime<'a>(
         &self,
         subsystem: &'a str,
         parameter: &'a str,
         value: &'a str,
+        value_type: Option<ValueType>,
     ) -> QueryResult<usize> {
-        let time = time::now_utc().to_timespec();
-        let timestamp = time.sec as f64 + (f64::from(time.nsec) / 1_000_000_000.0);
-        self.insert(timestamp, subsystem, parameter, value)
+        self.insert(systime(), subsystem, parameter, value, value_type)
     }
 
     pub fn insert_bulk(&self, entries: Vec<Entry>) -> QueryResult<usize> {
@@ -141,5 +202,16 @@ table! {
         subsystem -> Text,
         parameter -> Text,
         value -> Text,
+        value_type -> Nullable<Text>,
+        int_value -> Nullable<BigInt>,
+        real_value -> Nullable<Double>,
+        blob_value -> Nullable<Binary>,
     }
 }
+
+// Matches entries with numeric values, including untyped entries whose text looks numeric
+pub(crate) const NUMERIC_FILTER: &str = "(value_type IN ('integer', 'real') OR \
+     (value_type IS NULL AND value GLOB '*[0-9]*' AND value NOT GLOB '*[^0-9.eE+-]*'))";
+
+// Numeric form of an entry's value
+pub(crate) const NUMERIC_VALUE: &str = "CAST(COALESCE(real_value, int_value, value) AS REAL)";
@@ -16,7 +16,165 @@
 
 use super::telemetry;
 use serde_derive::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
+/// Type of a telemetry value
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    /// 64-bit signed integer
+    Integer,
+    /// 64-bit floating point number
+    Real,
+    /// UTF-8 string
+    Text,
+    /// Binary data, given as a hex string
+    Blob,
+}
+
+impl ValueType {
+    /// Name of the type, as stored in the database
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ValueType::Integer => "integer",
+            ValueType::Real => "real",
+            ValueType::Text => "text",
+            ValueType::Blob => "blob",
+        }
+    }
+}
+
+impl FromStr for ValueType {
+    type Err = String;
+
+    fn from_str(value_type: &str) -> Result<Self, Self::Err> {
+        match value_type {
+            "integer" => Ok(ValueType::Integer),
+            "real" => Ok(ValueType::Real),
+            "text" => Ok(ValueType::Text),
+            "blob" => Ok(ValueType::Blob),
+            other => Err(format!("Unknown value type '{}'", other)),
+        }
+    }
+}
+
+/// A typed telemetry value
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// 64-bit signed integer
+    Integer(i64),
+    /// 64-bit floating point number
+    Real(f64),
+    /// UTF-8 string
+    Text(String),
+    /// Binary data
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    /// Convert a string to a typed value
+    ///
+    /// If no type is given, the value is stored as an integer or real number if it can be
+    /// parsed as one, and as text otherwise. Blobs must be given as hex strings.
+    ///
+    /// # Arguments
+    /// `value` - String form of the value
+    /// `value_type` - Type to convert the value to
+    pub fn parse(value: &str, value_type: Option<ValueType>) -> Result<Self, ValueError> {
+        let err = |value_type| ValueError {
+            value: value.to_owned(),
+            value_type,
+        };
+
+        match value_type {
+            Some(ValueType::Integer) => value
+                .parse()
+                .map(Value::Integer)
+                .map_err(|_| err(ValueType::Integer)),
+            Some(ValueType::Real) => match value.parse::<f64>() {
+                Ok(real) if !real.is_nan() => Ok(Value::Real(real)),
+                _ => Err(err(ValueType::Real)),
+            },
+            Some(ValueType::Text) => Ok(Value::Text(value.to_owned())),
+            Some(ValueType::Blob) => from_hex(value)
+                .map(Value::Blob)
+                .ok_or_else(|| err(ValueType::Blob)),
+            None => Ok(if let Ok(integer) = value.parse() {
+                Value::Integer(integer)
+            } else {
+                match value.parse::<f64>() {
+                    Ok(real) if real.is_finite() => Value::Real(real),
+                    _ => Value::Text(value.to_owned()),
+                }
+            }),
+        }
+    }
+
+    /// Type of the value
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::Integer(_) => ValueType::Integer,
+            Value::Real(_) => ValueType::Real,
+            Value::Text(_) => ValueType::Text,
+            Value::Blob(_) => ValueType::Blob,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Integer(integer) => write!(f, "{}", integer),
+            Value::Real(real) => write!(f, "{}", real),
+            Value::Text(text) => write!(f, "{}", text),
+            Value::Blob(blob) => {
+                for byte in blob {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A value couldn't be converted to the requested type
+#[derive(Debug)]
+pub struct ValueError {
+    value: String,
+    value_type: ValueType,
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Unable to convert '{}' to {}",
+            self.value,
+            self.value_type.as_str()
+        )
+    }
+}
+
+impl Error for ValueError {}
+
+fn from_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 || !value.is_ascii() {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// A telemetry entry
+///
+/// `value` always holds the string form of the value, so that the entry can still be read as text.
+/// Depending on `value_type`, the value is also stored in `int_value`, `real_value` or `blob_value`.
+/// Entries written before typed values were supported have no `value_type`.
 #[derive(Debug, Queryable, Serialize, Deserialize, Insertable)]
 #[table_name = "telemetry"]
 pub struct Entry {
@@ -24,4 +182,100 @@ pub struct Entry {
     pub subsystem: String,
     pub parameter: String,
     pub value: String,
+    #[serde(skip)]
+    pub value_type: Option<String>,
+    #[serde(skip)]
+    pub int_value: Option<i64>,
+    #[serde(skip)]
+    pub real_value: Option<f64>,
+    #[serde(skip)]
+    pub blob_value: Option<Vec<u8>>,
+}
+
+impl Entry {
+    /// Create a new entry
+    ///
+    /// # Arguments
+    /// `timestamp` - Time of the entry
+    /// `subsystem` - Subsystem name
+    /// `parameter` - Telemetry parameter
+    /// `value` - String form of the value
+    /// `value_type` - Type of the value. Inferred from `value` if `None`
+    pub fn new(
+        timestamp: f64,
+        subsystem: &str,
+        parameter: &str,
+        value: &str,
+        value_type: Option<ValueType>,
+    ) -> Result<Self, ValueError> {
+        let typed = Value::parse(value, value_type)?;
+
+        let mut entry = Entry {
+            timestamp,
+            subsystem: subsystem.to_owned(),
+            parameter: parameter.to_owned(),
+            value: value.to_owned(),
+            value_type: Some(typed.value_type().as_str().to_owned()),
+            int_value: None,
+            real_value: None,
+            blob_value: None,
+        };
+
+        match typed {
+            Value::Integer(integer) => entry.int_value = Some(integer),
+            Value::Real(real) => entry.real_value = Some(real),
+            Value::Text(_) => {}
+            Value::Blob(blob) => {
+                // Store the hex string in a consistent form
+                entry.value = Value::Blob(blob.clone()).to_string();
+                entry.blob_value = Some(blob);
+            }
+        }
+
+        Ok(entry)
+    }
+
+    /// Type of the entry's value. Inferred from `value` for entries which have no stored type
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+            .as_ref()
+            .and_then(|value_type| value_type.parse().ok())
+            .unwrap_or_else(|| self.typed_value().value_type())
+    }
+
+    /// The entry's value, converted to its type
+    pub fn typed_value(&self) -> Value {
+        let value_type = self
+            .value_type
+            .as_ref()
+            .and_then(|value_type| value_type.parse().ok());
+
+        match (value_type, self) {
+            (
+                Some(ValueType::Integer),
+                Entry {
+                    int_value: Some(integer),
+                    ..
+                },
+            ) => Value::Integer(*integer),
+            (
+                Some(ValueType::Real),
+                Entry {
+                    real_value: Some(real),
+                    ..
+                },
+            ) => Value::Real(*real),
+            (
+                Some(ValueType::Blob),
+                Entry {
+                    blob_value: Some(blob),
+                    ..
+                },
+            ) => Value::Blob(blob.clone()),
+            (Some(ValueType::Text), _) => Value::Text(self.value.clone()),
+            _ => {
+                Value::parse(&self.value, None).unwrap_or_else(|_| Value::Text(self.value.clone()))
+            }
+        }
+    }
 }
@@ -0,0 +1,168 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use super::{Database, NUMERIC_FILTER, NUMERIC_VALUE};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Double, Nullable, Text};
+
+// Matches the entries covered by a rule. Binds: ?1 subsystem, ?2 parameter
+pub(crate) const RULE_FILTER: &str =
+    "(?1 IS NULL OR subsystem = ?1) AND (?2 IS NULL OR parameter = ?2)";
+
+/// Replace old entries with their averages over fixed intervals
+#[derive(Clone, Debug, PartialEq)]
+pub struct Downsample {
+    /// Age, in seconds, after which entries are downsampled
+    pub after: f64,
+    /// Length, in seconds, of each averaging interval
+    pub interval: f64,
+}
+
+/// Limits on how much telemetry is kept for a subsystem and/or parameter
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RetentionRule {
+    /// Subsystem the rule applies to. All subsystems if `None`
+    pub subsystem: Option<String>,
+    /// Parameter the rule applies to. All parameters if `None`
+    pub parameter: Option<String>,
+    /// Maximum age, in seconds, of entries to keep
+    pub max_age: Option<f64>,
+    /// Maximum number of entries to keep. The newest entries are kept
+    pub max_rows: Option<i64>,
+    /// Downsampling to apply to older entries
+    pub downsample: Option<Downsample>,
+}
+
+impl Database {
+    /// Enforce a retention rule, returning the number of entries removed
+    ///
+    /// Downsampling is applied first, so that averaged entries count towards `max_rows`.
+    /// Entries with non-numeric values are never downsampled, but one sitting exactly at the
+    /// start of a downsampled interval is replaced by that interval's average.
+    ///
+    /// # Arguments
+    /// `rule` - Rule to enforce
+    /// `now` - Current time, in the same units as the entries' timestamps
+    pub fn apply_retention(&self, rule: &RetentionRule, now: f64) -> QueryResult<usize> {
+        self.connection.transaction(|| {
+            let mut pruned = 0;
+
+            if let Some(downsample) = &rule.downsample {
+                pruned += self.downsample(rule, downsample, now)?;
+            }
+
+            if let Some(max_age) = rule.max_age {
+                pruned += sql_query(format!(
+                    "DELETE FROM telemetry WHERE {} AND timestamp < ?3",
+                    RULE_FILTER
+                ))
+                .bind::<Nullable<Text>, _>(&rule.subsystem)
+                .bind::<Nullable<Text>, _>(&rule.parameter)
+                .bind::<Double, _>(now - max_age)
+                .execute(&self.connection)?;
+            }
+
+            if let Some(max_rows) = rule.max_rows {
+                pruned += sql_query(format!(
+                    "DELETE FROM telemetry WHERE {filter} AND rowid NOT IN \
+                     (SELECT rowid FROM telemetry WHERE {filter} \
+                     ORDER BY timestamp DESC LIMIT ?3)",
+                    filter = RULE_FILTER
+                ))
+                .bind::<Nullable<Text>, _>(&rule.subsystem)
+                .bind::<Nullable<Text>, _>(&rule.parameter)
+                .bind::<BigInt, _>(max_rows)
+                .execute(&self.connection)?;
+            }
+
+            Ok(pruned)
+        })
+    }
+
+    // Replace each interval's worth of old entries with a single averaged entry,
+    // timestamped at the start of the interval
+    fn downsample(
+        &self,
+        rule: &RetentionRule,
+        downsample: &Downsample,
+        now: f64,
+    ) -> QueryResult<usize> {
+        // Only touch complete intervals, so that each one is only ever averaged once
+        let cutoff = ((now - downsample.after) / downsample.interval).floor() * downsample.interval;
+        let bucket = "CAST(timestamp / ?4 AS INTEGER) * ?4";
+
+        sql_query(
+            "CREATE TEMP TABLE IF NOT EXISTS retention_downsample (
+            timestamp DOUBLE NOT NULL,
+            subsystem VARCHAR(255) NOT NULL,
+            parameter VARCHAR(255) NOT NULL,
+            value DOUBLE NOT NULL)",
+        )
+        .execute(&self.connection)?;
+        sql_query("DELETE FROM retention_downsample").execute(&self.connection)?;
+
+        sql_query(format!(
+            "INSERT INTO retention_downsample \
+             SELECT {bucket}, subsystem, parameter, AVG({value}) \
+             FROM telemetry WHERE {filter} AND timestamp < ?3 AND {numeric} \
+             GROUP BY subsystem, parameter, {bucket} HAVING COUNT(*) > 1",
+            bucket = bucket,
+            filter = RULE_FILTER,
+            numeric = NUMERIC_FILTER,
+            value = NUMERIC_VALUE
+        ))
+        .bind::<Nullable<Text>, _>(&rule.subsystem)
+        .bind::<Nullable<Text>, _>(&rule.parameter)
+        .bind::<Double, _>(cutoff)
+        .bind::<Double, _>(downsample.interval)
+        .execute(&self.connection)?;
+
+        let removed = sql_query(format!(
+            "DELETE FROM telemetry WHERE {filter} AND timestamp < ?3 AND {numeric} \
+             AND EXISTS (SELECT 1 FROM retention_downsample AS d \
+             WHERE d.subsystem = telemetry.subsystem AND d.parameter = telemetry.parameter \
+             AND d.timestamp = CAST(telemetry.timestamp / ?4 AS INTEGER) * ?4)",
+            filter = RULE_FILTER,
+            numeric = NUMERIC_FILTER
+        ))
+        .bind::<Nullable<Text>, _>(&rule.subsystem)
+        .bind::<Nullable<Text>, _>(&rule.parameter)
+        .bind::<Double, _>(cutoff)
+        .bind::<Double, _>(downsample.interval)
+        .execute(&self.connection)?;
+
+        // Make room for the averages, which share their key with any entry left at the
+        // start of the interval
+        let replaced = sql_query(
+            "DELETE FROM telemetry WHERE EXISTS (SELECT 1 FROM retention_downsample AS d \
+             WHERE d.subsystem = telemetry.subsystem AND d.parameter = telemetry.parameter \
+             AND d.timestamp = telemetry.timestamp)",
+        )
+        .execute(&self.connection)?;
+
+        let added = sql_query(
+            "INSERT INTO telemetry (timestamp, subsystem, parameter, value, value_type, real_value) \
+             SELECT timestamp, subsystem, parameter, CAST(value AS TEXT), 'real', value \
+             FROM retention_downsample",
+        )
+        .execute(&self.connection)?;
+
+        sql_query("DELETE FROM retention_downsample").execute(&self.connection)?;
+
+        Ok(removed + replaced - added)
+    }
+}
@@ -15,24 +15,77 @@
 //
 
 use clap::{App, AppSettings, Arg, SubCommand};
-use failure::bail;
-use file_protocol::{FileProtocol, FileProtocolConfig, State};
-use log::{error, info};
+use failure::{bail, format_err};
+use file_protocol::{Compression, Direction, FileProtocol, FileProtocolConfig, Manifest, State};
+use log::{error, info, warn};
 use simplelog::*;
-use std::path::Path;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+// Attempt to resume an interrupted upload.
+// Returns `false` if there was nothing to resume, the source file has changed since the upload
+// was started, or the remote target wasn't able to resume it.
+fn resume_upload(protocol_instance: &FileProtocol, source_path: &str, target_path: &str) -> bool {
+    let transfer = match protocol_instance.find_upload(source_path, target_path) {
+        Ok(Some(transfer)) => transfer,
+        Ok(None) => {
+            info!(
+                "No interrupted upload of {} to {} found",
+                source_path, target_path
+            );
+            return false;
+        }
+        Err(error) => {
+            warn!("Unable to check for an interrupted upload: {}", error);
+            return false;
+        }
+    };
+
+    info!("Resuming upload of {} ({})", target_path, transfer.hash);
+
+    let result = protocol_instance
+        .generate_channel()
+        .and_then(|channel| {
+            protocol_instance.send_resume(channel, &transfer.hash, Direction::Transmit)
+        })
+        .and_then(|_| {
+            protocol_instance.message_engine(
+                |d| protocol_instance.recv(Some(d)),
+                Duration::from_secs(2),
+                &State::Transmitting,
+            )
+        });
+
+    match result {
+        Ok(()) => true,
+        Err(error) => {
+            warn!("Unable to resume upload: {}", error);
+            false
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn upload(
     protocol_instance: FileProtocol,
     source_path: &str,
     target_path: &str,
+    resume: bool,
+    compression: Option<Compression>,
 ) -> Result<(), failure::Error> {
     info!(
         "Uploading local:{} to remote:{}",
         &source_path, &target_path
     );
 
+    if resume && resume_upload(&protocol_instance, source_path, target_path) {
+        return Ok(());
+    }
+
     // Copy file to upload to temp storage. Calculate the hash and chunk info
     let (hash, num_chunks, mode) = protocol_instance.initialize_file(source_path)?;
 
@@ -43,7 +96,7 @@ fn upload(
     protocol_instance.send_metadata(channel, &hash, num_chunks)?;
 
     // Send export command for file
-    protocol_instance.send_export(channel, &hash, target_path, mode)?;
+    protocol_instance.send_export(channel, &hash, target_path, mode, compression)?;
 
     // Start the engine to send the file data chunks
     protocol_instance.message_engine(
@@ -54,22 +107,79 @@ fn upload(
     Ok(())
 }
 
+// Attempt to resume an interrupted download.
+// Returns `false` if there was nothing to resume or the remote target wasn't able to resume it.
+fn resume_download(protocol_instance: &FileProtocol, target_path: &str) -> bool {
+    let transfer = match protocol_instance.find_transfer(Direction::Receive, target_path) {
+        Some(transfer) => transfer,
+        None => {
+            info!("No interrupted download to {} found", target_path);
+            return false;
+        }
+    };
+
+    info!(
+        "Resuming download of {} ({}): {} of {} chunks already received",
+        target_path,
+        transfer.hash,
+        transfer.received_count(),
+        transfer.num_chunks
+    );
+
+    let result = protocol_instance
+        .generate_channel()
+        .and_then(|channel| {
+            protocol_instance.send_resume(channel, &transfer.hash, Direction::Receive)
+        })
+        // The remote target has already prepared the file, so the reply should be quick
+        .and_then(|_| protocol_instance.recv(Some(Duration::from_secs(2))))
+        .and_then(|reply| {
+            protocol_instance.process_message(
+                reply,
+                &State::StartReceive {
+                    path: target_path.to_string(),
+                },
+            )
+        })
+        .and_then(|state| {
+            protocol_instance.message_engine(
+                |d| protocol_instance.recv(Some(d)),
+                Duration::from_secs(2),
+                &state,
+            )
+        });
+
+    match result {
+        Ok(()) => true,
+        Err(error) => {
+            warn!("Unable to resume download: {}", error);
+            false
+        }
+    }
+}
+
 fn download(
     protocol_instance: FileProtocol,
     source_path: &str,
     target_path: &str,
+    resume: bool,
+    compression: Option<Compression>,
 ) -> Result<(), failure::Error> {
     info!(
         "Downloading remote: {} to local: {}",
         source_path, target_path
     );
 
+    if resume && resume_download(&protocol_instance, target_path) {
+        return Ok(());
+    }
+
     // Generate channel id for transaction
     let channel = protocol_instance.generate_channel()?;
 
     // Send our file request to the remote addr and verify that it's
     // going to be able to send it
-    protocol_instance.send_import(channel, source_path)?;
+    protocol_instance.send_import(channel, source_path, compression)?;
 
     // Wait for the request reply.
     // Note/TODO: We don't use a timeout here because we don't know how long it will
@@ -95,6 +205,171 @@ fn download(
     Ok(())
 }
 
+// Log the outcome of each file in a directory transfer.
+// The transfer as a whole fails if any of the files couldn't be transferred
+fn report(results: Vec<(String, Result<(), failure::Error>)>) -> Result<(), failure::Error> {
+    let mut failed = 0;
+    for (path, result) in &results {
+        match result {
+            Ok(()) => info!("Transferred {}", path),
+            Err(error) => {
+                error!("Failed to transfer {}: {}", path, error);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} of {} files failed to transfer", failed, results.len());
+    }
+    Ok(())
+}
+
+fn upload_dir(
+    protocol_instance: FileProtocol,
+    source: &str,
+    target_dir: &str,
+    compression: Option<Compression>,
+) -> Result<(), failure::Error> {
+    info!("Uploading local:{} to remote dir:{}", source, target_dir);
+
+    // Hash all of the files and build the manifest
+    let manifest = protocol_instance.initialize_manifest(source)?;
+    let root = Manifest::root(source);
+
+    let mut results = vec![];
+    for failure in manifest.failures {
+        results.push((failure.path, Err(format_err!("{}", failure.error))));
+    }
+
+    for file in manifest.files {
+        let target_path = Path::new(target_dir).join(&file.path);
+
+        let result = (|| -> Result<(), failure::Error> {
+            // Each file is only copied to temp storage when it's about to be sent,
+            // and is cleaned up once it has been
+            let (hash, num_chunks, _) =
+                protocol_instance.initialize_file(&root.join(&file.path).to_string_lossy())?;
+            if hash != file.hash {
+                bail!("File changed while the directory was being uploaded");
+            }
+
+            let channel = protocol_instance.generate_channel()?;
+            protocol_instance.send_metadata(channel, &file.hash, num_chunks)?;
+            protocol_instance.send_export(
+                channel,
+                &file.hash,
+                &target_path.to_string_lossy(),
+                file.mode,
+                compression,
+            )?;
+            protocol_instance.message_engine(
+                |d| protocol_instance.recv(Some(d)),
+                Duration::from_secs(2),
+                &State::Transmitting,
+            )?;
+            Ok(())
+        })();
+
+        results.push((file.path, result));
+    }
+
+    report(results)
+}
+
+fn download_dir(
+    protocol_instance: FileProtocol,
+    source: &str,
+    target_dir: &str,
+    compression: Option<Compression>,
+) -> Result<(), failure::Error> {
+    info!("Downloading remote:{} to local dir:{}", source, target_dir);
+
+    // Generate channel id for transaction
+    let channel = protocol_instance.generate_channel()?;
+
+    // Ask the remote target to prepare all of the files and send us their manifest.
+    // As with single files, this may take a while
+    protocol_instance.send_import_dir(channel, source, compression)?;
+
+    let reply = match protocol_instance.recv(None) {
+        Ok(message) => message,
+        Err(error) => bail!("Failed to import directory: {}", error),
+    };
+
+    // The manifest itself is transferred like any other file
+    let manifest_path = env::temp_dir()
+        .join(format!("kubos-manifest-{}", channel))
+        .to_string_lossy()
+        .into_owned();
+
+    let state = protocol_instance.process_message(
+        reply,
+        &State::StartReceive {
+            path: manifest_path.clone(),
+        },
+    )?;
+
+    protocol_instance.message_engine(
+        |d| protocol_instance.recv(Some(d)),
+        Duration::from_secs(2),
+        &state,
+    )?;
+
+    let manifest = fs::read(&manifest_path)
+        .map_err(failure::Error::from)
+        .and_then(|data| Ok(Manifest::from_slice(&data)?));
+    let _ = fs::remove_file(&manifest_path);
+    let manifest = manifest?;
+
+    let mut results = vec![];
+    for failure in manifest.failures {
+        results.push((failure.path, Err(format_err!("{}", failure.error))));
+    }
+
+    // Files are fetched by hash, so each unique file only needs to be transferred once
+    let mut received: HashMap<String, PathBuf> = HashMap::new();
+    for file in manifest.files {
+        let target_path = Path::new(target_dir).join(&file.path);
+
+        let result = match received.get(&file.hash) {
+            Some(existing) => (|| -> Result<(), failure::Error> {
+                if let Some(parent) = target_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(existing, &target_path)?;
+                fs::set_permissions(&target_path, Permissions::from_mode(file.mode))?;
+                Ok(())
+            })(),
+            None => (|| -> Result<(), failure::Error> {
+                let channel = protocol_instance.generate_channel()?;
+                protocol_instance.send_resume(channel, &file.hash, Direction::Receive)?;
+
+                // The remote target has already prepared the file, so the reply should be quick
+                let reply = protocol_instance.recv(Some(Duration::from_secs(2)))?;
+                let state = protocol_instance.process_message(
+                    reply,
+                    &State::StartReceive {
+                        path: target_path.to_string_lossy().into_owned(),
+                    },
+                )?;
+                protocol_instance.message_engine(
+                    |d| protocol_instance.recv(Some(d)),
+                    Duration::from_secs(2),
+                    &state,
+                )?;
+
+                received.insert(file.hash.clone(), target_path.clone());
+                Ok(())
+            })(),
+        };
+
+        results.push((file.path, result));
+    }
+
+    report(results)
+}
+
 fn cleanup(protocol_instance: FileProtocol, hash: Option<String>) -> Result<(), failure::Error> {
     match &hash {
         Some(s) => info!("Requesting remote cleanup of temp storage for hash {}", s),
@@ -133,6 +408,24 @@ fn main() {
                     Arg::with_name("target_path")
                         .help("Destination path on remote target")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("resume")
+                        .help("Resume an interrupted upload to the same destination, if possible")
+                        .long("resume"),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .help("Transfer a directory tree, or all of the files matching a glob pattern, into the destination directory")
+                        .long("dir")
+                        .conflicts_with("resume"),
+                )
+                .arg(
+                    Arg::with_name("compress")
+                        .help("Compress the file data in transit, if the remote target supports it")
+                        .long("compress")
+                        .takes_value(true)
+                        .possible_values(&["deflate"]),
                 ),
         )
         .subcommand(
@@ -148,6 +441,24 @@ fn main() {
                     Arg::with_name("target_path")
                         .help("Local destination path")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("resume")
+                        .help("Resume an interrupted download to the same destination, if possible")
+                        .long("resume"),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .help("Transfer a directory tree, or all of the files matching a glob pattern, into the destination directory")
+                        .long("dir")
+                        .conflicts_with("resume"),
+                )
+                .arg(
+                    Arg::with_name("compress")
+                        .help("Compress the file data in transit, if the remote target supports it")
+                        .long("compress")
+                        .takes_value(true)
+                        .possible_values(&["deflate"]),
                 ),
         )
         .subcommand(
@@ -237,6 +548,17 @@ fn main() {
                 .short("-m")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("window_size")
+                .help("Number of chunks to send before waiting for a selective ack")
+                .long("window-size")
+                .short("-w")
+                .takes_value(true)
+                .validator(|size| match size.parse::<u32>() {
+                    Ok(size) if size >= 1 => Ok(()),
+                    _ => Err("Window size must be at least 1".to_owned()),
+                }),
+        )
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .setting(AppSettings::DeriveDisplayOrder)
         .get_matches();
@@ -267,6 +589,9 @@ fn main() {
     } else {
         None
     };
+    let window_size: Option<u32> = args
+        .value_of("window_size")
+        .map(|size| size.parse().unwrap());
 
     let protocol_config = FileProtocolConfig::new(
         Some(storage_prefix),
@@ -275,6 +600,7 @@ fn main() {
         inter_chunk_delay,
         max_chunks_transmit,
         hash_chunk_size,
+        window_size,
     );
     let protocol_instance = FileProtocol::new(
         &format!("{}:{}", host_ip, host_port),
@@ -295,7 +621,26 @@ fn main() {
                     .into_owned(),
             };
 
-            upload(protocol_instance, source_path, &target_path)
+            let compression = upload_args
+                .value_of("compress")
+                .and_then(Compression::from_name);
+
+            if upload_args.is_present("dir") {
+                upload_dir(
+                    protocol_instance,
+                    source_path,
+                    upload_args.value_of("target_path").unwrap_or("."),
+                    compression,
+                )
+            } else {
+                upload(
+                    protocol_instance,
+                    source_path,
+                    &target_path,
+                    upload_args.is_present("resume"),
+                    compression,
+                )
+            }
         }
         Some("download") => {
             let download_args = args.subcommand_matches("download").unwrap();
@@ -309,7 +654,26 @@ fn main() {
                     .into_owned(),
             };
 
-            download(protocol_instance, source_path, &target_path)
+            let compression = download_args
+                .value_of("compress")
+                .and_then(Compression::from_name);
+
+            if download_args.is_present("dir") {
+                download_dir(
+                    protocol_instance,
+                    source_path,
+                    download_args.value_of("target_path").unwrap_or("."),
+                    compression,
+                )
+            } else {
+                download(
+                    protocol_instance,
+                    source_path,
+                    &target_path,
+                    download_args.is_present("resume"),
+                    compression,
+                )
+            }
         }
         Some("cleanup") => {
             let hash = args
@@ -0,0 +1,127 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use clap::{App, AppSettings, Arg};
+use failure::format_err;
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::process;
+use telemetry_export::Entry;
+
+// Read the raw export, unpacking it first if the telemetry service compressed it
+fn read_export(path: &str) -> Result<Vec<u8>, failure::Error> {
+    let data = fs::read(path)?;
+
+    // Compressed exports are a gzipped tar archive containing the single export file
+    if !data.starts_with(&[0x1f, 0x8b]) {
+        return Ok(data);
+    }
+
+    let mut archive = tar::Archive::new(GzDecoder::new(&data[..]));
+    let mut file = archive
+        .entries()?
+        .next()
+        .ok_or_else(|| format_err!("Archive {} is empty", path))??;
+
+    let mut contents = vec![];
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+fn write_entries(
+    entries: &[Entry],
+    format: &str,
+    mut output: Box<dyn Write>,
+) -> Result<(), failure::Error> {
+    match format {
+        "csv" => {
+            let mut writer = csv::Writer::from_writer(output);
+            for entry in entries {
+                writer.serialize(entry)?;
+            }
+            writer.flush()?;
+        }
+        _ => {
+            serde_json::to_writer(&mut output, entries)?;
+            writeln!(output)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn decode(input: &str, output: Option<&str>, format: &str) -> Result<usize, failure::Error> {
+    let data = read_export(input)?;
+    let entries = telemetry_export::decode(&data)?;
+
+    let output: Box<dyn Write> = match output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+
+    write_entries(&entries, format, output)?;
+
+    Ok(entries.len())
+}
+
+fn main() {
+    let args = App::new("Telemetry export decoder")
+        .about(
+            "Converts a binary telemetry export from the telemetry database service to JSON or CSV",
+        )
+        .arg(
+            Arg::with_name("input")
+                .help("Export file to decode. May be compressed (.tar.gz)")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .help("File to write the decoded entries to. Defaults to stdout")
+                .long("output")
+                .short("o")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .help("Format of the decoded entries")
+                .long("format")
+                .short("f")
+                .takes_value(true)
+                .possible_values(&["json", "csv"])
+                .default_value("json"),
+        )
+        .setting(AppSettings::DeriveDisplayOrder)
+        .get_matches();
+
+    let input = args.value_of("input").unwrap();
+    let output = args.value_of("output");
+    let format = args.value_of("format").unwrap();
+
+    match decode(input, output, format) {
+        Ok(count) => {
+            // Keep stdout clean for the decoded entries
+            if output.is_some() {
+                println!("Decoded {} entries", count);
+            }
+        }
+        Err(err) => {
+            eprintln!("Failed to decode {}: {}", input, err);
+            process::exit(1);
+        }
+    }
+}
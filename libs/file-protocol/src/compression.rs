@@ -0,0 +1,142 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::error::ProtocolError;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::io::{self, Read, Write};
+
+/// Compression applied to file chunks while they are in transit
+///
+/// Chunks are always stored uncompressed, so the file's hash is unaffected
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    /// Raw DEFLATE (RFC 1951)
+    Deflate,
+}
+
+impl Compression {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Compression::Deflate => "deflate",
+        }
+    }
+
+    /// Look up a compression method by its protocol name
+    ///
+    /// Returns `None` for methods which aren't supported by this implementation
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "deflate" => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+}
+
+// Compress a chunk of file data
+pub fn compress(compression: Compression, data: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    match compression {
+        Compression::Deflate => {
+            let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::best());
+            encoder
+                .write_all(data)
+                .and_then(|_| encoder.finish())
+                .map_err(|err| ProtocolError::CompressionError {
+                    action: "compress".to_owned(),
+                    err,
+                })
+        }
+    }
+}
+
+// Decompress a chunk of file data
+//
+// Chunks are never larger than `max_size` bytes once decompressed, so anything which would expand
+// beyond that is rejected rather than being allowed to fill up memory
+pub fn decompress(
+    compression: Compression,
+    data: &[u8],
+    max_size: usize,
+) -> Result<Vec<u8>, ProtocolError> {
+    let error = |err| ProtocolError::CompressionError {
+        action: "decompress".to_owned(),
+        err,
+    };
+
+    match compression {
+        Compression::Deflate => {
+            let mut decompressed = vec![];
+            DeflateDecoder::new(data)
+                .take(max_size as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .map_err(error)?;
+
+            if decompressed.len() > max_size {
+                return Err(error(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Decompressed chunk is larger than {} bytes", max_size),
+                )));
+            }
+
+            Ok(decompressed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deflate_round_trip() {
+        let data = b"Every byte counts on the downlink. ".repeat(20);
+
+        let compressed = compress(Compression::Deflate, &data).unwrap();
+        assert!(compressed.len() < data.len());
+        assert_eq!(
+            decompress(Compression::Deflate, &compressed, data.len()).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn deflate_bad_data() {
+        assert!(decompress(Compression::Deflate, &[0xFF, 0xFF, 0xFF], 1024).is_err());
+    }
+
+    #[test]
+    fn deflate_too_large() {
+        let data = vec![0; 4096];
+        let compressed = compress(Compression::Deflate, &data).unwrap();
+
+        match decompress(Compression::Deflate, &compressed, data.len() - 1) {
+            Err(ProtocolError::CompressionError { action, err }) => {
+                assert_eq!(action, "decompress");
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_name() {
+        assert_eq!(
+            Compression::from_name("deflate"),
+            Some(Compression::Deflate)
+        );
+        assert_eq!(Compression::from_name("zstd"), None);
+    }
+}
@@ -20,6 +20,14 @@ use std::io;
 /// Errors which occur when using FileProtocol
 #[derive(Debug, Fail)]
 pub enum ProtocolError {
+    /// An error was encountered when compressing or decompressing a chunk
+    #[fail(display = "Failed to {} chunk: {}", action, err)]
+    CompressionError {
+        /// The action which generated the error
+        action: String,
+        /// The underlying std::io::Error
+        err: io::Error,
+    },
     /// A file in storage was corrupt
     #[fail(display = "File was corrupt: {}", _0)]
     CorruptFile(String),
@@ -41,6 +49,9 @@ pub enum ProtocolError {
     /// An invalid value was found when parsing a message
     #[fail(display = "Unable to parse {} message: Invalid {} param", _0, _1)]
     InvalidParam(String, String),
+    /// An error was encountered when building or parsing a directory transfer manifest
+    #[fail(display = "Manifest error: {}", _0)]
+    ManifestError(String),
     /// An error was encountered when creating a message
     #[fail(display = "Failed to create {} message: {}", message, err)]
     MessageCreationError {
@@ -23,7 +23,7 @@
 //! use std::time::Duration;
 //!
 //! fn upload() -> Result<(), ProtocolError> {
-//!     let config = FileProtocolConfig::new(Some("storage/dir".to_owned()), 1024, 5, 1, None, 2048);
+//!     let config = FileProtocolConfig::new(Some("storage/dir".to_owned()), 1024, 5, 1, None, 2048, None);
 //!     let f_protocol = FileProtocol::new("0.0.0.0", "0.0.0.0:7000", config);
 //!
 //!     # ::std::fs::File::create("client.txt").unwrap();
@@ -40,7 +40,7 @@
 //!     f_protocol.send_metadata(channel_id, &hash, num_chunks)?;
 //!
 //!     // Send export command for file
-//!     f_protocol.send_export(channel_id, &hash, &target_path, mode)?;
+//!     f_protocol.send_export(channel_id, &hash, &target_path, mode, None)?;
 //!
 //!     // Start the engine to send the file data chunks
 //!     Ok(f_protocol.message_engine(|d| f_protocol.recv(Some(d)), Duration::from_millis(10), &State::Transmitting)?)
@@ -54,7 +54,7 @@
 //! use std::time::Duration;
 //!
 //! fn download() -> Result<(), ProtocolError> {
-//!     let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048);
+//!     let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
 //!     let f_protocol = FileProtocol::new("0.0.0.0", "0.0.0.0:7000", config);
 //!
 //!     let channel_id = f_protocol.generate_channel()?;
@@ -63,8 +63,8 @@
 //!     let target_path = "client.txt";
 //!
 //!     // Send our file request to the remote addr and verify that it's
-//!     // going to be able to send it
-//!     f_protocol.send_import(channel_id, source_path)?;
+//!     // going to be able to send it. Ask for the file data to be compressed in transit
+//!     f_protocol.send_import(channel_id, source_path, Some(Compression::Deflate))?;
 //!
 //!     // Wait for the request reply
 //!     let reply = match f_protocol.recv(None) {
@@ -86,40 +86,59 @@
 
 #![deny(missing_docs)]
 
+mod compression;
 mod error;
+mod manifest;
 mod messages;
 mod parsers;
 pub mod protocol;
 mod storage;
 
+pub use crate::compression::Compression;
 pub use crate::error::ProtocolError;
+pub use crate::manifest::{Manifest, ManifestEntry, ManifestFailure};
 pub use crate::protocol::Protocol as FileProtocol;
 pub use crate::protocol::ProtocolConfig as FileProtocolConfig;
 pub use crate::protocol::State;
+pub use crate::storage::{Direction, Transfer};
 
 pub use crate::parsers::parse_channel_id;
 
 /// File protocol message types
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Message {
-    /// TODO: Decide whether or not to keep this
+    /// Request for the receiver's current status of a file, sent after each window of chunks.
+    /// Receivers reply with a SACK, or an ACK if they have all of the file's chunks
     Sync(u32, String),
     /// Receiver should prepare a new temporary storage folder with the specified metadata
     Metadata(u32, String, u32),
-    /// File data chunk message
-    ReceiveChunk(u32, String, u32, Vec<u8>),
+    /// File data chunk message, along with the compression applied to the data
+    ReceiveChunk(u32, String, u32, Vec<u8>, Option<Compression>),
     /// Receiver has successfully gotten all data chunks of the requested file
     ACK(u32, String),
-    /// Receiver is missing the specified file data chunks
-    NAK(u32, String, Option<Vec<(u32, u32)>>),
-    /// (Client Only) Message requesting the recipient to receive the specified file
-    ReqReceive(u32, String, String, Option<u32>),
-    /// (Client Only) Message requesting the recipient to transmit the specified file
-    ReqTransmit(u32, String),
+    /// Receiver has all chunks before the given chunk index, and the chunks after it
+    /// which are marked in the bitmap. Missing chunks should be sent with the specified compression
+    SACK(u32, String, u32, Vec<u8>, Option<Compression>),
+    /// Receiver is missing the specified file data chunks,
+    /// which should be sent with the specified compression
+    NAK(u32, String, Option<Vec<(u32, u32)>>, Option<Compression>),
+    /// (Client Only) Message requesting the recipient to receive the specified file,
+    /// optionally compressing the file data in transit
+    ReqReceive(u32, String, String, Option<u32>, Option<Compression>),
+    /// (Client Only) Message requesting the recipient to transmit the specified file,
+    /// optionally compressing the file data in transit
+    ReqTransmit(u32, String, Option<Compression>),
+    /// (Client Only) Message requesting the recipient to transmit a directory tree,
+    /// or all of the files matching a glob pattern, optionally compressing the file data in transit
+    ReqTransmitDir(u32, String, Option<Compression>),
+    /// Message requesting the recipient to resume an interrupted transfer of the specified file.
+    /// The direction is the role of the message sender
+    Resume(u32, String, Direction),
     /// (Server Only) Recipient has successfully processed a request to receive a file
     SuccessReceive(u32, String),
-    /// (Server Only) Recipient has successfully prepared to transmit a file
-    SuccessTransmit(u32, String, u32, Option<u32>),
+    /// (Server Only) Recipient has successfully prepared to transmit a file.
+    /// Includes the requested compression, if the recipient supports it
+    SuccessTransmit(u32, String, u32, Option<u32>, Option<Compression>),
     /// (Server Only) The transmit or receive request has failed to be completed
     Failure(u32, String),
     /// Request Cleanup of either whole storage directory or individual file's storage
@@ -128,7 +147,7 @@ pub enum Message {
 
 #[cfg(test)]
 mod tests {
-    use super::{messages, parsers, Message};
+    use super::{messages, parsers, Compression, Direction, Message};
     use serde_cbor::de;
 
     #[test]
@@ -138,13 +157,104 @@ mod tests {
         let target_path = "/path/to/file".to_owned();
         let mode = 0o623;
 
-        let raw = messages::export_request(channel_id, &hash, &target_path, mode).unwrap();
+        let raw = messages::export_request(channel_id, &hash, &target_path, mode, None).unwrap();
 
         let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
 
         assert_eq!(
             msg.unwrap(),
-            Message::ReqReceive(channel_id, hash, target_path, Some(mode))
+            Message::ReqReceive(channel_id, hash, target_path, Some(mode), None)
+        );
+    }
+
+    #[test]
+    fn create_parse_export_request_compressed() {
+        let channel_id = 10;
+        let hash = "abcdedf".to_owned();
+        let target_path = "/path/to/file".to_owned();
+        let mode = 0o623;
+
+        let raw = messages::export_request(
+            channel_id,
+            &hash,
+            &target_path,
+            mode,
+            Some(Compression::Deflate),
+        )
+        .unwrap();
+
+        let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::ReqReceive(
+                channel_id,
+                hash,
+                target_path,
+                Some(mode),
+                Some(Compression::Deflate)
+            )
+        );
+    }
+
+    #[test]
+    fn parse_export_request_unknown_compression() {
+        let raw = serde_cbor::ser::to_vec_packed(&(10, "export", "abcdedf", "file", 0o623, "lzma"))
+            .unwrap();
+
+        let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::ReqReceive(
+                10,
+                "abcdedf".to_owned(),
+                "file".to_owned(),
+                Some(0o623),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn create_parse_import_request_compressed() {
+        let channel_id = 10;
+        let path = "/path/to/file".to_owned();
+
+        let raw = messages::import_request(channel_id, &path, Some(Compression::Deflate)).unwrap();
+        let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::ReqTransmit(channel_id, path, Some(Compression::Deflate))
+        );
+    }
+
+    #[test]
+    fn create_parse_import_dir_request() {
+        let channel_id = 10;
+        let source = "/path/to/*.png".to_owned();
+
+        let raw = messages::import_dir_request(channel_id, &source, None).unwrap();
+        let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::ReqTransmitDir(channel_id, source, None)
+        );
+    }
+
+    #[test]
+    fn create_parse_resume() {
+        let channel_id = 10;
+        let hash = "abcdefg".to_owned();
+
+        let raw = messages::resume(channel_id, &hash, Direction::Transmit).unwrap();
+        let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::Resume(channel_id, hash, Direction::Transmit)
         );
     }
 
@@ -181,15 +291,58 @@ mod tests {
         let chunk_num = 10;
         let chunk_data: Vec<u8> = vec![1, 2, 3, 4, 5, 6];
 
-        let raw = messages::chunk(channel_id, &hash, chunk_num, &chunk_data).unwrap();
+        let raw = messages::chunk(channel_id, &hash, chunk_num, &chunk_data, None).unwrap();
+        let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::ReceiveChunk(channel_id, hash, chunk_num, chunk_data, None)
+        );
+    }
+
+    #[test]
+    fn create_parse_chunk_compressed() {
+        let channel_id = 10;
+        let hash = "abcdefg".to_owned();
+        let chunk_num = 10;
+        let chunk_data: Vec<u8> = vec![1, 2, 3, 4, 5, 6];
+
+        let raw = messages::chunk(
+            channel_id,
+            &hash,
+            chunk_num,
+            &chunk_data,
+            Some(Compression::Deflate),
+        )
+        .unwrap();
         let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
 
         assert_eq!(
             msg.unwrap(),
-            Message::ReceiveChunk(channel_id, hash, chunk_num, chunk_data)
+            Message::ReceiveChunk(
+                channel_id,
+                hash,
+                chunk_num,
+                chunk_data,
+                Some(Compression::Deflate)
+            )
         );
     }
 
+    #[test]
+    fn parse_chunk_unknown_compression() {
+        let raw = serde_cbor::ser::to_vec_packed(&(
+            10,
+            "abcdefg",
+            1,
+            serde_cbor::Value::Bytes(vec![1, 2, 3]),
+            "lzma",
+        ))
+        .unwrap();
+
+        assert!(parsers::parse_message(de::from_slice(&raw).unwrap()).is_err());
+    }
+
     #[test]
     fn create_parse_ack() {
         let channel_id = 14;
@@ -209,12 +362,55 @@ mod tests {
         let missing_chunks = vec![0, 1, 4, 10];
         let chunk_ranges: Vec<(u32, u32)> = vec![(0, 1), (4, 10)];
 
-        let raw = messages::nak(channel_id, &hash, &missing_chunks).unwrap();
+        let raw = messages::nak(channel_id, &hash, &missing_chunks, None).unwrap();
+        let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::NAK(channel_id, hash, Some(chunk_ranges), None)
+        );
+    }
+
+    #[test]
+    fn create_parse_sack() {
+        let channel_id = 11;
+        let hash = "abcdefg".to_owned();
+        let bitmap = vec![0b1010_1010, 0b0000_0001];
+
+        let raw =
+            messages::sack(channel_id, &hash, 5, &bitmap, Some(Compression::Deflate)).unwrap();
+        let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::SACK(channel_id, hash, 5, bitmap, Some(Compression::Deflate))
+        );
+    }
+
+    #[test]
+    fn create_parse_nak_compressed() {
+        let channel_id = 11;
+        let hash = "abcdefg".to_owned();
+        let missing_chunks = vec![0, 1, 4, 10];
+        let chunk_ranges: Vec<(u32, u32)> = vec![(0, 1), (4, 10)];
+
+        let raw = messages::nak(
+            channel_id,
+            &hash,
+            &missing_chunks,
+            Some(Compression::Deflate),
+        )
+        .unwrap();
         let msg = parsers::parse_message(de::from_slice(&raw).unwrap());
 
         assert_eq!(
             msg.unwrap(),
-            Message::NAK(channel_id, hash, Some(chunk_ranges))
+            Message::NAK(
+                channel_id,
+                hash,
+                Some(chunk_ranges),
+                Some(Compression::Deflate)
+            )
         );
     }
 }
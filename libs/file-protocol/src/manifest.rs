@@ -0,0 +1,289 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::error::ProtocolError;
+use serde_cbor::{de, ser};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// A file included in a directory transfer
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManifestEntry {
+    /// Path of the file, relative to the directory being transferred
+    pub path: String,
+    /// Size of the file, in bytes
+    pub size: u64,
+    /// BLAKE2s hash of the file's contents
+    pub hash: String,
+    /// Number of chunks the file was split into
+    pub num_chunks: u32,
+    /// File mode
+    pub mode: u32,
+}
+
+/// A file which couldn't be prepared for a directory transfer
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManifestFailure {
+    /// Path of the file, relative to the directory being transferred
+    pub path: String,
+    /// Reason the file couldn't be prepared
+    pub error: String,
+}
+
+/// List of the files which make up a directory transfer
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Manifest {
+    /// Files which are ready to be transferred
+    pub files: Vec<ManifestEntry>,
+    /// Files which couldn't be prepared for transfer
+    pub failures: Vec<ManifestFailure>,
+}
+
+impl Manifest {
+    /// Get the directory which the paths in a manifest are relative to
+    ///
+    /// # Arguments
+    ///
+    /// * source - Directory or glob pattern the manifest was built from
+    pub fn root(source: &str) -> PathBuf {
+        let path = Path::new(source);
+        if path.is_dir() {
+            return path.to_owned();
+        }
+
+        // Everything up to the first component containing a wildcard
+        let mut root = PathBuf::new();
+        for component in path.components() {
+            if component
+                .as_os_str()
+                .to_string_lossy()
+                .contains(&['*', '?', '['][..])
+            {
+                return root;
+            }
+            root.push(component);
+        }
+
+        // Not a pattern, just a single file
+        path.parent().map(Path::to_owned).unwrap_or_default()
+    }
+
+    /// Encode the manifest for transfer
+    pub fn to_vec(&self) -> Result<Vec<u8>, ProtocolError> {
+        let files: Vec<_> = self
+            .files
+            .iter()
+            .map(|file| {
+                (
+                    &file.path,
+                    file.size,
+                    &file.hash,
+                    file.num_chunks,
+                    file.mode,
+                )
+            })
+            .collect();
+        let failures: Vec<_> = self
+            .failures
+            .iter()
+            .map(|failure| (&failure.path, &failure.error))
+            .collect();
+
+        Ok(ser::to_vec_packed(&(files, failures))?)
+    }
+
+    /// Decode a transferred manifest
+    ///
+    /// Manifests containing absolute paths, or paths which lead outside of the
+    /// transferred directory, are rejected
+    pub fn from_slice(data: &[u8]) -> Result<Self, ProtocolError> {
+        type Raw = (Vec<(String, u64, String, u32, u32)>, Vec<(String, String)>);
+
+        let (files, failures): Raw = de::from_slice(data)
+            .map_err(|err| ProtocolError::ManifestError(format!("Failed to parse: {}", err)))?;
+
+        let manifest = Manifest {
+            files: files
+                .into_iter()
+                .map(|(path, size, hash, num_chunks, mode)| ManifestEntry {
+                    path,
+                    size,
+                    hash,
+                    num_chunks,
+                    mode,
+                })
+                .collect(),
+            failures: failures
+                .into_iter()
+                .map(|(path, error)| ManifestFailure { path, error })
+                .collect(),
+        };
+
+        for path in manifest
+            .files
+            .iter()
+            .map(|file| &file.path)
+            .chain(manifest.failures.iter().map(|failure| &failure.path))
+        {
+            if !Path::new(path)
+                .components()
+                .all(|component| matches!(component, Component::CurDir | Component::Normal(_)))
+            {
+                return Err(ProtocolError::ManifestError(format!(
+                    "Invalid path {}",
+                    path
+                )));
+            }
+        }
+
+        Ok(manifest)
+    }
+}
+
+// Find all of the files in a directory tree, or matching a glob pattern.
+// Returns the full path of each file, along with its path relative to `Manifest::root`
+pub fn collect_files(source: &str) -> Result<Vec<(PathBuf, String)>, ProtocolError> {
+    let root = Manifest::root(source);
+    let mut files = vec![];
+
+    if Path::new(source).is_dir() {
+        walk(Path::new(source), &root, &mut files)?;
+    } else {
+        let paths = glob::glob(source).map_err(|err| {
+            ProtocolError::ManifestError(format!("Invalid pattern {}: {}", source, err))
+        })?;
+        for entry in paths {
+            let path = entry.map_err(|err| ProtocolError::StorageError {
+                action: format!("read {:?}", err.path()),
+                err: io::Error::new(err.error().kind(), format!("{}", err.error())),
+            })?;
+            walk(&path, &root, &mut files)?;
+        }
+    }
+
+    if files.is_empty() {
+        return Err(ProtocolError::ManifestError(format!(
+            "No files found in {}",
+            source
+        )));
+    }
+
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    files.dedup_by(|a, b| a.1 == b.1);
+    Ok(files)
+}
+
+fn walk(path: &Path, root: &Path, files: &mut Vec<(PathBuf, String)>) -> Result<(), ProtocolError> {
+    if path.is_dir() {
+        let entries = fs::read_dir(path).map_err(|err| ProtocolError::StorageError {
+            action: format!("read dir {:?}", path),
+            err,
+        })?;
+        for entry in entries {
+            let entry = entry.map_err(|err| ProtocolError::StorageError {
+                action: format!("read dir {:?}", path),
+                err,
+            })?;
+            let file_type = entry
+                .file_type()
+                .map_err(|err| ProtocolError::StorageError {
+                    action: format!("stat {:?}", entry.path()),
+                    err,
+                })?;
+            // Symlinked directories are skipped, since they could lead back up the tree
+            if file_type.is_symlink() && entry.path().is_dir() {
+                continue;
+            }
+            walk(&entry.path(), root, files)?;
+        }
+    } else if path.is_file() {
+        let relative: Vec<_> = path
+            .strip_prefix(root)
+            .unwrap_or(path)
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        files.push((path.to_owned(), relative.join("/")));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let manifest = Manifest {
+            files: vec![ManifestEntry {
+                path: "images/0001.png".to_owned(),
+                size: 4096,
+                hash: "abcdef".to_owned(),
+                num_chunks: 4,
+                mode: 0o644,
+            }],
+            failures: vec![ManifestFailure {
+                path: "images/0002.png".to_owned(),
+                error: "Permission denied".to_owned(),
+            }],
+        };
+
+        let raw = manifest.to_vec().unwrap();
+        assert_eq!(Manifest::from_slice(&raw).unwrap(), manifest);
+    }
+
+    #[test]
+    fn reject_escaping_path() {
+        let raw = ser::to_vec_packed(&(
+            vec![("../../etc/passwd", 10, "abcdef", 1, 0o644)],
+            Vec::<(String, String)>::new(),
+        ))
+        .unwrap();
+
+        assert!(Manifest::from_slice(&raw).is_err());
+    }
+
+    #[test]
+    fn skip_symlinked_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("images")).unwrap();
+        fs::write(dir.path().join("images/0001.png"), "image").unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("images/loop")).unwrap();
+
+        let files = collect_files(&dir.path().to_string_lossy()).unwrap();
+        assert_eq!(
+            files,
+            vec![(
+                dir.path().join("images/0001.png"),
+                "images/0001.png".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn pattern_root() {
+        assert_eq!(Manifest::root("data/*.csv"), PathBuf::from("data"));
+        assert_eq!(
+            Manifest::root("data/2019-*/images/*.png"),
+            PathBuf::from("data")
+        );
+        assert_eq!(Manifest::root("*.csv"), PathBuf::from(""));
+        assert_eq!(Manifest::root("data/missing.csv"), PathBuf::from("data"));
+    }
+}
@@ -14,7 +14,9 @@
 // limitations under the License.
 //
 
+use crate::compression::Compression;
 use crate::error::ProtocolError;
+use crate::storage::Direction;
 use log::info;
 use serde_cbor::{ser, Value};
 
@@ -24,26 +26,77 @@ pub fn export_request(
     hash: &str,
     target_path: &str,
     mode: u32,
+    compression: Option<Compression>,
 ) -> Result<Vec<u8>, ProtocolError> {
+    let compression = compression.map(Compression::as_str);
     info!(
-        "-> {{ {}, export, {}, {}, {} }}",
-        channel_id, hash, target_path, mode
+        "-> {{ {}, export, {}, {}, {}, {:?} }}",
+        channel_id, hash, target_path, mode, compression
     );
 
-    ser::to_vec_packed(&(channel_id, "export", hash, target_path, mode)).map_err(|err| {
-        ProtocolError::MessageCreationError {
-            message: "export".to_owned(),
-            err,
-        }
+    // Peers which don't support compression will ignore the extra field
+    let result = match compression {
+        Some(name) => ser::to_vec_packed(&(channel_id, "export", hash, target_path, mode, name)),
+        None => ser::to_vec_packed(&(channel_id, "export", hash, target_path, mode)),
+    };
+
+    result.map_err(|err| ProtocolError::MessageCreationError {
+        message: "export".to_owned(),
+        err,
     })
 }
 
 // Create import message
-pub fn import_request(channel_id: u32, source_path: &str) -> Result<Vec<u8>, ProtocolError> {
-    info!("-> {{ import, {} }}", source_path);
-    ser::to_vec_packed(&(channel_id, "import", source_path)).map_err(|err| {
+pub fn import_request(
+    channel_id: u32,
+    source_path: &str,
+    compression: Option<Compression>,
+) -> Result<Vec<u8>, ProtocolError> {
+    let compression = compression.map(Compression::as_str);
+    info!("-> {{ import, {}, {:?} }}", source_path, compression);
+
+    let result = match compression {
+        Some(name) => ser::to_vec_packed(&(channel_id, "import", source_path, name)),
+        None => ser::to_vec_packed(&(channel_id, "import", source_path)),
+    };
+
+    result.map_err(|err| ProtocolError::MessageCreationError {
+        message: "import".to_owned(),
+        err,
+    })
+}
+
+// Create directory import request message
+pub fn import_dir_request(
+    channel_id: u32,
+    source: &str,
+    compression: Option<Compression>,
+) -> Result<Vec<u8>, ProtocolError> {
+    let compression = compression.map(Compression::as_str);
+    info!("-> {{ import_dir, {}, {:?} }}", source, compression);
+
+    let result = match compression {
+        Some(name) => ser::to_vec_packed(&(channel_id, "import_dir", source, name)),
+        None => ser::to_vec_packed(&(channel_id, "import_dir", source)),
+    };
+
+    result.map_err(|err| ProtocolError::MessageCreationError {
+        message: "import_dir".to_owned(),
+        err,
+    })
+}
+
+// Create resume message
+pub fn resume(channel_id: u32, hash: &str, direction: Direction) -> Result<Vec<u8>, ProtocolError> {
+    info!(
+        "-> {{ {}, resume, {}, {} }}",
+        channel_id,
+        hash,
+        direction.as_str()
+    );
+    ser::to_vec_packed(&(channel_id, "resume", hash, direction.as_str())).map_err(|err| {
         ProtocolError::MessageCreationError {
-            message: "import".to_owned(),
+            message: "resume".to_owned(),
             err,
         }
     })
@@ -71,20 +124,35 @@ pub fn ack(channel_id: u32, hash: &str, num_chunks: Option<u32>) -> Result<Vec<u
     })
 }
 
-// Sends a nak with ranges of missing chunks
-pub fn nak(channel_id: u32, hash: &str, missing_chunks: &[u32]) -> Result<Vec<u8>, ProtocolError> {
+// Sends a nak with ranges of missing chunks,
+// along with the compression the chunks should be sent with
+pub fn nak(
+    channel_id: u32,
+    hash: &str,
+    missing_chunks: &[u32],
+    compression: Option<Compression>,
+) -> Result<Vec<u8>, ProtocolError> {
     let chunks = if missing_chunks.len() > 20 {
         &missing_chunks[0..20]
     } else {
         missing_chunks
     };
 
-    info!("-> {{ {}, {}, false, {:?} }}", channel_id, hash, chunks);
-    let mut vec = ser::to_vec_packed(&(channel_id, hash, false)).map_err(|err| {
-        ProtocolError::MessageCreationError {
-            message: "NAK".to_owned(),
-            err,
-        }
+    let compression = compression.map(Compression::as_str);
+    info!(
+        "-> {{ {}, {}, false, {:?}, {:?} }}",
+        channel_id, hash, compression, chunks
+    );
+
+    // Peers which don't support compression will skip over the non-numeric field
+    let result = match compression {
+        Some(name) => ser::to_vec_packed(&(channel_id, hash, false, name)),
+        None => ser::to_vec_packed(&(channel_id, hash, false)),
+    };
+
+    let mut vec = result.map_err(|err| ProtocolError::MessageCreationError {
+        message: "NAK".to_owned(),
+        err,
     })?;
 
     // Make the array indefinite-length
@@ -105,20 +173,57 @@ pub fn nak(channel_id: u32, hash: &str, missing_chunks: &[u32]) -> Result<Vec<u8
     Ok(vec)
 }
 
+// Create selective acknowledgement message,
+// along with the compression the missing chunks should be sent with
+pub fn sack(
+    channel_id: u32,
+    hash: &str,
+    base: u32,
+    bitmap: &[u8],
+    compression: Option<Compression>,
+) -> Result<Vec<u8>, ProtocolError> {
+    let bitmap_bytes = Value::Bytes(bitmap.to_vec());
+    let compression = compression.map(Compression::as_str);
+    info!(
+        "-> {{ {}, {}, sack, {}, {:?}, {:?} }}",
+        channel_id, hash, base, bitmap, compression
+    );
+
+    let result = match compression {
+        Some(name) => ser::to_vec_packed(&(channel_id, hash, "sack", base, bitmap_bytes, name)),
+        None => ser::to_vec_packed(&(channel_id, hash, "sack", base, bitmap_bytes)),
+    };
+
+    result.map_err(|err| ProtocolError::MessageCreationError {
+        message: "SACK".to_owned(),
+        err,
+    })
+}
+
 // Create chunk message
+// The chunk data has already been compressed with `compression`, if specified
 pub fn chunk(
     channel_id: u32,
     hash: &str,
     index: u32,
     chunk: &[u8],
+    compression: Option<Compression>,
 ) -> Result<Vec<u8>, ProtocolError> {
     let chunk_bytes = Value::Bytes(chunk.to_vec());
-    info!("-> {{ {}, {}, {}, chunk_data }}", channel_id, hash, index);
-    ser::to_vec_packed(&(channel_id, hash, index, chunk_bytes)).map_err(|err| {
-        ProtocolError::MessageCreationError {
-            message: "chunk".to_owned(),
-            err,
-        }
+    let compression = compression.map(Compression::as_str);
+    info!(
+        "-> {{ {}, {}, {}, chunk_data, {:?} }}",
+        channel_id, hash, index, compression
+    );
+
+    let result = match compression {
+        Some(name) => ser::to_vec_packed(&(channel_id, hash, index, chunk_bytes, name)),
+        None => ser::to_vec_packed(&(channel_id, hash, index, chunk_bytes)),
+    };
+
+    result.map_err(|err| ProtocolError::MessageCreationError {
+        message: "chunk".to_owned(),
+        err,
     })
 }
 
@@ -128,17 +233,22 @@ pub fn import_setup_success(
     hash: &str,
     num_chunks: u32,
     mode: u32,
+    compression: Option<Compression>,
 ) -> Result<Vec<u8>, ProtocolError> {
+    let compression = compression.map(Compression::as_str);
     info!(
-        "-> {{ {}, true, {}, {}, {} }}",
-        channel_id, hash, num_chunks, mode
+        "-> {{ {}, true, {}, {}, {}, {:?} }}",
+        channel_id, hash, num_chunks, mode, compression
     );
 
-    ser::to_vec_packed(&(channel_id, true, hash, num_chunks, mode)).map_err(|err| {
-        ProtocolError::MessageCreationError {
-            message: "import success".to_owned(),
-            err,
-        }
+    let result = match compression {
+        Some(name) => ser::to_vec_packed(&(channel_id, true, hash, num_chunks, mode, name)),
+        None => ser::to_vec_packed(&(channel_id, true, hash, num_chunks, mode)),
+    };
+
+    result.map_err(|err| ProtocolError::MessageCreationError {
+        message: "import success".to_owned(),
+        err,
     })
 }
 
@@ -165,7 +275,6 @@ pub fn operation_failure(channel_id: u32, error: &str) -> Result<Vec<u8>, Protoc
 }
 
 // Create sync message
-#[allow(dead_code)]
 pub fn sync(channel_id: u32, hash: &str) -> Result<Vec<u8>, ProtocolError> {
     info!("-> {{ {}, {} }}", channel_id, hash);
     ser::to_vec_packed(&(channel_id, hash)).map_err(|err| ProtocolError::MessageCreationError {
@@ -15,7 +15,9 @@
 //
 
 use super::Message;
+use crate::compression::Compression;
 use crate::error::ProtocolError;
+use crate::storage::Direction;
 use serde_cbor::Value;
 use std::slice::Iter;
 
@@ -48,6 +50,15 @@ pub fn parse_channel_id(message: &Value) -> Result<u32, ProtocolError> {
     }
 }
 
+// Parse out an optional compression method.
+// Methods we don't support are treated as though no compression was requested
+fn parse_compression(piece: Option<&Value>) -> Option<Compression> {
+    match piece {
+        Some(Value::String(name)) => Compression::from_name(name),
+        _ => None,
+    }
+}
+
 pub fn parse_message(message: Value) -> Result<Message, ProtocolError> {
     let raw = match message {
         Value::Array(val) => val,
@@ -78,6 +89,12 @@ pub fn parse_message(message: Value) -> Result<Message, ProtocolError> {
         if let Some(msg) = parse_import_request(channel_id, pieces.to_owned())? {
             return Ok(msg);
         }
+        if let Some(msg) = parse_import_dir_request(channel_id, pieces.to_owned())? {
+            return Ok(msg);
+        }
+        if let Some(msg) = parse_resume_request(channel_id, pieces.to_owned())? {
+            return Ok(msg);
+        }
         if let Some(msg) = parse_success_receive(channel_id, pieces.to_owned())? {
             return Ok(msg);
         }
@@ -93,6 +110,9 @@ pub fn parse_message(message: Value) -> Result<Message, ProtocolError> {
         if let Some(msg) = parse_nak(channel_id, pieces.to_owned())? {
             return Ok(msg);
         }
+        if let Some(msg) = parse_sack(channel_id, pieces.to_owned())? {
+            return Ok(msg);
+        }
         if let Some(msg) = parse_chunk(channel_id, pieces.to_owned())? {
             return Ok(msg);
         }
@@ -134,7 +154,7 @@ pub fn parse_cleanup_request(
 }
 
 // Parse out export request
-// { channel_id, "export", hash, path, [, mode] }
+// { channel_id, "export", hash, path, [, mode [, compression]] }
 pub fn parse_export_request(
     channel_id: u32,
     mut pieces: Iter<Value>,
@@ -170,11 +190,14 @@ pub fn parse_export_request(
                 _ => None,
             };
 
+            let compression = parse_compression(pieces.next());
+
             return Ok(Some(Message::ReqReceive(
                 channel_id,
                 hash.to_owned(),
                 path.to_owned(),
                 mode,
+                compression,
             )));
         }
     }
@@ -183,7 +206,7 @@ pub fn parse_export_request(
 }
 
 // Parse out import request
-// { channel_id, "import", path }
+// { channel_id, "import", path [, compression] }
 pub fn parse_import_request(
     channel_id: u32,
     mut pieces: Iter<Value>,
@@ -201,9 +224,89 @@ pub fn parse_import_request(
                     ));
                 }
             };
+            let compression = parse_compression(pieces.next());
+
             return Ok(Some(Message::ReqTransmit(
                 channel_id as u32,
                 path.to_owned(),
+                compression,
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+// Parse out directory import request
+// { channel_id, "import_dir", source [, compression] }
+pub fn parse_import_dir_request(
+    channel_id: u32,
+    mut pieces: Iter<Value>,
+) -> Result<Option<Message>, ProtocolError> {
+    if let Some(Value::String(op)) = pieces.next() {
+        if op == "import_dir" {
+            let source = match pieces.next().ok_or_else(|| {
+                ProtocolError::MissingParam("import_dir".to_owned(), "source".to_owned())
+            })? {
+                Value::String(val) => val,
+                _ => {
+                    return Err(ProtocolError::InvalidParam(
+                        "import_dir".to_owned(),
+                        "source".to_owned(),
+                    ));
+                }
+            };
+            let compression = parse_compression(pieces.next());
+
+            return Ok(Some(Message::ReqTransmitDir(
+                channel_id,
+                source.to_owned(),
+                compression,
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+// Parse out resume request
+// { channel_id, "resume", hash, direction }
+pub fn parse_resume_request(
+    channel_id: u32,
+    mut pieces: Iter<Value>,
+) -> Result<Option<Message>, ProtocolError> {
+    if let Some(Value::String(op)) = pieces.next() {
+        if op == "resume" {
+            let hash = match pieces.next().ok_or_else(|| {
+                ProtocolError::MissingParam("resume".to_owned(), "hash".to_owned())
+            })? {
+                Value::String(val) => val,
+                _ => {
+                    return Err(ProtocolError::InvalidParam(
+                        "resume".to_owned(),
+                        "hash".to_owned(),
+                    ));
+                }
+            };
+
+            let direction = match pieces.next().ok_or_else(|| {
+                ProtocolError::MissingParam("resume".to_owned(), "direction".to_owned())
+            })? {
+                Value::String(val) => Direction::from_name(val).ok_or_else(|| {
+                    ProtocolError::InvalidParam("resume".to_owned(), "direction".to_owned())
+                })?,
+                _ => {
+                    return Err(ProtocolError::InvalidParam(
+                        "resume".to_owned(),
+                        "direction".to_owned(),
+                    ));
+                }
+            };
+
+            return Ok(Some(Message::Resume(
+                channel_id,
+                hash.to_owned(),
+                direction,
             )));
         }
     }
@@ -276,12 +379,15 @@ pub fn parse_success_transmit(
                 _ => None,
             };
 
+            let compression = parse_compression(pieces.next());
+
             // Return the file info
             return Ok(Some(Message::SuccessTransmit(
                 channel_id,
                 hash.to_string(),
                 num_chunks as u32,
                 mode,
+                compression,
             )));
         }
     }
@@ -337,7 +443,7 @@ pub fn parse_ack(
 }
 
 // Parse out nak
-// { hash, false, ..missing_chunks }
+// { hash, false, [compression,] ..missing_chunks }
 pub fn parse_nak(
     channel_id: u32,
     mut pieces: Iter<Value>,
@@ -346,9 +452,12 @@ pub fn parse_nak(
         if let Some(Value::Bool(false)) = pieces.next() {
             let mut remaining_chunks: Vec<(u32, u32)> = vec![];
             let mut chunk_nums: Vec<u32> = vec![];
+            let mut compression = None;
             for entry in pieces {
-                if let Value::U64(chunk_num) = entry {
-                    chunk_nums.push(*chunk_num as u32);
+                match entry {
+                    Value::U64(chunk_num) => chunk_nums.push(*chunk_num as u32),
+                    Value::String(name) => compression = Compression::from_name(name),
+                    _ => {}
                 }
             }
 
@@ -362,6 +471,7 @@ pub fn parse_nak(
                 channel_id,
                 hash.to_owned(),
                 Some(remaining_chunks),
+                compression,
             )));
         }
     }
@@ -369,8 +479,57 @@ pub fn parse_nak(
     Ok(None)
 }
 
+// Parse out selective ack
+// { hash, "sack", base, bitmap [, compression] }
+pub fn parse_sack(
+    channel_id: u32,
+    mut pieces: Iter<Value>,
+) -> Result<Option<Message>, ProtocolError> {
+    if let Some(Value::String(hash)) = pieces.next() {
+        if let Some(Value::String(op)) = pieces.next() {
+            if op == "sack" {
+                let base = match pieces.next().ok_or_else(|| {
+                    ProtocolError::MissingParam("SACK".to_owned(), "base".to_owned())
+                })? {
+                    Value::U64(val) => *val as u32,
+                    _ => {
+                        return Err(ProtocolError::InvalidParam(
+                            "SACK".to_owned(),
+                            "base".to_owned(),
+                        ));
+                    }
+                };
+
+                let bitmap = match pieces.next().ok_or_else(|| {
+                    ProtocolError::MissingParam("SACK".to_owned(), "bitmap".to_owned())
+                })? {
+                    Value::Bytes(val) => val.to_vec(),
+                    _ => {
+                        return Err(ProtocolError::InvalidParam(
+                            "SACK".to_owned(),
+                            "bitmap".to_owned(),
+                        ));
+                    }
+                };
+
+                let compression = parse_compression(pieces.next());
+
+                return Ok(Some(Message::SACK(
+                    channel_id,
+                    hash.to_owned(),
+                    base,
+                    bitmap,
+                    compression,
+                )));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
 // Parse out chunk
-// { hash, chunk_index, data }
+// { hash, chunk_index, data [, compression] }
 pub fn parse_chunk(
     channel_id: u32,
     mut pieces: Iter<Value>,
@@ -379,11 +538,26 @@ pub fn parse_chunk(
         if let Some(Value::U64(num)) = pieces.next() {
             if let Some(third_param) = pieces.next() {
                 if let Value::Bytes(data) = third_param {
+                    // Unlike requests, we can't ignore an unknown compression method here,
+                    // since the chunk data would be unusable
+                    let compression = match pieces.next() {
+                        Some(Value::String(name)) => {
+                            Some(Compression::from_name(name).ok_or_else(|| {
+                                ProtocolError::InvalidParam(
+                                    "chunk".to_owned(),
+                                    "compression".to_owned(),
+                                )
+                            })?)
+                        }
+                        _ => None,
+                    };
+
                     return Ok(Some(Message::ReceiveChunk(
                         channel_id,
                         hash.to_owned(),
                         *num as u32,
                         data.to_vec(),
+                        compression,
                     )));
                 } else {
                     return Err(ProtocolError::InvalidParam(
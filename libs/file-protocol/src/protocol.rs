@@ -16,21 +16,31 @@
 
 //! File transfer protocol module
 
+use super::compression::{self, Compression};
+use super::manifest::{self, Manifest, ManifestEntry, ManifestFailure};
 use super::messages;
 use super::parsers;
-use super::storage;
+use super::storage::{self, Direction, Transfer};
 use super::Message;
 use crate::error::ProtocolError;
 use cbor_protocol::Protocol as CborProtocol;
 use log::{error, info, warn};
 use rand::{self, Rng};
 use serde_cbor::Value;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::net::SocketAddr;
+use std::path::Path;
 use std::str;
 use std::thread;
 use std::time::Duration;
 
+// Largest selective acknowledgement bitmap to send, in bytes
+const MAX_SACK_SIZE: usize = 128;
+// Largest inter-chunk delay which window mode will back off to
+const MAX_WINDOW_DELAY: Duration = Duration::from_secs(1);
+// Percentage of a window's chunks which can be lost before window mode backs off
+const WINDOW_LOSS_THRESHOLD: usize = 5;
+
 /// Configuration data for Protocol
 #[derive(Clone)]
 pub struct ProtocolConfig {
@@ -47,10 +57,14 @@ pub struct ProtocolConfig {
     max_chunks_transmit: Option<u32>,
     // Chunk size used in storage hashing
     hash_chunk_size: usize,
+    // Max number of chunks to send before asking the receiver which ones arrived.
+    // If set, the inter-chunk delay is adjusted based on how many chunks were lost
+    window_size: Option<u32>,
 }
 
 impl ProtocolConfig {
-    /// Creates new ProtocolConfig struct
+    /// Creates new ProtocolConfig struct.
+    /// A `window_size` of zero disables window mode, the same as `None`
     pub fn new(
         storage_prefix: Option<String>,
         transfer_chunk_size: usize,
@@ -58,6 +72,7 @@ impl ProtocolConfig {
         inter_chunk_delay: u64,
         max_chunks_transmit: Option<u32>,
         hash_chunk_size: usize,
+        window_size: Option<u32>,
     ) -> Self {
         ProtocolConfig {
             storage_prefix: storage_prefix.unwrap_or_else(|| "file-storage".to_owned()),
@@ -66,6 +81,7 @@ impl ProtocolConfig {
             inter_chunk_delay: Duration::from_millis(inter_chunk_delay),
             max_chunks_transmit,
             hash_chunk_size,
+            window_size: window_size.filter(|size| *size > 0),
         }
     }
 }
@@ -75,6 +91,12 @@ pub struct Protocol {
     cbor_proto: CborProtocol,
     remote_addr: Cell<SocketAddr>,
     config: ProtocolConfig,
+    // Current inter-chunk delay used in window mode
+    window_delay: Cell<Duration>,
+    // Chunks sent in the most recent window
+    window_chunks: RefCell<Vec<u32>>,
+    // Whether the remote peer has ever replied to a sync with a SACK
+    sack_seen: Cell<bool>,
 }
 
 /// Current state of the file protocol transaction
@@ -102,6 +124,8 @@ pub enum State {
         path: String,
         /// File mode
         mode: Option<u32>,
+        /// Compression to request file chunks with
+        compression: Option<Compression>,
     },
     /// All file chunks have been received
     ReceivingDone {
@@ -140,7 +164,7 @@ impl Protocol {
     /// ```no_run
     /// use file_protocol::*;
     ///
-    /// let config = FileProtocolConfig::new(Some("my/file/storage".to_owned()), 1024, 5, 1, None, 2048);
+    /// let config = FileProtocolConfig::new(Some("my/file/storage".to_owned()), 1024, 5, 1, None, 2048, None);
     /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "192.168.0.1:7000", config);
     /// ```
     ///
@@ -160,6 +184,9 @@ impl Protocol {
                     })
                     .unwrap(),
             ),
+            window_delay: Cell::new(config.inter_chunk_delay),
+            window_chunks: RefCell::new(vec![]),
+            sack_seen: Cell::new(false),
             config,
         }
     }
@@ -180,7 +207,7 @@ impl Protocol {
     /// use file_protocol::*;
     /// use serde_cbor::ser;
     ///
-    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048);
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
     /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
     /// let message = ser::to_vec_packed(&"ping").unwrap();
     ///
@@ -210,7 +237,7 @@ impl Protocol {
     /// use file_protocol::*;
     /// use std::time::Duration;
     ///
-    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048);
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
     /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
     ///
     /// let message = match f_protocol.recv(Some(Duration::from_secs(1))) {
@@ -242,7 +269,7 @@ impl Protocol {
     /// ```no_run
     /// use file_protocol::*;
     ///
-    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048);
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
     /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
     ///
     /// let channel_id = f_protocol.generate_channel();
@@ -271,7 +298,7 @@ impl Protocol {
     /// ```no_run
     /// use file_protocol::*;
     ///
-    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048);
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
     /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
     ///
     /// # ::std::fs::File::create("client.txt").unwrap();
@@ -303,6 +330,8 @@ impl Protocol {
     /// * hash - BLAKE2s hash of file
     /// * target_path - Destination file path
     /// * mode - File mode
+    /// * compression - Compression to request for the file data while in transit.
+    ///   If the remote target doesn't support it, the file data will be sent uncompressed
     ///
     /// # Errors
     ///
@@ -313,14 +342,14 @@ impl Protocol {
     /// ```no_run
     /// use file_protocol::*;
     ///
-    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048);
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
     /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
     ///
     /// # ::std::fs::File::create("client.txt").unwrap();
     ///
     /// let (hash, _num_chunks, mode) = f_protocol.initialize_file("client.txt").unwrap();
     /// let channel_id = f_protocol.generate_channel().unwrap();
-    /// f_protocol.send_export(channel_id, &hash, "final/dir/service.txt", mode);
+    /// f_protocol.send_export(channel_id, &hash, "final/dir/service.txt", mode, None);
     /// ```
     ///
     pub fn send_export(
@@ -329,22 +358,210 @@ impl Protocol {
         hash: &str,
         target_path: &str,
         mode: u32,
+        compression: Option<Compression>,
     ) -> Result<(), ProtocolError> {
         self.send(&messages::export_request(
             channel_id,
             hash,
             target_path,
             mode,
+            compression,
         )?)?;
 
+        // Remember the transfer so that it can be resumed if it's interrupted
+        self.save_transfer(
+            channel_id,
+            Direction::Transmit,
+            hash,
+            target_path,
+            Some(mode),
+            compression,
+        );
+
         Ok(())
     }
 
+    /// Request remote target to resume an interrupted transfer
+    ///
+    /// The remote target will reply as it would to the original export or import request,
+    /// so that only the missing chunks of the file are transferred.
+    ///
+    /// # Arguments
+    ///
+    /// * channel_id - Channel ID used for transaction
+    /// * hash - BLAKE2s hash of file
+    /// * direction - Whether the host is sending or receiving the file
+    ///
+    /// # Errors
+    ///
+    /// If this function encounters any errors, it will return an error message string
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use file_protocol::*;
+    ///
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
+    /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
+    ///
+    /// if let Some(transfer) = f_protocol.find_transfer(Direction::Transmit, "service.txt") {
+    ///     let channel_id = f_protocol.generate_channel().unwrap();
+    ///     f_protocol.send_resume(channel_id, &transfer.hash, Direction::Transmit);
+    /// }
+    /// ```
+    ///
+    pub fn send_resume(
+        &self,
+        channel_id: u32,
+        hash: &str,
+        direction: Direction,
+    ) -> Result<(), ProtocolError> {
+        self.send(&messages::resume(channel_id, hash, direction)?)
+    }
+
+    /// Find the saved state of an interrupted transfer
+    ///
+    /// # Arguments
+    ///
+    /// * direction - Whether the host was sending or receiving the file
+    /// * path - Destination path of a received file, or the requested path of a transmitted file
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use file_protocol::*;
+    ///
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
+    /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
+    ///
+    /// let transfer = f_protocol.find_transfer(Direction::Receive, "client.txt");
+    /// ```
+    ///
+    pub fn find_transfer(&self, direction: Direction, path: &str) -> Option<Transfer> {
+        storage::find_transfer(&self.config.storage_prefix, direction, path)
+    }
+
+    /// Find the saved state of an interrupted upload of a file
+    ///
+    /// The transfer is only returned if the file's contents haven't changed since the upload
+    /// was started
+    ///
+    /// # Arguments
+    ///
+    /// * source_path - File which was being sent
+    /// * target_path - Requested path of the file on the remote target
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read, an error will be returned
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use file_protocol::*;
+    ///
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
+    /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
+    ///
+    /// let transfer = f_protocol.find_upload("client.txt", "service.txt").unwrap();
+    /// ```
+    ///
+    pub fn find_upload(
+        &self,
+        source_path: &str,
+        target_path: &str,
+    ) -> Result<Option<Transfer>, ProtocolError> {
+        let transfer = match self.find_transfer(Direction::Transmit, target_path) {
+            Some(transfer) => transfer,
+            None => return Ok(None),
+        };
+
+        let (hash, _, _, _) = storage::describe_file(
+            source_path,
+            self.config.transfer_chunk_size,
+            self.config.hash_chunk_size,
+        )?;
+
+        if hash == transfer.hash {
+            Ok(Some(transfer))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Save the state of a transfer so that it can be resumed after a restart.
+    // Failing to do so doesn't stop the transfer, so errors are only logged
+    fn save_transfer(
+        &self,
+        channel_id: u32,
+        direction: Direction,
+        hash: &str,
+        path: &str,
+        mode: Option<u32>,
+        compression: Option<Compression>,
+    ) {
+        if let Err(e) = storage::store_transfer(
+            &self.config.storage_prefix,
+            channel_id,
+            direction,
+            hash,
+            path,
+            mode,
+            compression,
+        ) {
+            warn!("Failed to save transfer state for {}: {}", hash, e);
+        }
+    }
+
     /// Request a file from a remote target
     ///
     /// # Arguments
     ///
     /// * source_path - File remote target should send
+    /// * compression - Compression to request for the file data while in transit.
+    ///   If the remote target doesn't support it, the file data will be sent uncompressed
+    ///
+    /// # Errors
+    ///
+    /// If this function encounters any errors, it will return an error message string
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use file_protocol::*;
+    ///
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
+    /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
+    /// let channel_id = f_protocol.generate_channel().unwrap();
+    ///
+    /// f_protocol.send_import(channel_id, "service.txt", Some(Compression::Deflate));
+    /// ```
+    ///
+    pub fn send_import(
+        &self,
+        channel_id: u32,
+        source_path: &str,
+        compression: Option<Compression>,
+    ) -> Result<(), ProtocolError> {
+        self.send(&messages::import_request(
+            channel_id,
+            source_path,
+            compression,
+        )?)?;
+        Ok(())
+    }
+
+    /// Request a directory tree, or all of the files matching a glob pattern, from a remote target
+    ///
+    /// The remote target will reply as though the directory's manifest had been imported.
+    /// Once the manifest has been received, each of the files it lists can be requested
+    /// with a resume request
+    ///
+    /// # Arguments
+    ///
+    /// * source - Directory or glob pattern the remote target should send
+    /// * compression - Compression to request for the file data while in transit.
+    ///   If the remote target doesn't support it, the file data will be sent uncompressed
     ///
     /// # Errors
     ///
@@ -355,15 +572,24 @@ impl Protocol {
     /// ```no_run
     /// use file_protocol::*;
     ///
-    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048);
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
     /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
     /// let channel_id = f_protocol.generate_channel().unwrap();
     ///
-    /// f_protocol.send_import(channel_id, "service.txt");
+    /// f_protocol.send_import_dir(channel_id, "science/*.csv", None);
     /// ```
     ///
-    pub fn send_import(&self, channel_id: u32, source_path: &str) -> Result<(), ProtocolError> {
-        self.send(&messages::import_request(channel_id, source_path)?)?;
+    pub fn send_import_dir(
+        &self,
+        channel_id: u32,
+        source: &str,
+        compression: Option<Compression>,
+    ) -> Result<(), ProtocolError> {
+        self.send(&messages::import_dir_request(
+            channel_id,
+            source,
+            compression,
+        )?)?;
         Ok(())
     }
 
@@ -384,7 +610,7 @@ impl Protocol {
     /// ```no_run
     /// use file_protocol::*;
     ///
-    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048);
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
     /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
     ///
     /// # ::std::fs::File::create("client.txt").unwrap();
@@ -401,6 +627,126 @@ impl Protocol {
         )
     }
 
+    /// Prepare a directory tree, or all of the files matching a glob pattern, for transfer
+    ///
+    /// Each file is hashed where it is, rather than being imported into temporary storage,
+    /// so [`initialize_file`](#method.initialize_file) should be called for each file just
+    /// before it's sent. Files which can't be read are listed in the manifest's failures,
+    /// rather than stopping the whole transfer.
+    /// The manifest's paths are relative to [`Manifest::root`]
+    ///
+    /// # Arguments
+    ///
+    /// * source - Directory or glob pattern to initialize for transfer
+    ///
+    /// # Errors
+    ///
+    /// If the source can't be read, or doesn't contain any files, an error will be returned
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use file_protocol::*;
+    ///
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
+    /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
+    ///
+    /// let manifest = f_protocol.initialize_manifest("science").unwrap();
+    /// ```
+    ///
+    pub fn initialize_manifest(&self, source: &str) -> Result<Manifest, ProtocolError> {
+        let mut manifest = Manifest::default();
+
+        for (full_path, path) in manifest::collect_files(source)? {
+            let full_path = full_path.to_string_lossy();
+            let result = storage::describe_file(
+                &full_path,
+                self.config.transfer_chunk_size,
+                self.config.hash_chunk_size,
+            )
+            .map(|(hash, num_chunks, size, mode)| ManifestEntry {
+                path: path.clone(),
+                size,
+                hash,
+                num_chunks,
+                mode,
+            });
+
+            match result {
+                Ok(entry) => manifest.files.push(entry),
+                Err(error) => {
+                    warn!("Failed to initialize {}: {}", full_path, error);
+                    manifest.failures.push(ManifestFailure {
+                        path,
+                        error: format!("{}", error),
+                    });
+                }
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    // Prepare all of the files for a directory transfer, along with the manifest describing them.
+    // Each file is saved as an outgoing transfer, so that the requester can fetch them with
+    // resume requests once it has the manifest. The files' data is only imported into storage
+    // when they're requested, so that the whole directory isn't copied at once
+    fn initialize_dir(
+        &self,
+        channel_id: u32,
+        source: &str,
+        compression: Option<Compression>,
+    ) -> Result<(String, u32, u32), ProtocolError> {
+        let manifest = self.initialize_manifest(source)?;
+        let root = Manifest::root(source);
+
+        for file in &manifest.files {
+            storage::store_meta(&self.config.storage_prefix, &file.hash, file.num_chunks)?;
+            self.save_transfer(
+                channel_id,
+                Direction::Transmit,
+                &file.hash,
+                &root.join(&file.path).to_string_lossy(),
+                Some(file.mode),
+                compression,
+            );
+        }
+
+        storage::initialize_data(
+            &self.config.storage_prefix,
+            &manifest.to_vec()?,
+            self.config.transfer_chunk_size,
+            self.config.hash_chunk_size,
+        )
+    }
+
+    // Make sure all of the chunks of an outgoing transfer are in storage, importing them again
+    // from the source file if they aren't. Returns the number of chunks
+    fn prepare_resend(&self, hash: &str, transfer: &Transfer) -> Result<u32, ProtocolError> {
+        if let Ok((true, _)) = storage::validate_file(&self.config.storage_prefix, hash, None) {
+            return Ok(transfer.num_chunks);
+        }
+
+        if !Path::new(&transfer.path).is_file() {
+            return Err(ProtocolError::StorageParseError(format!(
+                "Stored chunks of {} are incomplete",
+                hash
+            )));
+        }
+
+        // Files in a directory transfer aren't imported until they're requested
+        let (new_hash, num_chunks, _) = self.initialize_file(&transfer.path)?;
+        if new_hash != hash {
+            storage::delete_file(&self.config.storage_prefix, &new_hash)?;
+            return Err(ProtocolError::StorageParseError(format!(
+                "{} has changed since its transfer was started",
+                transfer.path
+            )));
+        }
+
+        Ok(num_chunks)
+    }
+
     // Verify the integrity of received file data and then transfer into the requested permanent file location.
     // Notify the connection peer of the results
     //
@@ -434,29 +780,70 @@ impl Protocol {
         }
     }
 
+    // Prepare to receive a file, requesting any chunks which are missing from storage
+    fn start_receive(
+        &self,
+        channel_id: u32,
+        hash: &str,
+        path: &str,
+        mode: Option<u32>,
+        compression: Option<Compression>,
+    ) -> Result<State, ProtocolError> {
+        // See what state the file is currently in on our side
+        match storage::validate_file(&self.config.storage_prefix, hash, None) {
+            Ok((true, _)) => {
+                // We've already got all the file data in temporary storage
+                self.send(&messages::ack(channel_id, hash, None)?)?;
+
+                Ok(State::ReceivingDone {
+                    channel_id,
+                    hash: hash.to_string(),
+                    path: path.to_string(),
+                    mode,
+                })
+            }
+            Ok((false, chunks)) => {
+                // We're missing some number of data chunks of the requrested file
+                self.send(&messages::nak(channel_id, hash, &chunks, compression)?)?;
+                self.save_transfer(
+                    channel_id,
+                    Direction::Receive,
+                    hash,
+                    path,
+                    mode,
+                    compression,
+                );
+
+                Ok(State::Receiving {
+                    channel_id,
+                    hash: hash.to_string(),
+                    path: path.to_string(),
+                    mode,
+                    compression,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     /// Send all requested chunks of a file to the remote destination
     ///
     /// # Arguments
     /// * channel_id - ID of channel to communicate over
     /// * hash - Hash of file corresponding to chunks
     /// * chunks - List of chunk ranges to transmit
+    /// * compression - Compression requested by the remote destination
     fn send_chunks(
         &self,
         channel_id: u32,
         hash: &str,
         chunks: &[(u32, u32)],
+        compression: Option<Compression>,
     ) -> Result<(), ProtocolError> {
         let mut chunks_transmitted = 0;
         for (first, last) in chunks {
             for chunk_index in *first..*last {
-                match storage::load_chunk(&self.config.storage_prefix, hash, chunk_index) {
-                    Ok(c) => self.send(&messages::chunk(channel_id, hash, chunk_index, &c)?)?,
-                    Err(e) => {
-                        warn!("Failed to load chunk {}:{} : {}", hash, chunk_index, e);
-                        storage::delete_file(&self.config.storage_prefix, hash)?;
-                        return Err(ProtocolError::CorruptFile(hash.to_string()));
-                    }
-                };
+                self.send_chunk(channel_id, hash, chunk_index, compression)?;
                 if let Some(max_chunks_transmit) = self.config.max_chunks_transmit {
                     chunks_transmitted += 1;
                     if chunks_transmitted >= max_chunks_transmit {
@@ -470,6 +857,115 @@ impl Protocol {
         Ok(())
     }
 
+    /// Send the next window of missing chunks of a file, followed by a sync asking the
+    /// remote destination which of them it received
+    ///
+    /// # Arguments
+    /// * channel_id - ID of channel to communicate over
+    /// * hash - Hash of file corresponding to chunks
+    /// * missing - Chunks which the remote destination doesn't have yet
+    /// * window_size - Max number of chunks to send
+    /// * compression - Compression requested by the remote destination
+    fn send_window<I>(
+        &self,
+        channel_id: u32,
+        hash: &str,
+        missing: I,
+        window_size: u32,
+        compression: Option<Compression>,
+    ) -> Result<(), ProtocolError>
+    where
+        I: Iterator<Item = u32>,
+    {
+        let window: Vec<u32> = missing.take(window_size as usize).collect();
+        if window.is_empty() {
+            return Ok(());
+        }
+
+        for chunk_index in window.iter() {
+            self.send_chunk(channel_id, hash, *chunk_index, compression)?;
+            thread::sleep(self.window_delay.get());
+        }
+
+        *self.window_chunks.borrow_mut() = window;
+        self.send(&messages::sync(channel_id, hash)?)
+    }
+
+    // Adjust the window mode inter-chunk delay, based on how many chunks of the last window
+    // are still missing. Sending slows down quickly when chunks are lost and speeds
+    // back up gradually while they aren't
+    fn adapt_window_delay<F>(&self, is_missing: F)
+    where
+        F: Fn(u32) -> bool,
+    {
+        let window = self.window_chunks.borrow();
+        if window.is_empty() {
+            return;
+        }
+
+        let lost = window.iter().filter(|index| is_missing(**index)).count();
+        let delay = self.window_delay.get();
+        let delay = if lost * 100 > window.len() * WINDOW_LOSS_THRESHOLD {
+            (delay * 2 + Duration::from_millis(1)).min(MAX_WINDOW_DELAY)
+        } else {
+            delay * 3 / 4
+        };
+
+        info!(
+            "{} of {} chunks lost. Inter-chunk delay is now {:?}",
+            lost,
+            window.len(),
+            delay
+        );
+        self.window_delay.set(delay);
+    }
+
+    // Window size to use when sending chunks in reply to a NAK.
+    // A peer which answers our syncs with NAKs instead of SACKs (ex. an older version of this
+    // protocol) is sent chunks without windowing
+    fn nak_window_size(&self) -> Option<u32> {
+        let unresponsive = !self.sack_seen.get() && !self.window_chunks.borrow().is_empty();
+        self.config.window_size.filter(|_| !unresponsive)
+    }
+
+    // Load a chunk from storage and send it to the remote destination
+    fn send_chunk(
+        &self,
+        channel_id: u32,
+        hash: &str,
+        index: u32,
+        compression: Option<Compression>,
+    ) -> Result<(), ProtocolError> {
+        match storage::load_chunk(&self.config.storage_prefix, hash, index) {
+            Ok(c) => self.send(&self.chunk_message(channel_id, hash, index, c, compression)?),
+            Err(e) => {
+                warn!("Failed to load chunk {}:{} : {}", hash, index, e);
+                storage::delete_file(&self.config.storage_prefix, hash)?;
+                Err(ProtocolError::CorruptFile(hash.to_string()))
+            }
+        }
+    }
+
+    // Create a chunk message, compressing the chunk data if requested.
+    // Data which doesn't get any smaller (ex. a file which is already compressed) is sent as-is
+    fn chunk_message(
+        &self,
+        channel_id: u32,
+        hash: &str,
+        index: u32,
+        data: Vec<u8>,
+        compression: Option<Compression>,
+    ) -> Result<Vec<u8>, ProtocolError> {
+        if let Some(method) = compression {
+            let compressed = compression::compress(method, &data)?;
+            if compressed.len() < data.len() {
+                return messages::chunk(channel_id, hash, index, &compressed, Some(method));
+            }
+        }
+
+        messages::chunk(channel_id, hash, index, &data, None)
+    }
+
     /// Listen for and process file protocol messages
     ///
     /// # Arguments
@@ -488,7 +984,7 @@ impl Protocol {
     /// use file_protocol::*;
     /// use std::time::Duration;
     ///
-    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048);
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
     /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
     ///
     /// f_protocol.message_engine(
@@ -525,6 +1021,7 @@ impl Protocol {
                         hash,
                         path,
                         mode,
+                        compression,
                     } => {
                         match storage::validate_file(&self.config.storage_prefix, &hash, None) {
                             Ok((true, _)) => {
@@ -537,7 +1034,20 @@ impl Protocol {
                                 };
                             }
                             Ok((false, chunks)) => {
-                                self.send(&messages::nak(channel_id, &hash, &chunks)?)?;
+                                self.send(&messages::nak(
+                                    channel_id,
+                                    &hash,
+                                    &chunks,
+                                    compression,
+                                )?)?;
+                                self.save_transfer(
+                                    channel_id,
+                                    Direction::Receive,
+                                    &hash,
+                                    &path,
+                                    mode,
+                                    compression,
+                                );
                                 state = State::Holding {
                                     count: 0,
                                     prev_state: Box::new(state.clone()),
@@ -645,7 +1155,7 @@ impl Protocol {
     /// use file_protocol::*;
     /// use std::time::Duration;
     ///
-    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048);
+    /// let config = FileProtocolConfig::new(None, 1024, 5, 1, None, 2048, None);
     /// let f_protocol = FileProtocol::new("0.0.0.0:8000", "0.0.0.0:7000", config);
     ///
     /// if let Ok(message) = f_protocol.recv(Some(Duration::from_millis(100))) {
@@ -663,7 +1173,44 @@ impl Protocol {
         let new_state = match &parsed_message {
             Message::Sync(channel_id, hash) => {
                 info!("<- {{ {}, {} }}", channel_id, hash);
-                state.clone()
+                // The transmitter has finished sending a window of chunks and wants to know
+                // which ones we got
+                match state {
+                    State::Receiving {
+                        hash: receiving_hash,
+                        path,
+                        mode,
+                        compression,
+                        ..
+                    } if receiving_hash == hash => {
+                        let (base, bitmap) = storage::selective_ack(
+                            &self.config.storage_prefix,
+                            hash,
+                            (self.config.transfer_chunk_size / 2).clamp(1, MAX_SACK_SIZE),
+                        )?;
+
+                        if bitmap.is_empty() {
+                            // Nothing is missing
+                            self.send(&messages::ack(*channel_id, hash, Some(base))?)?;
+                            State::ReceivingDone {
+                                channel_id: *channel_id,
+                                hash: hash.to_owned(),
+                                path: path.to_owned(),
+                                mode: *mode,
+                            }
+                        } else {
+                            self.send(&messages::sack(
+                                *channel_id,
+                                hash,
+                                base,
+                                &bitmap,
+                                *compression,
+                            )?)?;
+                            state.clone()
+                        }
+                    }
+                    _ => state.clone(),
+                }
             }
             Message::Metadata(channel_id, hash, num_chunks) => {
                 info!("<- {{ {}, {}, {} }}", channel_id, hash, num_chunks);
@@ -672,12 +1219,23 @@ impl Protocol {
                     path: hash.to_owned(),
                 }
             }
-            Message::ReceiveChunk(channel_id, hash, chunk_num, data) => {
+            Message::ReceiveChunk(channel_id, hash, chunk_num, data, compression) => {
                 info!(
-                    "<- {{ {}, {}, {}, chunk_data }}",
-                    channel_id, hash, chunk_num
+                    "<- {{ {}, {}, {}, chunk_data, {:?} }}",
+                    channel_id, hash, chunk_num, compression
                 );
-                storage::store_chunk(&self.config.storage_prefix, hash, *chunk_num, data)?;
+                // Chunks are always stored uncompressed, so that they match the file's hash
+                match compression {
+                    Some(method) => storage::store_chunk(
+                        &self.config.storage_prefix,
+                        hash,
+                        *chunk_num,
+                        &compression::decompress(*method, data, self.config.transfer_chunk_size)?,
+                    )?,
+                    None => {
+                        storage::store_chunk(&self.config.storage_prefix, hash, *chunk_num, data)?
+                    }
+                }
                 state.clone()
             }
             Message::ACK(_channel_id, ack_hash) => {
@@ -685,12 +1243,33 @@ impl Protocol {
                 // TODO: Figure out hash verification here
                 State::TransmittingDone
             }
-            Message::NAK(channel_id, hash, Some(missing_chunks)) => {
+            Message::NAK(channel_id, hash, Some(missing_chunks), compression) => {
                 info!(
-                    "<- {{ {}, {}, false, {:?} }}",
-                    channel_id, hash, missing_chunks
+                    "<- {{ {}, {}, false, {:?}, {:?} }}",
+                    channel_id, hash, compression, missing_chunks
                 );
-                match self.send_chunks(*channel_id, hash, missing_chunks) {
+                let result = match self.nak_window_size() {
+                    Some(window_size) => {
+                        let is_missing = |index: u32| {
+                            missing_chunks
+                                .iter()
+                                .any(|(first, last)| *first <= index && index < *last)
+                        };
+                        self.adapt_window_delay(is_missing);
+
+                        self.send_window(
+                            *channel_id,
+                            hash,
+                            missing_chunks
+                                .iter()
+                                .flat_map(|(first, last)| *first..*last),
+                            window_size,
+                            *compression,
+                        )
+                    }
+                    None => self.send_chunks(*channel_id, hash, missing_chunks, *compression),
+                };
+                match result {
                     Ok(()) => {}
                     Err(error) => self.send(&messages::operation_failure(
                         *channel_id,
@@ -699,55 +1278,81 @@ impl Protocol {
                 };
                 State::Transmitting
             }
-            Message::NAK(channel_id, hash, None) => {
+            Message::SACK(channel_id, hash, base, bitmap, compression) => {
+                info!(
+                    "<- {{ {}, {}, sack, {}, {:?}, {:?} }}",
+                    channel_id, hash, base, bitmap, compression
+                );
+                self.sack_seen.set(true);
+
+                // Anything past the end of the bitmap is assumed to be missing
+                let is_missing = |index: u32| {
+                    index >= *base && {
+                        let offset = index - base;
+                        bitmap
+                            .get((offset / 8) as usize)
+                            .map_or(true, |byte| byte & (1 << (offset % 8)) == 0)
+                    }
+                };
+                self.adapt_window_delay(is_missing);
+
+                let result =
+                    storage::load_meta(&self.config.storage_prefix, hash).and_then(|num_chunks| {
+                        self.send_window(
+                            *channel_id,
+                            hash,
+                            (*base..num_chunks).filter(|index| is_missing(*index)),
+                            self.config.window_size.unwrap_or(num_chunks),
+                            *compression,
+                        )
+                    });
+                if let Err(error) = result {
+                    self.send(&messages::operation_failure(
+                        *channel_id,
+                        &format!("{}", error),
+                    )?)?;
+                }
+                State::Transmitting
+            }
+            Message::NAK(channel_id, hash, None, _) => {
                 info!("<- {{ {}, {}, false }}", channel_id, hash);
                 // TODO: Maybe trigger a failure?
                 state.clone()
             }
-            Message::ReqReceive(channel_id, hash, path, mode) => {
+            Message::ReqReceive(channel_id, hash, path, mode, compression) => {
                 info!(
-                    "<- {{ {}, export, {}, {}, {:?} }}",
-                    channel_id, hash, path, mode
+                    "<- {{ {}, export, {}, {}, {:?}, {:?} }}",
+                    channel_id, hash, path, mode, compression
                 );
                 // The client wants to send us a file.
-                // See what state the file is currently in on our side
-                match storage::validate_file(&self.config.storage_prefix, hash, None) {
-                    Ok((true, _)) => {
-                        // We've already got all the file data in temporary storage
-                        self.send(&messages::ack(*channel_id, hash, None)?)?;
-
-                        State::ReceivingDone {
-                            channel_id: *channel_id,
-                            hash: hash.to_string(),
-                            path: path.to_string(),
-                            mode: *mode,
-                        }
-                    }
-                    Ok((false, chunks)) => {
-                        // We're missing some number of data chunks of the requrested file
-                        self.send(&messages::nak(*channel_id, hash, &chunks)?)?;
-                        State::Receiving {
-                            channel_id: *channel_id,
-                            hash: hash.to_string(),
-                            path: path.to_string(),
-                            mode: *mode,
-                        }
-                    }
-                    Err(e) => return Err(e),
-                }
+                // Our NAKs will let it know whether we'll accept compressed chunks
+                self.start_receive(*channel_id, hash, path, *mode, *compression)?
             }
-            Message::ReqTransmit(channel_id, path) => {
-                info!("<- {{ {}, import, {} }}", channel_id, path);
+            Message::ReqTransmit(channel_id, path, compression) => {
+                info!(
+                    "<- {{ {}, import, {}, {:?} }}",
+                    channel_id, path, compression
+                );
                 // Set up the requested file for transmission
                 match self.initialize_file(path) {
                     Ok((hash, num_chunks, mode)) => {
                         // It worked, let the requester know we're ready to send
+                        // and confirm the compression we'll use
                         self.send(&messages::import_setup_success(
                             *channel_id,
                             &hash,
                             num_chunks,
                             mode,
+                            *compression,
                         )?)?;
+                        self.save_transfer(
+                            *channel_id,
+                            Direction::Transmit,
+                            &hash,
+                            path,
+                            Some(mode),
+                            *compression,
+                        );
 
                         State::Transmitting
                     }
@@ -763,12 +1368,115 @@ impl Protocol {
                     }
                 }
             }
+            Message::ReqTransmitDir(channel_id, source, compression) => {
+                info!(
+                    "<- {{ {}, import_dir, {}, {:?} }}",
+                    channel_id, source, compression
+                );
+                // Set up the requested files for transmission, and then send the
+                // manifest as though it were a regular imported file
+                match self.initialize_dir(*channel_id, source, *compression) {
+                    Ok((hash, num_chunks, mode)) => {
+                        self.send(&messages::import_setup_success(
+                            *channel_id,
+                            &hash,
+                            num_chunks,
+                            mode,
+                            *compression,
+                        )?)?;
+                        self.save_transfer(
+                            *channel_id,
+                            Direction::Transmit,
+                            &hash,
+                            source,
+                            Some(mode),
+                            *compression,
+                        );
+
+                        State::Transmitting
+                    }
+                    Err(error) => {
+                        self.send(&messages::operation_failure(
+                            *channel_id,
+                            &format!("{}", error),
+                        )?)?;
+
+                        State::Done
+                    }
+                }
+            }
+            Message::Resume(channel_id, hash, direction) => {
+                info!(
+                    "<- {{ {}, resume, {}, {} }}",
+                    channel_id,
+                    hash,
+                    direction.as_str()
+                );
+                // The requester wants to pick up where an earlier transfer left off.
+                // We should be doing the opposite of whatever they're doing.
+                match storage::load_transfer(&self.config.storage_prefix, hash) {
+                    Ok(ref transfer)
+                        if *direction == Direction::Transmit
+                            && transfer.direction == Direction::Receive =>
+                    {
+                        self.start_receive(
+                            *channel_id,
+                            hash,
+                            &transfer.path,
+                            transfer.mode,
+                            transfer.compression,
+                        )?
+                    }
+                    Ok(ref transfer)
+                        if *direction == Direction::Receive
+                            && transfer.direction == Direction::Transmit =>
+                    {
+                        match self.prepare_resend(hash, transfer) {
+                            Ok(num_chunks) => {
+                                self.send(&messages::import_setup_success(
+                                    *channel_id,
+                                    hash,
+                                    num_chunks,
+                                    transfer.mode.unwrap_or(0o644),
+                                    transfer.compression,
+                                )?)?;
+                                self.save_transfer(
+                                    *channel_id,
+                                    Direction::Transmit,
+                                    hash,
+                                    &transfer.path,
+                                    transfer.mode,
+                                    transfer.compression,
+                                );
+
+                                State::Transmitting
+                            }
+                            Err(error) => {
+                                self.send(&messages::operation_failure(
+                                    *channel_id,
+                                    &format!("{}", error),
+                                )?)?;
+
+                                State::Done
+                            }
+                        }
+                    }
+                    _ => {
+                        self.send(&messages::operation_failure(
+                            *channel_id,
+                            &format!("No resumable transfer found for {}", hash),
+                        )?)?;
+
+                        State::Done
+                    }
+                }
+            }
             Message::SuccessReceive(channel_id, hash) => {
                 info!("<- {{ {}, true }}", channel_id);
                 storage::delete_file(&self.config.storage_prefix, hash)?;
                 State::Done
             }
-            Message::SuccessTransmit(channel_id, hash, num_chunks, mode) => {
+            Message::SuccessTransmit(channel_id, hash, num_chunks, mode, compression) => {
                 match mode {
                     Some(value) => info!(
                         "<- {{ {}, true, {}, {}, {} }}",
@@ -794,14 +1502,26 @@ impl Protocol {
                         }
                     }
                     Ok((false, chunks)) => {
-                        self.send(&messages::nak(*channel_id, hash, &chunks)?)?;
+                        // Only request compression if the transmitter confirmed it'll be used
+                        self.send(&messages::nak(*channel_id, hash, &chunks, *compression)?)?;
                         match state.clone() {
-                            State::StartReceive { path } => State::Receiving {
-                                channel_id: *channel_id,
-                                hash: hash.to_string(),
-                                path,
-                                mode: *mode,
-                            },
+                            State::StartReceive { path } => {
+                                self.save_transfer(
+                                    *channel_id,
+                                    Direction::Receive,
+                                    hash,
+                                    &path,
+                                    *mode,
+                                    *compression,
+                                );
+                                State::Receiving {
+                                    channel_id: *channel_id,
+                                    hash: hash.to_string(),
+                                    path,
+                                    mode: *mode,
+                                    compression: *compression,
+                                }
+                            }
                             _ => state.clone(),
                         }
                     }
@@ -829,3 +1549,17 @@ impl Protocol {
         Ok(new_state)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_window_size_disabled() {
+        let config = ProtocolConfig::new(None, 1024, 5, 1, None, 2048, Some(0));
+        assert_eq!(config.window_size, None);
+
+        let config = ProtocolConfig::new(None, 1024, 5, 1, None, 2048, Some(16));
+        assert_eq!(config.window_size, Some(16));
+    }
+}
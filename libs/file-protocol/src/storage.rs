@@ -14,6 +14,7 @@
 // limitations under the License.
 //
 
+use crate::compression::Compression;
 use crate::error::ProtocolError;
 use blake2_rfc::blake2s::Blake2s;
 use log::warn;
@@ -30,6 +31,63 @@ use std::thread;
 use std::time::Duration;
 
 const HASH_SIZE: usize = 16;
+// Name of the file used to record the state of a transfer
+const TRANSFER_FILE: &str = "transfer";
+
+/// Direction of a file transfer, from the point of view of the local side
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// The local side is sending the file
+    Transmit,
+    /// The local side is receiving the file
+    Receive,
+}
+
+impl Direction {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Direction::Transmit => "transmit",
+            Direction::Receive => "receive",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "transmit" => Some(Direction::Transmit),
+            "receive" => Some(Direction::Receive),
+            _ => None,
+        }
+    }
+}
+
+/// Saved state of a file transfer, used to resume the transfer after a restart
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Transfer {
+    /// Channel ID last used for the transfer
+    pub channel_id: u32,
+    /// Whether the file is being sent or received
+    pub direction: Direction,
+    /// File hash
+    pub hash: String,
+    /// Destination path of a received file, or the requested path of a transmitted file
+    pub path: String,
+    /// File mode
+    pub mode: Option<u32>,
+    /// Compression applied to the file data in transit
+    pub compression: Option<Compression>,
+    /// Number of chunks in the file
+    pub num_chunks: u32,
+    /// Bitmap of the chunks present in storage when the state was saved.
+    /// Bit `n % 8` of byte `n / 8` is set if chunk `n` was present.
+    pub received: Vec<u8>,
+}
+
+impl Transfer {
+    /// Number of chunks present in storage when the state was saved
+    pub fn received_count(&self) -> u32 {
+        self.received.iter().map(|byte| byte.count_ones()).sum()
+    }
+}
 
 // Save new chunk in a temporary storage file
 pub fn store_chunk(prefix: &str, hash: &str, index: u32, data: &[u8]) -> Result<(), ProtocolError> {
@@ -95,6 +153,167 @@ pub fn store_meta(prefix: &str, hash: &str, num_chunks: u32) -> Result<(), Proto
     Ok(())
 }
 
+// Build a bitmap of the chunks of a file which are currently in storage
+fn chunk_bitmap(prefix: &str, hash: &str, num_chunks: u32) -> Result<Vec<u8>, ProtocolError> {
+    let hash_path = Path::new(&format!("{}/storage", prefix)).join(hash);
+    let mut bitmap = vec![0u8; ((num_chunks + 7) / 8) as usize];
+
+    let entries = fs::read_dir(&hash_path).map_err(|err| ProtocolError::StorageError {
+        action: format!("read {:?} directory", hash_path),
+        err,
+    })?;
+
+    for index in entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|name| name.parse::<u32>().ok())
+        .filter(|index| *index < num_chunks)
+    {
+        bitmap[(index / 8) as usize] |= 1 << (index % 8);
+    }
+
+    Ok(bitmap)
+}
+
+// Build a selective acknowledgement of the chunks of a file which are in storage.
+// Returns the index of the first missing chunk, along with a bitmap of the chunks
+// following it (bit `n % 8` of byte `n / 8` is set if chunk `base + n` is present).
+// The bitmap is limited to `max_bytes`. If no chunks are missing, it is empty.
+pub fn selective_ack(
+    prefix: &str,
+    hash: &str,
+    max_bytes: usize,
+) -> Result<(u32, Vec<u8>), ProtocolError> {
+    let num_chunks = load_meta(prefix, hash)?;
+    let present = chunk_bitmap(prefix, hash, num_chunks)?;
+    let is_present = |index: u32| present[(index / 8) as usize] & (1 << (index % 8)) != 0;
+
+    let base = (0..num_chunks)
+        .find(|index| !is_present(*index))
+        .unwrap_or(num_chunks);
+
+    let remaining = (num_chunks - base) as usize;
+    let mut bitmap = vec![0u8; ((remaining + 7) / 8).min(max_bytes)];
+    for offset in 0..(bitmap.len() * 8).min(remaining) {
+        if is_present(base + offset as u32) {
+            bitmap[offset / 8] |= 1 << (offset % 8);
+        }
+    }
+
+    Ok((base, bitmap))
+}
+
+// Save the current state of a transfer next to the file's chunks
+pub fn store_transfer(
+    prefix: &str,
+    channel_id: u32,
+    direction: Direction,
+    hash: &str,
+    path: &str,
+    mode: Option<u32>,
+    compression: Option<Compression>,
+) -> Result<(), ProtocolError> {
+    let num_chunks = load_meta(prefix, hash)?;
+    let received = Value::Bytes(chunk_bitmap(prefix, hash, num_chunks)?);
+
+    let vec = to_vec(&(
+        channel_id,
+        direction.as_str(),
+        hash,
+        path,
+        mode,
+        compression.map(Compression::as_str),
+        num_chunks,
+        received,
+    ))?;
+
+    let file_dir = Path::new(&format!("{}/storage", prefix)).join(hash);
+    let transfer_path = file_dir.join(TRANSFER_FILE);
+    let temp_path = file_dir.join(".transfer.tmp");
+
+    File::create(&temp_path)
+        .map_err(|err| ProtocolError::StorageError {
+            action: format!("create/open {:?} for writing", temp_path),
+            err,
+        })?
+        .write_all(&vec)
+        .map_err(|err| ProtocolError::StorageError {
+            action: format!("write transfer state to {:?}", temp_path),
+            err,
+        })?;
+
+    fs::rename(temp_path.clone(), transfer_path.clone()).map_err(|err| {
+        ProtocolError::StorageError {
+            action: format!("rename {:?} to {:?}", temp_path, transfer_path),
+            err,
+        }
+    })?;
+
+    Ok(())
+}
+
+// Load the saved state of a file's transfer
+pub fn load_transfer(prefix: &str, hash: &str) -> Result<Transfer, ProtocolError> {
+    let mut data = vec![];
+    let transfer_path = Path::new(&format!("{}/storage", prefix))
+        .join(hash)
+        .join(TRANSFER_FILE);
+
+    File::open(transfer_path)
+        .map_err(|err| ProtocolError::StorageError {
+            action: format!("open {} transfer file", hash),
+            err,
+        })?
+        .read_to_end(&mut data)
+        .map_err(|err| ProtocolError::StorageError {
+            action: format!("read {} transfer file", hash),
+            err,
+        })?;
+
+    let state: Value = de::from_slice(&data).map_err(|err| {
+        ProtocolError::StorageParseError(format!(
+            "Unable to parse transfer state for {}: {}",
+            hash, err
+        ))
+    })?;
+
+    // Saved data should be CBOR:
+    // '[channel_id, direction, hash, path, mode, compression, num_chunks, received]'
+    let parse_err =
+        || ProtocolError::StorageParseError(format!("Failed to parse transfer state for {}", hash));
+    let fields = state.as_array().ok_or_else(parse_err)?;
+    if fields.len() != 8 {
+        return Err(parse_err());
+    }
+
+    Ok(Transfer {
+        channel_id: fields[0].as_u64().ok_or_else(parse_err)? as u32,
+        direction: fields[1]
+            .as_string()
+            .and_then(|name| Direction::from_name(name))
+            .ok_or_else(parse_err)?,
+        hash: fields[2].as_string().ok_or_else(parse_err)?.to_owned(),
+        path: fields[3].as_string().ok_or_else(parse_err)?.to_owned(),
+        mode: fields[4].as_u64().map(|mode| mode as u32),
+        compression: fields[5]
+            .as_string()
+            .and_then(|name| Compression::from_name(name)),
+        num_chunks: fields[6].as_u64().ok_or_else(parse_err)? as u32,
+        received: fields[7].as_bytes().ok_or_else(parse_err)?.to_owned(),
+    })
+}
+
+// Look through storage for the saved state of a transfer with the given direction and path
+pub fn find_transfer(prefix: &str, direction: Direction, path: &str) -> Option<Transfer> {
+    let entries = fs::read_dir(format!("{}/storage", prefix)).ok()?;
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|hash| load_transfer(prefix, &hash).ok())
+        .find(|transfer| transfer.direction == direction && transfer.path == path)
+}
+
 // Load a chunk from its temporary storage file
 pub fn load_chunk(prefix: &str, hash: &str, index: u32) -> Result<Vec<u8>, ProtocolError> {
     let mut data = vec![];
@@ -320,6 +539,58 @@ pub fn initialize_file(
     }
 }
 
+// Hash a file and count the chunks it would be split into, without importing it into storage.
+// Returns the hash, number of chunks, size and mode of the file
+pub fn describe_file(
+    source_path: &str,
+    transfer_chunk_size: usize,
+    hash_chunk_size: usize,
+) -> Result<(String, u32, u64, u32), ProtocolError> {
+    let meta = fs::metadata(source_path).map_err(|err| ProtocolError::StorageError {
+        action: format!("stat file {}", source_path),
+        err,
+    })?;
+    let hash = calc_file_hash(source_path, hash_chunk_size)?;
+    let chunk_size = transfer_chunk_size as u64;
+    let num_chunks = (meta.len() + chunk_size - 1) / chunk_size;
+
+    Ok((hash, num_chunks as u32, meta.len(), meta.mode()))
+}
+
+// Import a block of data, such as a directory transfer manifest, into chunked storage for transfer
+pub fn initialize_data(
+    prefix: &str,
+    data: &[u8],
+    transfer_chunk_size: usize,
+    hash_chunk_size: usize,
+) -> Result<(String, u32, u32), ProtocolError> {
+    let storage_path = format!("{}/storage", prefix);
+
+    fs::create_dir_all(&storage_path).map_err(|err| ProtocolError::StorageError {
+        action: format!("create dir {}", storage_path),
+        err,
+    })?;
+
+    let temp_path = Path::new(&storage_path).join(format!(".data-{}", time::get_time().nsec));
+    fs::write(&temp_path, data).map_err(|err| ProtocolError::StorageError {
+        action: format!("write temp file {:?}", temp_path),
+        err,
+    })?;
+
+    let result = initialize_file(
+        prefix,
+        &temp_path.to_string_lossy(),
+        transfer_chunk_size,
+        hash_chunk_size,
+    );
+
+    if let Err(e) = fs::remove_file(&temp_path) {
+        warn!("Failed to remove temp file {:?} : {}", temp_path, e);
+    }
+
+    result
+}
+
 // Export received chunks into final file and verify correct file hash
 pub fn finalize_file(
     prefix: &str,
@@ -340,7 +611,17 @@ pub fn finalize_file(
     // Get the total number of chunks we're saving
     let num_chunks = load_meta(prefix, hash)?;
 
-    // Q: Do we want to create the parent directories if they don't exist?
+    // Create any missing parent directories, so that directory transfers can recreate
+    // their original tree
+    if let Some(parent) = Path::new(target_path).parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(|err| ProtocolError::StorageError {
+                action: format!("create dir {:?}", parent),
+                err,
+            })?;
+        }
+    }
+
     let mut file = File::create(target_path).map_err(|err| ProtocolError::StorageError {
         action: format!("create/open file for writing {}", target_path),
         err,
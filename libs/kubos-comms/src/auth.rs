@@ -0,0 +1,297 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Authentication of uplinked packets
+//!
+//! Authenticated frames carry a trailer after the bytes of the link packet:
+//!
+//! ```text
+//! | link packet | counter (u64, big-endian) | HMAC-SHA256 tag (32 bytes) |
+//! ```
+//!
+//! The tag covers the link packet and the counter. The counter must increase with every
+//! frame sent, which prevents a recorded frame from being replayed.
+
+use crate::config::AuthConfig;
+use crate::errors::*;
+use byteorder::{BigEndian, ByteOrder};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fs;
+use std::io::ErrorKind;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Size of the anti-replay counter
+const COUNTER_SIZE: usize = 8;
+// Size of an HMAC-SHA256 tag
+const TAG_SIZE: usize = 32;
+
+/// Number of bytes added to each frame by authentication
+pub const AUTH_TRAILER_SIZE: usize = COUNTER_SIZE + TAG_SIZE;
+
+fn decode_hex(hex: &str) -> CommsResult<Vec<u8>> {
+    let hex = hex.trim();
+    if hex.is_empty() || hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(CommsServiceError::ConfigError(
+            "Authentication key must be a non-empty hex string".to_owned(),
+        )
+        .into());
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| {
+                CommsServiceError::ConfigError(
+                    "Authentication key must be a non-empty hex string".to_owned(),
+                )
+                .into()
+            })
+        })
+        .collect()
+}
+
+fn calculate_mac(key: &[u8], frame: &[u8], counter: &[u8]) -> CommsResult<HmacSha256> {
+    let mut mac = HmacSha256::new_varkey(key).map_err(|_| {
+        CommsServiceError::ConfigError("Invalid authentication key length".to_owned())
+    })?;
+    mac.input(frame);
+    mac.input(counter);
+    Ok(mac)
+}
+
+/// Append an authentication trailer to a frame.
+///
+/// This is the counterpart of `Authenticator::verify`, and is intended for ground software
+/// which needs to send authenticated frames.
+pub fn sign(key: &[u8], counter: u64, frame: &[u8]) -> CommsResult<Vec<u8>> {
+    let mut counter_bytes = [0; COUNTER_SIZE];
+    BigEndian::write_u64(&mut counter_bytes, counter);
+
+    let tag = calculate_mac(key, frame, &counter_bytes)?.result().code();
+
+    let mut signed = frame.to_vec();
+    signed.extend_from_slice(&counter_bytes);
+    signed.extend_from_slice(&tag);
+    Ok(signed)
+}
+
+/// Verifies the authentication trailer of uplinked frames
+pub struct Authenticator {
+    key: Vec<u8>,
+    counter_file: Option<String>,
+    last_counter: u64,
+}
+
+impl Authenticator {
+    /// Create a new authenticator from the comms service's `auth` configuration.
+    ///
+    /// If a counter file is configured and exists, the last accepted counter is loaded from it.
+    /// A counter file which exists but can't be read is treated as a configuration error, rather
+    /// than silently restarting the counter from zero.
+    pub fn new(config: &AuthConfig) -> CommsResult<Self> {
+        let key = decode_hex(&config.key)?;
+
+        let last_counter = match config.counter_file {
+            Some(ref path) => match fs::read_to_string(path) {
+                Ok(contents) => contents.trim().parse::<u64>().map_err(|_| {
+                    CommsServiceError::ConfigError(format!("Invalid counter file: {}", path))
+                })?,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => 0,
+                Err(e) => {
+                    return Err(CommsServiceError::ConfigError(format!(
+                        "Unable to read counter file {}: {}",
+                        path, e
+                    ))
+                    .into());
+                }
+            },
+            None => 0,
+        };
+
+        Ok(Authenticator {
+            key,
+            counter_file: config.counter_file.clone(),
+            last_counter,
+        })
+    }
+
+    /// Check the authentication trailer of a received frame.
+    ///
+    /// Returns the frame with the trailer removed if the tag is valid and the counter is
+    /// newer than any previously accepted one.
+    pub fn verify(&mut self, raw: &[u8]) -> CommsResult<Vec<u8>> {
+        if raw.len() < AUTH_TRAILER_SIZE {
+            return Err(CommsServiceError::AuthenticationError(
+                "Frame is too short to contain an authentication trailer".to_owned(),
+            )
+            .into());
+        }
+
+        let (frame, trailer) = raw.split_at(raw.len() - AUTH_TRAILER_SIZE);
+        let (counter_bytes, tag) = trailer.split_at(COUNTER_SIZE);
+
+        calculate_mac(&self.key, frame, counter_bytes)?
+            .verify(tag)
+            .map_err(|_| {
+                CommsServiceError::AuthenticationError("Invalid authentication tag".to_owned())
+            })?;
+
+        let counter = BigEndian::read_u64(counter_bytes);
+        if counter <= self.last_counter {
+            return Err(CommsServiceError::AuthenticationError(format!(
+                "Replayed counter {} (last accepted {})",
+                counter, self.last_counter
+            ))
+            .into());
+        }
+
+        self.last_counter = counter;
+
+        if let Some(ref path) = self.counter_file {
+            // The frame is still genuine, so we don't reject it if the counter can't be saved.
+            // Replay protection across restarts will be weakened until the next successful write.
+            if let Err(e) = save_counter(path, counter) {
+                error!("Failed to save authentication counter to {}: {}", path, e);
+            }
+        }
+
+        Ok(frame.to_vec())
+    }
+}
+
+// Write to a temporary file first, so the saved counter isn't lost if we fail partway
+fn save_counter(path: &str, counter: u64) -> Result<(), std::io::Error> {
+    let temp_path = format!("{}.tmp", path);
+    fs::write(&temp_path, counter.to_string())?;
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f";
+
+    fn config(counter_file: Option<String>) -> AuthConfig {
+        AuthConfig {
+            key: KEY.to_owned(),
+            counter_file,
+        }
+    }
+
+    #[test]
+    fn verify_good() {
+        let mut auth = Authenticator::new(&config(None)).unwrap();
+        let signed = sign(&decode_hex(KEY).unwrap(), 1, &[1, 2, 3]).unwrap();
+
+        assert_eq!(signed.len(), 3 + AUTH_TRAILER_SIZE);
+        assert_eq!(auth.verify(&signed).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn verify_bad_tag() {
+        let mut auth = Authenticator::new(&config(None)).unwrap();
+        let mut signed = sign(&decode_hex(KEY).unwrap(), 1, &[1, 2, 3]).unwrap();
+        signed[0] ^= 0x01;
+
+        assert_eq!(
+            format!("{}", auth.verify(&signed).unwrap_err()),
+            "Authentication error: Invalid authentication tag"
+        );
+    }
+
+    #[test]
+    fn verify_wrong_key() {
+        let mut auth = Authenticator::new(&config(None)).unwrap();
+        let signed = sign(&[0xFF; 16], 1, &[1, 2, 3]).unwrap();
+
+        assert!(auth.verify(&signed).is_err());
+    }
+
+    #[test]
+    fn verify_replay() {
+        let mut auth = Authenticator::new(&config(None)).unwrap();
+        let key = decode_hex(KEY).unwrap();
+
+        let first = sign(&key, 5, &[1, 2, 3]).unwrap();
+        let older = sign(&key, 4, &[1, 2, 3]).unwrap();
+
+        assert!(auth.verify(&first).is_ok());
+        assert_eq!(
+            format!("{}", auth.verify(&first).unwrap_err()),
+            "Authentication error: Replayed counter 5 (last accepted 5)"
+        );
+        assert!(auth.verify(&older).is_err());
+    }
+
+    #[test]
+    fn verify_too_short() {
+        let mut auth = Authenticator::new(&config(None)).unwrap();
+
+        assert!(auth.verify(&[0; AUTH_TRAILER_SIZE - 1]).is_err());
+    }
+
+    #[test]
+    fn counter_persists() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("counter").to_string_lossy().to_string();
+        let key = decode_hex(KEY).unwrap();
+
+        let mut auth = Authenticator::new(&config(Some(path.clone()))).unwrap();
+        assert!(auth.verify(&sign(&key, 10, &[1]).unwrap()).is_ok());
+
+        // A restarted service shouldn't accept the old frame again
+        let mut auth = Authenticator::new(&config(Some(path))).unwrap();
+        assert!(auth.verify(&sign(&key, 10, &[1]).unwrap()).is_err());
+        assert!(auth.verify(&sign(&key, 11, &[1]).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn counter_saved_atomically() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("counter").to_string_lossy().to_string();
+        let key = decode_hex(KEY).unwrap();
+
+        let mut auth = Authenticator::new(&config(Some(path.clone()))).unwrap();
+        assert!(auth.verify(&sign(&key, 10, &[1]).unwrap()).is_ok());
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "10");
+        assert!(!dir.path().join("counter.tmp").exists());
+    }
+
+    #[test]
+    fn counter_file_unreadable() {
+        let dir = TempDir::new().unwrap();
+        // A directory can't be read as a counter file
+        let path = dir.path().to_string_lossy().to_string();
+
+        match Authenticator::new(&config(Some(path))) {
+            Err(e) => assert!(format!("{}", e).contains("Unable to read counter file")),
+            Ok(_) => panic!("Unreadable counter file was accepted"),
+        }
+    }
+
+    #[test]
+    fn bad_key() {
+        let mut config = config(None);
+        config.key = "xyz".to_owned();
+
+        assert!(Authenticator::new(&config).is_err());
+    }
+}
@@ -20,12 +20,23 @@
 //! struct containing configuration information for a `comms-service`.
 
 use crate::errors::*;
+use crate::packet::PayloadType;
+use crate::queue::{DrainOrder, EvictionPolicy, QueueLimits, TokenBucket};
 use serde_derive::Deserialize;
+use std::time::Duration;
 
 /// Default maximum number of message handlers
 pub const DEFAULT_MAX_HANDLERS: u16 = 50;
 /// Default message handler timeout
 pub const DEFAULT_TIMEOUT: u64 = 1500;
+/// Default maximum number of messages in each downlink queue
+pub const DEFAULT_QUEUE_SIZE: usize = 100;
+/// Default downlink message priority
+pub const DEFAULT_PRIORITY: u8 = 0;
+/// Default interval between checks of the link state while it is down (in milliseconds)
+pub const DEFAULT_LINK_POLL_INTERVAL: u64 = 1000;
+/// Default number of times a downlink message is tried before it is dropped
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
 
 /// A struct that holds useful configuration options to use in a `comms-service` implementation.
 /// Created by parsing a configuration file in the `toml` file format.
@@ -42,6 +53,206 @@ pub struct CommsConfig {
     pub timeout: Option<u64>,
     /// Required. IP address on which comms service will listen.
     pub ip: String,
+    /// Optional maximum size of a single link frame (in bytes).
+    /// Packets larger than this will be split into multiple segments before being downlinked.
+    pub mtu: Option<usize>,
+    /// Optional authentication settings. If present, every uplinked frame must carry a valid
+    /// authentication trailer.
+    pub auth: Option<AuthConfig>,
+    /// Optional downlink queueing, prioritisation and rate limiting settings.
+    pub downlink: Option<DownlinkConfig>,
+}
+
+/// Authentication settings for uplinked packets
+#[derive(Clone, Deserialize)]
+pub struct AuthConfig {
+    /// Hex-encoded HMAC-SHA256 key shared with the ground
+    pub key: String,
+    /// Optional file used to store the last accepted anti-replay counter,
+    /// so that it survives service restarts
+    pub counter_file: Option<String>,
+}
+
+// The key is deliberately left out so that it can't end up in logs
+impl ::std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(
+            f,
+            "AuthConfig {{ key: <redacted>, counter_file: {:?} }}",
+            self.counter_file
+        )
+    }
+}
+
+/// Downlink queueing, prioritisation and rate limiting settings.
+/// Each `write` function has its own queue and rate limit.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct DownlinkConfig {
+    /// Maximum number of messages waiting to be sent by each `write` function.
+    /// Default: 100
+    pub queue_size: Option<usize>,
+    /// Maximum data rate of each `write` function, in bytes per second.
+    /// Default: Unlimited
+    pub rate_limit: Option<u32>,
+    /// Maximum number of bytes which may be sent in a single burst.
+    /// Default: One second's worth of data (`rate_limit`)
+    pub burst_size: Option<u32>,
+    /// Priority of messages which don't match any of the priority rules.
+    /// Default: 0
+    pub default_priority: Option<u8>,
+    /// Rules assigning priorities to messages. Higher priority messages are sent first.
+    pub priorities: Option<Vec<PriorityRule>>,
+    /// Maximum total size of the messages waiting to be sent by each `write` function (in bytes).
+    /// Default: Unlimited
+    pub max_bytes: Option<usize>,
+    /// Which messages to drop when a queue is full.
+    /// Default: `lowest_priority`
+    pub eviction: Option<EvictionPolicy>,
+    /// The order in which queued messages are sent.
+    /// Default: `priority`
+    pub order: Option<DrainOrder>,
+    /// Optional directory used to keep queued messages on disk, so that messages gathered
+    /// while out of contact survive service restarts. Each `write` function stores its queue
+    /// in a numbered sub-directory.
+    pub store_path: Option<String>,
+    /// How often to check whether the link has come back up, and how long to wait before
+    /// retrying a failed write (in milliseconds).
+    /// Default: 1000
+    pub link_poll_interval: Option<u64>,
+    /// Number of times a message is tried before it is dropped from its queue.
+    /// Default: 5
+    pub max_attempts: Option<u32>,
+}
+
+/// Rule assigning a priority to downlinked messages.
+/// Exactly one of `port` or `payload_type` should be given.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PriorityRule {
+    /// Port the message is associated with. This is the downlink endpoint port for UDP messages
+    /// and the destination service port for GraphQL responses.
+    pub port: Option<u16>,
+    /// Payload type of the message ("graphql" or "udp")
+    pub payload_type: Option<String>,
+    /// Priority of matching messages
+    pub priority: u8,
+}
+
+fn payload_type_apid(name: &str) -> Option<u16> {
+    match name.to_lowercase().as_str() {
+        "graphql" => Some(u16::from(PayloadType::GraphQL)),
+        "udp" => Some(u16::from(PayloadType::UDP)),
+        _ => None,
+    }
+}
+
+impl DownlinkConfig {
+    /// Check that the settings are usable
+    pub fn validate(&self) -> CommsResult<()> {
+        if self.queue_size == Some(0) {
+            return Err(CommsServiceError::ConfigError(
+                "Downlink `queue_size` must be greater than 0".to_owned(),
+            )
+            .into());
+        }
+
+        if self.max_bytes == Some(0) {
+            return Err(CommsServiceError::ConfigError(
+                "Downlink `max_bytes` must be greater than 0".to_owned(),
+            )
+            .into());
+        }
+
+        if self.max_attempts == Some(0) {
+            return Err(CommsServiceError::ConfigError(
+                "Downlink `max_attempts` must be greater than 0".to_owned(),
+            )
+            .into());
+        }
+
+        if self.rate_limit == Some(0) || self.burst_size == Some(0) {
+            return Err(CommsServiceError::ConfigError(
+                "Downlink `rate_limit` and `burst_size` must be greater than 0".to_owned(),
+            )
+            .into());
+        }
+
+        for rule in self.priorities.iter().flatten() {
+            match (rule.port, &rule.payload_type) {
+                (Some(_), None) => {}
+                (None, Some(name)) if payload_type_apid(name).is_some() => {}
+                (None, Some(name)) => {
+                    return Err(CommsServiceError::ConfigError(format!(
+                        "Unknown payload type in downlink priority rule: {}",
+                        name
+                    ))
+                    .into());
+                }
+                _ => {
+                    return Err(CommsServiceError::ConfigError(
+                        "Downlink priority rules need either a `port` or a `payload_type`"
+                            .to_owned(),
+                    )
+                    .into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The maximum number of messages in each downlink queue
+    pub fn queue_size(&self) -> usize {
+        self.queue_size.unwrap_or(DEFAULT_QUEUE_SIZE)
+    }
+
+    /// How long to wait between checks of the link state while it is down
+    pub fn link_poll_interval(&self) -> Duration {
+        Duration::from_millis(
+            self.link_poll_interval
+                .unwrap_or(DEFAULT_LINK_POLL_INTERVAL),
+        )
+    }
+
+    /// How many times a message is tried before it is dropped
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS)
+    }
+
+    /// The size limits and ordering rules of each downlink queue
+    pub(crate) fn queue_limits(&self) -> QueueLimits {
+        QueueLimits {
+            max_messages: self.queue_size(),
+            max_bytes: self.max_bytes,
+            eviction: self.eviction.unwrap_or(EvictionPolicy::LowestPriority),
+            order: self.order.unwrap_or(DrainOrder::Priority),
+        }
+    }
+
+    /// Look up the priority of a message.
+    /// Rules matching the port take precedence over rules matching the payload type.
+    pub fn priority(&self, port: u16, apid: u16) -> u8 {
+        let rules = self.priorities.as_deref().unwrap_or(&[]);
+
+        rules
+            .iter()
+            .find(|rule| rule.port == Some(port))
+            .or_else(|| {
+                rules.iter().find(|rule| {
+                    rule.payload_type
+                        .as_ref()
+                        .and_then(|name| payload_type_apid(name))
+                        == Some(apid)
+                })
+            })
+            .map(|rule| rule.priority)
+            .unwrap_or_else(|| self.default_priority.unwrap_or(DEFAULT_PRIORITY))
+    }
+
+    /// Create the token bucket used to enforce the rate limit, if there is one
+    pub(crate) fn token_bucket(&self) -> Option<TokenBucket> {
+        self.rate_limit
+            .map(|rate| TokenBucket::new(rate, self.burst_size.unwrap_or(rate)))
+    }
 }
 
 impl CommsConfig {
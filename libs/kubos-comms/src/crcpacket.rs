@@ -0,0 +1,209 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Packet Definition for CRC-protected link packets
+
+use crate::errors::*;
+use crate::packet::{LinkPacket, PayloadType, SequenceFlag};
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+
+// Size of the CRC-16 trailer
+const CRC_SIZE: usize = 2;
+
+fn checksum(bytes: &[u8]) -> u16 {
+    crc16::State::<crc16::CCITT_FALSE>::calculate(bytes)
+}
+
+/// Wrapper which protects another link packet with a CRC-16/CCITT trailer.
+///
+/// The CRC is calculated over the full bytes of the wrapped packet and appended,
+/// big-endian, to the end of them. Packets whose trailer doesn't match their contents
+/// fail validation and are rejected by the communications service.
+///
+/// ```rust,no_run
+/// # use kubos_comms::*;
+/// # use std::sync::{Arc, Mutex};
+/// # fn func(controls: CommsControlBlock<u8>) -> CommsResult<()> {
+/// let telem = Arc::new(Mutex::new(CommsTelemetry::default()));
+/// CommsService::start::<u8, CrcPacket<SpacePacket>>(controls, &telem)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Eq, Debug, PartialEq)]
+pub struct CrcPacket<Packet: LinkPacket> {
+    packet: Box<Packet>,
+    valid: bool,
+}
+
+impl<Packet: LinkPacket> LinkPacket for CrcPacket<Packet> {
+    fn build(
+        command_id: u64,
+        payload_type: PayloadType,
+        destination_port: u16,
+        payload: &[u8],
+    ) -> CommsResult<Box<Self>> {
+        Ok(Box::new(CrcPacket {
+            packet: Packet::build(command_id, payload_type, destination_port, payload)?,
+            valid: true,
+        }))
+    }
+
+    fn parse(raw: &[u8]) -> CommsResult<Box<Self>> {
+        if raw.len() < CRC_SIZE {
+            return Err(CommsServiceError::ParsingError(
+                "Packet is too short to contain a CRC".to_owned(),
+            )
+            .into());
+        }
+
+        let (body, trailer) = raw.split_at(raw.len() - CRC_SIZE);
+
+        Ok(Box::new(CrcPacket {
+            packet: Packet::parse(body)?,
+            valid: BigEndian::read_u16(trailer) == checksum(body),
+        }))
+    }
+
+    fn to_bytes(&self) -> CommsResult<Vec<u8>> {
+        let mut bytes = self.packet.to_bytes()?;
+        let crc = checksum(&bytes);
+        bytes.write_u16::<BigEndian>(crc)?;
+
+        Ok(bytes)
+    }
+
+    fn command_id(&self) -> u64 {
+        self.packet.command_id()
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        self.packet.payload()
+    }
+
+    fn payload_type(&self) -> PayloadType {
+        self.packet.payload_type()
+    }
+
+    fn destination(&self) -> u16 {
+        self.packet.destination()
+    }
+
+    fn validate(&self) -> bool {
+        self.valid && self.packet.validate()
+    }
+
+    fn max_size() -> usize {
+        Packet::max_size()
+    }
+
+    fn header_size() -> usize {
+        Packet::header_size() + CRC_SIZE
+    }
+
+    fn sequence_flags(&self) -> SequenceFlag {
+        self.packet.sequence_flags()
+    }
+
+    fn sequence_count(&self) -> u16 {
+        self.packet.sequence_count()
+    }
+
+    fn set_sequence(&mut self, flags: SequenceFlag, count: u16) {
+        self.packet.set_sequence(flags, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::checksum;
+    use crate::*;
+
+    #[test]
+    fn checksum_check_value() {
+        // Standard check value for CRC-16/CCITT-FALSE
+        assert_eq!(checksum(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn do_build_parse() {
+        let packet =
+            CrcPacket::<SpacePacket>::build(1294, PayloadType::GraphQL, 15001, &[5, 4, 3, 2, 1])
+                .unwrap();
+
+        let raw = packet.to_bytes().unwrap();
+        let parsed = CrcPacket::<SpacePacket>::parse(&raw).unwrap();
+
+        assert!(parsed.validate());
+        assert_eq!(packet, parsed);
+    }
+
+    #[test]
+    fn trailer_follows_packet() {
+        let packet = CrcPacket::<SpacePacket>::build(0, PayloadType::GraphQL, 0, b"123456789")
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        let inner = SpacePacket::build(0, PayloadType::GraphQL, 0, b"123456789")
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+
+        assert_eq!(&packet[..inner.len()], &inner[..]);
+        assert_eq!(&packet[inner.len()..], &checksum(&inner).to_be_bytes());
+    }
+
+    #[test]
+    fn corrupted_packet_fails_validation() {
+        let packet =
+            CrcPacket::<SpacePacket>::build(1294, PayloadType::GraphQL, 15001, b"query").unwrap();
+
+        let mut raw = packet.to_bytes().unwrap();
+        raw[17] ^= 0x01;
+
+        let parsed = CrcPacket::<SpacePacket>::parse(&raw).unwrap();
+        assert!(!parsed.validate());
+    }
+
+    #[test]
+    fn corrupted_trailer_fails_validation() {
+        let packet =
+            CrcPacket::<SpacePacket>::build(1294, PayloadType::GraphQL, 15001, b"query").unwrap();
+
+        let mut raw = packet.to_bytes().unwrap();
+        let last = raw.len() - 1;
+        raw[last] ^= 0xFF;
+
+        let parsed = CrcPacket::<SpacePacket>::parse(&raw).unwrap();
+        assert!(!parsed.validate());
+    }
+
+    #[test]
+    fn sequence_is_covered_by_crc() {
+        let mut packet =
+            CrcPacket::<SpacePacket>::build(1, PayloadType::UDP, 15001, b"data").unwrap();
+        packet.set_sequence(SequenceFlag::First, 12);
+
+        let parsed = CrcPacket::<SpacePacket>::parse(&packet.to_bytes().unwrap()).unwrap();
+        assert!(parsed.validate());
+        assert_eq!(parsed.sequence_flags(), SequenceFlag::First);
+        assert_eq!(parsed.sequence_count(), 12);
+    }
+
+    #[test]
+    fn parse_too_short() {
+        assert!(CrcPacket::<SpacePacket>::parse(&[0x01]).is_err());
+    }
+}
@@ -53,6 +53,15 @@ pub enum CommsServiceError {
     /// Unknown payload type encountered
     #[fail(display = "Unknown payload type encountered: {}", _0)]
     UnknownPayloadType(u16),
+    /// A segmented packet could not be reassembled
+    #[fail(display = "Reassembly error: {}", _0)]
+    ReassemblyError(String),
+    /// A packet failed authentication
+    #[fail(display = "Authentication error: {}", _0)]
+    AuthenticationError(String),
+    /// A downlink queue is full of higher priority messages
+    #[fail(display = "Downlink queue is full")]
+    QueueFull,
 }
 
 /// Result returned by the `comms-service`.
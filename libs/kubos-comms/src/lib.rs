@@ -68,6 +68,31 @@
 //! downlink_ports = [13011]
 //! timeout = 1500"
 //! ip = "192.168.8.2"
+//! mtu = 256
+//!
+//! [service-name.comms.auth]
+//! key = "000102030405060708090a0b0c0d0e0f"
+//! counter_file = "/home/system/kubos/comms-counter"
+//!
+//! [service-name.comms.downlink]
+//! queue_size = 100
+//! rate_limit = 1200
+//! burst_size = 2400
+//! default_priority = 0
+//! max_bytes = 1048576
+//! eviction = "lowest_priority"
+//! order = "priority"
+//! store_path = "/home/system/kubos/downlink"
+//! link_poll_interval = 1000
+//! max_attempts = 5
+//!
+//! [[service-name.comms.downlink.priorities]]
+//! port = 13011
+//! priority = 10
+//!
+//! [[service-name.comms.downlink.priorities]]
+//! payload_type = "graphql"
+//! priority = 5
 //! ```
 
 #[macro_use]
@@ -79,9 +104,13 @@ extern crate log;
 extern crate byteorder;
 extern crate failure;
 
+mod auth;
 mod config;
+mod crcpacket;
 mod errors;
 mod packet;
+mod queue;
+mod sequence;
 mod service;
 mod spacepacket;
 mod telemetry;
@@ -101,6 +130,14 @@ pub use crate::telemetry::CommsTelemetry;
 /// Communication Service configuration parsing.
 pub use crate::config::*;
 
+/// Uplink authentication.
+pub use crate::auth::{sign, Authenticator, AUTH_TRAILER_SIZE};
+
+/// Downlink queue settings.
+pub use crate::queue::{DrainOrder, EvictionPolicy};
+
+pub use crcpacket::CrcPacket;
 pub use packet::LinkPacket;
 pub use packet::PayloadType;
+pub use packet::SequenceFlag;
 pub use spacepacket::SpacePacket;
@@ -50,6 +50,42 @@ impl From<PayloadType> for u16 {
     }
 }
 
+/// Sequence flags describing where a packet falls within a segmented payload,
+/// using the CCSDS Space Packet encoding
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SequenceFlag {
+    /// Continuation segment of a larger payload
+    Continuation,
+    /// First segment of a larger payload
+    First,
+    /// Last segment of a larger payload
+    Last,
+    /// Complete, unsegmented payload
+    Unsegmented,
+}
+
+impl From<u8> for SequenceFlag {
+    fn from(num: u8) -> SequenceFlag {
+        match num & 0b11 {
+            0b00 => SequenceFlag::Continuation,
+            0b01 => SequenceFlag::First,
+            0b10 => SequenceFlag::Last,
+            _ => SequenceFlag::Unsegmented,
+        }
+    }
+}
+
+impl From<SequenceFlag> for u8 {
+    fn from(value: SequenceFlag) -> u8 {
+        match value {
+            SequenceFlag::Continuation => 0b00,
+            SequenceFlag::First => 0b01,
+            SequenceFlag::Last => 0b10,
+            SequenceFlag::Unsegmented => 0b11,
+        }
+    }
+}
+
 /// Generic LinkPacket trait which defines the internal packet requirements
 /// of the communications service.
 pub trait LinkPacket {
@@ -83,4 +119,21 @@ pub trait LinkPacket {
         // (65,535 - 20 byte IP header - 8 byte UDP header)
         65507
     }
+    /// The number of bytes the packet adds around its payload.
+    /// Used to work out how much payload fits in a single link frame
+    /// when segmenting.
+    fn header_size() -> usize {
+        0
+    }
+    /// The sequence flags of the packet
+    fn sequence_flags(&self) -> SequenceFlag {
+        SequenceFlag::Unsegmented
+    }
+    /// The sequence count of the packet
+    fn sequence_count(&self) -> u16 {
+        0
+    }
+    /// Set the sequence flags and count of the packet.
+    /// Packet types which don't carry sequencing information can ignore this.
+    fn set_sequence(&mut self, _flags: SequenceFlag, _count: u16) {}
 }
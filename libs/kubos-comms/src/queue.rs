@@ -0,0 +1,622 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Downlink prioritisation, rate limiting and store-and-forward buffering
+//!
+//! Every message headed for the ground is placed in the queue belonging to the `write`
+//! function which will send it. A dedicated writer thread takes the next message from the
+//! queue and writes it to the gateway, without exceeding the configured data rate.
+//!
+//! Queues may optionally be backed by a directory on disk, so that messages gathered while
+//! the satellite is out of contact survive until the link comes back, even across restarts.
+
+use crate::errors::*;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use serde_derive::Deserialize;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// File extension of stored messages
+const MESSAGE_EXTENSION: &str = "msg";
+
+/// A message waiting to be sent to the ground
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QueuedMessage {
+    /// Priority of the message. Higher values are sent first
+    pub priority: u8,
+    /// Command ID to send the message with
+    pub command_id: u64,
+    /// APID (payload type) to send the message with
+    pub apid: u16,
+    /// Message contents
+    pub payload: Vec<u8>,
+}
+
+impl QueuedMessage {
+    fn to_bytes(&self) -> CommsResult<Vec<u8>> {
+        let mut bytes = vec![];
+        bytes.write_u8(self.priority)?;
+        bytes.write_u16::<BigEndian>(self.apid)?;
+        bytes.write_u64::<BigEndian>(self.command_id)?;
+        bytes.extend_from_slice(&self.payload);
+        Ok(bytes)
+    }
+
+    fn parse(raw: &[u8]) -> CommsResult<Self> {
+        let mut reader = Cursor::new(raw);
+        let priority = reader.read_u8()?;
+        let apid = reader.read_u16::<BigEndian>()?;
+        let command_id = reader.read_u64::<BigEndian>()?;
+        let payload = raw[reader.position() as usize..].to_vec();
+
+        Ok(QueuedMessage {
+            priority,
+            command_id,
+            apid,
+            payload,
+        })
+    }
+}
+
+/// Which messages to drop when a queue is full
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// Drop the oldest of the lowest priority messages, if it has a lower priority
+    /// than the new message. Otherwise, drop the new message.
+    LowestPriority,
+    /// Drop the oldest messages
+    Oldest,
+    /// Drop the new message
+    Newest,
+}
+
+/// The order in which queued messages are sent
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DrainOrder {
+    /// Highest priority first. Messages with the same priority are sent oldest first
+    Priority,
+    /// Oldest first, regardless of priority
+    Fifo,
+}
+
+/// Size limits and ordering rules for a downlink queue
+#[derive(Clone, Debug)]
+pub struct QueueLimits {
+    /// Maximum number of messages in the queue
+    pub max_messages: usize,
+    /// Maximum total size of the queued message payloads, in bytes
+    pub max_bytes: Option<usize>,
+    /// Which messages to drop when the queue is full
+    pub eviction: EvictionPolicy,
+    /// The order in which messages are sent
+    pub order: DrainOrder,
+}
+
+/// Outcome of adding a message to a queue
+#[derive(Debug, Eq, PartialEq)]
+pub enum PushResult {
+    /// The message was queued without displacing anything
+    Queued,
+    /// The message was queued, but this many older messages were dropped to make room
+    Evicted(usize),
+    /// There was no room for the message, so it was dropped
+    Dropped,
+}
+
+#[derive(Default)]
+struct QueueState {
+    // Messages, along with the order in which they were added
+    messages: Vec<(u64, QueuedMessage)>,
+    next_order: u64,
+    bytes: usize,
+}
+
+impl QueueState {
+    // Pick the messages which need to be dropped to make room for a new one.
+    // Returns `None` if the new message itself should be dropped instead.
+    fn eviction_candidates(
+        &self,
+        limits: &QueueLimits,
+        incoming: &QueuedMessage,
+    ) -> Option<Vec<u64>> {
+        let mut count = self.messages.len();
+        let mut bytes = self.bytes;
+        let mut evict = vec![];
+
+        let mut candidates = match limits.eviction {
+            EvictionPolicy::LowestPriority => {
+                let mut lower: Vec<_> = self
+                    .messages
+                    .iter()
+                    .filter(|(_, queued)| queued.priority < incoming.priority)
+                    .collect();
+                lower.sort_by_key(|(order, queued)| (queued.priority, *order));
+                lower
+            }
+            EvictionPolicy::Oldest => {
+                let mut all: Vec<_> = self.messages.iter().collect();
+                all.sort_by_key(|(order, _)| *order);
+                all
+            }
+            EvictionPolicy::Newest => vec![],
+        }
+        .into_iter();
+
+        while count >= limits.max_messages
+            || limits
+                .max_bytes
+                .map_or(false, |max| bytes + incoming.payload.len() > max)
+        {
+            let (order, queued) = candidates.next()?;
+            count -= 1;
+            bytes -= queued.payload.len();
+            evict.push(*order);
+        }
+
+        Some(evict)
+    }
+
+    // Index of the message which should be sent next
+    fn next(&self, order: DrainOrder) -> Option<usize> {
+        let next = match order {
+            DrainOrder::Priority => self
+                .messages
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, (order, queued))| (queued.priority, ::std::cmp::Reverse(*order))),
+            DrainOrder::Fifo => self
+                .messages
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (order, _))| *order),
+        };
+
+        next.map(|(index, _)| index)
+    }
+}
+
+/// Bounded queue of messages waiting to be downlinked
+pub struct DownlinkQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+    limits: QueueLimits,
+    store: Option<PathBuf>,
+}
+
+impl DownlinkQueue {
+    /// Create a new in-memory queue
+    pub fn new(limits: QueueLimits) -> Self {
+        DownlinkQueue {
+            state: Mutex::new(QueueState::default()),
+            ready: Condvar::new(),
+            limits,
+            store: None,
+        }
+    }
+
+    /// Create a new queue which keeps a copy of every message in the given directory.
+    ///
+    /// Any messages left in the directory by a previous run are loaded back into the queue.
+    pub fn open(limits: QueueLimits, dir: &Path) -> CommsResult<Self> {
+        fs::create_dir_all(dir)?;
+
+        let mut state = QueueState::default();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(MESSAGE_EXTENSION) {
+                continue;
+            }
+
+            let order = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok());
+
+            let message = fs::read(&path)
+                .map_err(|e| e.into())
+                .and_then(|raw| QueuedMessage::parse(&raw));
+
+            match (order, message) {
+                (Some(order), Ok(message)) => {
+                    state.bytes += message.payload.len();
+                    state.next_order = state.next_order.max(order + 1);
+                    state.messages.push((order, message));
+                }
+                _ => {
+                    warn!("Removing unreadable stored message {}", path.display());
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+
+        Ok(DownlinkQueue {
+            state: Mutex::new(state),
+            ready: Condvar::new(),
+            limits,
+            store: Some(dir.to_path_buf()),
+        })
+    }
+
+    fn message_path(&self, order: u64) -> Option<PathBuf> {
+        self.store
+            .as_ref()
+            .map(|dir| dir.join(format!("{:020}.{}", order, MESSAGE_EXTENSION)))
+    }
+
+    fn store_message(&self, order: u64, message: &QueuedMessage) -> CommsResult<()> {
+        if let Some(path) = self.message_path(order) {
+            // Write to a temporary file first, so that a partially written message
+            // is never loaded
+            let temp = path.with_extension("tmp");
+            fs::write(&temp, message.to_bytes()?)?;
+            fs::rename(&temp, &path)?;
+        }
+        Ok(())
+    }
+
+    fn delete_message(&self, order: u64) {
+        if let Some(path) = self.message_path(order) {
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Failed to remove stored message {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Add a message to the queue.
+    ///
+    /// If the queue is full, older messages are dropped to make room according to the
+    /// queue's eviction policy.
+    pub fn push(&self, message: QueuedMessage) -> CommsResult<PushResult> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let size = message.payload.len();
+
+        if self.limits.max_bytes.map_or(false, |max| size > max) {
+            return Ok(PushResult::Dropped);
+        }
+
+        // Work out which messages need to go before making any changes
+        let evict = match state.eviction_candidates(&self.limits, &message) {
+            Some(evict) => evict,
+            None => return Ok(PushResult::Dropped),
+        };
+
+        let order = state.next_order;
+        self.store_message(order, &message)?;
+
+        for evicted in evict.iter() {
+            if let Some(index) = state.messages.iter().position(|(o, _)| o == evicted) {
+                let (_, queued) = state.messages.remove(index);
+                state.bytes -= queued.payload.len();
+                self.delete_message(*evicted);
+            }
+        }
+
+        state.next_order += 1;
+        state.bytes += size;
+        state.messages.push((order, message));
+        self.ready.notify_one();
+
+        if evict.is_empty() {
+            Ok(PushResult::Queued)
+        } else {
+            Ok(PushResult::Evicted(evict.len()))
+        }
+    }
+
+    /// Get a copy of the message which should be sent next, along with its ID,
+    /// waiting until one is available.
+    ///
+    /// The message stays in the queue until it is removed with `remove`.
+    pub fn peek(&self) -> (u64, QueuedMessage) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        loop {
+            if let Some(index) = state.next(self.limits.order) {
+                return state.messages[index].clone();
+            }
+
+            state = self.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Remove a message from the queue.
+    ///
+    /// Returns `false` if the message had already been dropped.
+    pub fn remove(&self, id: u64) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        match state.messages.iter().position(|(order, _)| *order == id) {
+            Some(index) => {
+                let (_, queued) = state.messages.remove(index);
+                state.bytes -= queued.payload.len();
+                self.delete_message(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The number of messages currently waiting in the queue
+    pub fn len(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .messages
+            .len()
+    }
+
+    /// Returns `true` if there are no messages waiting in the queue
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Token bucket used to limit the downlink data rate
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// Create a new, full bucket which refills at `rate` bytes per second and holds
+    /// at most `capacity` bytes
+    pub fn new(rate: u32, capacity: u32) -> Self {
+        TokenBucket {
+            rate: f64::from(rate),
+            capacity: f64::from(capacity),
+            tokens: f64::from(capacity),
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+    }
+
+    /// How long the caller must wait before `bytes` bytes may be sent.
+    ///
+    /// Writes which are larger than the bucket only need to wait for a full bucket.
+    pub fn delay(&mut self, bytes: usize) -> Duration {
+        self.refill();
+
+        let needed = (bytes as f64).min(self.capacity);
+        if self.tokens >= needed {
+            return Duration::from_secs(0);
+        }
+
+        Duration::from_secs_f64((needed - self.tokens) / self.rate)
+    }
+
+    /// Block until `bytes` bytes may be sent, and then take them from the bucket
+    pub fn take(&mut self, bytes: usize) {
+        let delay = self.delay(bytes);
+        if delay > Duration::from_secs(0) {
+            thread::sleep(delay);
+            self.refill();
+        }
+
+        self.tokens -= bytes as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn limits(max_messages: usize) -> QueueLimits {
+        QueueLimits {
+            max_messages,
+            max_bytes: None,
+            eviction: EvictionPolicy::LowestPriority,
+            order: DrainOrder::Priority,
+        }
+    }
+
+    fn message(priority: u8, payload: u8) -> QueuedMessage {
+        QueuedMessage {
+            priority,
+            command_id: 0,
+            apid: 1,
+            payload: vec![payload],
+        }
+    }
+
+    fn pop(queue: &DownlinkQueue) -> Vec<u8> {
+        let (id, message) = queue.peek();
+        assert!(queue.remove(id));
+        message.payload
+    }
+
+    #[test]
+    fn pop_by_priority() {
+        let queue = DownlinkQueue::new(limits(10));
+
+        queue.push(message(1, 1)).unwrap();
+        queue.push(message(5, 2)).unwrap();
+        queue.push(message(1, 3)).unwrap();
+        queue.push(message(5, 4)).unwrap();
+
+        assert_eq!(queue.len(), 4);
+        assert_eq!(pop(&queue), vec![2]);
+        assert_eq!(pop(&queue), vec![4]);
+        assert_eq!(pop(&queue), vec![1]);
+        assert_eq!(pop(&queue), vec![3]);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn pop_fifo() {
+        let mut limits = limits(10);
+        limits.order = DrainOrder::Fifo;
+        let queue = DownlinkQueue::new(limits);
+
+        queue.push(message(1, 1)).unwrap();
+        queue.push(message(5, 2)).unwrap();
+        queue.push(message(1, 3)).unwrap();
+
+        assert_eq!(pop(&queue), vec![1]);
+        assert_eq!(pop(&queue), vec![2]);
+        assert_eq!(pop(&queue), vec![3]);
+    }
+
+    #[test]
+    fn peek_leaves_message() {
+        let queue = DownlinkQueue::new(limits(10));
+        queue.push(message(1, 1)).unwrap();
+
+        let (id, _) = queue.peek();
+        assert_eq!(queue.len(), 1);
+        assert!(queue.remove(id));
+        assert!(!queue.remove(id));
+    }
+
+    #[test]
+    fn full_queue_evicts_lower_priority() {
+        let queue = DownlinkQueue::new(limits(2));
+
+        assert_eq!(queue.push(message(1, 1)).unwrap(), PushResult::Queued);
+        assert_eq!(queue.push(message(1, 2)).unwrap(), PushResult::Queued);
+        assert_eq!(queue.push(message(5, 3)).unwrap(), PushResult::Evicted(1));
+
+        assert_eq!(pop(&queue), vec![3]);
+        assert_eq!(pop(&queue), vec![2]);
+    }
+
+    #[test]
+    fn full_queue_drops_new_message() {
+        let queue = DownlinkQueue::new(limits(2));
+
+        queue.push(message(5, 1)).unwrap();
+        queue.push(message(1, 2)).unwrap();
+
+        assert_eq!(queue.push(message(1, 3)).unwrap(), PushResult::Dropped);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn full_queue_evicts_oldest() {
+        let mut limits = limits(2);
+        limits.eviction = EvictionPolicy::Oldest;
+        let queue = DownlinkQueue::new(limits);
+
+        queue.push(message(5, 1)).unwrap();
+        queue.push(message(1, 2)).unwrap();
+
+        assert_eq!(queue.push(message(1, 3)).unwrap(), PushResult::Evicted(1));
+        assert_eq!(pop(&queue), vec![2]);
+        assert_eq!(pop(&queue), vec![3]);
+    }
+
+    #[test]
+    fn full_queue_keeps_oldest() {
+        let mut limits = limits(1);
+        limits.eviction = EvictionPolicy::Newest;
+        let queue = DownlinkQueue::new(limits);
+
+        queue.push(message(1, 1)).unwrap();
+
+        assert_eq!(queue.push(message(5, 2)).unwrap(), PushResult::Dropped);
+        assert_eq!(pop(&queue), vec![1]);
+    }
+
+    #[test]
+    fn byte_limit() {
+        let mut limits = limits(10);
+        limits.max_bytes = Some(2);
+        limits.eviction = EvictionPolicy::Oldest;
+        let queue = DownlinkQueue::new(limits);
+
+        queue.push(message(1, 1)).unwrap();
+        queue.push(message(1, 2)).unwrap();
+
+        assert_eq!(queue.push(message(1, 3)).unwrap(), PushResult::Evicted(1));
+
+        let big = QueuedMessage {
+            priority: 1,
+            command_id: 0,
+            apid: 1,
+            payload: vec![0; 3],
+        };
+        assert_eq!(queue.push(big).unwrap(), PushResult::Dropped);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn store_survives_restart() {
+        let dir = TempDir::new().unwrap();
+
+        {
+            let queue = DownlinkQueue::open(limits(10), dir.path()).unwrap();
+            queue.push(message(1, 1)).unwrap();
+            queue.push(message(5, 2)).unwrap();
+            queue.push(message(1, 3)).unwrap();
+            assert_eq!(pop(&queue), vec![2]);
+        }
+
+        let queue = DownlinkQueue::open(limits(10), dir.path()).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(pop(&queue), vec![1]);
+
+        // New messages are still ordered after the restored ones
+        queue.push(message(1, 4)).unwrap();
+        assert_eq!(pop(&queue), vec![3]);
+        assert_eq!(pop(&queue), vec![4]);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn store_evicted_messages_removed() {
+        let dir = TempDir::new().unwrap();
+        let queue = DownlinkQueue::open(limits(1), dir.path()).unwrap();
+
+        queue.push(message(1, 1)).unwrap();
+        queue.push(message(5, 2)).unwrap();
+
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        let queue = DownlinkQueue::open(limits(1), dir.path()).unwrap();
+        assert_eq!(pop(&queue), vec![2]);
+    }
+
+    #[test]
+    fn bucket_allows_burst() {
+        let mut bucket = TokenBucket::new(100, 200);
+
+        assert_eq!(bucket.delay(200), Duration::from_secs(0));
+        bucket.take(200);
+        assert!(bucket.delay(100) > Duration::from_millis(900));
+    }
+
+    #[test]
+    fn bucket_large_write() {
+        let mut bucket = TokenBucket::new(100, 50);
+
+        // Writes larger than the bucket only need a full bucket
+        assert_eq!(bucket.delay(500), Duration::from_secs(0));
+    }
+}
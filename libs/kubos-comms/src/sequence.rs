@@ -0,0 +1,425 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Packet sequencing, segmentation and reassembly
+//!
+//! Sequence counts are kept separately for each APID (payload type), as described in the
+//! CCSDS Space Packet Protocol. Payloads which don't fit in a single link frame are split
+//! into first/continuation/last segments which all share the same APID.
+
+use crate::errors::*;
+use crate::packet::{LinkPacket, PayloadType, SequenceFlag};
+use std::collections::HashMap;
+
+// Sequence counts are 14 bits wide, so they wrap at 16384
+const SEQUENCE_COUNT_MODULO: u16 = 0x4000;
+
+fn next_count(count: u16) -> u16 {
+    (count + 1) % SEQUENCE_COUNT_MODULO
+}
+
+/// Per-APID sequence counters for packets sent to the ground
+#[derive(Debug, Default)]
+pub struct SequenceCounters {
+    counts: HashMap<u16, u16>,
+}
+
+impl SequenceCounters {
+    /// Get the sequence count to use for the next packet with the given APID
+    pub fn next(&mut self, apid: u16) -> u16 {
+        let count = self.counts.entry(apid).or_insert(0);
+        let current = *count;
+        *count = next_count(current);
+        current
+    }
+}
+
+/// Build the raw link frames needed to send a payload to the ground.
+///
+/// If an MTU is given and the payload doesn't fit in a single frame, it is split
+/// into multiple segmented packets. Each packet takes the next sequence count for its APID.
+pub fn build_frames<Packet: LinkPacket>(
+    counters: &mut SequenceCounters,
+    command_id: u64,
+    apid: u16,
+    destination: u16,
+    payload: &[u8],
+    mtu: Option<usize>,
+) -> CommsResult<Vec<Vec<u8>>> {
+    let chunk_size = match mtu {
+        Some(mtu) if mtu > Packet::header_size() => mtu - Packet::header_size(),
+        Some(mtu) => {
+            return Err(CommsServiceError::ConfigError(format!(
+                "MTU of {} bytes is too small for the packet header",
+                mtu
+            ))
+            .into());
+        }
+        None => payload.len(),
+    };
+
+    if payload.len() <= chunk_size {
+        let mut packet = Packet::build(command_id, PayloadType::from(apid), destination, payload)?;
+        packet.set_sequence(SequenceFlag::Unsegmented, counters.next(apid));
+        return Ok(vec![packet.to_bytes()?]);
+    }
+
+    let num_chunks = (payload.len() + chunk_size - 1) / chunk_size;
+    let mut frames = Vec::with_capacity(num_chunks);
+
+    for (index, chunk) in payload.chunks(chunk_size).enumerate() {
+        let flags = if index == 0 {
+            SequenceFlag::First
+        } else if index == num_chunks - 1 {
+            SequenceFlag::Last
+        } else {
+            SequenceFlag::Continuation
+        };
+
+        let mut packet = Packet::build(command_id, PayloadType::from(apid), destination, chunk)?;
+        packet.set_sequence(flags, counters.next(apid));
+        frames.push(packet.to_bytes()?);
+    }
+
+    Ok(frames)
+}
+
+/// Result of checking an uplinked packet's sequence count against the previous packet
+#[derive(Debug, Eq, PartialEq)]
+pub enum SequenceCheck {
+    /// The packet was the next one expected
+    InOrder,
+    /// The packet repeated the previous sequence count
+    Duplicate,
+    /// The packet arrived after one or more packets were skipped
+    Gap(u16),
+    /// The packet is older than the previous one received
+    OutOfOrder,
+}
+
+/// Tracks the sequence counts of uplinked packets for each APID
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    last: HashMap<u16, u16>,
+}
+
+impl SequenceTracker {
+    /// Record a received packet and report how it relates to the previous packet with
+    /// the same APID
+    pub fn check(&mut self, apid: u16, count: u16) -> SequenceCheck {
+        let last = match self.last.insert(apid, count) {
+            Some(last) => last,
+            None => return SequenceCheck::InOrder,
+        };
+
+        if count == last {
+            return SequenceCheck::Duplicate;
+        }
+
+        // Number of packets between the expected count and the received one
+        let skipped = (count + SEQUENCE_COUNT_MODULO - next_count(last)) % SEQUENCE_COUNT_MODULO;
+
+        if skipped == 0 {
+            SequenceCheck::InOrder
+        } else if skipped < SEQUENCE_COUNT_MODULO / 2 {
+            SequenceCheck::Gap(skipped)
+        } else {
+            // Keep tracking from the newest packet we've seen
+            self.last.insert(apid, last);
+            SequenceCheck::OutOfOrder
+        }
+    }
+}
+
+struct PendingPayload {
+    command_id: u64,
+    destination: u16,
+    next_count: u16,
+    payload: Vec<u8>,
+}
+
+/// Reassembles segmented uplink packets into their original payloads
+#[derive(Default)]
+pub struct Reassembler {
+    pending: HashMap<u16, PendingPayload>,
+}
+
+impl Reassembler {
+    /// Add a received packet.
+    ///
+    /// Returns the complete packet once all of its segments have arrived, or `None` if
+    /// more segments are needed.
+    ///
+    /// A continuation packet which arrives while no payload is being reassembled is treated
+    /// as complete, since older ground software leaves the sequence flags zeroed.
+    pub fn push<Packet: LinkPacket>(
+        &mut self,
+        packet: Box<Packet>,
+    ) -> CommsResult<Option<Box<Packet>>> {
+        let apid = u16::from(packet.payload_type());
+        let count = packet.sequence_count();
+
+        match packet.sequence_flags() {
+            SequenceFlag::Unsegmented => {
+                if self.pending.remove(&apid).is_some() {
+                    warn!("Discarding incomplete segmented payload for APID {}", apid);
+                }
+                Ok(Some(packet))
+            }
+            SequenceFlag::First => {
+                if self.pending.remove(&apid).is_some() {
+                    warn!("Discarding incomplete segmented payload for APID {}", apid);
+                }
+                self.pending.insert(
+                    apid,
+                    PendingPayload {
+                        command_id: packet.command_id(),
+                        destination: packet.destination(),
+                        next_count: next_count(count),
+                        payload: packet.payload(),
+                    },
+                );
+                Ok(None)
+            }
+            SequenceFlag::Continuation => {
+                if !self.pending.contains_key(&apid) {
+                    return Ok(Some(packet));
+                }
+                self.append::<Packet>(apid, count, &packet.payload())?;
+                Ok(None)
+            }
+            SequenceFlag::Last => {
+                if !self.pending.contains_key(&apid) {
+                    return Err(CommsServiceError::ReassemblyError(format!(
+                        "Received last segment for APID {} without a first segment",
+                        apid
+                    ))
+                    .into());
+                }
+                self.append::<Packet>(apid, count, &packet.payload())?;
+
+                // `append` only succeeds if the entry exists
+                let pending = self.pending.remove(&apid).unwrap();
+                let mut complete = Packet::build(
+                    pending.command_id,
+                    PayloadType::from(apid),
+                    pending.destination,
+                    &pending.payload,
+                )?;
+                complete.set_sequence(SequenceFlag::Unsegmented, count);
+                Ok(Some(complete))
+            }
+        }
+    }
+
+    fn append<Packet: LinkPacket>(
+        &mut self,
+        apid: u16,
+        count: u16,
+        payload: &[u8],
+    ) -> CommsResult<()> {
+        let pending = match self.pending.get_mut(&apid) {
+            Some(pending) => pending,
+            None => {
+                return Err(CommsServiceError::ReassemblyError(format!(
+                    "No segmented payload in progress for APID {}",
+                    apid
+                ))
+                .into());
+            }
+        };
+
+        if pending.next_count != count {
+            let expected = pending.next_count;
+            self.pending.remove(&apid);
+            return Err(CommsServiceError::ReassemblyError(format!(
+                "Missing segment for APID {}: expected sequence count {}, received {}",
+                apid, expected, count
+            ))
+            .into());
+        }
+
+        if pending.payload.len() + payload.len() > Packet::max_size() {
+            self.pending.remove(&apid);
+            return Err(CommsServiceError::ReassemblyError(format!(
+                "Reassembled payload for APID {} exceeds the maximum packet size",
+                apid
+            ))
+            .into());
+        }
+
+        pending.payload.extend_from_slice(payload);
+        pending.next_count = next_count(count);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpacePacket;
+
+    #[allow(clippy::vec_box)]
+    fn parse_all(frames: &[Vec<u8>]) -> Vec<Box<SpacePacket>> {
+        frames
+            .iter()
+            .map(|frame| SpacePacket::parse(frame).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn counters_are_per_apid() {
+        let mut counters = SequenceCounters::default();
+
+        assert_eq!(counters.next(0), 0);
+        assert_eq!(counters.next(0), 1);
+        assert_eq!(counters.next(1), 0);
+        assert_eq!(counters.next(0), 2);
+    }
+
+    #[test]
+    fn counters_wrap() {
+        let mut counters = SequenceCounters::default();
+        counters.counts.insert(0, 0x3FFF);
+
+        assert_eq!(counters.next(0), 0x3FFF);
+        assert_eq!(counters.next(0), 0);
+    }
+
+    #[test]
+    fn build_frames_unsegmented() {
+        let mut counters = SequenceCounters::default();
+        let frames =
+            build_frames::<SpacePacket>(&mut counters, 1, 1, 0, &[0; 20], Some(100)).unwrap();
+
+        assert_eq!(frames.len(), 1);
+        let packet = SpacePacket::parse(&frames[0]).unwrap();
+        assert_eq!(packet.sequence_flags(), SequenceFlag::Unsegmented);
+        assert_eq!(packet.sequence_count(), 0);
+    }
+
+    #[test]
+    fn build_frames_segmented() {
+        let mut counters = SequenceCounters::default();
+        let payload: Vec<u8> = (0..50).collect();
+        // 16 byte header leaves 20 bytes of payload per frame
+        let frames =
+            build_frames::<SpacePacket>(&mut counters, 1, 1, 0, &payload, Some(36)).unwrap();
+
+        let packets = parse_all(&frames);
+        assert_eq!(packets.len(), 3);
+        assert!(frames.iter().all(|frame| frame.len() <= 36));
+        assert_eq!(packets[0].sequence_flags(), SequenceFlag::First);
+        assert_eq!(packets[1].sequence_flags(), SequenceFlag::Continuation);
+        assert_eq!(packets[2].sequence_flags(), SequenceFlag::Last);
+        assert_eq!(
+            packets
+                .iter()
+                .map(|packet| packet.sequence_count())
+                .collect::<Vec<u16>>(),
+            vec![0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn build_frames_small_mtu() {
+        let mut counters = SequenceCounters::default();
+        let result = build_frames::<SpacePacket>(&mut counters, 1, 1, 0, &[0; 20], Some(16));
+
+        assert_eq!(
+            format!("{}", result.unwrap_err()),
+            "Config error: MTU of 16 bytes is too small for the packet header"
+        );
+    }
+
+    #[test]
+    fn tracker_detects_gaps() {
+        let mut tracker = SequenceTracker::default();
+
+        assert_eq!(tracker.check(0, 5), SequenceCheck::InOrder);
+        assert_eq!(tracker.check(0, 6), SequenceCheck::InOrder);
+        assert_eq!(tracker.check(0, 9), SequenceCheck::Gap(2));
+        assert_eq!(tracker.check(0, 9), SequenceCheck::Duplicate);
+        assert_eq!(tracker.check(0, 7), SequenceCheck::OutOfOrder);
+        assert_eq!(tracker.check(0, 10), SequenceCheck::InOrder);
+        assert_eq!(tracker.check(1, 0), SequenceCheck::InOrder);
+    }
+
+    #[test]
+    fn tracker_handles_wrap() {
+        let mut tracker = SequenceTracker::default();
+
+        assert_eq!(tracker.check(0, 0x3FFF), SequenceCheck::InOrder);
+        assert_eq!(tracker.check(0, 0), SequenceCheck::InOrder);
+        assert_eq!(tracker.check(0, 0x3FFF), SequenceCheck::OutOfOrder);
+    }
+
+    #[test]
+    fn reassemble_segments() {
+        let mut counters = SequenceCounters::default();
+        let payload: Vec<u8> = (0..50).collect();
+        let frames =
+            build_frames::<SpacePacket>(&mut counters, 12, 0, 8000, &payload, Some(36)).unwrap();
+
+        let mut reassembler = Reassembler::default();
+        let mut packets = parse_all(&frames).into_iter();
+
+        assert!(reassembler.push(packets.next().unwrap()).unwrap().is_none());
+        assert!(reassembler.push(packets.next().unwrap()).unwrap().is_none());
+        let complete = reassembler.push(packets.next().unwrap()).unwrap().unwrap();
+
+        assert_eq!(complete.payload(), payload);
+        assert_eq!(complete.command_id(), 12);
+        assert_eq!(complete.destination(), 8000);
+    }
+
+    #[test]
+    fn reassemble_missing_segment() {
+        let mut counters = SequenceCounters::default();
+        let payload: Vec<u8> = (0..50).collect();
+        let frames =
+            build_frames::<SpacePacket>(&mut counters, 12, 0, 8000, &payload, Some(36)).unwrap();
+
+        let mut reassembler = Reassembler::default();
+        let packets = parse_all(&frames);
+        let mut packets = packets.into_iter();
+        let first = packets.next().unwrap();
+        let _lost = packets.next().unwrap();
+        let last = packets.next().unwrap();
+
+        assert!(reassembler.push(first).unwrap().is_none());
+        assert!(reassembler.push(last).is_err());
+    }
+
+    #[test]
+    fn reassemble_last_without_first() {
+        let mut packet = SpacePacket::build(1, PayloadType::UDP, 0, &[1, 2, 3]).unwrap();
+        packet.set_sequence(SequenceFlag::Last, 4);
+
+        let mut reassembler = Reassembler::default();
+        assert!(reassembler.push(packet).is_err());
+    }
+
+    #[test]
+    fn reassemble_legacy_packet() {
+        let mut packet = SpacePacket::build(1, PayloadType::UDP, 0, &[1, 2, 3]).unwrap();
+        packet.set_sequence(SequenceFlag::Continuation, 0);
+
+        let mut reassembler = Reassembler::default();
+        let complete = reassembler.push(packet).unwrap().unwrap();
+        assert_eq!(complete.payload(), vec![1, 2, 3]);
+    }
+}
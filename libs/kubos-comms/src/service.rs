@@ -16,13 +16,17 @@
 // Contributed by: William Greer (wgreer184@gmail.com) and Sam Justice (sam.justice1@gmail.com)
 //
 
+use crate::auth::Authenticator;
 use crate::config::*;
 use crate::errors::*;
 use crate::packet::{LinkPacket, PayloadType};
+use crate::queue::*;
+use crate::sequence::*;
 use crate::telemetry::*;
 use log::info;
 use std::fmt::Debug;
 use std::net::{Ipv4Addr, UdpSocket};
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -33,6 +37,8 @@ pub type ReadFn<Connection> = dyn Fn(&Connection) -> CommsResult<Vec<u8>> + Send
 /// Type definition for a "write" function pointer.
 pub type WriteFn<Connection> =
     dyn Fn(&Connection, &[u8]) -> CommsResult<()> + Send + Sync + 'static;
+/// Type definition for a "link up" function pointer.
+pub type LinkUpFn<Connection> = dyn Fn(&Connection) -> bool + Send + Sync + 'static;
 
 /// Struct that holds configuration data to allow users to set up a Communication Service.
 #[derive(Clone)]
@@ -54,6 +60,17 @@ pub struct CommsControlBlock<Connection: Clone> {
     /// Optional list of ports used by downlink endpoints that send messages to the ground.
     /// Each port in the list will be used by one downlink endpoint.
     pub downlink_ports: Option<Vec<u16>>,
+    /// Optional maximum size of a single link frame (in bytes).
+    /// Larger packets are segmented before being written to the gateway.
+    pub mtu: Option<usize>,
+    /// Optional authenticator used to verify uplinked frames.
+    pub auth: Option<Arc<Mutex<Authenticator>>>,
+    /// Downlink queueing, prioritisation and rate limiting settings.
+    pub downlink: DownlinkConfig,
+    /// Optional function pointer to a function that reports whether the ground can currently
+    /// be reached over the write connection. While it returns `false`, downlink messages are
+    /// held in their queues. If not set, the link is assumed to always be up.
+    pub link_up: Option<Arc<LinkUpFn<Connection>>>,
 }
 
 impl<Connection: Clone + Debug> Debug for CommsControlBlock<Connection> {
@@ -64,6 +81,18 @@ impl<Connection: Clone + Debug> Debug for CommsControlBlock<Connection> {
             "None"
         };
 
+        let auth = if self.auth.is_some() {
+            "Some(Authenticator)"
+        } else {
+            "None"
+        };
+
+        let link_up = if self.link_up.is_some() {
+            "Some(fn)"
+        } else {
+            "None"
+        };
+
         let mut write = vec![];
 
         if !self.write.is_empty() {
@@ -75,7 +104,8 @@ impl<Connection: Clone + Debug> Debug for CommsControlBlock<Connection> {
         write!(
             f,
             "CommsControlBlock {{ read: {}, write: {:?}, read_conn: {:?}, write_conn: {:?},
-            max_num_handlers: {:?}, timeout: {:?}, ip: {:?}, downlink_ports: {:?} }}",
+            max_num_handlers: {:?}, timeout: {:?}, ip: {:?}, downlink_ports: {:?}, mtu: {:?},
+            auth: {}, downlink: {:?}, link_up: {} }}",
             read,
             write,
             self.read_conn,
@@ -84,6 +114,10 @@ impl<Connection: Clone + Debug> Debug for CommsControlBlock<Connection> {
             self.timeout,
             self.ip,
             self.downlink_ports,
+            self.mtu,
+            auth,
+            self.downlink,
+            link_up,
         )
     }
 }
@@ -112,6 +146,14 @@ impl<Connection: Clone> CommsControlBlock<Connection> {
             }
         }
 
+        let downlink = config.downlink.unwrap_or_default();
+        downlink.validate()?;
+
+        let auth = match config.auth {
+            Some(ref auth) => Some(Arc::new(Mutex::new(Authenticator::new(auth)?))),
+            None => None,
+        };
+
         Ok(CommsControlBlock {
             read,
             write,
@@ -121,6 +163,10 @@ impl<Connection: Clone> CommsControlBlock<Connection> {
             timeout: config.timeout.unwrap_or(DEFAULT_TIMEOUT),
             ip: Ipv4Addr::from_str(&config.ip)?,
             downlink_ports: config.downlink_ports,
+            mtu: config.mtu,
+            auth,
+            downlink,
+            link_up: None,
         })
     }
 }
@@ -134,25 +180,77 @@ impl CommsService {
         control: CommsControlBlock<Connection>,
         telem: &Arc<Mutex<CommsTelemetry>>,
     ) -> CommsResult<()> {
+        if let Some(mtu) = control.mtu {
+            if mtu <= Packet::header_size() {
+                return Err(CommsServiceError::ConfigError(format!(
+                    "MTU of {} bytes is too small for the packet header",
+                    mtu
+                ))
+                .into());
+            }
+        }
+
+        // Sequence counters are shared by everything which writes to the gateway
+        let counters = Arc::new(Mutex::new(SequenceCounters::default()));
+
+        // For each provided `write()` function, spawn a writer thread which sends
+        // queued messages to the gateway.
+        let mut queues = vec![];
+        for (index, write) in control.write.iter().enumerate() {
+            let limits = control.downlink.queue_limits();
+            let queue = match control.downlink.store_path {
+                Some(ref path) => {
+                    let queue =
+                        DownlinkQueue::open(limits, &Path::new(path).join(index.to_string()))?;
+                    if !queue.is_empty() {
+                        log_telemetry(telem, &TelemType::Restored(queue.len()))?;
+                        info!("Restored {} stored downlink messages", queue.len());
+                    }
+                    queue
+                }
+                None => DownlinkQueue::new(limits),
+            };
+            let queue = Arc::new(queue);
+            queues.push(queue.clone());
+
+            let telem_ref = telem.clone();
+            let control_ref = control.clone();
+            let write_ref = write.clone();
+            let counters_ref = counters.clone();
+            thread::spawn(move || {
+                downlink_writer::<Connection, Packet>(
+                    &telem_ref,
+                    &queue,
+                    &control_ref,
+                    &write_ref,
+                    &counters_ref,
+                );
+            });
+        }
+
         // If desired, spawn a read thread
         if control.read.is_some() {
             let telem_ref = telem.clone();
             let control_ref = control.clone();
-            thread::spawn(move || read_thread::<Connection, Packet>(control_ref, &telem_ref));
+            let queue_ref = queues[0].clone();
+            thread::spawn(move || {
+                read_thread::<Connection, Packet>(control_ref, &telem_ref, &queue_ref)
+            });
         }
 
-        // For each provided `write()` function, spawn a downlink endpoint thread.
+        // For each downlink port, spawn a downlink endpoint thread which feeds the
+        // matching writer.
         if let Some(ports) = control.downlink_ports {
-            for (_, (port, write)) in ports.iter().zip(control.write.iter()).enumerate() {
+            for (port, queue) in ports.iter().zip(queues.iter()) {
                 let telem_ref = telem.clone();
                 let port_ref = *port;
-                let conn_ref = control.write_conn.clone();
-                let write_ref = write.clone();
+                let queue_ref = queue.clone();
                 let ip = control.ip;
+                let priority = control
+                    .downlink
+                    .priority(port_ref, u16::from(PayloadType::UDP));
                 thread::spawn(move || {
-                    downlink_endpoint::<Connection, Packet>(
-                        &telem_ref, port_ref, conn_ref, &write_ref, ip,
-                    );
+                    downlink_endpoint::<Packet>(&telem_ref, port_ref, &queue_ref, ip, priority);
                 });
             }
         }
@@ -166,6 +264,7 @@ impl CommsService {
 fn read_thread<Connection: Clone + Send + 'static, Packet: LinkPacket + Send + 'static>(
     comms: CommsControlBlock<Connection>,
     data: &Arc<Mutex<CommsTelemetry>>,
+    queue: &Arc<DownlinkQueue>,
 ) {
     // Take reader from control block.
     let read = comms.read.unwrap();
@@ -173,6 +272,10 @@ fn read_thread<Connection: Clone + Send + 'static, Packet: LinkPacket + Send + '
     // Initiate counter for handlers
     let num_handlers: Arc<Mutex<u16>> = Arc::new(Mutex::new(0));
 
+    // Uplink sequence tracking and reassembly state
+    let mut tracker = SequenceTracker::default();
+    let mut reassembler = Reassembler::default();
+
     loop {
         // Read bytes from the radio.
         let bytes = match (read)(&comms.read_conn.clone()) {
@@ -183,6 +286,28 @@ fn read_thread<Connection: Clone + Send + 'static, Packet: LinkPacket + Send + '
             }
         };
 
+        // Check the authentication trailer and strip it from the frame.
+        let bytes = match comms.auth {
+            Some(ref auth) => {
+                let result = match auth.lock() {
+                    Ok(mut auth) => auth.verify(&bytes),
+                    Err(_) => Err(CommsServiceError::MutexPoisoned.into()),
+                };
+
+                match result {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        log_telemetry(data, &TelemType::UpFailed).unwrap();
+                        log_telemetry(data, &TelemType::AuthFailed).unwrap();
+                        log_error(data, e.to_string()).unwrap();
+                        error!("Packet failed authentication: {}", e);
+                        continue;
+                    }
+                }
+            }
+            None => bytes,
+        };
+
         // Create a link packet from the received information.
         let packet = match Packet::parse(&bytes) {
             Ok(packet) => packet,
@@ -202,6 +327,32 @@ fn read_thread<Connection: Clone + Send + 'static, Packet: LinkPacket + Send + '
             continue;
         }
 
+        // Check for dropped or reordered packets
+        let apid = u16::from(packet.payload_type());
+        match tracker.check(apid, packet.sequence_count()) {
+            SequenceCheck::Gap(count) => {
+                log_telemetry(data, &TelemType::SequenceGap(count)).unwrap();
+                warn!("{} uplink packet(s) missing for APID {}", count, apid);
+            }
+            SequenceCheck::OutOfOrder => {
+                log_telemetry(data, &TelemType::OutOfOrder).unwrap();
+                warn!("Uplink packet received out of order for APID {}", apid);
+            }
+            SequenceCheck::InOrder | SequenceCheck::Duplicate => {}
+        }
+
+        // Wait until all the segments of a segmented packet have been received
+        let packet = match reassembler.push(packet) {
+            Ok(Some(packet)) => packet,
+            Ok(None) => continue,
+            Err(e) => {
+                log_telemetry(data, &TelemType::ReassemblyFailed).unwrap();
+                log_error(data, e.to_string()).unwrap();
+                error!("Failed to reassemble packet: {}", e);
+                continue;
+            }
+        };
+
         // Update number of packets up.
         log_telemetry(data, &TelemType::Up).unwrap();
         info!("Packet successfully uplinked");
@@ -244,24 +395,31 @@ fn read_thread<Connection: Clone + Send + 'static, Packet: LinkPacket + Send + '
                 }
 
                 // Spawn new message handler.
-                let conn_ref = comms.write_conn.clone();
-                let write_ref = comms.write[0].clone();
+                let queue_ref = queue.clone();
                 let data_ref = data.clone();
                 let sat_ref = comms.ip;
                 let time_ref = comms.timeout;
+                let command_id = packet.command_id();
+                let priority = comms
+                    .downlink
+                    .priority(packet.destination(), u16::from(PayloadType::GraphQL));
                 let num_handlers_ref = num_handlers.clone();
                 thread::spawn(move || {
-                    let res =
-                        handle_graphql_request(conn_ref, &write_ref, packet, time_ref, sat_ref);
+                    let res = handle_graphql_request(packet, time_ref, sat_ref);
 
                     if let Ok(mut num_handlers) = num_handlers_ref.lock() {
                         *num_handlers -= 1;
                     }
 
                     match res {
-                        Ok(_) => {
-                            log_telemetry(&data_ref, &TelemType::Down).unwrap();
-                            info!("GraphQL Packet successfully downlinked");
+                        Ok(payload) => {
+                            let message = QueuedMessage {
+                                priority,
+                                command_id,
+                                apid: u16::from(PayloadType::GraphQL),
+                                payload,
+                            };
+                            queue_message(&data_ref, &queue_ref, message);
                         }
                         Err(e) => {
                             log_telemetry(&data_ref, &TelemType::DownFailed).unwrap();
@@ -276,15 +434,13 @@ fn read_thread<Connection: Clone + Send + 'static, Packet: LinkPacket + Send + '
 }
 
 // This thread sends a query/mutation to its intended destination and waits for a response.
-// The thread then writes the response to the gateway.
+// The response is then returned so that it can be queued for downlink.
 #[allow(clippy::boxed_local)]
-fn handle_graphql_request<Connection: Clone, Packet: LinkPacket>(
-    write_conn: Connection,
-    write: &Arc<WriteFn<Connection>>,
+fn handle_graphql_request<Packet: LinkPacket>(
     message: Box<Packet>,
     timeout: u64,
     sat_ip: Ipv4Addr,
-) -> Result<(), String> {
+) -> Result<Vec<u8>, String> {
     let payload = message.payload().to_vec();
 
     let client = reqwest::Client::builder()
@@ -302,13 +458,151 @@ fn handle_graphql_request<Connection: Clone, Packet: LinkPacket>(
     let buf = res.text().unwrap_or_else(|_| "".to_owned());
     let buf = buf.as_bytes();
 
-    // Take received message and wrap it in a LinkPacket
-    let packet = Packet::build(message.command_id(), PayloadType::GraphQL, 0, &buf[0..size])
-        .and_then(|packet| packet.to_bytes())
-        .map_err(|e| e.to_string())?;
+    Ok(buf[0..size].to_vec())
+}
 
-    // Write packet to the gateway
-    write(&write_conn, &packet).map_err(|e| e.to_string())
+// Adds a message to a downlink queue and updates the queue telemetry.
+fn queue_message(data: &Arc<Mutex<CommsTelemetry>>, queue: &DownlinkQueue, message: QueuedMessage) {
+    match queue.push(message) {
+        Ok(PushResult::Queued) => log_telemetry(data, &TelemType::Queued).unwrap(),
+        Ok(PushResult::Evicted(count)) => {
+            log_telemetry(data, &TelemType::Queued).unwrap();
+            log_telemetry(data, &TelemType::Evicted(count)).unwrap();
+            warn!("Downlink queue full. Dropped {} older messages", count);
+        }
+        Ok(PushResult::Dropped) => {
+            log_telemetry(data, &TelemType::QueueDropped).unwrap();
+            log_error(data, CommsServiceError::QueueFull.to_string()).unwrap();
+            error!("Downlink queue full. Message dropped");
+        }
+        Err(e) => {
+            log_telemetry(data, &TelemType::QueueDropped).unwrap();
+            log_error(data, e.to_string()).unwrap();
+            error!("Failed to store downlink message: {}", e);
+        }
+    }
+}
+
+// Wraps a payload in one or more LinkPackets and writes them to the gateway.
+// Each gateway has a single writer thread, so segments of different payloads are never
+// interleaved.
+fn write_frames<Connection: Clone, Packet: LinkPacket>(
+    write_conn: &Connection,
+    write: &Arc<WriteFn<Connection>>,
+    counters: &Arc<Mutex<SequenceCounters>>,
+    message: &QueuedMessage,
+    mtu: Option<usize>,
+    bucket: &mut Option<TokenBucket>,
+) -> CommsResult<()> {
+    let frames = {
+        let mut counters = counters
+            .lock()
+            .map_err(|_| CommsServiceError::MutexPoisoned)?;
+
+        // Setting port to 0 because we don't know the ground port...
+        // That is known by the ground comms service
+        build_frames::<Packet>(
+            &mut counters,
+            message.command_id,
+            message.apid,
+            0,
+            &message.payload,
+            mtu,
+        )?
+    };
+
+    for frame in frames {
+        if let Some(ref mut bucket) = bucket {
+            bucket.take(frame.len());
+        }
+        write(write_conn, &frame)?;
+    }
+
+    Ok(())
+}
+
+// This thread sends queued messages to the gateway, in the configured order,
+// and updates telemetry. While the link is down, messages are left in the queue.
+fn downlink_writer<Connection: Clone, Packet: LinkPacket>(
+    data: &Arc<Mutex<CommsTelemetry>>,
+    queue: &DownlinkQueue,
+    control: &CommsControlBlock<Connection>,
+    write: &Arc<WriteFn<Connection>>,
+    counters: &Arc<Mutex<SequenceCounters>>,
+) {
+    let mut bucket = control.downlink.token_bucket();
+    let poll_interval = control.downlink.link_poll_interval();
+    let max_attempts = control.downlink.max_attempts();
+    // The message at the head of the queue which has failed to be written, and how many times
+    let mut failing: Option<(u64, u32)> = None;
+
+    loop {
+        let (id, message) = queue.peek();
+
+        if let Some(ref link_up) = control.link_up {
+            if !link_up(&control.write_conn) {
+                thread::sleep(poll_interval);
+                continue;
+            }
+        }
+
+        let result = write_frames::<Connection, Packet>(
+            &control.write_conn,
+            write,
+            counters,
+            &message,
+            control.mtu,
+            &mut bucket,
+        );
+
+        match result {
+            Ok(_) => {
+                failing = None;
+
+                // The message is only taken out of the queue (and its store) once it has been
+                // written, so that it isn't lost if the service stops part way through.
+                // It may already have been evicted in the meantime.
+                if queue.remove(id) {
+                    log_telemetry(data, &TelemType::Dequeued).unwrap();
+                }
+
+                log_telemetry(data, &TelemType::Down).unwrap();
+                info!("Packet successfully downlinked");
+            }
+            Err(e) => {
+                let attempts = match failing {
+                    Some((failed_id, attempts)) if failed_id == id => attempts + 1,
+                    _ => 1,
+                };
+
+                // Each message is only counted as failed once, however many times it's retried
+                if attempts == 1 {
+                    log_telemetry(data, &TelemType::DownFailed).unwrap();
+                    log_error(data, e.to_string()).unwrap();
+                }
+
+                if attempts >= max_attempts {
+                    // The message may never be writable (for example, if it's too large for
+                    // the radio), so give up on it rather than blocking the rest of the queue
+                    error!(
+                        "Packet failed to downlink after {} attempts. Dropping it",
+                        attempts
+                    );
+                    failing = None;
+                    if queue.remove(id) {
+                        log_telemetry(data, &TelemType::Dequeued).unwrap();
+                    }
+                } else {
+                    error!("Packet failed to downlink. Retrying in {:?}", poll_interval);
+                    failing = Some((id, attempts));
+
+                    // Leave the message at the head of the queue and try it again once the
+                    // radio has had a chance to recover
+                    thread::sleep(poll_interval);
+                }
+            }
+        };
+    }
 }
 
 // This function takes a Packet with PayloadType::UDP and sends the payload over a
@@ -326,14 +620,14 @@ fn handle_udp_passthrough<Packet: LinkPacket>(
         .map(|_c| ())
 }
 
-// This thread reads indefinitely from a UDP socket, creating link packets from
-// the UDP packet payload and then writes the link packets to a gateway.
-fn downlink_endpoint<Connection: Clone, Packet: LinkPacket>(
+// This thread reads indefinitely from a UDP socket and queues the UDP packet payloads
+// to be written to a gateway.
+fn downlink_endpoint<Packet: LinkPacket>(
     data: &Arc<Mutex<CommsTelemetry>>,
     port: u16,
-    write_conn: Connection,
-    write: &Arc<WriteFn<Connection>>,
+    queue: &DownlinkQueue,
     sat_ip: Ipv4Addr,
+    priority: u8,
 ) {
     // Bind the downlink endpoint to a UDP socket.
     let socket = match UdpSocket::bind((sat_ip, port)) {
@@ -353,30 +647,15 @@ fn downlink_endpoint<Connection: Clone, Packet: LinkPacket>(
             }
         };
 
-        // Take received message and wrap it in a Link packet.
-        // Setting port to 0 because we don't know the ground port...
-        // That is known by the ground comms service
-        let packet = match Packet::build(0, PayloadType::UDP, 0, &buf[0..size])
-            .and_then(|packet| packet.to_bytes())
-        {
-            Ok(packet) => packet,
-            Err(e) => {
-                log_error(data, e.to_string()).unwrap();
-                continue;
-            }
-        };
+        buf.truncate(size);
 
-        // Write packet to the gateway and update telemetry.
-        match write(&write_conn.clone(), &packet) {
-            Ok(_) => {
-                log_telemetry(data, &TelemType::Down).unwrap();
-                info!("Packet successfully downlinked");
-            }
-            Err(e) => {
-                log_telemetry(data, &TelemType::DownFailed).unwrap();
-                log_error(data, e.to_string()).unwrap();
-                error!("Packet failed to downlink");
-            }
+        // Queue the received message to be wrapped in a Link packet and sent to the gateway
+        let message = QueuedMessage {
+            priority,
+            command_id: 0,
+            apid: u16::from(PayloadType::UDP),
+            payload: buf,
         };
+        queue_message(data, queue, message);
     }
 }
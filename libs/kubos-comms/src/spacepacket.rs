@@ -16,11 +16,16 @@
 
 //! Packet Definition for SpacePacket
 
-use crate::packet::{LinkPacket, PayloadType};
+use crate::packet::{LinkPacket, PayloadType, SequenceFlag};
 use crate::CommsResult;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::io::Cursor;
 
+// Primary header (6 bytes) + secondary header (10 bytes)
+const HEADER_SIZE: usize = 16;
+// The sequence count field is 14 bits wide
+const SEQUENCE_COUNT_MASK: u16 = 0x3FFF;
+
 #[derive(Eq, Debug, PartialEq)]
 struct PrimaryHeader {
     /// Packet Version Number - 3 bits
@@ -68,7 +73,7 @@ impl LinkPacket for SpacePacket {
                 packet_type: 0,
                 sec_header_flag: 0,
                 app_proc_id: u16::from(payload_type),
-                sequence_flags: 0,
+                sequence_flags: u8::from(SequenceFlag::Unsegmented),
                 sequence_count: 0,
                 data_length: (payload.len() + 10) as u16,
             },
@@ -154,6 +159,23 @@ impl LinkPacket for SpacePacket {
     fn destination(&self) -> u16 {
         self.secondary_header.destination_port
     }
+
+    fn header_size() -> usize {
+        HEADER_SIZE
+    }
+
+    fn sequence_flags(&self) -> SequenceFlag {
+        SequenceFlag::from(self.primary_header.sequence_flags)
+    }
+
+    fn sequence_count(&self) -> u16 {
+        self.primary_header.sequence_count
+    }
+
+    fn set_sequence(&mut self, flags: SequenceFlag, count: u16) {
+        self.primary_header.sequence_flags = u8::from(flags);
+        self.primary_header.sequence_count = count & SEQUENCE_COUNT_MASK;
+    }
 }
 
 #[cfg(test)]
@@ -181,4 +203,33 @@ mod tests {
         let parsed = SpacePacket::parse(raw).unwrap();
         dbg!(parsed);
     }
+
+    #[test]
+    fn build_is_unsegmented() {
+        let packet = SpacePacket::build(1, PayloadType::UDP, 15001, &[1, 2, 3]).unwrap();
+
+        assert_eq!(packet.sequence_flags(), SequenceFlag::Unsegmented);
+        assert_eq!(packet.sequence_count(), 0);
+    }
+
+    #[test]
+    fn sequence_round_trip() {
+        let mut packet = SpacePacket::build(1, PayloadType::UDP, 15001, &[1, 2, 3]).unwrap();
+        packet.set_sequence(SequenceFlag::Last, 0x3FFE);
+
+        let raw = packet.to_bytes().unwrap();
+        assert_eq!(&raw[2..4], &[0xBF, 0xFE]);
+
+        let parsed = SpacePacket::parse(&raw).unwrap();
+        assert_eq!(parsed.sequence_flags(), SequenceFlag::Last);
+        assert_eq!(parsed.sequence_count(), 0x3FFE);
+    }
+
+    #[test]
+    fn sequence_count_wraps() {
+        let mut packet = SpacePacket::build(1, PayloadType::UDP, 15001, &[1, 2, 3]).unwrap();
+        packet.set_sequence(SequenceFlag::First, 0x4001);
+
+        assert_eq!(packet.sequence_count(), 1);
+    }
 }
@@ -32,6 +32,18 @@ pub struct CommsTelemetry {
     pub packets_up: i32,
     /// Number of packets successfully downlinked.
     pub packets_down: i32,
+    /// Number of uplink packets skipped, according to their sequence counts.
+    pub sequence_gaps_up: i32,
+    /// Number of uplink packets received out of order.
+    pub out_of_order_packets_up: i32,
+    /// Number of segmented uplink payloads which could not be reassembled.
+    pub reassembly_errors: i32,
+    /// Number of uplink packets which failed authentication.
+    pub auth_failures: i32,
+    /// Number of messages waiting to be downlinked.
+    pub downlink_queue_depth: i32,
+    /// Number of messages dropped because a downlink queue was full.
+    pub downlink_queue_drops: i32,
 }
 
 /// Enum used to differentiate types of telemetry collected by the communication service.
@@ -44,6 +56,24 @@ pub enum TelemType {
     Up,
     /// Packets up that failed
     UpFailed,
+    /// Uplink packets skipped in the sequence
+    SequenceGap(u16),
+    /// Uplink packets received out of order
+    OutOfOrder,
+    /// Segmented uplink payloads that failed to reassemble
+    ReassemblyFailed,
+    /// Uplink packets that failed authentication
+    AuthFailed,
+    /// Message added to a downlink queue
+    Queued,
+    /// Message taken from a downlink queue
+    Dequeued,
+    /// Message dropped from a full downlink queue
+    QueueDropped,
+    /// Queued messages dropped to make room for a new message
+    Evicted(usize),
+    /// Messages loaded from a downlink queue's store at startup
+    Restored(usize),
 }
 
 // Function used to obtain a mutex lock and update communication service errors.
@@ -66,6 +96,18 @@ pub fn log_telemetry(data: &Arc<Mutex<CommsTelemetry>>, telem_type: &TelemType)
                 TelemType::DownFailed => telem.failed_packets_down += 1,
                 TelemType::Up => telem.packets_up += 1,
                 TelemType::UpFailed => telem.failed_packets_up += 1,
+                TelemType::SequenceGap(count) => telem.sequence_gaps_up += i32::from(*count),
+                TelemType::OutOfOrder => telem.out_of_order_packets_up += 1,
+                TelemType::ReassemblyFailed => telem.reassembly_errors += 1,
+                TelemType::AuthFailed => telem.auth_failures += 1,
+                TelemType::Queued => telem.downlink_queue_depth += 1,
+                TelemType::Dequeued => telem.downlink_queue_depth -= 1,
+                TelemType::QueueDropped => telem.downlink_queue_drops += 1,
+                TelemType::Evicted(count) => {
+                    telem.downlink_queue_depth -= *count as i32;
+                    telem.downlink_queue_drops += *count as i32;
+                }
+                TelemType::Restored(count) => telem.downlink_queue_depth += *count as i32,
             };
             Ok(())
         }
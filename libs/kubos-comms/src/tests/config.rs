@@ -16,8 +16,10 @@
 
 use crate::config::*;
 use crate::errors::*;
+use crate::queue::{DrainOrder, EvictionPolicy};
 use crate::service::*;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[allow(clippy::trivially_copy_pass_by_ref)]
 fn test_read(_read_conn: &u8) -> CommsResult<Vec<u8>> {
@@ -109,3 +111,192 @@ fn config_write_downlink_mismatch() {
         "Config error: There must be a unique write function for each downlink port"
     );
 }
+
+#[test]
+fn config_auth_good() {
+    let config = kubos_system::Config::new_from_str(
+        "comms-service",
+        r#"
+        [comms-service.comms]
+        ip = "0.0.0.0"
+
+        [comms-service.comms.auth]
+        key = "000102030405060708090a0b0c0d0e0f"
+        "#,
+    )
+    .unwrap();
+
+    let config = CommsConfig::new(config).unwrap();
+
+    let result = CommsControlBlock::new(
+        Some(Arc::new(test_read)),
+        vec![Arc::new(test_write)],
+        1,
+        2,
+        config,
+    );
+
+    assert!(result.unwrap().auth.is_some());
+}
+
+#[test]
+fn config_auth_bad_key() {
+    let config = kubos_system::Config::new_from_str(
+        "comms-service",
+        r#"
+        [comms-service.comms]
+        ip = "0.0.0.0"
+
+        [comms-service.comms.auth]
+        key = "not hex"
+        "#,
+    )
+    .unwrap();
+
+    let config = CommsConfig::new(config).unwrap();
+
+    let result = CommsControlBlock::new(
+        Some(Arc::new(test_read)),
+        vec![Arc::new(test_write)],
+        1,
+        2,
+        config,
+    );
+
+    assert_eq!(
+        format!("{}", result.unwrap_err()),
+        "Config error: Authentication key must be a non-empty hex string"
+    );
+}
+
+#[test]
+fn config_downlink_priorities() {
+    let config = kubos_system::Config::new_from_str(
+        "comms-service",
+        r#"
+        [comms-service.comms]
+        ip = "0.0.0.0"
+
+        [comms-service.comms.downlink]
+        rate_limit = 1200
+        default_priority = 1
+
+        [[comms-service.comms.downlink.priorities]]
+        port = 13011
+        priority = 10
+
+        [[comms-service.comms.downlink.priorities]]
+        payload_type = "graphql"
+        priority = 5
+        "#,
+    )
+    .unwrap();
+
+    let config = CommsConfig::new(config).unwrap();
+
+    let controls = CommsControlBlock::new(
+        Some(Arc::new(test_read)),
+        vec![Arc::new(test_write)],
+        1,
+        2,
+        config,
+    )
+    .unwrap();
+
+    // Port rules take precedence over payload type rules
+    assert_eq!(controls.downlink.priority(13011, 0), 10);
+    assert_eq!(controls.downlink.priority(8000, 0), 5);
+    assert_eq!(controls.downlink.priority(8000, 1), 1);
+    assert_eq!(controls.downlink.queue_size(), DEFAULT_QUEUE_SIZE);
+}
+
+#[test]
+fn config_downlink_bad_rule() {
+    let config = kubos_system::Config::new_from_str(
+        "comms-service",
+        r#"
+        [comms-service.comms]
+        ip = "0.0.0.0"
+
+        [[comms-service.comms.downlink.priorities]]
+        payload_type = "tcp"
+        priority = 5
+        "#,
+    )
+    .unwrap();
+
+    let config = CommsConfig::new(config).unwrap();
+
+    let result = CommsControlBlock::new(
+        Some(Arc::new(test_read)),
+        vec![Arc::new(test_write)],
+        1,
+        2,
+        config,
+    );
+
+    assert_eq!(
+        format!("{}", result.unwrap_err()),
+        "Config error: Unknown payload type in downlink priority rule: tcp"
+    );
+}
+
+#[test]
+fn config_downlink_store() {
+    let config = kubos_system::Config::new_from_str(
+        "comms-service",
+        r#"
+        [comms-service.comms]
+        ip = "0.0.0.0"
+
+        [comms-service.comms.downlink]
+        queue_size = 500
+        max_bytes = 65536
+        eviction = "oldest"
+        order = "fifo"
+        store_path = "/home/system/kubos/downlink"
+        "#,
+    )
+    .unwrap();
+
+    let config = CommsConfig::new(config).unwrap();
+
+    let controls = CommsControlBlock::new(
+        Some(Arc::new(test_read)),
+        vec![Arc::new(test_write)],
+        1,
+        2,
+        config,
+    )
+    .unwrap();
+
+    assert_eq!(controls.downlink.eviction, Some(EvictionPolicy::Oldest));
+    assert_eq!(controls.downlink.order, Some(DrainOrder::Fifo));
+    assert_eq!(
+        controls.downlink.store_path,
+        Some("/home/system/kubos/downlink".to_owned())
+    );
+    assert_eq!(
+        controls.downlink.link_poll_interval(),
+        Duration::from_millis(DEFAULT_LINK_POLL_INTERVAL)
+    );
+    assert_eq!(controls.downlink.max_attempts(), DEFAULT_MAX_ATTEMPTS);
+    assert!(controls.link_up.is_none());
+}
+
+#[test]
+fn config_downlink_bad_eviction() {
+    let config = kubos_system::Config::new_from_str(
+        "comms-service",
+        r#"
+        [comms-service.comms]
+        ip = "0.0.0.0"
+
+        [comms-service.comms.downlink]
+        eviction = "random"
+        "#,
+    )
+    .unwrap();
+
+    assert!(CommsConfig::new(config).is_err());
+}
@@ -21,6 +21,7 @@ mod util;
 
 use kubos_comms::*;
 use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
@@ -73,3 +74,207 @@ fn downlink_to_ground() {
     assert_eq!(packet.destination(), 0);
     assert_eq!(packet.payload().to_vec(), payload);
 }
+
+// Testing that payloads larger than the configured MTU
+// are split into sequenced segments
+#[test]
+fn downlink_segmented() {
+    let sat_ip = "127.0.0.1";
+    let downlink_port = 16003;
+    let mut config = comms_config(sat_ip, downlink_port);
+    config.mtu = Some(36);
+    let mock_comms = Arc::new(Mutex::new(MockComms::new()));
+    let payload: Vec<u8> = (0..50).collect();
+
+    // Control block to configure communication service.
+    let controls = CommsControlBlock::new(
+        Some(Arc::new(read)),
+        vec![Arc::new(write)],
+        mock_comms.clone(),
+        mock_comms.clone(),
+        config,
+    )
+    .unwrap();
+
+    // Initialize new `CommsTelemetry` object.
+    let telem = Arc::new(Mutex::new(CommsTelemetry::default()));
+
+    // Start communication service.
+    CommsService::start::<Arc<Mutex<MockComms>>, SpacePacket>(controls, &telem).unwrap();
+
+    let downlink_writer = UdpSocket::bind((sat_ip, 0)).unwrap();
+
+    // Let the wheels turn
+    thread::sleep(Duration::from_millis(10));
+
+    // Send packet to comm service's downlink port
+    downlink_writer
+        .send_to(&payload, (sat_ip, downlink_port))
+        .unwrap();
+
+    // Let the wheels turn
+    thread::sleep(Duration::from_millis(10));
+
+    // Pretend to be the ground and read the
+    // segments which were written to the radio
+    let mut packets = vec![];
+    while let Some(data) = mock_comms.lock().unwrap().pop_write() {
+        packets.insert(0, SpacePacket::parse(&data).unwrap());
+    }
+
+    assert_eq!(packets.len(), 3);
+    assert_eq!(packets[0].sequence_flags(), SequenceFlag::First);
+    assert_eq!(packets[1].sequence_flags(), SequenceFlag::Continuation);
+    assert_eq!(packets[2].sequence_flags(), SequenceFlag::Last);
+
+    let received: Vec<u8> = packets.iter().flat_map(|packet| packet.payload()).collect();
+    assert_eq!(received, payload);
+}
+
+// Testing that a message which fails to be written stays
+// queued and is sent when the write is retried
+#[test]
+fn downlink_retry_failed_write() {
+    let sat_ip = "127.0.0.1";
+    let downlink_port = 16004;
+    let mut config = comms_config(sat_ip, downlink_port);
+    config.downlink = Some(DownlinkConfig {
+        link_poll_interval: Some(20),
+        ..Default::default()
+    });
+    let mock_comms = Arc::new(Mutex::new(MockComms::new()));
+    let payload = vec![5, 4, 3, 2];
+
+    // The radio rejects the first write
+    let failed = Arc::new(AtomicBool::new(false));
+    let write_once_failed = {
+        let failed = failed.clone();
+        move |socket: &Arc<Mutex<MockComms>>, data: &[u8]| {
+            if failed.swap(true, Ordering::SeqCst) {
+                write(socket, data)
+            } else {
+                bail!("Radio not ready");
+            }
+        }
+    };
+
+    // Control block to configure communication service.
+    let controls = CommsControlBlock::new(
+        Some(Arc::new(read)),
+        vec![Arc::new(write_once_failed)],
+        mock_comms.clone(),
+        mock_comms.clone(),
+        config,
+    )
+    .unwrap();
+
+    // Initialize new `CommsTelemetry` object.
+    let telem = Arc::new(Mutex::new(CommsTelemetry::default()));
+
+    // Start communication service.
+    CommsService::start::<Arc<Mutex<MockComms>>, SpacePacket>(controls, &telem).unwrap();
+
+    let downlink_writer = UdpSocket::bind((sat_ip, 0)).unwrap();
+
+    // Let the wheels turn
+    thread::sleep(Duration::from_millis(10));
+
+    // Send packet to comm service's downlink port
+    downlink_writer
+        .send_to(&payload, (sat_ip, downlink_port))
+        .unwrap();
+
+    // Give the writer time to fail, back off and try again
+    thread::sleep(Duration::from_millis(100));
+
+    assert!(failed.load(Ordering::SeqCst));
+
+    // Pretend to be the ground and read the
+    // packet which was written to the radio
+    let data = mock_comms.lock().unwrap().pop_write().unwrap();
+    let packet = SpacePacket::parse(&data).unwrap();
+
+    assert_eq!(packet.payload().to_vec(), payload);
+    assert!(mock_comms.lock().unwrap().pop_write().is_none());
+
+    let telem = telem.lock().unwrap();
+    assert_eq!(telem.failed_packets_down, 1);
+    assert_eq!(telem.packets_down, 1);
+}
+
+// Testing that a message which can never be written is dropped after
+// `max_attempts` tries, so that the messages behind it still get sent
+#[test]
+fn downlink_drop_unwritable() {
+    let sat_ip = "127.0.0.1";
+    let downlink_port = 16006;
+    let mut config = comms_config(sat_ip, downlink_port);
+    config.downlink = Some(DownlinkConfig {
+        link_poll_interval: Some(20),
+        max_attempts: Some(3),
+        order: Some(DrainOrder::Fifo),
+        ..Default::default()
+    });
+    let mock_comms = Arc::new(Mutex::new(MockComms::new()));
+    let oversized = vec![9; 8];
+    let payload = vec![5, 4, 3, 2];
+
+    // The radio rejects anything carrying more than four bytes of payload
+    let attempts = Arc::new(Mutex::new(0));
+    let write_small = {
+        let attempts = attempts.clone();
+        move |socket: &Arc<Mutex<MockComms>>, data: &[u8]| {
+            *attempts.lock().unwrap() += 1;
+            if SpacePacket::parse(data).unwrap().payload().len() > 4 {
+                bail!("Packet too large");
+            }
+            write(socket, data)
+        }
+    };
+
+    // Control block to configure communication service.
+    let controls = CommsControlBlock::new(
+        Some(Arc::new(read)),
+        vec![Arc::new(write_small)],
+        mock_comms.clone(),
+        mock_comms.clone(),
+        config,
+    )
+    .unwrap();
+
+    // Initialize new `CommsTelemetry` object.
+    let telem = Arc::new(Mutex::new(CommsTelemetry::default()));
+
+    // Start communication service.
+    CommsService::start::<Arc<Mutex<MockComms>>, SpacePacket>(controls, &telem).unwrap();
+
+    let downlink_writer = UdpSocket::bind((sat_ip, 0)).unwrap();
+
+    // Let the wheels turn
+    thread::sleep(Duration::from_millis(10));
+
+    // Send both packets to comm service's downlink port
+    downlink_writer
+        .send_to(&oversized, (sat_ip, downlink_port))
+        .unwrap();
+    thread::sleep(Duration::from_millis(5));
+    downlink_writer
+        .send_to(&payload, (sat_ip, downlink_port))
+        .unwrap();
+
+    // Give the writer time to give up on the first packet
+    thread::sleep(Duration::from_millis(200));
+
+    // Only the second packet reaches the ground
+    let data = mock_comms.lock().unwrap().pop_write().unwrap();
+    let packet = SpacePacket::parse(&data).unwrap();
+
+    assert_eq!(packet.payload().to_vec(), payload);
+    assert!(mock_comms.lock().unwrap().pop_write().is_none());
+    assert_eq!(*attempts.lock().unwrap(), 4);
+
+    let telem = telem.lock().unwrap();
+    assert_eq!(telem.failed_packets_down, 1);
+    assert_eq!(telem.packets_down, 1);
+    assert_eq!(telem.downlink_queue_depth, 0);
+}
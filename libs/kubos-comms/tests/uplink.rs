@@ -279,3 +279,53 @@ fn uplink_udp_passthrough() {
 
     assert_eq!(recv_buffer, payload);
 }
+
+// Tests that a corrupted packet is rejected before reaching its destination
+#[test]
+fn uplink_bad_crc() {
+    let sat_ip = "127.0.0.1";
+    let downlink_port = 20002;
+    let service_port = 20006;
+    let config = comms_config(sat_ip, downlink_port);
+    let mock_comms = Arc::new(Mutex::new(MockComms::new()));
+    let payload = vec![0, 1, 4, 5];
+
+    // Control block to configure communication service.
+    let controls = CommsControlBlock::new(
+        Some(Arc::new(read)),
+        vec![Arc::new(write)],
+        mock_comms.clone(),
+        mock_comms.clone(),
+        config,
+    )
+    .unwrap();
+
+    // Initialize new `CommsTelemetry` object.
+    let telem = Arc::new(Mutex::new(CommsTelemetry::default()));
+
+    let ground_packet =
+        CrcPacket::<SpacePacket>::build(1, PayloadType::UDP, service_port, &payload).unwrap();
+
+    // Flip a bit in the payload
+    let mut raw = ground_packet.to_bytes().unwrap();
+    raw[16] ^= 0x01;
+
+    let downlink_reader = UdpSocket::bind((sat_ip, service_port)).unwrap();
+    downlink_reader
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+
+    // Start communication service.
+    CommsService::start::<Arc<Mutex<MockComms>>, CrcPacket<SpacePacket>>(controls, &telem).unwrap();
+
+    // Pretend to be the ground and provide a packet
+    // for the comms service to read from the radio
+    mock_comms.lock().unwrap().push_read(&raw);
+
+    let mut recv_buffer = vec![0; 4];
+
+    // Nothing should be passed through to the service
+    assert!(downlink_reader.recv(&mut recv_buffer).is_err());
+    assert_eq!(telem.lock().unwrap().failed_packets_up, 1);
+    assert_eq!(telem.lock().unwrap().packets_up, 0);
+}
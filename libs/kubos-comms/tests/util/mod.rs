@@ -97,6 +97,9 @@ pub fn comms_config(sat_ip: &str, downlink_port: u16) -> CommsConfig {
         downlink_ports: Some(vec![downlink_port]),
         timeout: Some(1000),
         ip: sat_ip.to_owned(),
+        mtu: None,
+        auth: None,
+        downlink: None,
     }
 }
 
@@ -0,0 +1,389 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Compact binary format for telemetry exported by the telemetry database service
+//!
+//! An export is a single CBOR array:
+//!
+//! ```text
+//! [version, [[subsystem, parameter], ...], [[id, delta, value], ...]]
+//! ```
+//!
+//! Each subsystem/parameter pair is only stored once, in the dictionary, and entries refer to
+//! it by its position. Each entry's timestamp is stored in milliseconds, as the difference from
+//! the previous entry's timestamp (or from zero, for the first entry), so timestamps are only
+//! kept to millisecond precision. Values are stored as native CBOR integers, floats, text
+//! strings, or byte strings, according to their type.
+//!
+//! # Examples
+//!
+//! ```
+//! use telemetry_export::*;
+//!
+//! let entries = vec![
+//!     Entry {
+//!         timestamp: 1000.5,
+//!         subsystem: "eps".to_owned(),
+//!         parameter: "voltage".to_owned(),
+//!         value: Value::Real(3.3),
+//!     },
+//!     Entry {
+//!         timestamp: 1001.5,
+//!         subsystem: "eps".to_owned(),
+//!         parameter: "voltage".to_owned(),
+//!         value: Value::Real(3.4),
+//!     },
+//! ];
+//!
+//! let export = encode(&entries).unwrap();
+//! assert_eq!(decode(&export).unwrap(), entries);
+//! ```
+//!
+
+#![deny(missing_docs)]
+#![deny(warnings)]
+
+use failure::Fail;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_cbor::{de, ser};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Version of the export format written by this crate
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Errors which occur when encoding or decoding an export
+#[derive(Debug, Fail)]
+pub enum ExportError {
+    /// The export couldn't be serialized
+    #[fail(display = "Failed to encode export: {}", _0)]
+    EncodeError(String),
+    /// The export couldn't be parsed
+    #[fail(display = "Failed to decode export: {}", _0)]
+    DecodeError(String),
+    /// The export was written with an unknown version of the format
+    #[fail(display = "Unsupported export format version: {}", _0)]
+    UnsupportedVersion(u8),
+}
+
+/// A typed telemetry value
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A 64-bit signed integer
+    Integer(i64),
+    /// A 64-bit floating point number
+    Real(f64),
+    /// A string
+    Text(String),
+    /// Binary data
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    /// Name of the value's type, as used by the telemetry database service
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "integer",
+            Value::Real(_) => "real",
+            Value::Text(_) => "text",
+            Value::Blob(_) => "blob",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    // Matches the string form of values returned by the telemetry database service.
+    // Blobs are written as lowercase hex
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Real(value) => write!(f, "{}", value),
+            Value::Text(value) => write!(f, "{}", value),
+            Value::Blob(value) => {
+                for byte in value {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A single telemetry entry
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// Timestamp, in fractional seconds
+    pub timestamp: f64,
+    /// Subsystem name
+    pub subsystem: String,
+    /// Parameter name
+    pub parameter: String,
+    /// Typed value
+    pub value: Value,
+}
+
+// Entries are serialized the same way as the service's JSON output, with the addition of the
+// value's type
+impl Serialize for Entry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entry = serializer.serialize_struct("Entry", 5)?;
+        entry.serialize_field("timestamp", &self.timestamp)?;
+        entry.serialize_field("subsystem", &self.subsystem)?;
+        entry.serialize_field("parameter", &self.parameter)?;
+        entry.serialize_field("value", &self.value.to_string())?;
+        entry.serialize_field("type", self.value.type_name())?;
+        entry.end()
+    }
+}
+
+type Export = (
+    u8,
+    Vec<(String, String)>,
+    Vec<(u32, i64, serde_cbor::Value)>,
+);
+
+fn millis(timestamp: f64) -> i64 {
+    (timestamp * 1000.0).round() as i64
+}
+
+/// Encode a list of telemetry entries, preserving their order
+pub fn encode(entries: &[Entry]) -> Result<Vec<u8>, ExportError> {
+    let mut ids: HashMap<(&str, &str), u32> = HashMap::new();
+    let mut dictionary = vec![];
+    let mut rows = vec![];
+    let mut previous = 0;
+
+    for entry in entries {
+        let next_id = dictionary.len() as u32;
+        let id = *ids
+            .entry((entry.subsystem.as_str(), entry.parameter.as_str()))
+            .or_insert_with(|| {
+                dictionary.push((entry.subsystem.clone(), entry.parameter.clone()));
+                next_id
+            });
+
+        let timestamp = millis(entry.timestamp);
+
+        let value = match &entry.value {
+            Value::Integer(value) if *value >= 0 => serde_cbor::Value::U64(*value as u64),
+            Value::Integer(value) => serde_cbor::Value::I64(*value),
+            Value::Real(value) => serde_cbor::Value::F64(*value),
+            Value::Text(value) => serde_cbor::Value::String(value.clone()),
+            Value::Blob(value) => serde_cbor::Value::Bytes(value.clone()),
+        };
+
+        let delta = timestamp.checked_sub(previous).ok_or_else(|| {
+            ExportError::EncodeError(format!(
+                "Timestamp {} is too far from the previous entry's",
+                entry.timestamp
+            ))
+        })?;
+
+        rows.push((id, delta, value));
+        previous = timestamp;
+    }
+
+    let export: Export = (FORMAT_VERSION, dictionary, rows);
+
+    ser::to_vec(&export).map_err(|err| ExportError::EncodeError(format!("{}", err)))
+}
+
+/// Decode an export back into its list of telemetry entries
+pub fn decode(data: &[u8]) -> Result<Vec<Entry>, ExportError> {
+    let (version, dictionary, rows): Export =
+        de::from_slice(data).map_err(|err| ExportError::DecodeError(format!("{}", err)))?;
+
+    if version != FORMAT_VERSION {
+        return Err(ExportError::UnsupportedVersion(version));
+    }
+
+    let mut entries = Vec::with_capacity(rows.len());
+    let mut timestamp: i64 = 0;
+
+    for (id, delta, value) in rows {
+        let (subsystem, parameter) = dictionary
+            .get(id as usize)
+            .ok_or_else(|| ExportError::DecodeError(format!("Unknown parameter ID {}", id)))?;
+
+        timestamp = timestamp.checked_add(delta).ok_or_else(|| {
+            ExportError::DecodeError(format!(
+                "Timestamp out of range for {}/{}",
+                subsystem, parameter
+            ))
+        })?;
+
+        let value = match value {
+            serde_cbor::Value::U64(value) if value <= i64::MAX as u64 => {
+                Value::Integer(value as i64)
+            }
+            serde_cbor::Value::I64(value) => Value::Integer(value),
+            serde_cbor::Value::F64(value) => Value::Real(value),
+            serde_cbor::Value::String(value) => Value::Text(value),
+            serde_cbor::Value::Bytes(value) => Value::Blob(value),
+            other => {
+                return Err(ExportError::DecodeError(format!(
+                    "Invalid value for {}/{}: {:?}",
+                    subsystem, parameter, other
+                )))
+            }
+        };
+
+        entries.push(Entry {
+            timestamp: timestamp as f64 / 1000.0,
+            subsystem: subsystem.clone(),
+            parameter: parameter.clone(),
+            value,
+        });
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: f64, subsystem: &str, parameter: &str, value: Value) -> Entry {
+        Entry {
+            timestamp,
+            subsystem: subsystem.to_owned(),
+            parameter: parameter.to_owned(),
+            value,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let entries = vec![
+            entry(1004.25, "eps", "voltage", Value::Real(3.6)),
+            entry(1004.0, "mcu", "count", Value::Integer(-12)),
+            entry(1003.0, "eps", "voltage", Value::Real(20.0)),
+            entry(1002.0, "obc", "mode", Value::Text("safe".to_owned())),
+            entry(1001.0, "obc", "status", Value::Blob(vec![0x01, 0xff, 0x3a])),
+            entry(
+                1000.0,
+                "mcu",
+                "count",
+                Value::Integer(i64::from(u32::MAX) + 1),
+            ),
+        ];
+
+        let export = encode(&entries).unwrap();
+        assert_eq!(decode(&export).unwrap(), entries);
+    }
+
+    #[test]
+    fn empty() {
+        let export = encode(&[]).unwrap();
+        assert_eq!(decode(&export).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn smaller_than_json() {
+        let entries: Vec<Entry> = (0..100)
+            .map(|i| entry(1000.0 + f64::from(i), "eps", "voltage", Value::Real(3.3)))
+            .collect();
+
+        let export = encode(&entries).unwrap();
+        let json: Vec<u8> = entries
+            .iter()
+            .flat_map(|entry| {
+                format!(
+                    r#"{{"timestamp":{:.1},"subsystem":"{}","parameter":"{}","value":"{}"}},"#,
+                    entry.timestamp, entry.subsystem, entry.parameter, entry.value
+                )
+                .into_bytes()
+            })
+            .collect();
+
+        assert!(export.len() * 4 < json.len());
+    }
+
+    #[test]
+    fn millisecond_precision() {
+        let export = encode(&[entry(1000.0004, "eps", "voltage", Value::Real(3.3))]).unwrap();
+        assert_eq!(decode(&export).unwrap()[0].timestamp, 1000.0);
+    }
+
+    #[test]
+    fn unknown_parameter() {
+        let export = ser::to_vec(&(
+            FORMAT_VERSION,
+            vec![("eps", "voltage")],
+            vec![(1, 1000, 3.3)],
+        ))
+        .unwrap();
+
+        match decode(&export) {
+            Err(ExportError::DecodeError(err)) => assert_eq!(err, "Unknown parameter ID 1"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn timestamp_overflow() {
+        let export = ser::to_vec(&(
+            FORMAT_VERSION,
+            vec![("eps", "voltage")],
+            vec![(0, i64::MAX, 3.3), (0, 1, 3.4)],
+        ))
+        .unwrap();
+
+        match decode(&export) {
+            Err(ExportError::DecodeError(err)) => {
+                assert_eq!(err, "Timestamp out of range for eps/voltage")
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn integer_out_of_range() {
+        let export = ser::to_vec(&(
+            FORMAT_VERSION,
+            vec![("mcu", "count")],
+            vec![(0, 1000, u64::MAX)],
+        ))
+        .unwrap();
+
+        match decode(&export) {
+            Err(ExportError::DecodeError(err)) => {
+                assert!(err.starts_with("Invalid value for mcu/count"), "{}", err)
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let export = ser::to_vec(&(
+            2,
+            Vec::<(String, String)>::new(),
+            Vec::<(u32, i64, f64)>::new(),
+        ))
+        .unwrap();
+
+        match decode(&export) {
+            Err(ExportError::UnsupportedVersion(2)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn display_blob() {
+        assert_eq!(Value::Blob(vec![0x01, 0xff, 0x3a]).to_string(), "01ff3a");
+    }
+}
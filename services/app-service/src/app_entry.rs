@@ -15,6 +15,7 @@
  */
 
 use crate::error::*;
+use crate::integrity::Checksums;
 use serde_derive::{Deserialize, Serialize};
 use std::fs;
 use std::io::Write;
@@ -35,6 +36,57 @@ pub struct AppMetadata {
     pub author: String,
     /// The custom configuration file which should be passed to the application when it is started
     pub config: Option<String>,
+    /// Optional. What to do when the application exits. If not specified, it will not be restarted
+    pub restart: Option<RestartPolicy>,
+    /// Optional. Resource limits to apply to the application when it is started
+    pub limits: Option<Limits>,
+}
+
+/// When an application should be restarted after it exits
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartMode {
+    /// Never restart the application
+    Never,
+    /// Restart the application if it exits with a non-zero return code or is stopped by a signal
+    OnFailure,
+    /// Restart the application whenever it exits
+    Always,
+}
+
+/// How the app service should handle an application exiting
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// When the application should be restarted
+    pub mode: RestartMode,
+    /// Optional. The most times the application will be restarted before giving up, without a
+    /// run lasting long enough to be considered stable in between. If not specified, there is
+    /// no limit
+    pub max_retries: Option<u32>,
+    /// Optional. Seconds to wait before restarting the application. The wait is doubled for
+    /// each run in a row which exits soon after starting. If not specified, one second is used
+    pub backoff: Option<u64>,
+}
+/// Resource limits and sandboxing applied to an application when it is started
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Limits {
+    /// Optional. The most virtual memory the application may use, in bytes (RLIMIT_AS)
+    pub memory: Option<u64>,
+    /// Optional. The most CPU time the application may use, in seconds (RLIMIT_CPU)
+    pub cpu_time: Option<u64>,
+    /// Optional. The nice value to run the application with
+    pub nice: Option<i32>,
+    /// Optional. The most files the application may have open at once (RLIMIT_NOFILE)
+    pub open_files: Option<u64>,
+    /// Optional. The name or ID of the user to run the application as
+    pub user: Option<String>,
+    /// Optional. The name or ID of the group to run the application as.
+    /// If not specified, the primary group of `user` is used
+    pub group: Option<String>,
+    /// Optional. The most memory the application may use, in bytes, enforced by a cgroup
+    pub cgroup_memory: Option<u64>,
+    /// Optional. The percentage of a single CPU the application may use, enforced by a cgroup
+    pub cgroup_cpu: Option<u32>,
 }
 /// Kubos App struct
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -49,6 +101,12 @@ pub struct App {
     pub author: String,
     /// Configuration file to be passed to the application
     pub config: String,
+    /// What to do when the application exits
+    pub restart: Option<RestartPolicy>,
+    /// Resource limits to apply to the application
+    pub limits: Option<Limits>,
+    /// Checksums of the application's files, taken when it was registered
+    pub checksums: Option<Checksums>,
 }
 /// AppRegistryEntry
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -27,6 +27,8 @@ pub enum StartErrorKind {
     SpawnError(std::io::ErrorKind),
     NonZeroExit,
     NoStatus,
+    LimitError,
+    IntegrityError,
 }
 
 #[derive(Debug, Fail, PartialEq, Eq)]
@@ -0,0 +1,230 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::error::*;
+use crate::logs::LOG_DIR;
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extension added to the path of an app package to get the path of its signature
+pub static SIGNATURE_EXTENSION: &str = "sig";
+
+/// SHA-256 checksums of the files making up an app version, keyed by their path within the
+/// version's directory
+pub type Checksums = BTreeMap<String, String>;
+
+// File in each app version's directory which holds its registry entry. Like the version's logs,
+// it's written by the app service rather than being part of the app
+const REGISTRY_FILE: &str = "app.toml";
+
+fn register_error(err: String) -> AppError {
+    AppError::RegisterError { err }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Parse the `trusted-keys` service config option: a list of hex-encoded Ed25519 public keys
+pub fn parse_trusted_keys(value: &toml::Value) -> Result<Vec<PublicKey>, AppError> {
+    let parse_error = |err: String| AppError::ParseError {
+        entity: "trusted-keys".to_owned(),
+        err,
+    };
+
+    let keys = value
+        .as_array()
+        .ok_or_else(|| parse_error("Expected a list of keys".to_owned()))?;
+
+    keys.iter()
+        .map(|key| {
+            let key = key
+                .as_str()
+                .ok_or_else(|| parse_error(format!("Expected a hex string, found {}", key)))?;
+            decode_hex(key)
+                .and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+                .ok_or_else(|| parse_error(format!("Invalid Ed25519 public key {}", key)))
+        })
+        .collect()
+}
+
+fn signature_path(package: &Path) -> Result<PathBuf, AppError> {
+    let name = package
+        .file_name()
+        .ok_or_else(|| register_error(format!("Invalid package path {}", package.display())))?;
+
+    let mut name = name.to_owned();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    Ok(package.with_file_name(name))
+}
+
+// The checksums of a directory's files, formatted the same way as `sha256sum` output
+fn listing(checksums: &Checksums) -> String {
+    checksums
+        .iter()
+        .map(|(path, checksum)| format!("{}  ./{}\n", checksum, path))
+        .collect()
+}
+
+// Check that the signature next to an app package is one of the trusted keys' signature of
+// `message`
+fn verify_signature(package: &Path, message: &[u8], keys: &[PublicKey]) -> Result<(), AppError> {
+    let signature_path = signature_path(package)?;
+    let signature = fs::read(&signature_path).map_err(|err| {
+        register_error(format!(
+            "Unable to read signature {}: {}",
+            signature_path.display(),
+            err
+        ))
+    })?;
+    let signature = Signature::try_from(&signature[..]).map_err(|_| {
+        register_error(format!("Invalid signature in {}", signature_path.display()))
+    })?;
+
+    if keys
+        .iter()
+        .any(|key| key.verify(message, &signature).is_ok())
+    {
+        Ok(())
+    } else {
+        Err(register_error(format!(
+            "Signature of {} doesn't match any trusted key",
+            package.display()
+        )))
+    }
+}
+
+/// Check that an app directory was signed by one of the trusted keys, before anything in it is
+/// used. Directories are signed by the listing of their files' checksums, and the signature is
+/// read from the file next to the directory with `.sig` added to its name.
+///
+/// Returns the checksums which were signed, so that the copy of the app which gets installed can
+/// be checked against them
+///
+/// # Arguments
+///
+/// * `dir` - Path to the app's directory
+/// * `keys` - Keys trusted to sign app packages
+pub fn verify_dir(dir: &Path, keys: &[PublicKey]) -> Result<Checksums, AppError> {
+    let checksums = checksums(dir)?;
+    verify_signature(dir, listing(&checksums).as_bytes(), keys)?;
+    Ok(checksums)
+}
+
+/// Check that an app archive was signed by one of the trusted keys, before anything in it is used.
+/// Archives are signed as they are, and the signature is read from the file next to the archive
+/// with `.sig` added to its name
+///
+/// # Arguments
+///
+/// * `package` - Path to the app's archive
+/// * `copy` - Private copy of the archive, which is checked in place of the original so that the
+///   archive can't be swapped out between being checked and being extracted
+/// * `keys` - Keys trusted to sign app packages
+pub fn verify_archive(package: &Path, copy: &Path, keys: &[PublicKey]) -> Result<(), AppError> {
+    verify_signature(package, &fs::read(copy)?, keys)
+}
+
+fn file_checksum(path: &Path) -> Result<String, io::Error> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(hasher
+        .result()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+fn add_checksums(root: &Path, dir: &Path, checksums: &mut Checksums) -> Result<(), io::Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if dir == root {
+            let name = entry.file_name();
+            if name == REGISTRY_FILE || name == LOG_DIR {
+                continue;
+            }
+        }
+
+        if entry.file_type()?.is_dir() {
+            add_checksums(root, &path, checksums)?;
+        } else if path.is_file() {
+            let relative = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .into_owned();
+            checksums.insert(relative, file_checksum(&path)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Calculate the checksums of the files in an app's directory, leaving out those the app service
+/// writes itself
+pub fn checksums(dir: &Path) -> Result<Checksums, AppError> {
+    let mut checksums = Checksums::new();
+    add_checksums(dir, dir, &mut checksums).map_err(|err| AppError::FileError {
+        err: format!(
+            "Failed to calculate checksums for {}: {}",
+            dir.display(),
+            err
+        ),
+    })?;
+    Ok(checksums)
+}
+
+/// Check that the files recorded when an app version was registered haven't changed since.
+/// Files added to the directory afterwards, such as those the app writes itself, aren't checked
+///
+/// # Arguments
+///
+/// * `dir` - The app version's directory
+/// * `expected` - The checksums recorded when the app version was registered
+pub fn verify_checksums(dir: &Path, expected: &Checksums) -> Result<(), AppError> {
+    for (path, checksum) in expected {
+        let problem = match file_checksum(&dir.join(path)) {
+            Ok(ref actual) if actual == checksum => continue,
+            Ok(_) => format!("{} has changed since the app was registered", path),
+            Err(err) => format!("Unable to check {}: {}", path, err),
+        };
+
+        return Err(AppError::StartError {
+            err: format!("Integrity check failed: {}", problem),
+            cause: StartErrorKind::IntegrityError,
+        });
+    }
+
+    Ok(())
+}
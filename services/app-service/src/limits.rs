@@ -0,0 +1,355 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::app_entry::{App, Limits};
+use crate::error::*;
+use log::*;
+use nix::libc;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::ptr;
+
+/// Root of the cgroup v2 hierarchy
+pub static CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// Group within the cgroup root which holds a group for each app with cgroup limits
+pub static CGROUP_APPS: &str = "kubos-apps";
+
+// Period used for cgroup CPU limits, in microseconds
+const CPU_PERIOD: u64 = 100_000;
+// Size of the buffer used when looking up users and groups
+const LOOKUP_BUFFER_SIZE: usize = 16384;
+
+// Everything needed to apply an app's limits to its process, between forking and executing it.
+// Users, groups and cgroups are resolved beforehand, since only async-signal-safe calls may be
+// made at that point
+struct Sandbox {
+    memory: Option<u64>,
+    cpu_time: Option<u64>,
+    nice: Option<i32>,
+    open_files: Option<u64>,
+    uid: Option<libc::uid_t>,
+    gid: Option<libc::gid_t>,
+    cgroup_procs: Option<CString>,
+}
+
+impl Sandbox {
+    fn enter(&self) -> io::Result<()> {
+        // Join the cgroup first, while we still have permission to
+        if let Some(procs) = &self.cgroup_procs {
+            join_cgroup(procs)?;
+        }
+
+        if let Some(nice) = self.nice {
+            check(unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, nice) })?;
+        }
+
+        if let Some(memory) = self.memory {
+            check(unsafe { libc::setrlimit(libc::RLIMIT_AS, &rlimit(memory, memory)) })?;
+        }
+
+        if let Some(cpu_time) = self.cpu_time {
+            // The app is sent SIGXCPU at the limit, then SIGKILL a second later if it's still going
+            check(unsafe { libc::setrlimit(libc::RLIMIT_CPU, &rlimit(cpu_time, cpu_time + 1)) })?;
+        }
+
+        if let Some(open_files) = self.open_files {
+            check(unsafe {
+                libc::setrlimit(libc::RLIMIT_NOFILE, &rlimit(open_files, open_files))
+            })?;
+        }
+
+        // The group has to be changed before the user, since we won't be allowed to afterwards
+        if let Some(gid) = self.gid {
+            check(unsafe { libc::setgroups(1, &gid) })?;
+            check(unsafe { libc::setgid(gid) })?;
+        }
+
+        if let Some(uid) = self.uid {
+            check(unsafe { libc::setuid(uid) })?;
+        }
+
+        Ok(())
+    }
+}
+
+fn rlimit(soft: u64, hard: u64) -> libc::rlimit {
+    libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    }
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn join_cgroup(procs: &CString) -> io::Result<()> {
+    let fd = unsafe { libc::open(procs.as_ptr(), libc::O_WRONLY) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Writing 0 moves the writing process into the group
+    let result = unsafe { libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1) };
+    let error = io::Error::last_os_error();
+    unsafe { libc::close(fd) };
+
+    if result == -1 {
+        Err(error)
+    } else {
+        Ok(())
+    }
+}
+
+fn limit_error(err: String) -> AppError {
+    AppError::StartError {
+        err,
+        cause: StartErrorKind::LimitError,
+    }
+}
+
+// Look up a user by name or ID, returning their user ID and primary group ID
+fn lookup_user(user: &str) -> Result<(libc::uid_t, libc::gid_t), AppError> {
+    let name = CString::new(user).map_err(|_| limit_error(format!("Invalid user {}", user)))?;
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut buffer = vec![0; LOOKUP_BUFFER_SIZE];
+    let mut result = ptr::null_mut();
+
+    let code = match user.parse::<libc::uid_t>() {
+        Ok(uid) => unsafe {
+            libc::getpwuid_r(
+                uid,
+                &mut passwd,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        },
+        Err(_) => unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut passwd,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        },
+    };
+
+    if code != 0 {
+        Err(limit_error(format!(
+            "Failed to look up user {}: {}",
+            user,
+            io::Error::from_raw_os_error(code)
+        )))
+    } else if result.is_null() {
+        Err(limit_error(format!("Unknown user {}", user)))
+    } else {
+        Ok((passwd.pw_uid, passwd.pw_gid))
+    }
+}
+
+// Look up a group by name or ID, returning its group ID
+fn lookup_group(group: &str) -> Result<libc::gid_t, AppError> {
+    let name = CString::new(group).map_err(|_| limit_error(format!("Invalid group {}", group)))?;
+    let mut entry: libc::group = unsafe { mem::zeroed() };
+    let mut buffer = vec![0; LOOKUP_BUFFER_SIZE];
+    let mut result = ptr::null_mut();
+
+    let code = match group.parse::<libc::gid_t>() {
+        Ok(gid) => unsafe {
+            libc::getgrgid_r(
+                gid,
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        },
+        Err(_) => unsafe {
+            libc::getgrnam_r(
+                name.as_ptr(),
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        },
+    };
+
+    if code != 0 {
+        Err(limit_error(format!(
+            "Failed to look up group {}: {}",
+            group,
+            io::Error::from_raw_os_error(code)
+        )))
+    } else if result.is_null() {
+        Err(limit_error(format!("Unknown group {}", group)))
+    } else {
+        Ok(entry.gr_gid)
+    }
+}
+
+fn cgroup_path(name: &str) -> PathBuf {
+    Path::new(CGROUP_ROOT).join(CGROUP_APPS).join(name)
+}
+
+fn create_cgroup(group: &Path, limits: &Limits) -> io::Result<()> {
+    let root = Path::new(CGROUP_ROOT);
+    let apps = root.join(CGROUP_APPS);
+
+    let mut controllers = vec![];
+    if limits.cgroup_memory.is_some() {
+        controllers.push("+memory");
+    }
+    if limits.cgroup_cpu.is_some() {
+        controllers.push("+cpu");
+    }
+    let controllers = controllers.join(" ");
+
+    if !apps.exists() {
+        fs::create_dir(&apps)?;
+    }
+
+    // Controllers have to be enabled at each level above the app's group
+    fs::write(root.join("cgroup.subtree_control"), &controllers)?;
+    fs::write(apps.join("cgroup.subtree_control"), &controllers)?;
+
+    // Each run gets a new group, so that the group's memory events only count that run.
+    // If processes from an earlier run are still in the group, it can't be removed and is reused
+    if group.exists() && fs::remove_dir(group).is_err() {
+        debug!("Reusing cgroup {}", group.display());
+    } else {
+        fs::create_dir(group)?;
+    }
+
+    if let Some(memory) = limits.cgroup_memory {
+        fs::write(group.join("memory.max"), memory.to_string())?;
+    }
+    if let Some(cpu) = limits.cgroup_cpu {
+        let quota = u64::from(cpu) * CPU_PERIOD / 100;
+        fs::write(group.join("cpu.max"), format!("{} {}", quota, CPU_PERIOD))?;
+    }
+
+    Ok(())
+}
+
+/// Set up a command so that the app it starts runs within the given limits
+///
+/// # Arguments
+///
+/// * `cmd` - The command which will start the app
+/// * `name` - The name of the app, used to name its cgroup
+/// * `limits` - The limits to apply
+pub fn apply_limits(cmd: &mut Command, name: &str, limits: &Limits) -> Result<(), AppError> {
+    let (uid, user_gid) = match &limits.user {
+        Some(user) => {
+            let (uid, gid) = lookup_user(user)?;
+            (Some(uid), Some(gid))
+        }
+        None => (None, None),
+    };
+
+    let gid = match &limits.group {
+        Some(group) => Some(lookup_group(group)?),
+        None => user_gid,
+    };
+
+    let cgroup_procs = if limits.cgroup_memory.is_some() || limits.cgroup_cpu.is_some() {
+        if limits.cgroup_cpu == Some(0) {
+            return Err(limit_error(
+                "cgroup_cpu must be greater than zero".to_owned(),
+            ));
+        }
+
+        let group = cgroup_path(name);
+        create_cgroup(&group, limits).map_err(|err| {
+            limit_error(format!(
+                "Failed to set up cgroup {}: {}",
+                group.display(),
+                err
+            ))
+        })?;
+
+        Some(
+            CString::new(group.join("cgroup.procs").as_os_str().as_bytes())
+                .map_err(|_| limit_error(format!("Invalid cgroup path {}", group.display())))?,
+        )
+    } else {
+        None
+    };
+
+    let sandbox = Sandbox {
+        memory: limits.memory,
+        cpu_time: limits.cpu_time,
+        nice: limits.nice,
+        open_files: limits.open_files,
+        uid,
+        gid,
+        cgroup_procs,
+    };
+
+    unsafe {
+        cmd.pre_exec(move || sandbox.enter());
+    }
+
+    Ok(())
+}
+
+// Number of times processes in an app's cgroup have been killed for using too much memory
+fn oom_kills(name: &str) -> u64 {
+    fs::read_to_string(cgroup_path(name).join("memory.events"))
+        .ok()
+        .and_then(|events| {
+            events.lines().find_map(|line| {
+                let mut fields = line.split_whitespace();
+                match (fields.next(), fields.next()) {
+                    (Some("oom_kill"), Some(count)) => count.parse().ok(),
+                    _ => None,
+                }
+            })
+        })
+        .unwrap_or(0)
+}
+
+/// Check whether an app was stopped for going over one of its limits, and if so, which one
+pub fn exceeded_limit(app: &App, status: ExitStatus) -> Option<String> {
+    let limits = app.limits.as_ref()?;
+
+    match status.signal() {
+        Some(libc::SIGXCPU) => limits
+            .cpu_time
+            .map(|cpu_time| format!("CPU time limit of {} seconds exceeded", cpu_time)),
+        Some(libc::SIGKILL) => limits.cgroup_memory.and_then(|memory| {
+            if oom_kills(&app.name) > 0 {
+                Some(format!("cgroup memory limit of {} bytes exceeded", memory))
+            } else {
+                None
+            }
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,239 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::error::*;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use log::*;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Directory, within an app version's directory, which holds the output of its runs
+pub static LOG_DIR: &str = "logs";
+/// Size at which a log file is rotated. One rotated file is kept per log
+pub const MAX_LOG_SIZE: u64 = 256 * 1024;
+/// Number of runs of each app version which have their output kept
+pub const LOG_RUNS_KEPT: usize = 10;
+/// Number of lines returned from each log if no other amount is requested
+pub const DEFAULT_LOG_LINES: usize = 20;
+
+// Format of the start time used to name log files. Sorts the same way as the times it represents
+const TIME_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+const STDOUT_SUFFIX: &str = "-stdout.log";
+const STDERR_SUFFIX: &str = "-stderr.log";
+
+/// Output captured from a single run of an application
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct AppLogs {
+    pub name: String,
+    pub version: String,
+    /// Start time of the run. Matches the `startTime` of the run's monitoring entry
+    pub start_time: DateTime<Utc>,
+    pub stdout_path: String,
+    pub stderr_path: String,
+    /// Last lines written to stdout
+    pub stdout: Vec<String>,
+    /// Last lines written to stderr
+    pub stderr: Vec<String>,
+}
+
+/// Log file receiving one of an app's output streams
+pub struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn create(path: PathBuf) -> Result<LogFile, io::Error> {
+        let file = File::create(&path)?;
+        Ok(LogFile {
+            path,
+            file,
+            size: 0,
+        })
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        // Move the current contents out of the way once the file is full
+        if self.size > 0 && self.size + data.len() as u64 > MAX_LOG_SIZE {
+            fs::rename(&self.path, rotated_path(&self.path))?;
+            self.file = File::create(&self.path)?;
+            self.size = 0;
+        }
+
+        self.file.write_all(data)?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    path.with_extension("log.1")
+}
+
+fn log_dir(app_dir: &Path) -> PathBuf {
+    app_dir.join(LOG_DIR)
+}
+
+// Start times of the runs with logs in a log directory, oldest first
+fn logged_runs(log_dir: &Path) -> Vec<String> {
+    let mut runs: Vec<String> = match fs::read_dir(log_dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.ends_with(STDOUT_SUFFIX) {
+                    Some(name[..name.len() - STDOUT_SUFFIX.len()].to_owned())
+                } else {
+                    None
+                }
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+
+    runs.sort();
+    runs
+}
+
+fn remove_run(log_dir: &Path, run: &str) {
+    for suffix in &[STDOUT_SUFFIX, STDERR_SUFFIX] {
+        let path = log_dir.join(format!("{}{}", run, suffix));
+        // Don't really care if these fail, since the files may not exist
+        let _ = fs::remove_file(rotated_path(&path));
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Create the log files for a new run of an app, removing the logs of its oldest runs to make room
+///
+/// # Arguments
+///
+/// * `executable` - Path to the app's executable. Logs are kept in the directory containing it
+/// * `start_time` - Start time of the run, used to name the log files
+pub fn open_logs(
+    executable: &Path,
+    start_time: DateTime<Utc>,
+) -> Result<(LogFile, LogFile), AppError> {
+    let app_dir = executable.parent().ok_or_else(|| AppError::FileError {
+        err: format!("Failed to get parent dir of {}", executable.display()),
+    })?;
+    let log_dir = log_dir(app_dir);
+    fs::create_dir_all(&log_dir)?;
+
+    let runs = logged_runs(&log_dir);
+    if runs.len() >= LOG_RUNS_KEPT {
+        for run in &runs[..=runs.len() - LOG_RUNS_KEPT] {
+            remove_run(&log_dir, run);
+        }
+    }
+
+    let run = start_time.format(TIME_FORMAT).to_string();
+    Ok((
+        LogFile::create(log_dir.join(format!("{}{}", run, STDOUT_SUFFIX)))?,
+        LogFile::create(log_dir.join(format!("{}{}", run, STDERR_SUFFIX)))?,
+    ))
+}
+
+/// Copy everything an app writes to one of its output streams into a log file, until the
+/// stream is closed
+pub fn capture<R: Read + Send + 'static>(mut stream: R, mut log: LogFile) {
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        let mut failed = false;
+
+        loop {
+            let count = match stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(count) => count,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    warn!("Failed to read output for {}: {}", log.path.display(), err);
+                    break;
+                }
+            };
+
+            // Keep reading even if the log can't be written, so the app doesn't block
+            // once the pipe fills up
+            if let Err(err) = log.write(&buffer[..count]) {
+                if !failed {
+                    error!("Failed to write to {}: {}", log.path.display(), err);
+                    failed = true;
+                }
+            }
+        }
+    });
+}
+
+// Last lines of a log, including those which have been rotated out of the current file
+fn tail(path: &Path, lines: usize) -> Vec<String> {
+    let mut contents = vec![];
+    for path in &[rotated_path(path), path.to_owned()] {
+        if let Ok(data) = fs::read(path) {
+            contents.extend(data);
+        }
+    }
+
+    let contents = String::from_utf8_lossy(&contents);
+    let all: Vec<&str> = contents.lines().collect();
+    all[all.len().saturating_sub(lines)..]
+        .iter()
+        .map(|line| (*line).to_owned())
+        .collect()
+}
+
+/// Read the output kept from the runs of an app version, newest first
+///
+/// # Arguments
+///
+/// * `executable` - Path to the app's executable
+/// * `name` - Name of the app
+/// * `version` - Version of the app
+/// * `lines` - Number of lines to return from the end of each log
+pub fn read_logs(executable: &Path, name: &str, version: &str, lines: usize) -> Vec<AppLogs> {
+    let log_dir = match executable.parent() {
+        Some(app_dir) => log_dir(app_dir),
+        None => return vec![],
+    };
+
+    logged_runs(&log_dir)
+        .iter()
+        .rev()
+        .filter_map(|run| {
+            let start_time = match NaiveDateTime::parse_from_str(run, TIME_FORMAT) {
+                Ok(time) => Utc.from_utc_datetime(&time),
+                Err(_) => {
+                    debug!("Skipping unexpected log {} in {}", run, log_dir.display());
+                    return None;
+                }
+            };
+            let stdout_path = log_dir.join(format!("{}{}", run, STDOUT_SUFFIX));
+            let stderr_path = log_dir.join(format!("{}{}", run, STDERR_SUFFIX));
+
+            Some(AppLogs {
+                name: name.to_owned(),
+                version: version.to_owned(),
+                start_time,
+                stdout: tail(&stdout_path, lines),
+                stderr: tail(&stderr_path, lines),
+                stdout_path: stdout_path.to_string_lossy().into_owned(),
+                stderr_path: stderr_path.to_string_lossy().into_owned(),
+            })
+        })
+        .collect()
+}
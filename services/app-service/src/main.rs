@@ -20,6 +20,9 @@ extern crate juniper;
 
 mod app_entry;
 mod error;
+mod integrity;
+mod limits;
+mod logs;
 mod monitor;
 mod objects;
 mod registry;
@@ -40,7 +43,7 @@ fn main() -> Result<(), Error> {
         err
     })?;
 
-    let registry = {
+    let mut registry = {
         match config.get("registry-dir") {
             Some(dir) => AppRegistry::new_from_dir(dir.as_str().unwrap()).map_err(|err| {
                 error!(
@@ -57,6 +60,13 @@ fn main() -> Result<(), Error> {
         }
     };
 
+    if let Some(keys) = config.get("trusted-keys") {
+        registry.trusted_keys = integrity::parse_trusted_keys(&keys).map_err(|err| {
+            error!("Failed to load trusted keys: {:?}", err);
+            err
+        })?;
+    }
+
     Service::new(config, registry, schema::QueryRoot, schema::MutationRoot).start();
 
     Ok(())
@@ -14,12 +14,31 @@
  * limitations under the License.
  */
 
+use crate::app_entry::{App, RestartMode, RestartPolicy};
 use crate::error::*;
+use crate::limits::exceeded_limit;
+use crate::registry::AppRegistry;
 use chrono::{DateTime, Utc};
 use log::*;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
 use std::os::unix::process::ExitStatusExt;
 use std::process::{Child, ExitStatus};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Seconds to wait before restarting an app, if its restart policy doesn't say
+pub const DEFAULT_BACKOFF: u64 = 1;
+/// Longest time to wait before restarting an app
+pub const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Runs shorter than this count towards crash loop detection
+pub const STABLE_RUN_TIME: Duration = Duration::from_secs(60);
+/// Number of short runs in a row after which an app is no longer restarted
+pub const CRASH_LOOP_RUNS: u32 = 5;
+/// Time an app is given to exit after being sent SIGTERM for running past its timeout, before
+/// it is sent SIGKILL
+pub const TIMEOUT_GRACE: Duration = Duration::from_secs(5);
 
 /// Apps which have been started and are being monitored until they finish
 #[derive(Clone, Debug, GraphQLObject)]
@@ -34,6 +53,14 @@ pub struct MonitorEntry {
     pub last_signal: Option<i32>,
     pub args: Option<Vec<String>>,
     pub config: String,
+    /// Number of times the app has been automatically restarted since it was started
+    pub restarts: i32,
+    /// Why the app is no longer being automatically restarted
+    pub fault: Option<String>,
+    /// Limit from the app's manifest which the app was stopped for exceeding, if any
+    pub limit_exceeded: Option<String>,
+    /// Whether the app was stopped for running past its timeout
+    pub timed_out: bool,
 }
 
 // Check if any version of the application is running
@@ -54,20 +81,336 @@ pub fn find_running(
     }))
 }
 
+// Wait for an app to finish running, then restart it if its restart policy says to
 pub fn monitor_app(
-    registry: Arc<Mutex<Vec<MonitorEntry>>>,
+    registry: AppRegistry,
     mut process_handle: Child,
+    app: App,
+    config: String,
+    args: Option<Vec<String>>,
+    timeout: Option<Duration>,
+) -> Result<(), AppError> {
+    let started = Instant::now();
+    let status = wait_for(&app.name, &mut process_handle)?;
+
+    if end_run(&registry, &app, process_handle.id() as i32, status)? {
+        restart_app(registry, app, config, args, timeout, started)
+    } else {
+        Ok(())
+    }
+}
+
+// Keep restarting an app which has exited, for as long as its restart policy says to.
+// `started` is when the run which just ended was started
+pub fn restart_app(
+    registry: AppRegistry,
+    app: App,
+    config: String,
+    args: Option<Vec<String>>,
+    timeout: Option<Duration>,
+    mut started: Instant,
+) -> Result<(), AppError> {
+    let name = &app.name;
+    let version = &app.version;
+    let policy = match &app.restart {
+        Some(policy) => policy,
+        None => return Ok(()),
+    };
+    let mut restarts = 0;
+    // Number of restarts since the last run which lasted long enough to be considered stable
+    let mut retries = 0;
+    // Number of runs in a row which ended soon after they started
+    let mut short_runs = 0;
+
+    loop {
+        if started.elapsed() < STABLE_RUN_TIME {
+            short_runs += 1;
+        } else {
+            short_runs = 0;
+            retries = 0;
+        }
+
+        if short_runs >= CRASH_LOOP_RUNS {
+            return set_fault(
+                &registry.monitoring,
+                name,
+                version,
+                format!(
+                    "Crash loop detected: exited {} times in a row within {} seconds of starting",
+                    short_runs,
+                    STABLE_RUN_TIME.as_secs()
+                ),
+            );
+        }
+
+        if let Some(max_retries) = policy.max_retries {
+            if retries >= max_retries {
+                return set_fault(
+                    &registry.monitoring,
+                    name,
+                    version,
+                    format!("Still failing after {} restarts", retries),
+                );
+            }
+        }
+
+        let delay = backoff(policy, short_runs);
+        info!("Restarting {} in {} seconds", name, delay.as_secs());
+        thread::sleep(delay);
+
+        // The app may have been started again or uninstalled while we were waiting
+        if !restartable(&registry.monitoring, name, version)? {
+            info!("Not restarting {}, since its status has changed", name);
+            return Ok(());
+        }
+
+        restarts += 1;
+        retries += 1;
+        started = Instant::now();
+        let mut process_handle =
+            match registry.launch(&app, &config, args.clone(), timeout, restarts as i32) {
+                Ok(child) => child,
+                Err(error) => {
+                    return set_fault(
+                        &registry.monitoring,
+                        name,
+                        version,
+                        format!("Failed to restart: {}", error),
+                    );
+                }
+            };
+
+        let status = wait_for(name, &mut process_handle)?;
+
+        if !end_run(&registry, &app, process_handle.id() as i32, status)? {
+            return Ok(());
+        }
+    }
+}
+
+// Record that a run of an app has finished, returning whether the app's restart policy says to
+// start it again
+pub fn end_run(
+    registry: &AppRegistry,
+    app: &App,
+    pid: i32,
+    status: ExitStatus,
+) -> Result<bool, AppError> {
+    finish_entry(
+        &registry.monitoring,
+        &app.name,
+        &app.version,
+        status,
+        exceeded_limit(app, status),
+    )?;
+
+    if was_killed(&registry.killed, pid)? {
+        return Ok(false);
+    }
+
+    // Like runs stopped by `killApp`, runs stopped for going past their timeout aren't restarted
+    if timed_out(&registry.monitoring, &app.name, &app.version)? {
+        return Ok(false);
+    }
+
+    Ok(app
+        .restart
+        .as_ref()
+        .map_or(false, |policy| should_restart(policy.mode, status)))
+}
+
+// Wait for a run of an app to finish
+fn wait_for(name: &str, process_handle: &mut Child) -> Result<ExitStatus, AppError> {
+    process_handle.wait().map_err(|err| AppError::MonitorError {
+        err: format!("Failed to wait for {} to finish: {:?}", name, err),
+    })
+}
+
+// Stop a run of an app if it's still going once its timeout has passed. The app is sent SIGTERM,
+// then SIGKILL if it hasn't exited after the grace period
+pub fn watch_timeout(
+    registry: Arc<Mutex<Vec<MonitorEntry>>>,
+    name: String,
+    version: String,
+    pid: i32,
+    timeout: Duration,
+) {
+    thread::spawn(move || {
+        thread::sleep(timeout);
+
+        match mark_timed_out(&registry, &name, &version, pid) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(error) => {
+                error!("{:?}", error);
+                return;
+            }
+        }
+
+        warn!(
+            "App {} still running after timeout of {} seconds. Stopping it",
+            name,
+            timeout.as_secs()
+        );
+        if let Err(err) = signal::kill(Pid::from_raw(pid), Signal::SIGTERM) {
+            error!("Failed to stop {}: {}", name, err);
+            return;
+        }
+
+        let deadline = Instant::now() + TIMEOUT_GRACE;
+        while Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(100));
+            match is_running(&registry, &name, &version, pid) {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(error) => {
+                    error!("{:?}", error);
+                    return;
+                }
+            }
+        }
+
+        warn!("App {} didn't exit after SIGTERM. Killing it", name);
+        if let Err(err) = signal::kill(Pid::from_raw(pid), Signal::SIGKILL) {
+            error!("Failed to kill {}: {}", name, err);
+        }
+    });
+}
+
+// Check whether a particular run of an app is still going.
+// The run's PID can't have been reused until its monitor has waited on it and marked it finished
+fn is_running(
+    registry: &Arc<Mutex<Vec<MonitorEntry>>>,
     name: &str,
     version: &str,
+    pid: i32,
+) -> Result<bool, AppError> {
+    let entries = registry.lock().map_err(|err| AppError::MonitorError {
+        err: format!("Failed get entries mutex: {:?}", err),
+    })?;
+
+    Ok(entries.iter().any(|entry| {
+        entry.name == name && entry.version == version && entry.running && entry.pid == Some(pid)
+    }))
+}
+
+// Record that a run of an app has timed out, if it's still going
+fn mark_timed_out(
+    registry: &Arc<Mutex<Vec<MonitorEntry>>>,
+    name: &str,
+    version: &str,
+    pid: i32,
+) -> Result<bool, AppError> {
+    let mut entries = registry.lock().map_err(|err| AppError::MonitorError {
+        err: format!(
+            "Failed to record timeout for {}. Couldn't get entries mutex: {:?}",
+            name, err
+        ),
+    })?;
+
+    match entries.iter_mut().find(|entry| {
+        entry.name == name && entry.version == version && entry.running && entry.pid == Some(pid)
+    }) {
+        Some(entry) => {
+            entry.timed_out = true;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+// Check whether the last run of an app was stopped for running past its timeout
+fn timed_out(
+    registry: &Arc<Mutex<Vec<MonitorEntry>>>,
+    name: &str,
+    version: &str,
+) -> Result<bool, AppError> {
+    let entries = registry.lock().map_err(|err| AppError::MonitorError {
+        err: format!("Failed get entries mutex: {:?}", err),
+    })?;
+
+    Ok(entries
+        .iter()
+        .any(|entry| entry.name == name && entry.version == version && entry.timed_out))
+}
+
+fn should_restart(mode: RestartMode, status: ExitStatus) -> bool {
+    match mode {
+        RestartMode::Never => false,
+        RestartMode::OnFailure => !status.success(),
+        RestartMode::Always => true,
+    }
+}
+
+// The wait before a restart doubles with each short run, so that an app which keeps failing
+// quickly isn't restarted as fast as possible
+fn backoff(policy: &RestartPolicy, short_runs: u32) -> Duration {
+    let base = Duration::from_secs(policy.backoff.unwrap_or(DEFAULT_BACKOFF));
+    let factor = 1 << short_runs.saturating_sub(1).min(16);
+
+    base.checked_mul(factor)
+        .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF))
+}
+
+// Check whether an app was stopped by a `killApp` request, forgetting its PID if so
+fn was_killed(killed: &Arc<Mutex<Vec<i32>>>, pid: i32) -> Result<bool, AppError> {
+    let mut killed = killed.lock().map_err(|err| AppError::MonitorError {
+        err: format!("Failed to get killed mutex: {:?}", err),
+    })?;
+
+    match killed.iter().position(|killed_pid| *killed_pid == pid) {
+        Some(index) => {
+            killed.remove(index);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+// An app can be restarted if it's still being monitored and no other run of it has been started
+fn restartable(
+    registry: &Arc<Mutex<Vec<MonitorEntry>>>,
+    name: &str,
+    version: &str,
+) -> Result<bool, AppError> {
+    let entries = registry.lock().map_err(|err| AppError::MonitorError {
+        err: format!("Failed get entries mutex: {:?}", err),
+    })?;
+
+    Ok(entries
+        .iter()
+        .any(|entry| entry.name == name && entry.version == version)
+        && !entries
+            .iter()
+            .any(|entry| entry.name == name && entry.running))
+}
+
+// An app will no longer be restarted. Record why in its entry
+fn set_fault(
+    registry: &Arc<Mutex<Vec<MonitorEntry>>>,
+    name: &str,
+    version: &str,
+    fault: String,
 ) -> Result<(), AppError> {
-    // Wait for the application to finish running
-    let status = process_handle
-        .wait()
-        .map_err(|err| AppError::MonitorError {
-            err: format!("Failed to wait for {} to finish: {:?}", name, err),
-        })?;
+    error!("Not restarting {} {}: {}", name, version, fault);
+
+    let mut entries = registry.lock().map_err(|err| AppError::MonitorError {
+        err: format!(
+            "Failed to record fault for {}. Couldn't get entries mutex: {:?}",
+            name, err
+        ),
+    })?;
 
-    finish_entry(&registry, name, version, status)
+    if let Some(index) = entries
+        .iter()
+        .position(|e| e.name == name && e.version == version)
+    {
+        entries[index].fault = Some(fault);
+    } else {
+        warn!("Unable to find entry for {} {}", name, version);
+    }
+
+    Ok(())
 }
 
 // Update/add an entry to denote the start of a new execution of an app
@@ -101,12 +444,14 @@ pub fn start_entry(
     Ok(())
 }
 
-// An app has finished running. Update its entry with the end time and RC or signal
+// An app has finished running. Update its entry with the end time, RC or signal, and any limit
+// it was stopped for exceeding
 pub fn finish_entry(
     registry: &Arc<Mutex<Vec<MonitorEntry>>>,
     name: &str,
     version: &str,
     status: ExitStatus,
+    limit_exceeded: Option<String>,
 ) -> Result<(), AppError> {
     let mut last_rc = None;
     let mut last_signal = None;
@@ -126,6 +471,10 @@ pub fn finish_entry(
         warn!("App {} terminated for unknown reasons", name);
     }
 
+    if let Some(limit) = &limit_exceeded {
+        warn!("App {} stopped: {}", name, limit);
+    }
+
     let mut entries = registry.lock().map_err(|err| AppError::MonitorError {
         err: format!(
             "Failed to remove {} from monitoring. Couldn't get entries mutex: {:?}",
@@ -143,6 +492,7 @@ pub fn finish_entry(
         entries[index].last_signal = last_signal;
         entries[index].pid = None;
         entries[index].end_time = Some(end_time);
+        entries[index].limit_exceeded = limit_exceeded;
     } else {
         warn!("Unable to find entry for {} {}", name, version);
     }
@@ -16,21 +16,26 @@
 
 use crate::app_entry::*;
 use crate::error::*;
+use crate::integrity::*;
+use crate::limits::*;
+use crate::logs::*;
 use crate::monitor::*;
-use chrono::Utc;
+use chrono::{SubsecRound, Utc};
+use ed25519_dalek::PublicKey;
 use failure::format_err;
 use log::*;
 use nix::sys::signal;
 use nix::unistd::Pid;
+use std::cmp::Reverse;
 use std::ffi::OsStr;
 use std::fs;
 use std::io::Read;
 use std::os::unix;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Child, Command, Stdio};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
 
 /// The default application registry directory in KubOS
@@ -43,8 +48,12 @@ pub struct AppRegistry {
     #[doc(hidden)]
     pub entries: Arc<Mutex<Vec<AppRegistryEntry>>>,
     pub monitoring: Arc<Mutex<Vec<MonitorEntry>>>,
+    /// PIDs of apps which were stopped with `kill_app`, and so shouldn't be restarted
+    pub killed: Arc<Mutex<Vec<i32>>>,
     /// The managed root directory of the AppRegistry
     pub apps_dir: String,
+    /// Keys trusted to sign app packages. If empty, packages don't need to be signed
+    pub trusted_keys: Vec<PublicKey>,
 }
 
 impl AppRegistry {
@@ -80,7 +89,9 @@ impl AppRegistry {
         let registry = AppRegistry {
             entries: Arc::new(Mutex::new(Vec::new())),
             monitoring: Arc::new(Mutex::new(Vec::new())),
+            killed: Arc::new(Mutex::new(Vec::new())),
             apps_dir: String::from(apps_dir),
+            trusted_keys: Vec::new(),
         };
 
         registry
@@ -217,7 +228,9 @@ impl AppRegistry {
             return Err(AppError::RegisterError {
                 err: format!("{} does not exist", path),
             });
-        } else if !app_path.is_dir() {
+        }
+
+        if !app_path.is_dir() {
             // Handle tgz archives.
             return match app_path.extension().and_then(OsStr::to_str) {
                 Some("tgz") => extract_archive(self, path),
@@ -230,6 +243,24 @@ impl AppRegistry {
             };
         }
 
+        // Make sure the package came from someone we trust before looking inside it
+        let signed = if !self.trusted_keys.is_empty() {
+            Some(verify_dir(app_path, &self.trusted_keys)?)
+        } else {
+            None
+        };
+
+        self.register_dir(app_path, signed.as_ref())
+    }
+
+    // Register the app in an unpacked app directory.
+    // If the package was signed, `signed` holds the checksums of its files which were verified,
+    // and the files copied into the registry must match them
+    fn register_dir(
+        &self,
+        app_path: &Path,
+        signed: Option<&Checksums>,
+    ) -> Result<AppRegistryEntry, AppError> {
         // Load the metadata
         let mut data = String::new();
         fs::File::open(app_path.join("manifest.toml"))
@@ -326,6 +357,25 @@ impl AppRegistry {
             },
         )?;
 
+        // Record the app's files as they are now, so that we can tell if they get corrupted
+        let checksums = checksums(app_dir).map_err(|err| {
+            let _ = fs::remove_dir_all(app_dir);
+            let _ = fs::remove_dir(format!("{}/{}", self.apps_dir, app_name));
+            err
+        })?;
+
+        // The package may have been changed after its signature was checked
+        if signed.map_or(false, |signed| *signed != checksums) {
+            let _ = fs::remove_dir_all(app_dir);
+            let _ = fs::remove_dir(format!("{}/{}", self.apps_dir, app_name));
+            return Err(AppError::RegisterError {
+                err: format!(
+                    "Files in {} changed after its signature was checked",
+                    app_path.display()
+                ),
+            });
+        }
+
         let reg_entry = AppRegistryEntry {
             app: App {
                 name: app_name.clone(),
@@ -333,6 +383,9 @@ impl AppRegistry {
                 version: metadata.version,
                 author: metadata.author,
                 config,
+                restart: metadata.restart,
+                limits: metadata.limits,
+                checksums: Some(checksums),
             },
             active_version: true,
         };
@@ -584,19 +637,21 @@ impl AppRegistry {
     /// * `app_name` - The name of the app to start
     /// * `config` - (Optional) The custom config file path to use
     /// * `args` - (Optional) Arguments which should be passed to the application
+    /// * `timeout` - (Optional) How long each run of the application may last before it is stopped
     ///
     /// # Examples
     ///
     /// ```
     /// # use kubos_app::registry::AppRegistry;
     /// let registry = AppRegistry::new();
-    /// registry.start_app("my-app", None, None);
+    /// registry.start_app("my-app", None, None, None);
     /// ```
     pub fn start_app(
         &self,
         app_name: &str,
         config: Option<String>,
         args: Option<Vec<String>>,
+        timeout: Option<Duration>,
     ) -> Result<Option<i32>, AppError> {
         // Look up the active version of the requested application
         let app = {
@@ -646,20 +701,6 @@ impl AppRegistry {
             });
         }
 
-        // Change our current directory to the app's directory so that it can access any
-        // auxiliary files with relative file paths
-        if let Err(err) = app_path
-            .parent()
-            .ok_or_else(|| format_err!("Failed to get parent dir"))
-            .and_then(|parent_dir| {
-                ::std::env::set_current_dir(parent_dir).map_err(|err| err.into())
-            })
-        {
-            // If we can't change the current directory, we'll log an error and then just
-            // continue trying to execute the application
-            warn!("Failed to set cwd before executing {}: {:?}", app_name, err);
-        }
-
         // Check if app is already running
         let running_status = find_running(&self.monitoring, app_name);
         match running_status {
@@ -679,8 +720,6 @@ impl AppRegistry {
             }
         }
 
-        let mut cmd = Command::new(app_path);
-
         let config_path = match config {
             // Use the requested config file
             Some(path) => path,
@@ -688,48 +727,8 @@ impl AppRegistry {
             None => app.config.clone(),
         };
 
-        cmd.arg("-c").arg(config_path.clone());
-
-        if let Some(add_args) = args.clone() {
-            cmd.args(&add_args);
-        }
-
-        debug!("{:?} {:?}", cmd.get_program(), cmd.get_args());
-
-        let mut child = cmd.spawn().map_err(|err| {
-            error!("Failed to spawn app {}: {:?}", app_name, err);
-            AppError::StartError {
-                err: format!("Failed to spawn app: {:?}", err),
-                cause: StartErrorKind::SpawnError(err.kind()),
-            }
-        })?;
-
-        let start_time = Utc::now();
-        info!(
-            "Starting {}. Config: {:?}, Args: {:?}",
-            app_name, config_path, args
-        );
-
-        // Add/update the monitoring registry with the new run info
-        let entry = MonitorEntry {
-            start_time,
-            end_time: None,
-            name: app.name.clone(),
-            version: app.version.clone(),
-            running: true,
-            pid: Some(child.id() as i32),
-            last_rc: None,
-            last_signal: None,
-            args,
-            config: config_path,
-        };
-        if let Err(error) = start_entry(&self.monitoring, &entry) {
-            // The only way this happens is if the monitoring registry mutex gets poisoned.
-            // In that case, we want to crash this service so that it can be restarted in a
-            // good state.
-            error!("Crashing service: {:?}", error);
-            panic!("{:?}", error);
-        }
+        let started = Instant::now();
+        let mut child = self.launch(&app, &config_path, args.clone(), timeout, 0)?;
 
         // Give the app a moment to run
         thread::sleep(Duration::from_millis(300));
@@ -742,7 +741,20 @@ impl AppRegistry {
         //   - Err(err) - Something went wrong while trying to check if the app is still running.
         match child.try_wait() {
             Ok(Some(status)) => {
-                finish_entry(&self.monitoring, app_name, &app.version, status)?;
+                if end_run(self, &app, child.id() as i32, status)? {
+                    let registry = self.clone();
+
+                    // Apply the app's restart policy in the background, as if it had been
+                    // monitored from the start
+                    thread::spawn(move || {
+                        let result =
+                            restart_app(registry, app, config_path, args, timeout, started);
+
+                        if let Err(error) = result {
+                            error!("{:?}", error);
+                        }
+                    });
+                }
 
                 if !status.success() {
                     Err(AppError::StartError {
@@ -754,13 +766,12 @@ impl AppRegistry {
                 }
             }
             Ok(None) => {
-                let name = app_name.to_owned();
                 let pid = child.id() as i32;
-                let registry = self.monitoring.clone();
+                let registry = self.clone();
 
                 // Spawn monitor thread
                 thread::spawn(move || {
-                    let result = monitor_app(registry, child, &name, &app.version);
+                    let result = monitor_app(registry, child, app, config_path, args, timeout);
 
                     if let Err(error) = result {
                         error!("{:?}", error);
@@ -779,6 +790,162 @@ impl AppRegistry {
         }
     }
 
+    // Spawn a new run of an application and add it to the monitoring registry
+    pub fn launch(
+        &self,
+        app: &App,
+        config_path: &str,
+        args: Option<Vec<String>>,
+        timeout: Option<Duration>,
+        restarts: i32,
+    ) -> Result<Child, AppError> {
+        let app_path = PathBuf::from(&app.executable);
+
+        // Refuse to run the app if its files have been corrupted since it was registered
+        if let Some(checksums) = &app.checksums {
+            let app_dir = Path::new(&self.apps_dir).join(&app.name).join(&app.version);
+            verify_checksums(&app_dir, checksums)?;
+        }
+
+        // Change our current directory to the app's directory so that it can access any
+        // auxiliary files with relative file paths
+        if let Err(err) = app_path
+            .parent()
+            .ok_or_else(|| format_err!("Failed to get parent dir"))
+            .and_then(|parent_dir| {
+                ::std::env::set_current_dir(parent_dir).map_err(|err| err.into())
+            })
+        {
+            // If we can't change the current directory, we'll log an error and then just
+            // continue trying to execute the application
+            warn!("Failed to set cwd before executing {}: {:?}", app.name, err);
+        }
+
+        // The start time is used to name the run's log files, which only have millisecond
+        // precision, so truncate it to match
+        let start_time = Utc::now().trunc_subsecs(3);
+
+        // If the log files can't be created, the app's output is left going to our own
+        let logs = match open_logs(&app_path, start_time) {
+            Ok(logs) => Some(logs),
+            Err(err) => {
+                warn!("Failed to create logs for {}: {}", app.name, err);
+                None
+            }
+        };
+
+        let mut cmd = Command::new(&app_path);
+
+        cmd.arg("-c").arg(config_path);
+
+        if let Some(add_args) = &args {
+            cmd.args(add_args);
+        }
+
+        if logs.is_some() {
+            cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+
+        if let Some(limits) = &app.limits {
+            apply_limits(&mut cmd, &app.name, limits)?;
+        }
+
+        debug!("{:?} {:?}", cmd.get_program(), cmd.get_args());
+
+        let mut child = cmd.spawn().map_err(|err| {
+            error!("Failed to spawn app {}: {:?}", app.name, err);
+            AppError::StartError {
+                err: format!("Failed to spawn app: {:?}", err),
+                cause: StartErrorKind::SpawnError(err.kind()),
+            }
+        })?;
+
+        if let Some((stdout_log, stderr_log)) = logs {
+            if let Some(stdout) = child.stdout.take() {
+                capture(stdout, stdout_log);
+            }
+            if let Some(stderr) = child.stderr.take() {
+                capture(stderr, stderr_log);
+            }
+        }
+
+        info!(
+            "Starting {}. Config: {:?}, Args: {:?}",
+            app.name, config_path, args
+        );
+
+        // Add/update the monitoring registry with the new run info
+        let entry = MonitorEntry {
+            start_time,
+            end_time: None,
+            name: app.name.clone(),
+            version: app.version.clone(),
+            running: true,
+            pid: Some(child.id() as i32),
+            last_rc: None,
+            last_signal: None,
+            args,
+            config: config_path.to_owned(),
+            restarts,
+            fault: None,
+            limit_exceeded: None,
+            timed_out: false,
+        };
+        if let Err(error) = start_entry(&self.monitoring, &entry) {
+            // The only way this happens is if the monitoring registry mutex gets poisoned.
+            // In that case, we want to crash this service so that it can be restarted in a
+            // good state.
+            error!("Crashing service: {:?}", error);
+            panic!("{:?}", error);
+        }
+
+        if let Some(timeout) = timeout {
+            watch_timeout(
+                self.monitoring.clone(),
+                app.name.clone(),
+                app.version.clone(),
+                child.id() as i32,
+                timeout,
+            );
+        }
+
+        Ok(child)
+    }
+
+    /// Fetch the output captured from the recent runs of an application, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `app_name` - The name of the application
+    /// * `version` - Only return the output of this version of the application
+    /// * `lines` - The number of lines to return from the end of each log
+    pub fn app_logs(
+        &self,
+        app_name: &str,
+        version: Option<&str>,
+        lines: usize,
+    ) -> Result<Vec<AppLogs>, AppError> {
+        let entries = self.entries.lock().map_err(|err| AppError::RegistryError {
+            err: format!("Couldn't get entries mutex: {:?}", err),
+        })?;
+
+        let mut logs: Vec<AppLogs> = entries
+            .iter()
+            .filter(|e| e.app.name == app_name && version.map_or(true, |v| e.app.version == v))
+            .flat_map(|e| {
+                read_logs(
+                    Path::new(&e.app.executable),
+                    &e.app.name,
+                    &e.app.version,
+                    lines,
+                )
+            })
+            .collect();
+
+        logs.sort_by_key(|log| Reverse(log.start_time));
+        Ok(logs)
+    }
+
     pub fn kill_app(&self, name: &str, signal: Option<i32>) -> Result<(), AppError> {
         // Lookup the app in the monitoring registry to get the PID to kill
         let app = find_running(&self.monitoring, name)?.ok_or(AppError::KillError {
@@ -789,13 +956,26 @@ impl AppRegistry {
             err: "No active PID found in registry".to_owned(),
         })?;
 
-        let pid = Pid::from_raw(pid);
+        // Holding the lock stops the app's monitor from checking whether the run was killed
+        // until it has been recorded
+        let mut killed = self.killed.lock().map_err(|err| AppError::KillError {
+            err: format!("Couldn't get killed mutex: {:?}", err),
+        })?;
+
         let sig = signal::Signal::from_c_int(signal.unwrap_or(15) as i32)
             .unwrap_or(signal::Signal::SIGTERM);
 
-        signal::kill(pid, sig).map_err(|err| AppError::KillError {
+        signal::kill(Pid::from_raw(pid), sig).map_err(|err| AppError::KillError {
             err: err.to_string(),
-        })
+        })?;
+
+        // Make sure the app's monitor doesn't restart it once it stops.
+        // The PID is forgotten again once the monitor sees this run exit
+        if !killed.contains(&pid) {
+            killed.push(pid);
+        }
+
+        Ok(())
     }
 }
 
@@ -821,6 +1001,21 @@ fn extract_archive(registry: &AppRegistry, path: &str) -> Result<AppRegistryEntr
         ),
     })?;
 
+    // Work from our own copy of the archive, so that the archive which is extracted is the same
+    // one whose signature was checked
+    let archive = tmp_dir.path().join("app.tgz");
+    let contents = tmp_dir.path().join("app");
+    fs::copy(path, &archive)
+        .and_then(|_| fs::create_dir(&contents))
+        .map_err(|error| AppError::RegisterError {
+            err: format!("Error copying archive: {}", error),
+        })?;
+
+    // Make sure the package came from someone we trust before looking inside it
+    if !registry.trusted_keys.is_empty() {
+        verify_archive(Path::new(path), &archive, &registry.trusted_keys)?;
+    }
+
     let mut command = if PathBuf::from("/usr/bin/tar").exists() {
         Command::new("/usr/bin/tar")
     } else if PathBuf::from("/bin/tar").exists() {
@@ -833,9 +1028,9 @@ fn extract_archive(registry: &AppRegistry, path: &str) -> Result<AppRegistryEntr
 
     let output = command
         .arg("-zxf")
-        .arg(path)
+        .arg(&archive)
         .arg("--directory")
-        .arg(tmp_dir.path())
+        .arg(&contents)
         .output()
         .map_err(|error| AppError::RegisterError {
             err: format!("Error expanding archive: {}", error),
@@ -843,12 +1038,8 @@ fn extract_archive(registry: &AppRegistry, path: &str) -> Result<AppRegistryEntr
 
     if output.status.success() {
         // Ensure they packaged the tarball correctly.
-        if Path::new(&tmp_dir.path().join("manifest.toml")).exists() {
-            let tmp_dir_path =
-                OsStr::to_str(tmp_dir.path().as_os_str()).ok_or(AppError::RegisterError {
-                    err: String::from("Error converting temp dir path to UTF8"),
-                })?;
-            registry.register(tmp_dir_path)
+        if Path::new(&contents.join("manifest.toml")).exists() {
+            registry.register_dir(&contents, None)
         } else {
             Err(AppError::RegisterError {
                 err: String::from("Manifest file manifest.toml not found in root of archive. When you create the archive, do so in the application directory, with a command like: tar -czf archive.tgz *")
@@ -14,10 +14,12 @@
  * limitations under the License.
  */
 
+use crate::logs::{AppLogs, DEFAULT_LOG_LINES};
 use crate::monitor::MonitorEntry;
 use crate::objects::*;
 use crate::registry::AppRegistry;
 use juniper::FieldResult;
+use std::time::Duration;
 
 type Context = kubos_service::Context<AppRegistry>;
 
@@ -84,6 +86,29 @@ graphql_object!(QueryRoot : Context as "Query" |&self| {
         Ok(result)
     }
 
+    // Output captured from the recent runs of an app, newest first
+    //
+    // {
+    //    appLogs(name: String!, version: String, lines: Int): [{
+    //        name: String!,
+    //        version: String!,
+    //        startTime: String!,
+    //        stdoutPath: String!,
+    //        stderrPath: String!,
+    //        stdout: [String!]!,
+    //        stderr: [String!]!
+    //    }]
+    // }
+    field app_logs(&executor,
+        name: String,
+        version: Option<String>,
+        lines: Option<i32>)
+        -> FieldResult<Vec<AppLogs>> as "App Logs Query"
+    {
+        let lines = lines.map_or(DEFAULT_LOG_LINES, |lines| lines.max(0) as usize);
+        Ok(executor.context().subsystem().app_logs(&name, version.as_deref(), lines)?)
+    }
+
 });
 
 ///
@@ -131,10 +156,24 @@ graphql_object!(MutationRoot : Context as "Mutation" |&self| {
         })
     }
 
-    field start_app(&executor, name: String, config: Option<String>, args: Option<Vec<String>>) -> FieldResult<StartResponse>
-        as "Start App"
+    field start_app(&executor,
+        name: String,
+        config: Option<String>,
+        args: Option<Vec<String>>,
+        timeout: Option<i32>)
+        -> FieldResult<StartResponse> as "Start App"
     {
-        Ok(match executor.context().subsystem().start_app(&name, config, args) {
+        let timeout = match timeout {
+            Some(secs) if secs <= 0 => return Ok(StartResponse {
+                success: false,
+                errors: "Timeout must be greater than zero".to_owned(),
+                pid: None,
+            }),
+            Some(secs) => Some(Duration::from_secs(secs as u64)),
+            None => None,
+        };
+
+        Ok(match executor.context().subsystem().start_app(&name, config, args, timeout) {
             Ok(pid) => StartResponse { success: true, errors: "".to_owned(), pid},
             Err(error) => StartResponse { success: false, errors: error.to_string(), pid: None },
         })
@@ -0,0 +1,121 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+
+use super::setup_registry;
+use crate::logs::*;
+
+#[test]
+fn app_logs_captures_output() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(&registry_dir, "echo out1\necho out2\necho err1 >&2", "");
+
+    registry.start_app("tiny-app", None, None, None).unwrap();
+
+    // Give the capture threads a moment to finish writing
+    thread::sleep(Duration::from_millis(100));
+
+    let logs = registry
+        .app_logs("tiny-app", None, DEFAULT_LOG_LINES)
+        .unwrap();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].version, "1.0");
+    assert_eq!(logs[0].stdout, vec!["out1", "out2"]);
+    assert_eq!(logs[0].stderr, vec!["err1"]);
+    assert!(Path::new(&logs[0].stdout_path).exists());
+    assert!(Path::new(&logs[0].stderr_path).exists());
+
+    // The logs should be tied to the run's monitoring entry
+    let entries = registry.monitoring.lock().unwrap();
+    assert_eq!(logs[0].start_time, entries[0].start_time);
+}
+
+#[test]
+fn app_logs_last_lines() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "for i in $(seq 1 30); do echo line$i; done",
+        "",
+    );
+
+    registry.start_app("tiny-app", None, None, None).unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let logs = registry.app_logs("tiny-app", Some("1.0"), 3).unwrap();
+    assert_eq!(logs[0].stdout, vec!["line28", "line29", "line30"]);
+    assert!(logs[0].stderr.is_empty());
+}
+
+#[test]
+fn app_logs_keeps_recent_runs() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(&registry_dir, "echo ${@: -1}", "");
+
+    for run in 0..LOG_RUNS_KEPT + 2 {
+        registry
+            .start_app("tiny-app", None, Some(vec![run.to_string()]), None)
+            .unwrap();
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    let logs = registry.app_logs("tiny-app", None, 1).unwrap();
+    assert_eq!(logs.len(), LOG_RUNS_KEPT);
+
+    // The newest run should come first, and the oldest runs should have been removed
+    assert_eq!(logs[0].stdout, vec![(LOG_RUNS_KEPT + 1).to_string()]);
+    assert_eq!(logs[LOG_RUNS_KEPT - 1].stdout, vec!["2"]);
+}
+
+#[test]
+fn app_logs_rotates() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        &format!(
+            "head -c {} /dev/zero | tr '\\0' 'a'\necho\necho last",
+            MAX_LOG_SIZE + 1000
+        ),
+        "",
+    );
+
+    registry.start_app("tiny-app", None, None, None).unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    let logs = registry.app_logs("tiny-app", None, 1).unwrap();
+    assert_eq!(logs[0].stdout, vec!["last"]);
+
+    let rotated = format!("{}.1", logs[0].stdout_path);
+    assert!(fs::metadata(&rotated).unwrap().len() <= MAX_LOG_SIZE);
+    assert!(fs::metadata(&logs[0].stdout_path).unwrap().len() < MAX_LOG_SIZE);
+}
+
+#[test]
+fn app_logs_unknown_app() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(&registry_dir, "echo out1", "");
+
+    assert!(registry
+        .app_logs("other-app", None, DEFAULT_LOG_LINES)
+        .unwrap()
+        .is_empty());
+}
@@ -64,18 +64,70 @@ macro_rules! test {
     }};
 }
 
+mod app_logs;
+mod package_signing;
 mod register_app;
 mod registry_start_app;
 mod registry_test;
+mod resource_limits;
+mod restart_policy;
+mod run_timeout;
 mod set_version;
 mod upgrade_app;
 
+use crate::monitor::MonitorEntry;
 use crate::registry::*;
 use crate::schema;
 use kubos_service::{Config, Service};
 use serde_json::json;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
 use tempfile::TempDir;
 
+// Create a registry containing a single app, tiny-app 1.0, which runs the given shell script.
+// `extra` is added to the end of the app's registry entry, for sections like `[app.restart]`
+fn setup_registry(registry_dir: &TempDir, script: &str, extra: &str) -> AppRegistry {
+    // Since we're creating the app files directly in the app registry, we need to manually
+    // control the lifetime of the app binary so that all the data gets written and the file gets
+    // closed before we attempt to execute it
+    {
+        let app_dir = registry_dir.path().join("tiny-app/1.0");
+
+        fs::create_dir_all(app_dir.clone()).unwrap();
+
+        let mut bin = fs::File::create(app_dir.join("tiny-app")).unwrap();
+        bin.write_all(format!("#!/bin/bash\n{}", script).as_bytes())
+            .unwrap();
+        let mut perms = bin.metadata().unwrap().permissions();
+        perms.set_mode(0o755);
+        bin.set_permissions(perms).unwrap();
+
+        let app_toml = format!(
+            r#"active_version = true
+
+            [app]
+            executable = "{}/tiny-app/1.0/tiny-app"
+            name = "tiny-app"
+            version = "1.0"
+            author = "user"
+            config = "/custom/config.toml"
+
+            {}"#,
+            registry_dir.path().to_string_lossy(),
+            extra
+        );
+        fs::write(app_dir.join("app.toml"), app_toml).unwrap();
+    }
+
+    AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap()
+}
+
+// Get the monitoring entry of the registry's only app
+fn status(registry: &AppRegistry) -> MonitorEntry {
+    registry.monitoring.lock().unwrap()[0].clone()
+}
+
 #[test]
 fn ping() {
     let registry_dir = TempDir::new().unwrap();
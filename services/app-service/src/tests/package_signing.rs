@@ -0,0 +1,269 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use tempfile::TempDir;
+
+use crate::app_entry::*;
+use crate::error::*;
+use crate::integrity::*;
+use crate::registry::*;
+
+fn keypair(seed: u8) -> Keypair {
+    let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+// Create an app directory, ready to be registered, within the given directory
+fn create_app(parent: &TempDir, script: &str) -> PathBuf {
+    let app_dir = parent.path().join("tiny-app");
+    fs::create_dir(&app_dir).unwrap();
+
+    let bin = app_dir.join("tiny-app");
+    fs::write(&bin, format!("#!/bin/bash\n{}", script)).unwrap();
+    fs::set_permissions(&bin, fs::Permissions::from_mode(0o755)).unwrap();
+
+    fs::write(
+        app_dir.join("manifest.toml"),
+        r#"name = "tiny-app"
+        version = "1.0"
+        author = "user""#,
+    )
+    .unwrap();
+
+    app_dir
+}
+
+fn create_archive(parent: &TempDir, app_dir: &Path) -> PathBuf {
+    let archive = parent.path().join("tiny-app.tgz");
+    let status = Command::new("tar")
+        .arg("-czf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(app_dir)
+        .arg(".")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    archive
+}
+
+fn signature_path(package: &Path) -> PathBuf {
+    let mut path = package.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+// Sign an app directory the same way the docs describe
+fn sign_dir(app_dir: &Path, keypair: &Keypair) {
+    let listing = Command::new("sh")
+        .arg("-c")
+        .arg("find . -type f | LC_ALL=C sort | xargs sha256sum")
+        .current_dir(app_dir)
+        .output()
+        .unwrap();
+
+    fs::write(
+        signature_path(app_dir),
+        &keypair.sign(&listing.stdout).to_bytes()[..],
+    )
+    .unwrap();
+}
+
+fn sign_archive(archive: &Path, keypair: &Keypair) {
+    let data = fs::read(archive).unwrap();
+    fs::write(signature_path(archive), &keypair.sign(&data).to_bytes()[..]).unwrap();
+}
+
+fn signed_registry(registry_dir: &TempDir) -> AppRegistry {
+    let mut registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.trusted_keys = vec![keypair(1).public];
+    registry
+}
+
+fn register_error(result: Result<AppRegistryEntry, AppError>) -> String {
+    match result {
+        Err(AppError::RegisterError { err }) => err,
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn trusted_keys_parse() {
+    let public = keypair(1).public;
+    let hex: String = public
+        .as_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    let config: toml::Value = toml::from_str(&format!("trusted-keys = [\"{}\"]", hex)).unwrap();
+    assert_eq!(
+        parse_trusted_keys(&config["trusted-keys"]).unwrap(),
+        vec![public]
+    );
+
+    let config: toml::Value = toml::from_str("trusted-keys = [\"abcd\"]").unwrap();
+    assert_eq!(
+        parse_trusted_keys(&config["trusted-keys"]),
+        Err(AppError::ParseError {
+            entity: "trusted-keys".to_owned(),
+            err: "Invalid Ed25519 public key abcd".to_owned(),
+        })
+    );
+}
+
+#[test]
+fn signed_dir_registers() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let app_dir = create_app(&package_dir, "exit 0");
+    sign_dir(&app_dir, &keypair(1));
+
+    let registry = signed_registry(&registry_dir);
+    let entry = registry.register(&app_dir.to_string_lossy()).unwrap();
+    assert_eq!(entry.app.name, "tiny-app");
+}
+
+#[test]
+fn signed_archive_registers() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let app_dir = create_app(&package_dir, "exit 0");
+    let archive = create_archive(&package_dir, &app_dir);
+    sign_archive(&archive, &keypair(1));
+
+    let registry = signed_registry(&registry_dir);
+    let entry = registry.register(&archive.to_string_lossy()).unwrap();
+    assert_eq!(entry.app.name, "tiny-app");
+}
+
+#[test]
+fn tampered_archive_rejected() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let app_dir = create_app(&package_dir, "exit 0");
+    let archive = create_archive(&package_dir, &app_dir);
+    sign_archive(&archive, &keypair(1));
+
+    let mut data = fs::read(&archive).unwrap();
+    data.push(0);
+    fs::write(&archive, data).unwrap();
+
+    let registry = signed_registry(&registry_dir);
+    let err = register_error(registry.register(&archive.to_string_lossy()));
+    assert_eq!(
+        err,
+        format!(
+            "Signature of {} doesn't match any trusted key",
+            archive.display()
+        )
+    );
+    assert!(registry.entries.lock().unwrap().is_empty());
+}
+
+#[test]
+fn tampered_dir_rejected() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let app_dir = create_app(&package_dir, "exit 0");
+    sign_dir(&app_dir, &keypair(1));
+    fs::write(app_dir.join("extra"), "not signed").unwrap();
+
+    let registry = signed_registry(&registry_dir);
+    let err = register_error(registry.register(&app_dir.to_string_lossy()));
+    assert!(err.contains("doesn't match any trusted key"));
+}
+
+#[test]
+fn untrusted_key_rejected() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let app_dir = create_app(&package_dir, "exit 0");
+    sign_dir(&app_dir, &keypair(2));
+
+    let registry = signed_registry(&registry_dir);
+    let err = register_error(registry.register(&app_dir.to_string_lossy()));
+    assert!(err.contains("doesn't match any trusted key"));
+}
+
+#[test]
+fn unsigned_rejected() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let app_dir = create_app(&package_dir, "exit 0");
+
+    let registry = signed_registry(&registry_dir);
+    let err = register_error(registry.register(&app_dir.to_string_lossy()));
+    assert!(err.starts_with(&format!(
+        "Unable to read signature {}",
+        signature_path(&app_dir).display()
+    )));
+}
+
+#[test]
+fn checksums_saved() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let app_dir = create_app(&package_dir, "exit 0");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry.register(&app_dir.to_string_lossy()).unwrap();
+
+    let checksums = entry.app.checksums.clone().unwrap();
+    assert_eq!(
+        checksums.keys().collect::<Vec<_>>(),
+        vec!["manifest.toml", "tiny-app"]
+    );
+
+    // The checksums should be saved with the rest of the app's registry entry
+    let saved = AppRegistryEntry::from_dir(&registry_dir.path().join("tiny-app/1.0")).unwrap();
+    assert_eq!(saved.app.checksums, Some(checksums));
+}
+
+#[test]
+fn start_app_detects_corruption() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let app_dir = create_app(&package_dir, "echo data > output.txt");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.register(&app_dir.to_string_lossy()).unwrap();
+
+    // Files the app writes itself shouldn't stop it from starting again
+    registry.start_app("tiny-app", None, None, None).unwrap();
+    registry.start_app("tiny-app", None, None, None).unwrap();
+
+    let bin = registry_dir.path().join("tiny-app/1.0/tiny-app");
+    fs::write(&bin, "#!/bin/bash\nexit 1").unwrap();
+
+    match registry.start_app("tiny-app", None, None, None) {
+        Err(AppError::StartError { err, cause }) => {
+            assert_eq!(
+                err,
+                "Integrity check failed: tiny-app has changed since the app was registered"
+            );
+            assert_eq!(cause, StartErrorKind::IntegrityError);
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
@@ -67,7 +67,7 @@ fn start_app_good() {
 
     // Create the registry
     let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
-    let result = registry.start_app("tiny-app", None, None);
+    let result = registry.start_app("tiny-app", None, None, None);
 
     // Small sleep to prevent tiny-app from being destroyed before
     // the system finishes calling it
@@ -97,7 +97,7 @@ fn start_app_bad_permissions() {
     // Create the registry
     let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
 
-    let result = registry.start_app("tiny-app", None, None);
+    let result = registry.start_app("tiny-app", None, None, None);
 
     assert!(matches!(
         result,
@@ -123,7 +123,7 @@ fn start_app_bad_install() {
     // Create the registry
     let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
 
-    let result = registry.start_app("tiny-app", None, None);
+    let result = registry.start_app("tiny-app", None, None, None);
 
     assert!(matches!(
         result,
@@ -161,7 +161,7 @@ fn start_app_nonzero_exit() {
     // Create the registry
     let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
 
-    let result = registry.start_app("tiny-app", None, None);
+    let result = registry.start_app("tiny-app", None, None, None);
 
     // Small sleep to prevent tiny-app from being destroyed before
     // the system finishes calling it
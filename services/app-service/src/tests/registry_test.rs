@@ -64,6 +64,9 @@ fn serialize_entry() {
             author: String::from("noone"),
             executable: String::from("/fake/path"),
             config: String::from("/etc/kubos-config.toml"),
+            restart: None,
+            limits: None,
+            checksums: None,
         },
         active_version: true,
     };
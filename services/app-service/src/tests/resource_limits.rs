@@ -0,0 +1,124 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+
+use super::setup_registry;
+use crate::app_entry::*;
+use crate::error::*;
+use crate::registry::*;
+
+// Start the app and return what it wrote to stdout
+fn run_app(registry: &AppRegistry) -> Vec<String> {
+    registry.start_app("tiny-app", None, None, None).unwrap();
+
+    // Give the capture threads a moment to finish writing
+    thread::sleep(Duration::from_millis(100));
+
+    registry.app_logs("tiny-app", None, 10).unwrap()[0]
+        .stdout
+        .clone()
+}
+
+#[test]
+fn limits_parse() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "exit 0",
+        "[app.limits]\nmemory = 1000000\ncpu_time = 10\nnice = 5\nopen_files = 64\nuser = \"kubos\"\ncgroup_cpu = 50",
+    );
+
+    assert_eq!(
+        registry.entries.lock().unwrap()[0].app.limits,
+        Some(Limits {
+            memory: Some(1_000_000),
+            cpu_time: Some(10),
+            nice: Some(5),
+            open_files: Some(64),
+            user: Some("kubos".to_owned()),
+            group: None,
+            cgroup_memory: None,
+            cgroup_cpu: Some(50),
+        })
+    );
+}
+
+#[test]
+fn limits_rlimits() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "ulimit -n\nulimit -v\nulimit -t",
+        "[app.limits]\nmemory = 1073741824\nopen_files = 64\ncpu_time = 30",
+    );
+
+    // ulimit reports memory in KiB
+    assert_eq!(run_app(&registry), vec!["64", "1048576", "30"]);
+}
+
+#[test]
+fn limits_nice() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(&registry_dir, "nice", "[app.limits]\nnice = 7");
+
+    assert_eq!(run_app(&registry), vec!["7"]);
+}
+
+#[test]
+fn limits_cpu_time_exceeded() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "sleep 0.2\nwhile :; do :; done",
+        "[app.limits]\ncpu_time = 1",
+    );
+
+    registry.start_app("tiny-app", None, None, None).unwrap();
+
+    // Wait for the app to use up its CPU time
+    thread::sleep(Duration::from_millis(2500));
+
+    let entry = registry.monitoring.lock().unwrap()[0].clone();
+    assert!(!entry.running);
+    assert_eq!(entry.last_signal, Some(24));
+    assert_eq!(
+        entry.limit_exceeded,
+        Some("CPU time limit of 1 seconds exceeded".to_owned())
+    );
+}
+
+#[test]
+fn limits_unknown_user() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "exit 0",
+        "[app.limits]\nuser = \"no-such-user\"",
+    );
+
+    match registry.start_app("tiny-app", None, None, None) {
+        Err(AppError::StartError { err, cause }) => {
+            assert_eq!(err, "Unknown user no-such-user");
+            assert_eq!(cause, StartErrorKind::LimitError);
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert!(registry.monitoring.lock().unwrap().is_empty());
+}
@@ -0,0 +1,240 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+
+use super::{setup_registry, status};
+use crate::app_entry::*;
+use crate::monitor::*;
+use crate::registry::*;
+
+#[test]
+fn restart_policy_parse() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "exit 0",
+        "[app.restart]\nmode = \"on-failure\"\nmax_retries = 3\nbackoff = 5",
+    );
+
+    assert_eq!(
+        registry.entries.lock().unwrap()[0].app.restart,
+        Some(RestartPolicy {
+            mode: RestartMode::OnFailure,
+            max_retries: Some(3),
+            backoff: Some(5),
+        })
+    );
+}
+
+#[test]
+fn restart_policy_register() {
+    let registry_dir = TempDir::new().unwrap();
+    let app_dir = TempDir::new().unwrap();
+
+    fs::write(app_dir.path().join("tiny-app"), "#!/bin/bash\nexit 0").unwrap();
+    fs::write(
+        app_dir.path().join("manifest.toml"),
+        r#"name = "tiny-app"
+        version = "1.0"
+        author = "user"
+
+        [restart]
+        mode = "always"
+        backoff = 10"#,
+    )
+    .unwrap();
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry
+        .register(&app_dir.path().to_string_lossy())
+        .unwrap();
+
+    let policy = Some(RestartPolicy {
+        mode: RestartMode::Always,
+        max_retries: None,
+        backoff: Some(10),
+    });
+    assert_eq!(entry.app.restart, policy);
+
+    // The policy should be saved with the rest of the app's registry entry
+    let saved = AppRegistryEntry::from_dir(&registry_dir.path().join("tiny-app/1.0")).unwrap();
+    assert_eq!(saved.app.restart, policy);
+}
+
+#[test]
+fn restart_on_failure_max_retries() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "sleep 0.5\nexit 1",
+        "[app.restart]\nmode = \"on-failure\"\nmax_retries = 2\nbackoff = 0",
+    );
+
+    registry.start_app("tiny-app", None, None, None).unwrap();
+
+    // Wait for the app to run three times
+    thread::sleep(Duration::from_millis(2500));
+
+    let entry = status(&registry);
+    assert!(!entry.running);
+    assert_eq!(entry.restarts, 2);
+    assert_eq!(entry.last_rc, Some(1));
+    assert_eq!(
+        entry.fault,
+        Some("Still failing after 2 restarts".to_owned())
+    );
+}
+
+#[test]
+fn restart_on_failure_success() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "sleep 0.5\nexit 0",
+        "[app.restart]\nmode = \"on-failure\"\nbackoff = 0",
+    );
+
+    registry.start_app("tiny-app", None, None, None).unwrap();
+    thread::sleep(Duration::from_millis(1500));
+
+    let entry = status(&registry);
+    assert!(!entry.running);
+    assert_eq!(entry.restarts, 0);
+    assert_eq!(entry.fault, None);
+}
+
+#[test]
+fn restart_always_crash_loop() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "sleep 0.4",
+        "[app.restart]\nmode = \"always\"\nbackoff = 0",
+    );
+
+    registry.start_app("tiny-app", None, None, None).unwrap();
+
+    // Wait for the app to run until it's detected as crash looping
+    thread::sleep(Duration::from_millis(
+        (CRASH_LOOP_RUNS as u64 + 1) * 400 + 1000,
+    ));
+
+    let entry = status(&registry);
+    assert!(!entry.running);
+    assert_eq!(entry.restarts, CRASH_LOOP_RUNS as i32 - 1);
+    assert_eq!(
+        entry.fault,
+        Some(format!(
+            "Crash loop detected: exited {} times in a row within 60 seconds of starting",
+            CRASH_LOOP_RUNS
+        ))
+    );
+}
+
+#[test]
+fn restart_not_after_kill() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "sleep 10",
+        "[app.restart]\nmode = \"always\"\nbackoff = 0",
+    );
+
+    registry.start_app("tiny-app", None, None, None).unwrap();
+    registry.kill_app("tiny-app", None).unwrap();
+    thread::sleep(Duration::from_millis(500));
+
+    let entry = status(&registry);
+    assert!(!entry.running);
+    assert_eq!(entry.restarts, 0);
+    assert_eq!(entry.last_signal, Some(15));
+    assert!(registry.killed.lock().unwrap().is_empty());
+}
+
+#[test]
+fn restart_kill_ignored_signal() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "trap '' USR1\nsleep 1\nexit 1",
+        "[app.restart]\nmode = \"on-failure\"\nbackoff = 0",
+    );
+
+    registry.start_app("tiny-app", None, None, None).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    registry.kill_app("tiny-app", Some(10)).unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    // The app survives the signal, so it's still recorded as killed until it exits
+    assert!(status(&registry).running);
+    assert_eq!(registry.killed.lock().unwrap().len(), 1);
+
+    thread::sleep(Duration::from_millis(1500));
+
+    let entry = status(&registry);
+    assert!(!entry.running);
+    assert_eq!(entry.restarts, 0);
+    assert!(registry.killed.lock().unwrap().is_empty());
+}
+
+#[test]
+fn restart_manual_start_resets() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "sleep 0.5\nexit 1",
+        "[app.restart]\nmode = \"on-failure\"\nmax_retries = 1\nbackoff = 0",
+    );
+
+    registry.start_app("tiny-app", None, None, None).unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    assert_eq!(status(&registry).restarts, 1);
+
+    // Starting the app again should clear its restart count and fault
+    registry.start_app("tiny-app", None, None, None).unwrap();
+    let entry = status(&registry);
+    assert!(entry.running);
+    assert_eq!(entry.restarts, 0);
+    assert_eq!(entry.fault, None);
+}
+
+#[test]
+fn restart_immediate_failure() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "exit 1",
+        "[app.restart]\nmode = \"on-failure\"\nmax_retries = 1\nbackoff = 0",
+    );
+
+    // The app exits before it's finished being started, but should still be restarted
+    assert!(registry.start_app("tiny-app", None, None, None).is_err());
+    thread::sleep(Duration::from_millis(1000));
+
+    let entry = status(&registry);
+    assert!(!entry.running);
+    assert_eq!(entry.restarts, 1);
+    assert_eq!(entry.last_rc, Some(1));
+    assert_eq!(
+        entry.fault,
+        Some("Still failing after 1 restarts".to_owned())
+    );
+}
@@ -0,0 +1,107 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+
+use super::{setup_registry, status};
+use crate::monitor::*;
+
+#[test]
+fn timeout_terminates_app() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(&registry_dir, "sleep 10", "");
+
+    registry
+        .start_app("tiny-app", None, None, Some(Duration::from_secs(1)))
+        .unwrap();
+    thread::sleep(Duration::from_millis(1500));
+
+    let entry = status(&registry);
+    assert!(!entry.running);
+    assert!(entry.timed_out);
+    assert_eq!(entry.last_signal, Some(15));
+
+    // The app should be able to run again straight away
+    registry.start_app("tiny-app", None, None, None).unwrap();
+    let entry = status(&registry);
+    assert!(entry.running);
+    assert!(!entry.timed_out);
+}
+
+#[test]
+fn timeout_kills_after_grace() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "trap '' TERM\nwhile :; do sleep 0.1; done",
+        "",
+    );
+
+    registry
+        .start_app("tiny-app", None, None, Some(Duration::from_secs(1)))
+        .unwrap();
+
+    // The app should survive SIGTERM, then be killed once the grace period is over
+    thread::sleep(Duration::from_millis(1500));
+    assert!(status(&registry).running);
+    thread::sleep(TIMEOUT_GRACE);
+
+    let entry = status(&registry);
+    assert!(!entry.running);
+    assert!(entry.timed_out);
+    assert_eq!(entry.last_signal, Some(9));
+}
+
+#[test]
+fn timeout_not_reached() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(&registry_dir, "sleep 0.5", "");
+
+    registry
+        .start_app("tiny-app", None, None, Some(Duration::from_secs(2)))
+        .unwrap();
+    thread::sleep(Duration::from_millis(2500));
+
+    let entry = status(&registry);
+    assert!(!entry.running);
+    assert!(!entry.timed_out);
+    assert_eq!(entry.last_rc, Some(0));
+}
+
+#[test]
+fn timeout_not_restarted() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = setup_registry(
+        &registry_dir,
+        "sleep 10",
+        "[app.restart]\nmode = \"on-failure\"\nbackoff = 0",
+    );
+
+    registry
+        .start_app("tiny-app", None, None, Some(Duration::from_secs(1)))
+        .unwrap();
+    thread::sleep(Duration::from_millis(2000));
+
+    // The app was stopped on purpose, so its restart policy shouldn't start it again
+    let entry = status(&registry);
+    assert!(!entry.running);
+    assert!(entry.timed_out);
+    assert_eq!(entry.restarts, 0);
+    assert_eq!(entry.last_signal, Some(15));
+}
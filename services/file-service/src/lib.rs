@@ -92,6 +92,23 @@ pub fn recv_loop(config: &ServiceConfig) -> Result<(), failure::Error> {
         .and_then(|chunks| chunks.as_integer())
         .map(|chunks| chunks as u32);
 
+    // Get the number of chunks sent before waiting for a selective ack
+    let window_size = match config
+        .get("window_size")
+        .and_then(|chunks| chunks.as_integer())
+    {
+        Some(chunks) if (1..=i64::from(u32::MAX)).contains(&chunks) => Some(chunks as u32),
+        Some(chunks) => {
+            error!(
+                "Invalid window_size {}. Must be between 1 and {}",
+                chunks,
+                u32::MAX
+            );
+            failure::bail!("Invalid window_size {}", chunks);
+        }
+        None => None,
+    };
+
     info!("Starting file transfer service");
     info!("Listening on {}", host);
     info!("Downlinking to {}:{}", downlink_ip, downlink_port);
@@ -105,6 +122,7 @@ pub fn recv_loop(config: &ServiceConfig) -> Result<(), failure::Error> {
         inter_chunk_delay,
         max_chunks_transmit,
         hash_chunk_size,
+        window_size,
     );
 
     let c_protocol = cbor_protocol::Protocol::new(&host.clone(), transfer_chunk_size);
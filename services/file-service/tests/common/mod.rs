@@ -17,8 +17,10 @@
 #![allow(dead_code)]
 
 use blake2_rfc::blake2s::Blake2s;
-use file_protocol::{FileProtocol, FileProtocolConfig, ProtocolError, State};
-use serde_cbor::{from_slice, ser};
+use file_protocol::{
+    Compression, Direction, FileProtocol, FileProtocolConfig, Manifest, ProtocolError, State,
+};
+use serde_cbor::{from_slice, ser, Value};
 use std::fs::File;
 use std::io::prelude::*;
 use std::thread;
@@ -63,6 +65,29 @@ pub fn download(
     target_path: &str,
     prefix: Option<String>,
     chunk_size: u32,
+) -> Result<(), ProtocolError> {
+    download_compressed(
+        host_ip,
+        host_port,
+        remote_addr,
+        source_path,
+        target_path,
+        prefix,
+        chunk_size,
+        None,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn download_compressed(
+    host_ip: &str,
+    host_port: u16,
+    remote_addr: &str,
+    source_path: &str,
+    target_path: &str,
+    prefix: Option<String>,
+    chunk_size: u32,
+    compression: Option<Compression>,
 ) -> Result<(), ProtocolError> {
     let hold_count = 5;
     let f_config = FileProtocolConfig::new(
@@ -72,6 +97,7 @@ pub fn download(
         1,
         None,
         (chunk_size as usize) * 2,
+        None,
     );
     let f_protocol =
         FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
@@ -80,7 +106,7 @@ pub fn download(
 
     // Send our file request to the remote addr and verify that it's
     // going to be able to send it
-    f_protocol.send_import(channel, source_path)?;
+    f_protocol.send_import(channel, source_path, compression)?;
 
     // Wait for the request reply.
     // Note/TODO: We don't use a timeout here because we don't know how long it will
@@ -118,6 +144,7 @@ pub fn download_partial(
         1,
         None,
         (chunk_size as usize) * 2,
+        None,
     );
     let f_protocol =
         FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
@@ -126,7 +153,7 @@ pub fn download_partial(
 
     // Send our file request to the remote addr and verify that it's
     // going to be able to send it
-    f_protocol.send_import(channel, source_path)?;
+    f_protocol.send_import(channel, source_path, None)?;
 
     // Wait for the request reply.
     // Note/TODO: We don't use a timeout here because we don't know how long it will
@@ -170,6 +197,78 @@ pub fn upload(
     target_path: &str,
     prefix: Option<String>,
     chunk_size: u32,
+) -> Result<String, ProtocolError> {
+    upload_compressed(
+        host_ip,
+        host_port,
+        remote_addr,
+        source_path,
+        target_path,
+        prefix,
+        chunk_size,
+        None,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn upload_compressed(
+    host_ip: &str,
+    host_port: u16,
+    remote_addr: &str,
+    source_path: &str,
+    target_path: &str,
+    prefix: Option<String>,
+    chunk_size: u32,
+    compression: Option<Compression>,
+) -> Result<String, ProtocolError> {
+    upload_with(
+        host_ip,
+        host_port,
+        remote_addr,
+        source_path,
+        target_path,
+        prefix,
+        chunk_size,
+        compression,
+        None,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn upload_windowed(
+    host_ip: &str,
+    host_port: u16,
+    remote_addr: &str,
+    source_path: &str,
+    target_path: &str,
+    prefix: Option<String>,
+    chunk_size: u32,
+    window_size: u32,
+) -> Result<String, ProtocolError> {
+    upload_with(
+        host_ip,
+        host_port,
+        remote_addr,
+        source_path,
+        target_path,
+        prefix,
+        chunk_size,
+        None,
+        Some(window_size),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn upload_with(
+    host_ip: &str,
+    host_port: u16,
+    remote_addr: &str,
+    source_path: &str,
+    target_path: &str,
+    prefix: Option<String>,
+    chunk_size: u32,
+    compression: Option<Compression>,
+    window_size: Option<u32>,
 ) -> Result<String, ProtocolError> {
     let hold_count = 5;
     let f_config = FileProtocolConfig::new(
@@ -179,6 +278,7 @@ pub fn upload(
         1,
         None,
         (chunk_size as usize) * 2,
+        window_size,
     );
     let f_protocol =
         FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
@@ -192,7 +292,7 @@ pub fn upload(
     f_protocol.send_metadata(channel, &hash, num_chunks)?;
 
     // send export command for file
-    f_protocol.send_export(channel, &hash, target_path, mode)?;
+    f_protocol.send_export(channel, &hash, target_path, mode, compression)?;
 
     // start the engine to send the file data chunks
     f_protocol.message_engine(
@@ -223,6 +323,7 @@ pub fn upload_partial(
         1,
         None,
         (chunk_size as usize) * 2,
+        None,
     );
     let f_protocol =
         FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
@@ -236,7 +337,7 @@ pub fn upload_partial(
     f_protocol.send_metadata(channel, &hash, num_chunks - 1)?;
 
     // Send export command for file
-    f_protocol.send_export(channel, &hash, target_path, mode)?;
+    f_protocol.send_export(channel, &hash, target_path, mode, None)?;
 
     // Start the engine to send the file data chunks
     f_protocol.message_engine(
@@ -250,6 +351,179 @@ pub fn upload_partial(
     Ok(hash)
 }
 
+// Start an upload, but stop after sending the first chunk
+pub fn upload_interrupted(
+    host_ip: &str,
+    host_port: u16,
+    remote_addr: &str,
+    source_path: &str,
+    target_path: &str,
+    prefix: Option<String>,
+    chunk_size: u32,
+) -> Result<String, ProtocolError> {
+    let hold_count = 5;
+    let f_config = FileProtocolConfig::new(
+        prefix.clone(),
+        chunk_size as usize,
+        hold_count,
+        1,
+        None,
+        (chunk_size as usize) * 2,
+        None,
+    );
+    let f_protocol =
+        FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
+
+    let (hash, num_chunks, mode) = f_protocol.initialize_file(source_path)?;
+
+    let channel = f_protocol.generate_channel()?;
+
+    f_protocol.send_metadata(channel, &hash, num_chunks)?;
+    f_protocol.send_export(channel, &hash, target_path, mode, None)?;
+
+    // Wait for the initial NAK and then only send the first chunk
+    f_protocol.recv(Some(Duration::from_secs(1)))?;
+
+    let data = std::fs::read(format!(
+        "{}/storage/{}/0",
+        prefix.unwrap_or_else(|| "file-storage".to_owned()),
+        hash
+    ))
+    .unwrap();
+    let chunk = ser::to_vec_packed(&(channel, &hash, 0, Value::Bytes(data))).unwrap();
+    f_protocol.send(&chunk)?;
+
+    thread::sleep(Duration::from_millis(100));
+
+    Ok(hash)
+}
+
+// Resume an interrupted upload
+pub fn upload_resume(
+    host_ip: &str,
+    host_port: u16,
+    remote_addr: &str,
+    source_path: &str,
+    target_path: &str,
+    prefix: Option<String>,
+    chunk_size: u32,
+) -> Result<(), ProtocolError> {
+    let hold_count = 5;
+    let f_config = FileProtocolConfig::new(
+        prefix,
+        chunk_size as usize,
+        hold_count,
+        1,
+        None,
+        (chunk_size as usize) * 2,
+        None,
+    );
+    let f_protocol =
+        FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
+
+    let transfer = f_protocol
+        .find_upload(source_path, target_path)?
+        .ok_or_else(|| ProtocolError::FinalizeError {
+            cause: "no interrupted upload found".to_owned(),
+        })?;
+
+    let channel = f_protocol.generate_channel()?;
+
+    f_protocol.send_resume(channel, &transfer.hash, Direction::Transmit)?;
+
+    f_protocol.message_engine(
+        |d| f_protocol.recv(Some(d)),
+        Duration::from_secs(2),
+        &State::Transmitting,
+    )
+}
+
+// Start a download, but stop after receiving the first chunk
+pub fn download_interrupted(
+    host_ip: &str,
+    host_port: u16,
+    remote_addr: &str,
+    source_path: &str,
+    target_path: &str,
+    prefix: Option<String>,
+    chunk_size: u32,
+) -> Result<(), ProtocolError> {
+    let hold_count = 5;
+    let f_config = FileProtocolConfig::new(
+        prefix,
+        chunk_size as usize,
+        hold_count,
+        1,
+        None,
+        (chunk_size as usize) * 2,
+        None,
+    );
+    let f_protocol =
+        FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
+
+    let channel = f_protocol.generate_channel()?;
+
+    f_protocol.send_import(channel, source_path, None)?;
+
+    let reply = f_protocol.recv(None)?;
+
+    let state = f_protocol.process_message(
+        reply,
+        &State::StartReceive {
+            path: target_path.to_string(),
+        },
+    )?;
+
+    let chunk = f_protocol.recv(Some(Duration::from_secs(1)))?;
+    f_protocol.process_message(chunk, &state)?;
+
+    Ok(())
+}
+
+// Resume an interrupted download
+pub fn download_resume(
+    host_ip: &str,
+    host_port: u16,
+    remote_addr: &str,
+    target_path: &str,
+    prefix: Option<String>,
+    chunk_size: u32,
+) -> Result<(), ProtocolError> {
+    let hold_count = 5;
+    let f_config = FileProtocolConfig::new(
+        prefix,
+        chunk_size as usize,
+        hold_count,
+        1,
+        None,
+        (chunk_size as usize) * 2,
+        None,
+    );
+    let f_protocol =
+        FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
+
+    let transfer = f_protocol
+        .find_transfer(Direction::Receive, target_path)
+        .ok_or_else(|| ProtocolError::FinalizeError {
+            cause: "no interrupted download found".to_owned(),
+        })?;
+
+    let channel = f_protocol.generate_channel()?;
+
+    f_protocol.send_resume(channel, &transfer.hash, Direction::Receive)?;
+
+    let reply = f_protocol.recv(Some(Duration::from_secs(2)))?;
+
+    let state = f_protocol.process_message(
+        reply,
+        &State::StartReceive {
+            path: target_path.to_string(),
+        },
+    )?;
+
+    f_protocol.message_engine(|d| f_protocol.recv(Some(d)), Duration::from_secs(2), &state)
+}
+
 pub fn cleanup(
     host_ip: &str,
     host_port: u16,
@@ -266,6 +540,7 @@ pub fn cleanup(
         1,
         None,
         (chunk_size as usize) * 2,
+        None,
     );
     let f_protocol =
         FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
@@ -280,6 +555,150 @@ pub fn cleanup(
     Ok(())
 }
 
+// Recursively copy a directory
+pub fn copy_dir(source: &str, dest: &str) {
+    std::fs::create_dir_all(dest).unwrap();
+
+    for entry in std::fs::read_dir(source).unwrap() {
+        let entry = entry.unwrap();
+        let target = format!("{}/{}", dest, entry.file_name().to_string_lossy());
+
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(entry.path().to_str().unwrap(), &target);
+        } else {
+            std::fs::copy(entry.path(), &target).unwrap();
+        }
+    }
+}
+
+// Outcome of an individual file in a directory transfer
+pub type FileResult = (String, Result<(), ProtocolError>);
+
+// Upload a directory tree, or all of the files matching a glob pattern.
+// Returns the result of each individual file's transfer
+pub fn upload_dir(
+    host_ip: &str,
+    host_port: u16,
+    remote_addr: &str,
+    source: &str,
+    target_dir: &str,
+    prefix: Option<String>,
+    chunk_size: u32,
+) -> Result<Vec<FileResult>, ProtocolError> {
+    let hold_count = 5;
+    let f_config = FileProtocolConfig::new(
+        prefix,
+        chunk_size as usize,
+        hold_count,
+        1,
+        None,
+        (chunk_size as usize) * 2,
+        None,
+    );
+    let f_protocol =
+        FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
+
+    let manifest = f_protocol.initialize_manifest(source)?;
+    let root = Manifest::root(source);
+
+    let mut results = vec![];
+    for file in manifest.files {
+        let target_path = format!("{}/{}", target_dir, file.path);
+
+        let result = (|| {
+            // Files are only copied to temp storage just before they're sent
+            let (_, num_chunks, _) =
+                f_protocol.initialize_file(&root.join(&file.path).to_string_lossy())?;
+
+            let channel = f_protocol.generate_channel()?;
+            f_protocol.send_metadata(channel, &file.hash, num_chunks)?;
+            f_protocol.send_export(channel, &file.hash, &target_path, file.mode, None)?;
+            f_protocol.message_engine(
+                |d| f_protocol.recv(Some(d)),
+                Duration::from_secs(2),
+                &State::Transmitting,
+            )
+        })();
+
+        results.push((file.path, result));
+    }
+
+    Ok(results)
+}
+
+// Download a directory tree, or all of the files matching a glob pattern.
+// Returns the result of each individual file's transfer, including any files
+// the service failed to prepare
+pub fn download_dir(
+    host_ip: &str,
+    host_port: u16,
+    remote_addr: &str,
+    source: &str,
+    target_dir: &str,
+    prefix: Option<String>,
+    chunk_size: u32,
+) -> Result<Vec<FileResult>, ProtocolError> {
+    let hold_count = 5;
+    let f_config = FileProtocolConfig::new(
+        prefix,
+        chunk_size as usize,
+        hold_count,
+        1,
+        None,
+        (chunk_size as usize) * 2,
+        None,
+    );
+    let f_protocol =
+        FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
+
+    let channel = f_protocol.generate_channel()?;
+    f_protocol.send_import_dir(channel, source, None)?;
+
+    let reply = f_protocol.recv(None)?;
+
+    // Receive the manifest like any other file
+    let manifest_path = format!("{}/.manifest", target_dir);
+    let state = f_protocol.process_message(
+        reply,
+        &State::StartReceive {
+            path: manifest_path.clone(),
+        },
+    )?;
+    f_protocol.message_engine(|d| f_protocol.recv(Some(d)), Duration::from_secs(2), &state)?;
+
+    let manifest = Manifest::from_slice(&std::fs::read(&manifest_path).unwrap())?;
+    std::fs::remove_file(&manifest_path).unwrap();
+
+    let mut results = vec![];
+    for failure in manifest.failures {
+        results.push((
+            failure.path,
+            Err(ProtocolError::TransmissionError {
+                channel_id: channel,
+                error_message: failure.error,
+            }),
+        ));
+    }
+
+    for file in manifest.files {
+        let target_path = format!("{}/{}", target_dir, file.path);
+
+        let result = (|| {
+            let channel = f_protocol.generate_channel()?;
+            f_protocol.send_resume(channel, &file.hash, Direction::Receive)?;
+
+            let reply = f_protocol.recv(Some(Duration::from_secs(2)))?;
+            let state =
+                f_protocol.process_message(reply, &State::StartReceive { path: target_path })?;
+            f_protocol.message_engine(|d| f_protocol.recv(Some(d)), Duration::from_secs(2), &state)
+        })();
+
+        results.push((file.path, result));
+    }
+
+    Ok(results)
+}
+
 pub fn create_test_file(name: &str, contents: &[u8]) -> String {
     let mut file = File::create(name).unwrap();
     file.write_all(contents).unwrap();
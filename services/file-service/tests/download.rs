@@ -17,6 +17,7 @@
 mod common;
 
 use crate::common::*;
+use file_protocol::Compression;
 use file_service::recv_loop;
 use kubos_system::Config as ServiceConfig;
 use std::fs;
@@ -262,3 +263,222 @@ fn download_bad_hash() {
     );
     assert_eq!("File hash mismatch", format!("{}", result.unwrap_err()));
 }
+
+// Resume a download which was interrupted by a client restart
+#[test]
+fn download_resume_after_restart() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let client_storage = Some(format!("{}/client", test_dir_str));
+
+    let contents = [8; 10000];
+
+    create_test_file(&source, &contents);
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    let storage_ref = storage_dir.clone();
+    service_new!(8005, 7005, 4096, storage_ref);
+
+    // Only get part of the way through the download
+    download_interrupted(
+        "127.0.0.1",
+        7005,
+        "127.0.0.1:8005",
+        &source,
+        &dest,
+        client_storage.clone(),
+        4096,
+    )
+    .unwrap();
+
+    // "Restart" the service as well, using the same storage directory
+    service_new!(8006, 7006, 4096, storage_dir);
+
+    let result = download_resume(
+        "127.0.0.1",
+        7006,
+        "127.0.0.1:8006",
+        &dest,
+        client_storage,
+        4096,
+    );
+
+    if let Err(err) = &result {
+        println!("Error: {}", err);
+    }
+
+    result.unwrap();
+
+    // Verify the final file's contents
+    let dest_contents = fs::read(dest).unwrap();
+    assert_eq!(&contents[..], dest_contents.as_slice());
+}
+
+// Download multi-chunk file with the chunks compressed in transit
+#[test]
+fn download_multi_compressed() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let service_port = 8007;
+    let downlink_port = 7007;
+
+    let contents = "download_compressed ".repeat(700).into_bytes();
+
+    create_test_file(&source, &contents);
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    service_new!(service_port, downlink_port, 4096, storage_dir);
+
+    let result = download_compressed(
+        "127.0.0.1",
+        downlink_port,
+        &format!("127.0.0.1:{}", service_port),
+        &source,
+        &dest,
+        Some(format!("{}/client", test_dir_str)),
+        4096,
+        Some(Compression::Deflate),
+    );
+
+    if let Err(err) = &result {
+        println!("Error: {}", err);
+    }
+
+    result.unwrap();
+
+    // Verify the final file's contents
+    let dest_contents = fs::read(dest).unwrap();
+    assert_eq!(&contents[..], dest_contents.as_slice());
+}
+
+// Download a directory tree, including a nested directory
+#[test]
+fn download_dir_tree() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let service_port = 8009;
+    let downlink_port = 7009;
+
+    fs::create_dir_all(format!("{}/data/2019", source)).unwrap();
+    fs::create_dir_all(&dest).unwrap();
+    create_test_file(&format!("{}/summary.txt", source), b"download_dir_tree");
+    create_test_file(&format!("{}/data/2019/0001.bin", source), &[6; 9000]);
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    service_new!(service_port, downlink_port, 4096, storage_dir);
+
+    let results = download_dir(
+        "127.0.0.1",
+        downlink_port,
+        &format!("127.0.0.1:{}", service_port),
+        &source,
+        &dest,
+        Some(format!("{}/client", test_dir_str)),
+        4096,
+    )
+    .unwrap();
+
+    let paths: Vec<&str> = results.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(paths, vec!["data/2019/0001.bin", "summary.txt"]);
+    for (path, result) in &results {
+        assert!(result.is_ok(), "Failed to download {}: {:?}", path, result);
+    }
+
+    // Verify the final files' contents
+    assert_eq!(
+        fs::read(format!("{}/data/2019/0001.bin", dest)).unwrap(),
+        vec![6; 9000]
+    );
+    assert_eq!(
+        fs::read(format!("{}/summary.txt", dest)).unwrap(),
+        b"download_dir_tree"
+    );
+}
+
+// Download only the files matching a glob pattern
+#[test]
+fn download_dir_glob() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let service_port = 8010;
+    let downlink_port = 7010;
+
+    fs::create_dir_all(&source).unwrap();
+    fs::create_dir_all(&dest).unwrap();
+    create_test_file(&format!("{}/a.csv", source), b"download_dir_glob a");
+    create_test_file(&format!("{}/b.csv", source), b"download_dir_glob b");
+    create_test_file(&format!("{}/notes.txt", source), b"download_dir_glob notes");
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    service_new!(service_port, downlink_port, 4096, storage_dir);
+
+    let results = download_dir(
+        "127.0.0.1",
+        downlink_port,
+        &format!("127.0.0.1:{}", service_port),
+        &format!("{}/*.csv", source),
+        &dest,
+        Some(format!("{}/client", test_dir_str)),
+        4096,
+    )
+    .unwrap();
+
+    let paths: Vec<&str> = results.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(paths, vec!["a.csv", "b.csv"]);
+    for (path, result) in &results {
+        assert!(result.is_ok(), "Failed to download {}: {:?}", path, result);
+    }
+
+    assert_eq!(
+        fs::read(format!("{}/b.csv", dest)).unwrap(),
+        b"download_dir_glob b"
+    );
+    assert!(fs::metadata(format!("{}/notes.txt", dest)).is_err());
+}
+
+// Download a directory tree containing a symlink back to one of its parents.
+// The symlinked directory should be skipped rather than followed forever
+#[test]
+fn download_dir_symlink_loop() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let service_port = 8011;
+    let downlink_port = 7015;
+
+    fs::create_dir_all(format!("{}/data", source)).unwrap();
+    fs::create_dir_all(&dest).unwrap();
+    create_test_file(&format!("{}/data/0001.bin", source), &[7; 100]);
+    std::os::unix::fs::symlink(&source, format!("{}/data/loop", source)).unwrap();
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    service_new!(service_port, downlink_port, 4096, storage_dir);
+
+    let results = download_dir(
+        "127.0.0.1",
+        downlink_port,
+        &format!("127.0.0.1:{}", service_port),
+        &source,
+        &dest,
+        Some(format!("{}/client", test_dir_str)),
+        4096,
+    )
+    .unwrap();
+
+    let paths: Vec<&str> = results.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(paths, vec!["data/0001.bin"]);
+    assert!(results[0].1.is_ok());
+    assert_eq!(
+        fs::read(format!("{}/data/0001.bin", dest)).unwrap(),
+        vec![7; 100]
+    );
+}
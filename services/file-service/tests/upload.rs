@@ -17,7 +17,7 @@
 mod common;
 
 use crate::common::*;
-use file_protocol::ProtocolError;
+use file_protocol::{Compression, FileProtocol, FileProtocolConfig, ProtocolError};
 use file_service::recv_loop;
 use kubos_system::Config as ServiceConfig;
 use std::fs;
@@ -335,3 +335,290 @@ fn upload_single_after_bad_input() {
     // of the hash mismatch
     let _ = fs::remove_dir_all(format!("service/storage/{}", hash));
 }
+
+// Resume an upload which was interrupted by a service restart
+#[test]
+fn upload_resume_after_restart() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let client_storage = Some(format!("{}/client", test_dir_str));
+
+    let contents = [8; 10000];
+
+    create_test_file(&source, &contents);
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    let storage_ref = storage_dir.clone();
+    service_new!(7008, 6008, 4096, storage_ref);
+
+    // Only get part of the way through the upload
+    let hash = upload_interrupted(
+        "127.0.0.1",
+        6008,
+        "127.0.0.1:7008",
+        &source,
+        &dest,
+        client_storage.clone(),
+        4096,
+    )
+    .unwrap();
+
+    // The service should have saved the state of the transfer with the first chunk
+    let transfer = fs::read(format!("{}/storage/{}/transfer", storage_dir, hash));
+    assert!(transfer.is_ok());
+
+    // "Restart" the service with a copy of the storage directory, so that the original
+    // service can't interfere with the resumed transfer
+    let restarted_dir = format!("{}/restarted", test_dir_str);
+    copy_dir(&storage_dir, &restarted_dir);
+    service_new!(7009, 6009, 4096, restarted_dir);
+
+    let result = upload_resume(
+        "127.0.0.1",
+        6009,
+        "127.0.0.1:7009",
+        &source,
+        &dest,
+        client_storage,
+        4096,
+    );
+
+    if let Err(err) = &result {
+        println!("Error: {}", err);
+    }
+
+    result.unwrap();
+
+    // Verify the final file's contents
+    let dest_contents = fs::read(dest).unwrap();
+    assert_eq!(&contents[..], dest_contents.as_slice());
+}
+
+// An interrupted upload shouldn't be resumed once its source file has changed
+#[test]
+fn upload_resume_changed_source() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let client_storage = Some(format!("{}/client", test_dir_str));
+
+    create_test_file(&source, &[8; 10000]);
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    service_new!(7016, 6016, 4096, storage_dir);
+
+    // Only get part of the way through the upload
+    upload_interrupted(
+        "127.0.0.1",
+        6016,
+        "127.0.0.1:7016",
+        &source,
+        &dest,
+        client_storage.clone(),
+        4096,
+    )
+    .unwrap();
+
+    let config = FileProtocolConfig::new(client_storage, 4096, 5, 1, None, 8192, None);
+    let f_protocol = FileProtocol::new("127.0.0.1:6017", "127.0.0.1:7017", config);
+    assert!(f_protocol.find_upload(&source, &dest).unwrap().is_some());
+
+    // Once the file has changed, the interrupted upload no longer applies to it
+    create_test_file(&source, &[9; 10000]);
+    assert!(f_protocol.find_upload(&source, &dest).unwrap().is_none());
+}
+
+// Resuming an upload the service knows nothing about should fail
+#[test]
+fn upload_resume_unknown() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let client_storage = Some(format!("{}/client", test_dir_str));
+
+    create_test_file(&source, "upload_resume_unknown".as_bytes());
+
+    // Record an upload on the client side only
+    let config = FileProtocolConfig::new(client_storage.clone(), 4096, 5, 1, None, 8192, None);
+    let f_protocol = FileProtocol::new("127.0.0.1:6010", "127.0.0.1:7010", config);
+    let (hash, _num_chunks, mode) = f_protocol.initialize_file(&source).unwrap();
+    f_protocol.send_export(1, &hash, &dest, mode, None).unwrap();
+    drop(f_protocol);
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    service_new!(7010, 6010, 4096, storage_dir);
+
+    let result = upload_resume(
+        "127.0.0.1",
+        6010,
+        "127.0.0.1:7010",
+        &source,
+        &dest,
+        client_storage,
+        4096,
+    );
+
+    match result.unwrap_err() {
+        ProtocolError::TransmissionError { error_message, .. } => assert_eq!(
+            error_message,
+            format!("No resumable transfer found for {}", hash)
+        ),
+        other => panic!("Unexpected error: {}", other),
+    }
+}
+
+// Upload multi-chunk file with the chunks compressed in transit
+#[test]
+fn upload_multi_compressed() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let service_port = 7011;
+    let downlink_port = 6011;
+
+    let contents = "upload_compressed ".repeat(700).into_bytes();
+
+    create_test_file(&source, &contents);
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    service_new!(service_port, downlink_port, 4096, storage_dir);
+
+    let result = upload_compressed(
+        "127.0.0.1",
+        downlink_port,
+        &format!("127.0.0.1:{}", service_port),
+        &source,
+        &dest,
+        Some(format!("{}/client", test_dir_str)),
+        4096,
+        Some(Compression::Deflate),
+    );
+
+    if let Err(err) = &result {
+        println!("Error: {}", err);
+    }
+
+    result.unwrap();
+
+    // Verify the final file's contents
+    let dest_contents = fs::read(dest).unwrap();
+    assert_eq!(&contents[..], dest_contents.as_slice());
+}
+
+// Upload a multi-chunk file a few chunks at a time, using selective acks
+#[test]
+fn upload_multi_windowed() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let service_port = 7012;
+    let downlink_port = 6012;
+
+    let contents = [4; 40000];
+
+    create_test_file(&source, &contents);
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    service_new!(service_port, downlink_port, 4096, storage_dir);
+
+    let result = upload_windowed(
+        "127.0.0.1",
+        downlink_port,
+        &format!("127.0.0.1:{}", service_port),
+        &source,
+        &dest,
+        Some(format!("{}/client", test_dir_str)),
+        4096,
+        3,
+    );
+
+    if let Err(err) = &result {
+        println!("Error: {}", err);
+    }
+
+    result.unwrap();
+
+    // Verify the final file's contents
+    let dest_contents = fs::read(dest).unwrap();
+    assert_eq!(&contents[..], dest_contents.as_slice());
+}
+
+// The service shouldn't start with a window size which would never send any chunks
+#[test]
+fn invalid_window_size() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let config = ServiceConfig::new_from_str(
+        "file-transfer-service",
+        &format!(
+            r#"
+            [file-transfer-service]
+            storage_dir = "{}/service"
+            window_size = 0
+            downlink_ip = "127.0.0.1"
+            downlink_port = 6014
+            [file-transfer-service.addr]
+            ip = "127.0.0.1"
+            port = 7014
+            "#,
+            test_dir.path().to_str().unwrap()
+        ),
+    )
+    .unwrap();
+
+    assert_eq!(
+        recv_loop(&config).unwrap_err().to_string(),
+        "Invalid window_size 0"
+    );
+}
+
+// Upload a directory tree, including a duplicate file and a nested directory
+#[test]
+fn upload_dir_tree() {
+    let test_dir = TempDir::new().expect("Failed to create test dir");
+    let test_dir_str = test_dir.path().to_str().unwrap();
+    let source = format!("{}/source", test_dir_str);
+    let dest = format!("{}/dest", test_dir_str);
+    let service_port = 7013;
+    let downlink_port = 6013;
+
+    fs::create_dir_all(format!("{}/images", source)).unwrap();
+    create_test_file(&format!("{}/log.txt", source), b"upload_dir_tree log");
+    create_test_file(&format!("{}/images/0001.png", source), &[5; 6000]);
+    create_test_file(&format!("{}/images/0002.png", source), &[5; 6000]);
+
+    let storage_dir = format!("{}/service", test_dir_str);
+    service_new!(service_port, downlink_port, 4096, storage_dir);
+
+    let results = upload_dir(
+        "127.0.0.1",
+        downlink_port,
+        &format!("127.0.0.1:{}", service_port),
+        &source,
+        &dest,
+        Some(format!("{}/client", test_dir_str)),
+        4096,
+    )
+    .unwrap();
+
+    let paths: Vec<&str> = results.iter().map(|(path, _)| path.as_str()).collect();
+    assert_eq!(paths, vec!["images/0001.png", "images/0002.png", "log.txt"]);
+    for (path, result) in &results {
+        assert!(result.is_ok(), "Failed to upload {}: {:?}", path, result);
+    }
+
+    // Verify the final files' contents
+    assert_eq!(
+        fs::read(format!("{}/images/0002.png", dest)).unwrap(),
+        vec![5; 6000]
+    );
+    assert_eq!(
+        fs::read(format!("{}/log.txt", dest)).unwrap(),
+        b"upload_dir_tree log"
+    );
+}
@@ -19,7 +19,7 @@
 //!
 
 use crate::error::SchedulerError;
-use crate::schema::GenericResponse;
+use crate::task::parse_hms_field;
 use juniper::GraphQLObject;
 use log::{debug, error, info};
 use reqwest::Client;
@@ -28,10 +28,18 @@ use serde_json::from_str;
 use std::collections::HashMap;
 use std::time::Duration;
 
+// Result of a startApp mutation
+#[derive(Debug, Deserialize)]
+pub struct StartResponse {
+    pub success: bool,
+    pub errors: String,
+    pub pid: Option<i32>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct StartAppResponse {
     #[serde(rename = "startApp")]
-    pub start_app: GenericResponse,
+    pub start_app: StartResponse,
 }
 
 #[derive(Debug, Deserialize)]
@@ -77,10 +85,37 @@ pub struct App {
     pub name: String,
     pub args: Option<Vec<String>>,
     pub config: Option<String>,
+    // Longest each run of the app may last, specified in Xh Ym Zs format.
+    // The app service stops the app once it's exceeded
+    pub timeout: Option<String>,
 }
 
 impl App {
-    pub fn execute(&self, service_url: &str) {
+    // Parse the timeout for runs of the app
+    pub fn get_timeout(&self) -> Result<Option<Duration>, SchedulerError> {
+        match &self.timeout {
+            Some(field) => {
+                let timeout = parse_hms_field(field.to_owned())?;
+                if timeout.as_secs() == 0 {
+                    return Err(SchedulerError::HmsParseError {
+                        err: "Timeout must be at least one second".to_owned(),
+                        field: field.to_owned(),
+                    });
+                }
+                // The app service takes the timeout as a 32-bit number of seconds
+                if timeout.as_secs() > i32::MAX as u64 {
+                    return Err(SchedulerError::HmsParseError {
+                        err: format!("Timeout must be at most {} seconds", i32::MAX),
+                        field: field.to_owned(),
+                    });
+                }
+                Ok(Some(timeout))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn execute(&self, service_url: &str) -> StartResponse {
         info!("Start app {}", self.name);
         let mut query_args = format!("name: \"{}\"", self.name);
         if let Some(config) = &self.config {
@@ -92,13 +127,32 @@ impl App {
             let app_args = app_args.join(",");
             query_args.push_str(&format!(", args: [{}]", app_args));
         }
+        match self.get_timeout() {
+            Ok(Some(timeout)) => {
+                query_args.push_str(&format!(", timeout: {}", timeout.as_secs()));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("Failed to parse app timeout: {}", e);
+                return StartResponse {
+                    success: false,
+                    errors: e.to_string(),
+                    pid: None,
+                };
+            }
+        }
         let query = format!(
-            r#"mutation {{ startApp({}) {{ success, errors }} }}"#,
+            r#"mutation {{ startApp({}) {{ success, errors, pid }} }}"#,
             query_args
         );
         match service_query(&query, service_url) {
             Err(e) => {
                 error!("Failed to send start app query: {}", e);
+                StartResponse {
+                    success: false,
+                    errors: e.to_string(),
+                    pid: None,
+                }
             }
             Ok(resp) => {
                 if !resp.data.start_app.success {
@@ -107,6 +161,7 @@ impl App {
                         resp.data.start_app.errors
                     );
                 }
+                resp.data.start_app
             }
         }
     }
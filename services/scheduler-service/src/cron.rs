@@ -0,0 +1,319 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//!
+//! Parsing and evaluation of cron-style recurrence rules
+//!
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Timelike, Utc};
+use std::str::FromStr;
+
+// How far ahead to search for a matching time before deciding that a rule can never match
+// (ex. "0 0 30 2 *"). Long enough to always include a leap day
+const SEARCH_DAYS: i64 = 366 * 5;
+
+// Recurrence rule in the standard five field cron format:
+// "minute hour day-of-month month day-of-week", evaluated in UTC.
+// Each field is stored as a bitmask of the values it matches
+#[derive(Clone, Debug, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Whether the day-of-month and day-of-week fields were restricted (not starting with '*')
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let rule = match rule.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = rule.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Expected 5 fields, found {}", fields.len()));
+        }
+
+        // Sunday may be given as either 0 or 7
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays: weekdays & 0x7f,
+            days_restricted: !fields[2].starts_with('*'),
+            weekdays_restricted: !fields[4].starts_with('*'),
+        })
+    }
+}
+
+impl CronSchedule {
+    // Find the first time matching the rule which is strictly after the given time
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        // Rules have a resolution of one minute, so start from the beginning of the next minute
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + chrono::Duration::minutes(1);
+        let limit = after + chrono::Duration::days(SEARCH_DAYS);
+
+        while time < limit {
+            let date = time.naive_utc().date();
+
+            if !matches(self.months, time.month()) {
+                let (year, month) = if time.month() == 12 {
+                    (time.year() + 1, 1)
+                } else {
+                    (time.year(), time.month() + 1)
+                };
+                let start = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+                time = Utc.from_utc_datetime(&start);
+            } else if !self.day_matches(&time) {
+                time = Utc.from_utc_datetime(&date.succ_opt()?.and_hms_opt(0, 0, 0)?);
+            } else if !matches(self.hours, time.hour()) {
+                time = time.with_minute(0)? + chrono::Duration::hours(1);
+            } else if !matches(self.minutes, time.minute()) {
+                time += chrono::Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+
+        None
+    }
+
+    // As with standard cron, if both the day-of-month and day-of-week are restricted then a day
+    // matching either of them is used
+    fn day_matches(&self, time: &DateTime<Utc>) -> bool {
+        let day = matches(self.days, time.day());
+        let weekday = matches(self.weekdays, time.weekday().num_days_from_sunday());
+
+        if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+}
+
+fn matches(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+// Parse a single field made up of a comma-separated list of values ("5"), ranges ("1-5"),
+// and wildcards ("*"), each optionally followed by a step ("*/15", "1-10/3")
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(index) => (&part[..index], Some(&part[index + 1..])),
+            None => (part, None),
+        };
+
+        let step = match step {
+            Some(step) => step
+                .parse::<u32>()
+                .ok()
+                .filter(|step| *step > 0)
+                .ok_or_else(|| format!("Invalid step in '{}'", part))?,
+            None => 1,
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(index) = range.find('-') {
+            (
+                parse_value(&range[..index], part)?,
+                parse_value(&range[index + 1..], part)?,
+            )
+        } else {
+            // A single value with a step runs until the end of the field's range
+            let value = parse_value(range, part)?;
+            (value, if step > 1 { max } else { value })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!(
+                "Value out of range in '{}', expected {}-{}",
+                part, min, max
+            ));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+
+    Ok(mask)
+}
+
+fn parse_value(value: &str, part: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value in '{}'", part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(time: &str) -> DateTime<Utc> {
+        Utc.datetime_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn next(rule: &str, after: &str) -> Option<DateTime<Utc>> {
+        rule.parse::<CronSchedule>()
+            .unwrap()
+            .next_after(time(after))
+    }
+
+    #[test]
+    fn test_next_daily() {
+        assert_eq!(
+            next("0 2 * * *", "2019-08-11 15:20:10"),
+            Some(time("2019-08-12 02:00:00"))
+        );
+    }
+
+    #[test]
+    fn test_next_strictly_after() {
+        assert_eq!(
+            next("0 2 * * *", "2019-08-12 02:00:00"),
+            Some(time("2019-08-13 02:00:00"))
+        );
+    }
+
+    #[test]
+    fn test_next_step() {
+        assert_eq!(
+            next("*/15 * * * *", "2019-08-11 15:20:10"),
+            Some(time("2019-08-11 15:30:00"))
+        );
+    }
+
+    #[test]
+    fn test_next_list_and_range() {
+        assert_eq!(
+            next("30 9-17/4,22 * * *", "2019-08-11 17:31:00"),
+            Some(time("2019-08-11 22:30:00"))
+        );
+    }
+
+    #[test]
+    fn test_next_weekday() {
+        // 2019-08-11 was a Sunday
+        assert_eq!(
+            next("0 0 * * 1-5", "2019-08-10 12:00:00"),
+            Some(time("2019-08-12 00:00:00"))
+        );
+        assert_eq!(
+            next("0 0 * * 7", "2019-08-10 12:00:00"),
+            Some(time("2019-08-11 00:00:00"))
+        );
+    }
+
+    #[test]
+    fn test_next_day_or_weekday() {
+        assert_eq!(
+            next("0 0 15 * 0", "2019-08-01 00:00:00"),
+            Some(time("2019-08-04 00:00:00"))
+        );
+    }
+
+    #[test]
+    fn test_next_day_and_stepped_weekday() {
+        // A stepped '*' doesn't count as a restriction, so both fields must match
+        assert_eq!(
+            next("0 0 1 * */2", "2019-08-01 12:00:00"),
+            Some(time("2019-09-01 00:00:00"))
+        );
+        assert_eq!(
+            next("0 0 */2 * 1", "2019-08-01 12:00:00"),
+            Some(time("2019-08-05 00:00:00"))
+        );
+    }
+
+    #[test]
+    fn test_next_month_rollover() {
+        assert_eq!(
+            next("0 0 1 1 *", "2019-08-11 15:20:10"),
+            Some(time("2020-01-01 00:00:00"))
+        );
+        assert_eq!(
+            next("@monthly", "2019-12-11 15:20:10"),
+            Some(time("2020-01-01 00:00:00"))
+        );
+    }
+
+    #[test]
+    fn test_next_leap_day() {
+        assert_eq!(
+            next("0 0 29 2 *", "2019-08-11 15:20:10"),
+            Some(time("2020-02-29 00:00:00"))
+        );
+    }
+
+    #[test]
+    fn test_next_never() {
+        assert_eq!(next("0 0 30 2 *", "2019-08-11 15:20:10"), None);
+    }
+
+    #[test]
+    fn test_parse_field_count() {
+        assert_eq!(
+            "0 2 * *".parse::<CronSchedule>(),
+            Err("Expected 5 fields, found 4".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_out_of_range() {
+        assert_eq!(
+            "0 24 * * *".parse::<CronSchedule>(),
+            Err("Value out of range in '24', expected 0-23".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_bad_step() {
+        assert_eq!(
+            "*/0 * * * *".parse::<CronSchedule>(),
+            Err("Invalid step in '*/0'".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_bad_value() {
+        assert_eq!(
+            "0 two * * *".parse::<CronSchedule>(),
+            Err("Invalid value in 'two'".to_owned())
+        );
+    }
+}
@@ -76,6 +76,12 @@ pub enum SchedulerError {
         /// The path of the mode that failed to load
         path: String,
     },
+    // A task's precondition didn't hold, or couldn't be checked
+    #[fail(display = "Precondition not met: {}", err)]
+    PreconditionError {
+        /// Why the precondition failed
+        err: String,
+    },
     // An error was raised when sending a graphql query
     #[fail(display = "Scheduler query failed: {}", err)]
     QueryError {
@@ -0,0 +1,221 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//!
+//! Persistent record of task executions
+//!
+
+use crate::error::SchedulerError;
+use chrono::offset::TimeZone;
+use chrono::{DateTime, Utc};
+use juniper::GraphQLObject;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Name of the history file kept in the schedules directory
+pub static HISTORY_FILE: &str = "history.json";
+// Number of executions kept in the history
+pub const HISTORY_LENGTH: usize = 1000;
+
+// Format used for all times in the history. Sorts the same way as the times it represents
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+pub fn format_time(time: DateTime<Utc>) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+// Record of a single attempt to run a task
+#[derive(Clone, Debug, GraphQLObject, Serialize, Deserialize)]
+pub struct Execution {
+    // Name of the task list containing the task
+    pub task_list: String,
+    // Description of the task
+    pub description: String,
+    // Name of the app which was started
+    pub app: String,
+    // UTC time the task was scheduled to run
+    pub scheduled: String,
+    // UTC time the app was actually started
+    pub started: String,
+    // Which attempt this was, starting from 1. Zero if the task was skipped
+    pub attempt: i32,
+    // Whether the app service started the app
+    pub success: bool,
+    // Errors returned when starting the app
+    pub errors: String,
+    // PID of the started app
+    pub pid: Option<i32>,
+}
+
+struct Entries {
+    executions: VecDeque<Execution>,
+    // Number of lines currently in the history file. Old lines are only removed from the file
+    // once it holds twice as many executions as are kept, to avoid rewriting it on every run
+    file_lines: usize,
+}
+
+pub struct History {
+    path: PathBuf,
+    entries: Mutex<Entries>,
+}
+
+impl History {
+    // Load any existing history from the schedules directory
+    pub fn new(scheduler_dir: &str) -> History {
+        let path = Path::new(scheduler_dir).join(HISTORY_FILE);
+        let mut executions = VecDeque::new();
+        let mut file_lines = 0;
+
+        if let Ok(contents) = fs::read_to_string(&path) {
+            for line in contents.lines() {
+                file_lines += 1;
+                match serde_json::from_str(line) {
+                    Ok(execution) => executions.push_back(execution),
+                    Err(e) => warn!("Skipping invalid history entry '{}': {}", line, e),
+                }
+            }
+        }
+
+        while executions.len() > HISTORY_LENGTH {
+            executions.pop_front();
+        }
+
+        History {
+            path,
+            entries: Mutex::new(Entries {
+                executions,
+                file_lines,
+            }),
+        }
+    }
+
+    // Add an execution to the history and save it to disk
+    pub fn record(&self, execution: Execution) {
+        let mut entries = match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to get lock on execution history: {}", e);
+                return;
+            }
+        };
+
+        let line = match serde_json::to_string(&execution) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize execution history: {}", e);
+                return;
+            }
+        };
+
+        entries.executions.push_back(execution);
+        if entries.executions.len() > HISTORY_LENGTH {
+            entries.executions.pop_front();
+        }
+
+        let result = if entries.file_lines >= HISTORY_LENGTH * 2 {
+            self.rewrite(&entries.executions)
+                .map(|_| entries.file_lines = entries.executions.len())
+        } else {
+            self.append(&line).map(|_| entries.file_lines += 1)
+        };
+
+        if let Err(e) = result {
+            error!(
+                "Failed to save execution history to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+
+    fn append(&self, line: &str) -> Result<(), std::io::Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+
+    fn rewrite(&self, executions: &VecDeque<Execution>) -> Result<(), std::io::Error> {
+        let mut contents = String::new();
+        for execution in executions {
+            contents.push_str(&serde_json::to_string(execution)?);
+            contents.push('\n');
+        }
+
+        // Write to a temporary file first, so the history isn't lost if we fail partway
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, contents)?;
+        fs::rename(&temp_path, &self.path)
+    }
+
+    // Get recorded executions, oldest first.
+    // Optionally only those started at or after a given time, or from a specific task list,
+    // and limited to the most recent few
+    pub fn get(
+        &self,
+        since: Option<String>,
+        task_list: Option<String>,
+        limit: Option<i32>,
+    ) -> Result<Vec<Execution>, SchedulerError> {
+        let since = match since {
+            Some(since) => Some(format_time(
+                Utc.datetime_from_str(&since, "%Y-%m-%d %H:%M:%S")
+                    .map_err(|e| SchedulerError::GenericError {
+                        err: format!("Failed to parse time '{}': {}", since, e),
+                    })?,
+            )),
+            None => None,
+        };
+
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|e| SchedulerError::GenericError {
+                err: format!("Failed to get lock on execution history: {}", e),
+            })?;
+
+        let mut executions: Vec<Execution> = entries
+            .executions
+            .iter()
+            .filter(|execution| {
+                since
+                    .as_ref()
+                    .map_or(true, |since| &execution.started >= since)
+            })
+            .filter(|execution| {
+                task_list
+                    .as_ref()
+                    .map_or(true, |name| &execution.task_list == name)
+            })
+            .cloned()
+            .collect();
+
+        if let Some(limit) = limit {
+            let limit = limit.max(0) as usize;
+            if executions.len() > limit {
+                executions.drain(..executions.len() - limit);
+            }
+        }
+
+        Ok(executions)
+    }
+}
@@ -20,16 +20,22 @@
 #![deny(missing_docs)]
 
 mod app;
+mod cron;
 mod error;
+mod history;
 mod mode;
+mod precondition;
+mod rules;
 mod scheduler;
 mod schema;
 mod task;
 mod task_list;
+mod timeline;
 
 use crate::error::SchedulerError;
 use kubos_service::{Config, Logger, Service};
 use log::{error, info};
+use rules::ModeRules;
 use scheduler::{Scheduler, DEFAULT_SCHEDULES_DIR};
 use schema::{MutationRoot, QueryRoot};
 
@@ -65,15 +71,21 @@ fn main() -> Result<(), SchedulerError> {
 
     let scheduler = Scheduler::new(&scheduler_dir, &apps_service_url)?;
 
+    let mode_rules = ModeRules::from_config(&config)?;
+
     info!("Starting scheduler-service - {:?}", scheduler.scheduler_dir);
 
     scheduler.init()?;
 
+    mode_rules.validate_modes(&scheduler.scheduler_dir)?;
+
     // For now we will only kick off scheduling when the scheduler comes up
     if let Err(e) = scheduler.start() {
         error!("Failed to schedule tasks: {:?}", e);
     }
 
+    mode_rules.start(scheduler.clone());
+
     Service::new(config, scheduler, QueryRoot, MutationRoot).start();
 
     Ok(())
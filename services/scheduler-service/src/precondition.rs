@@ -0,0 +1,170 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//!
+//! Conditions checked against other services before running a task
+//!
+
+use crate::error::SchedulerError;
+use juniper::GraphQLObject;
+use kubos_service::Config;
+use log::debug;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// How long to wait for a service to answer a precondition query
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+static OPERATORS: &[&str] = &["==", "!=", "<", "<=", ">", ">="];
+
+// Condition on the result of a GraphQL query which must hold for a task to run
+#[derive(Clone, Debug, GraphQLObject, Serialize, Deserialize)]
+pub struct Precondition {
+    // Name of the service to query, as found in the system config file
+    pub service: String,
+    // GraphQL query to send to the service
+    pub query: String,
+    // Dot-separated path to the checked value within the query's response data
+    pub field: String,
+    // Comparison operator: ==, !=, <, <=, >, or >=
+    pub operator: String,
+    // Value to compare against
+    pub value: String,
+}
+
+impl Precondition {
+    // Check that the precondition is well formed
+    pub fn validate(&self) -> Result<(), String> {
+        if !OPERATORS.contains(&self.operator.as_str()) {
+            return Err(format!("Invalid precondition operator '{}'", self.operator));
+        }
+        if self.is_ordering() && self.value.parse::<f64>().is_err() {
+            return Err(format!(
+                "Precondition value '{}' must be a number to use operator '{}'",
+                self.value, self.operator
+            ));
+        }
+        Ok(())
+    }
+
+    fn is_ordering(&self) -> bool {
+        self.operator != "==" && self.operator != "!="
+    }
+
+    // Query the service and compare the result
+    pub fn check(&self) -> Result<(), SchedulerError> {
+        let actual = self.read()?;
+
+        if self.holds(&actual)? {
+            Ok(())
+        } else {
+            Err(SchedulerError::PreconditionError {
+                err: format!(
+                    "{} is {}, expected {} {}",
+                    self.field, actual, self.operator, self.value
+                ),
+            })
+        }
+    }
+
+    // Query the service and return the current value of the checked field
+    pub fn read(&self) -> Result<String, SchedulerError> {
+        let data = self
+            .query_service()
+            .map_err(|err| SchedulerError::PreconditionError {
+                err: format!("Failed to query {}: {}", self.service, err),
+            })?;
+
+        let actual = self
+            .field
+            .split('.')
+            .try_fold(&data, |value, key| match value {
+                Value::Array(list) => key.parse::<usize>().ok().and_then(|index| list.get(index)),
+                _ => value.get(key),
+            })
+            .ok_or_else(|| SchedulerError::PreconditionError {
+                err: format!("Field '{}' not found in response", self.field),
+            })?;
+
+        Ok(match actual {
+            Value::String(value) => value.to_owned(),
+            other => other.to_string(),
+        })
+    }
+
+    // Compare a value read from the service against the expected value
+    pub fn holds(&self, actual: &str) -> Result<bool, SchedulerError> {
+        // Values are compared as numbers if both can be, otherwise as strings
+        if let (Ok(actual), Ok(expected)) = (actual.parse::<f64>(), self.value.parse::<f64>()) {
+            return Ok(match self.operator.as_str() {
+                "==" => (actual - expected).abs() < f64::EPSILON,
+                "!=" => (actual - expected).abs() >= f64::EPSILON,
+                "<" => actual < expected,
+                "<=" => actual <= expected,
+                ">" => actual > expected,
+                _ => actual >= expected,
+            });
+        }
+
+        match self.operator.as_str() {
+            "==" => Ok(actual == self.value),
+            "!=" => Ok(actual != self.value),
+            _ => Err(SchedulerError::PreconditionError {
+                err: format!(
+                    "{} is {}, which can't be compared using '{}'",
+                    self.field, actual, self.operator
+                ),
+            }),
+        }
+    }
+
+    // Send the query and return the data from its response
+    fn query_service(&self) -> Result<Value, String> {
+        let url = Config::new(&self.service)
+            .map_err(|e| format!("Failed to load service config: {}", e))?
+            .hosturl()
+            .ok_or_else(|| "Failed to fetch service url".to_owned())?;
+
+        debug!("precondition query {}, url: {}", self.query, url);
+
+        let client = Client::builder()
+            .timeout(QUERY_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Error building client: {}", e))?;
+        let mut map = HashMap::new();
+        map.insert("query", &self.query);
+
+        let mut response: Value = client
+            .post(&format!("http://{}", url))
+            .json(&map)
+            .send()
+            .map_err(|e| format!("Error posting query: {}", e))?
+            .json()
+            .map_err(|e| format!("Error parsing response as JSON: {}", e))?;
+
+        if let Some(errors) = response.get("errors") {
+            return Err(format!("Query returned errors: {}", errors));
+        }
+
+        match response.get_mut("data") {
+            Some(data) => Ok(data.take()),
+            None => Err("Response contained no data".to_owned()),
+        }
+    }
+}
@@ -0,0 +1,233 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//!
+//! Rules for automatically changing modes based on the health of other services
+//!
+
+use crate::error::SchedulerError;
+use crate::mode::{activate_mode, get_active_mode, get_available_modes};
+use crate::precondition::Precondition;
+use crate::scheduler::Scheduler;
+use crate::task::parse_hms_field;
+use kubos_service::Config;
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Default time between evaluations of the mode rules
+pub static DEFAULT_CHECK_INTERVAL: &str = "10s";
+// Default minimum time between automatic mode changes
+pub static DEFAULT_HOLD_DOWN: &str = "5m";
+
+// Rule for automatically activating a mode, as read from the service's config
+#[derive(Clone, Debug, Deserialize)]
+pub struct ModeRule {
+    // Name used to identify the rule in logs
+    pub name: String,
+    // Mode to activate when the rule triggers
+    pub mode: String,
+    // How long the condition must hold before the rule triggers, in Xh Ym Zs format
+    pub duration: Option<String>,
+    // Condition on the result of a query to another service
+    pub condition: Precondition,
+}
+
+struct RuleState {
+    rule: ModeRule,
+    duration: Duration,
+    // When the condition was first seen to hold, and the value last read
+    held: Option<(Instant, String)>,
+}
+
+pub struct ModeRules {
+    rules: Vec<RuleState>,
+    check_interval: Duration,
+    hold_down: Duration,
+    // When a rule last changed the mode
+    last_change: Option<Instant>,
+}
+
+impl ModeRules {
+    // Read the mode rules and their settings from the service's config
+    pub fn from_config(config: &Config) -> Result<ModeRules, SchedulerError> {
+        let get_duration = |key: &str, default: &str| -> Result<Duration, SchedulerError> {
+            match config.get(key) {
+                Some(value) => parse_hms_field(
+                    value
+                        .as_str()
+                        .ok_or_else(|| SchedulerError::StartError {
+                            err: format!("Error parsing '{}' config value", key),
+                        })?
+                        .to_owned(),
+                ),
+                None => parse_hms_field(default.to_owned()),
+            }
+        };
+
+        let check_interval = get_duration("mode_check_interval", DEFAULT_CHECK_INTERVAL)?;
+        let hold_down = get_duration("mode_hold_down", DEFAULT_HOLD_DOWN)?;
+
+        if check_interval == Duration::from_secs(0) {
+            return Err(SchedulerError::StartError {
+                err: "'mode_check_interval' config value must be greater than zero".to_owned(),
+            });
+        }
+
+        let rules: Vec<ModeRule> = match config.get("mode_rules") {
+            Some(rules) => rules
+                .try_into()
+                .map_err(|e| SchedulerError::StartError {
+                    err: format!("Error parsing 'mode_rules' config value: {}", e),
+                })?,
+            None => vec![],
+        };
+
+        let rules = rules
+            .into_iter()
+            .map(|mut rule| {
+                rule.mode = rule.mode.to_lowercase();
+                rule.condition
+                    .validate()
+                    .map_err(|err| SchedulerError::StartError {
+                        err: format!("Invalid mode rule '{}': {}", rule.name, err),
+                    })?;
+                let duration = match &rule.duration {
+                    Some(duration) => parse_hms_field(duration.to_owned())?,
+                    None => Duration::from_secs(0),
+                };
+                Ok(RuleState {
+                    rule,
+                    duration,
+                    held: None,
+                })
+            })
+            .collect::<Result<Vec<RuleState>, SchedulerError>>()?;
+
+        Ok(ModeRules {
+            rules,
+            check_interval,
+            hold_down,
+            last_change: None,
+        })
+    }
+
+    // Check that each rule's mode exists, rather than falling back to safe mode once it triggers
+    pub fn validate_modes(&self, scheduler_dir: &str) -> Result<(), SchedulerError> {
+        for state in &self.rules {
+            if get_available_modes(scheduler_dir, Some(state.rule.mode.clone()))?.is_empty() {
+                return Err(SchedulerError::StartError {
+                    err: format!(
+                        "Invalid mode rule '{}': mode '{}' not found",
+                        state.rule.name, state.rule.mode
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Periodically evaluate the rules in the background
+    pub fn start(mut self, scheduler: Scheduler) {
+        if self.rules.is_empty() {
+            return;
+        }
+
+        info!("Monitoring {} mode rules", self.rules.len());
+
+        thread::spawn(move || loop {
+            thread::sleep(self.check_interval);
+            self.check(&scheduler);
+        });
+    }
+
+    fn check(&mut self, scheduler: &Scheduler) {
+        let now = Instant::now();
+
+        for state in self.rules.iter_mut() {
+            let condition = &state.rule.condition;
+            match condition
+                .read()
+                .and_then(|value| condition.holds(&value).map(|holds| (holds, value)))
+            {
+                Ok((true, value)) => {
+                    let since = state.held.as_ref().map_or(now, |(since, _)| *since);
+                    state.held = Some((since, value));
+                }
+                Ok((false, _)) => state.held = None,
+                Err(e) => {
+                    warn!("Failed to evaluate mode rule '{}': {}", state.rule.name, e);
+                    state.held = None;
+                }
+            }
+        }
+
+        let triggered = self.rules.iter().find_map(|state| match &state.held {
+            Some((since, value)) if now.duration_since(*since) >= state.duration => {
+                Some((&state.rule, value))
+            }
+            _ => None,
+        });
+
+        let (rule, value) = match triggered {
+            Some(triggered) => triggered,
+            None => return,
+        };
+
+        match get_active_mode(&scheduler.scheduler_dir) {
+            Ok(Some(active)) if active.name == rule.mode => return,
+            Err(e) => {
+                error!("Failed to get active mode: {}", e);
+                return;
+            }
+            _ => {}
+        }
+
+        if let Some(last_change) = self.last_change {
+            if now.duration_since(last_change) < self.hold_down {
+                debug!(
+                    "Mode rule '{}' triggered during hold down, not activating mode '{}'",
+                    rule.name, rule.mode
+                );
+                return;
+            }
+        }
+
+        let condition = &rule.condition;
+        warn!(
+            "Mode rule '{}' triggered ({} is {}, limit {} {}), activating mode '{}'",
+            rule.name, condition.field, value, condition.operator, condition.value, rule.mode
+        );
+
+        if let Err(e) = activate_mode(&scheduler.scheduler_dir, &rule.mode) {
+            error!(
+                "Mode rule '{}' failed to activate mode '{}': {}",
+                rule.name, rule.mode, e
+            );
+            return;
+        }
+
+        self.last_change = Some(now);
+
+        if let Err(e) = scheduler.stop().and_then(|_| scheduler.start()) {
+            error!(
+                "Mode rule '{}' failed to schedule tasks for mode '{}': {}",
+                rule.name, rule.mode, e
+            );
+        }
+    }
+}
@@ -19,6 +19,7 @@
 //!
 
 use crate::error::SchedulerError;
+use crate::history::History;
 use crate::mode::{
     activate_mode, create_mode, get_active_mode, get_available_modes, is_mode_active,
 };
@@ -53,6 +54,8 @@ pub struct Scheduler {
     // Map of active task list names and scheduler handles. This allows us to
     // start/stop tasks associated with individual task lists
     scheduler_map: Arc<Mutex<HashMap<String, SchedulerHandle>>>,
+    // Record of task executions
+    pub history: Arc<History>,
 }
 
 impl Scheduler {
@@ -80,6 +83,7 @@ impl Scheduler {
         };
 
         Ok(Scheduler {
+            history: Arc::new(History::new(&scheduler_dir)),
             scheduler_dir,
             scheduler_map: Arc::new(Mutex::new(HashMap::<String, SchedulerHandle>::new())),
             app_service_url: app_service_url.to_owned(),
@@ -141,7 +145,7 @@ impl Scheduler {
     // Schedules tasks associated with task list
     fn start_task_list(&self, list: TaskList) -> Result<(), SchedulerError> {
         let mut schedules_map = self.scheduler_map.lock().unwrap();
-        let scheduler_handle = list.schedule_tasks(&self.app_service_url)?;
+        let scheduler_handle = list.schedule_tasks(&self.app_service_url, self.history.clone())?;
         schedules_map.insert(list.filename, scheduler_handle);
         Ok(())
     }
@@ -18,9 +18,11 @@
 //! GraphQL schema for scheduler service's public interface
 //!
 
+use crate::history::Execution;
 use crate::mode::*;
 use crate::scheduler::{Scheduler, SAFE_MODE};
 use crate::task_list::{import_raw_task_list, import_task_list, remove_task_list};
+use crate::timeline::{get_timeline, Timeline};
 use juniper::FieldResult;
 use juniper::{graphql_object, GraphQLObject};
 use serde::Deserialize;
@@ -81,6 +83,63 @@ graphql_object!(QueryRoot: Context as "Query" |&self| {
     {
         Ok(get_available_modes(&executor.context().subsystem().scheduler_dir, name)?)
     }
+
+    // Returns the record of task executions, oldest first.
+    // Optionally filtered to executions started at or after a UTC time,
+    // or to those from a single task list, and limited to the most recent executions
+    // {
+    //     executionHistory(since: String, taskList: String, limit: Int): [
+    //         {
+    //             taskList: String,
+    //             description: String,
+    //             app: String,
+    //             scheduled: String,
+    //             started: String,
+    //             attempt: Int,
+    //             success: Boolean,
+    //             errors: String,
+    //             pid: Int
+    //         }
+    //     ]
+    // }
+    field execution_history(&executor, since: Option<String>, task_list: Option<String>, limit: Option<i32>) -> FieldResult<Vec<Execution>> as "Execution History"
+    {
+        Ok(executor.context().subsystem().history.get(since, task_list, limit)?)
+    }
+
+    // Previews when the tasks in a mode would run if it were activated now,
+    // up to a horizon in Xh Ym Zs format. Runs of the same app starting within the
+    // earlier run's app timeout, or window (default 1s) if it has none, are flagged as overlapping
+    // {
+    //     timeline(mode: String!, horizon: String!, window: String): {
+    //         start: String,
+    //         end: String,
+    //         entries: [
+    //             {
+    //                 time: String,
+    //                 taskList: String,
+    //                 description: String,
+    //                 app: String,
+    //                 args: [String],
+    //                 overlaps: Boolean
+    //             }
+    //         ],
+    //         outsideHorizon: [
+    //             {
+    //                 taskList: String,
+    //                 description: String,
+    //                 app: String,
+    //                 nextRun: String
+    //             }
+    //         ],
+    //         truncated: Boolean
+    //     }
+    // }
+    field timeline(&executor, mode: String, horizon: String, window: Option<String>) -> FieldResult<Timeline>
+    {
+        let window = window.unwrap_or_else(|| "1s".to_owned());
+        Ok(get_timeline(&executor.context().subsystem().scheduler_dir, &mode, &horizon, &window)?)
+    }
 });
 
 pub struct MutationRoot;
@@ -19,18 +19,26 @@
 //!
 
 use crate::app::App;
+use crate::cron::CronSchedule;
 use crate::error::SchedulerError;
+use crate::history::{format_time, Execution, History};
+use crate::precondition::Precondition;
 use chrono::offset::TimeZone;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use juniper::GraphQLObject;
-use log::error;
+use log::{error, warn};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 use tokio::prelude::*;
 use tokio::timer::Delay;
 use tokio::timer::Interval;
 
+// Longest time the scheduler waits before re-checking the system clock for tasks scheduled
+// by time or cron rule, so that corrections to the clock are picked up
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
 // Configuration used to schedule app execution
 #[derive(Clone, Debug, GraphQLObject, Serialize, Deserialize)]
 pub struct Task {
@@ -40,17 +48,94 @@ pub struct Task {
     // Used by init and recurring tasks
     pub delay: Option<String>,
     // Start time specified in yyyy-mm-dd hh:mm:ss format
-    // Used by onetime tasks
+    // Used by onetime and absolute-start recurring tasks
     pub time: Option<String>,
     // Period of recurrence specified in Xh Ym Zs format
     // Used by recurring tasks
     pub period: Option<String>,
+    // Recurrence rule in five field cron format, evaluated in UTC
+    // Used by recurring tasks
+    pub cron: Option<String>,
+    // Description of another task in the same task list. The task runs each time
+    // that task succeeds, instead of having its own start time
+    pub depends_on: Option<String>,
+    // Condition which must hold for the task to run
+    pub precondition: Option<Precondition>,
+    // How to retry the task if it fails to run
+    pub retry: Option<Retry>,
     // Details of the app to be executed
     pub app: App,
 }
 
+// Retry policy for a task whose precondition isn't met or whose app fails to start
+#[derive(Clone, Debug, GraphQLObject, Serialize, Deserialize)]
+pub struct Retry {
+    // Maximum number of retries after the first attempt
+    pub count: i32,
+    // Delay between attempts specified in Xh Ym Zs format
+    pub delay: String,
+}
+
+// Everything a scheduled task needs in order to run
+#[derive(Clone)]
+pub struct TaskContext {
+    // URL of App Service - for start app queries
+    pub service_url: String,
+    // Name of the task list the task belongs to
+    pub task_list: String,
+    // Where executions are recorded
+    pub history: Arc<History>,
+    // All of the tasks in the task list, for finding dependent tasks
+    pub tasks: Arc<Vec<Task>>,
+}
+
+impl TaskContext {
+    // Tasks which run after the given task succeeds
+    fn dependents(&self, description: &str) -> Vec<Task> {
+        self.tasks
+            .iter()
+            .filter(|task| task.depends_on.as_deref() == Some(description))
+            .cloned()
+            .collect()
+    }
+}
+
 impl Task {
-    // Parse timer delay duration from either delay or time fields
+    // Check the task's fields. Dependencies on other tasks are checked with the rest of the
+    // task list in validate_task_list
+    pub fn validate(&self) -> Result<(), SchedulerError> {
+        if self.depends_on.is_some() {
+            if self.delay.is_some()
+                || self.time.is_some()
+                || self.cron.is_some()
+                || self.period.is_some()
+            {
+                return Err(SchedulerError::TaskParseError {
+                    err: "Dependent task can't have delay, time, cron or period".to_owned(),
+                    description: self.description.to_owned(),
+                });
+            }
+        } else {
+            let _ = self.get_duration()?;
+            let _ = self.get_period()?;
+        }
+
+        let _ = self.get_retry()?;
+        let _ = self.app.get_timeout()?;
+
+        if let Some(precondition) = &self.precondition {
+            precondition
+                .validate()
+                .map_err(|err| SchedulerError::TaskParseError {
+                    err,
+                    description: self.description.to_owned(),
+                })?;
+        }
+
+        Ok(())
+    }
+
+    // Parse timer delay duration from either delay, time, or cron fields
     pub fn get_duration(&self) -> Result<Duration, SchedulerError> {
         if self.delay.is_some() && self.time.is_some() {
             return Err(SchedulerError::TaskParseError {
@@ -58,38 +143,53 @@ impl Task {
                 description: self.description.to_owned(),
             });
         }
+        if self.cron.is_some()
+            && (self.delay.is_some() || self.time.is_some() || self.period.is_some())
+        {
+            return Err(SchedulerError::TaskParseError {
+                err: "Cron defined with delay, time or period".to_owned(),
+                description: self.description.to_owned(),
+            });
+        }
         if let Some(delay) = &self.delay {
             Ok(parse_hms_field(delay.to_owned())?)
-        } else if let Some(time) = &self.time {
-            let run_time = Utc
-                .datetime_from_str(time, "%Y-%m-%d %H:%M:%S")
-                .map_err(|e| SchedulerError::TaskParseError {
-                    err: format!("Failed to parse time field '{}': {}", time, e),
-                    description: self.description.to_owned(),
-                })?;
+        } else if self.time.is_some() || self.cron.is_some() {
             let now = chrono::Utc::now();
 
-            if run_time < now {
-                Err(SchedulerError::TaskTimeError {
-                    err: format!("Task scheduled for past time: {}", time),
-                    description: self.description.to_owned(),
-                })
-            } else if (run_time - now) > chrono::Duration::days(90) {
-                Err(SchedulerError::TaskTimeError {
-                    err: format!("Task scheduled beyond 90 days in the future: {}", time),
-                    description: self.description.to_owned(),
-                })
-            } else {
-                Ok((run_time - now)
-                    .to_std()
-                    .map_err(|e| SchedulerError::TaskParseError {
-                        err: format!("Failed to calculate run time: {}", e),
+            if let Some(time) = &self.time {
+                let run_time = self.get_time(time)?;
+
+                // Recurring tasks may have a start time in the past. They will next run at
+                // the first period boundary after now
+                if run_time < now && self.period.is_none() {
+                    return Err(SchedulerError::TaskTimeError {
+                        err: format!("Task scheduled for past time: {}", time),
                         description: self.description.to_owned(),
-                    })?)
+                    });
+                } else if (run_time - now) > chrono::Duration::days(90) {
+                    return Err(SchedulerError::TaskTimeError {
+                        err: format!("Task scheduled beyond 90 days in the future: {}", time),
+                        description: self.description.to_owned(),
+                    });
+                }
             }
+
+            let run_time = self
+                .next_run(now)?
+                .ok_or_else(|| SchedulerError::TaskParseError {
+                    err: "Task will never run".to_owned(),
+                    description: self.description.to_owned(),
+                })?;
+
+            Ok((run_time - now)
+                .to_std()
+                .map_err(|e| SchedulerError::TaskParseError {
+                    err: format!("Failed to calculate run time: {}", e),
+                    description: self.description.to_owned(),
+                })?)
         } else {
             Err(SchedulerError::TaskParseError {
-                err: "No delay or time defined".to_owned(),
+                err: "No delay, time or cron defined".to_owned(),
                 description: self.description.to_owned(),
             })
         }
@@ -97,13 +197,179 @@ impl Task {
 
     pub fn get_period(&self) -> Result<Option<Duration>, SchedulerError> {
         if let Some(period) = &self.period {
-            Ok(Some(parse_hms_field(period.to_owned())?))
+            let period = parse_hms_field(period.to_owned())?;
+            if period == Duration::from_secs(0) {
+                return Err(SchedulerError::TaskParseError {
+                    err: "Period must be greater than zero".to_owned(),
+                    description: self.description.to_owned(),
+                });
+            }
+            Ok(Some(period))
         } else {
             Ok(None)
         }
     }
 
-    pub fn schedule(&self, service_url: String) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+    // Parse the number of retries and the delay between them
+    pub fn get_retry(&self) -> Result<(i32, Duration), SchedulerError> {
+        match &self.retry {
+            Some(retry) => {
+                if retry.count < 0 {
+                    return Err(SchedulerError::TaskParseError {
+                        err: "Retry count can't be negative".to_owned(),
+                        description: self.description.to_owned(),
+                    });
+                }
+                Ok((retry.count, parse_hms_field(retry.delay.to_owned())?))
+            }
+            None => Ok((0, Duration::from_secs(0))),
+        }
+    }
+
+    fn get_time(&self, time: &str) -> Result<DateTime<Utc>, SchedulerError> {
+        Utc.datetime_from_str(time, "%Y-%m-%d %H:%M:%S")
+            .map_err(|e| SchedulerError::TaskParseError {
+                err: format!("Failed to parse time field '{}': {}", time, e),
+                description: self.description.to_owned(),
+            })
+    }
+
+    fn get_cron(&self, cron: &str) -> Result<CronSchedule, SchedulerError> {
+        cron.parse().map_err(|e| SchedulerError::TaskParseError {
+            err: format!("Failed to parse cron field '{}': {}", cron, e),
+            description: self.description.to_owned(),
+        })
+    }
+
+    // Find the first run of a time or cron task which is strictly after the given time.
+    // Returns None once a task has no more runs left, and for delay tasks
+    pub fn next_run(&self, after: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, SchedulerError> {
+        if let Some(cron) = &self.cron {
+            Ok(self.get_cron(cron)?.next_after(after))
+        } else if let Some(time) = &self.time {
+            let start = self.get_time(time)?;
+            if start > after {
+                return Ok(Some(start));
+            }
+
+            match self.get_period()? {
+                // Runs stay aligned to the start time, no matter when the task was scheduled
+                Some(period) => {
+                    let period = period.as_secs() as i64 * 1000;
+                    let elapsed = (after - start).num_milliseconds();
+                    let periods = elapsed / period + 1;
+                    Ok(Some(
+                        start + chrono::Duration::milliseconds(periods * period),
+                    ))
+                }
+                None => Ok(None),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Run the task, retrying it according to its retry policy,
+    // and then start or skip the tasks which depend on it
+    fn run(
+        &self,
+        context: &TaskContext,
+        scheduled: DateTime<Utc>,
+    ) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+        let task = self.clone();
+        let context = context.clone();
+        let (retries, retry_delay) = self.get_retry().unwrap_or((0, Duration::from_secs(0)));
+
+        Box::new(future::loop_fn(1, move |attempt| {
+            let success = task.attempt(&context, scheduled, attempt);
+
+            if success || attempt > retries {
+                task.finish(&context, success);
+                return future::Either::A(future::ok(future::Loop::Break(())));
+            }
+
+            let name = task.app.name.to_owned();
+            future::Either::B(
+                Delay::new(Instant::now() + retry_delay)
+                    .map(move |_| future::Loop::Continue(attempt + 1))
+                    .map_err(move |e| {
+                        error!("Retry delay errored for task '{}': {}", name, e);
+                        panic!("Retry delay errored for task '{}': {}", name, e)
+                    }),
+            )
+        }))
+    }
+
+    // Check the task's precondition and start its app, recording the outcome
+    fn attempt(&self, context: &TaskContext, scheduled: DateTime<Utc>, attempt: i32) -> bool {
+        let started = Utc::now();
+
+        let checked = match &self.precondition {
+            Some(precondition) => precondition.check(),
+            None => Ok(()),
+        };
+
+        let (success, errors, pid) = match checked {
+            Ok(()) => {
+                let response = self.app.execute(&context.service_url);
+                (response.success, response.errors, response.pid)
+            }
+            Err(e) => {
+                warn!("Not running task '{}': {}", self.description, e);
+                (false, e.to_string(), None)
+            }
+        };
+
+        context.history.record(Execution {
+            task_list: context.task_list.to_owned(),
+            description: self.description.to_owned(),
+            app: self.app.name.to_owned(),
+            scheduled: format_time(scheduled),
+            started: format_time(started),
+            attempt,
+            success,
+            errors,
+            pid,
+        });
+
+        success
+    }
+
+    // Start the tasks which depend on this one, or skip them if it didn't succeed
+    fn finish(&self, context: &TaskContext, success: bool) {
+        for task in context.dependents(&self.description) {
+            if success {
+                tokio::spawn(task.run(context, Utc::now()));
+            } else {
+                task.skip(
+                    context,
+                    &format!("Dependency '{}' did not succeed", self.description),
+                );
+            }
+        }
+    }
+
+    // Record that the task didn't run, along with any tasks depending on it
+    fn skip(&self, context: &TaskContext, reason: &str) {
+        warn!("Skipping task '{}': {}", self.description, reason);
+
+        let now = format_time(Utc::now());
+        context.history.record(Execution {
+            task_list: context.task_list.to_owned(),
+            description: self.description.to_owned(),
+            app: self.app.name.to_owned(),
+            scheduled: now.to_owned(),
+            started: now,
+            attempt: 0,
+            success: false,
+            errors: reason.to_owned(),
+            pid: None,
+        });
+
+        self.finish(context, false);
+    }
+
+    pub fn schedule(&self, context: TaskContext) -> Box<dyn Future<Item = (), Error = ()> + Send> {
         let name = self.app.name.to_owned();
         let duration = match self.get_duration() {
             Ok(d) => d,
@@ -116,15 +382,24 @@ impl Task {
             }
         };
 
-        let when = Instant::now() + duration;
+        if self.delay.is_none() {
+            return self.schedule_wall_clock(context, duration);
+        }
+
+        let start = Instant::now();
+        let when = start + duration;
+        // Used to convert timer instants into the times recorded in the execution history
+        let start_time = Utc::now();
         let period = self.get_period();
-        let app = self.app.clone();
+        let task = self.clone();
 
         match period {
             Ok(Some(period)) => Box::new(
                 Interval::new(when, period)
-                    .for_each(move |_| {
-                        app.execute(&service_url.clone());
+                    .for_each(move |instant| {
+                        let offset = chrono::Duration::from_std(instant - start)
+                            .unwrap_or_else(|_| chrono::Duration::zero());
+                        tokio::spawn(task.run(&context, start_time + offset));
                         Ok(())
                     })
                     .map_err(move |e| {
@@ -135,7 +410,9 @@ impl Task {
             _ => Box::new(
                 Delay::new(when)
                     .and_then(move |_| {
-                        app.execute(&service_url);
+                        let offset = chrono::Duration::from_std(duration)
+                            .unwrap_or_else(|_| chrono::Duration::zero());
+                        tokio::spawn(task.run(&context, start_time + offset));
                         Ok(())
                     })
                     .map_err(move |e| {
@@ -145,9 +422,72 @@ impl Task {
             ),
         }
     }
+
+    // Time and cron tasks follow the system clock rather than the time since the scheduler
+    // started. Each run is worked out again from the current UTC time whenever the scheduler
+    // wakes up, so the task still runs at the intended time if the clock is corrected
+    // while it's waiting.
+    fn schedule_wall_clock(
+        &self,
+        context: TaskContext,
+        duration: Duration,
+    ) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+        let task = self.clone();
+        let first = Utc::now()
+            + chrono::Duration::from_std(duration).unwrap_or_else(|_| chrono::Duration::zero());
+
+        Box::new(future::loop_fn(first, move |run_time| {
+            let task = task.clone();
+            let context = context.clone();
+            let name = task.app.name.to_owned();
+
+            let wait = (run_time - Utc::now())
+                .to_std()
+                .unwrap_or_else(|_| Duration::from_secs(0))
+                .min(CLOCK_CHECK_INTERVAL);
+
+            Delay::new(Instant::now() + wait)
+                .map_err(move |e| {
+                    error!("Delay errored for task '{}': {}", name, e);
+                    panic!("Delay errored for task '{}': {}", name, e)
+                })
+                .map(move |_| {
+                    let now = Utc::now();
+
+                    if now >= run_time {
+                        // We wake up at least once a minute, so being any later than that means
+                        // the clock was moved forward past the run
+                        if (now - run_time).to_std().unwrap_or(CLOCK_CHECK_INTERVAL)
+                            < CLOCK_CHECK_INTERVAL
+                        {
+                            tokio::spawn(task.run(&context, run_time));
+                        } else {
+                            warn!(
+                                "Skipping run of task '{}' scheduled for {}: system clock moved past it",
+                                task.description, run_time
+                            );
+                        }
+                    }
+
+                    // If the clock was moved back, the next run might now be earlier than the
+                    // one we were waiting for
+                    match task.next_run(now) {
+                        Ok(Some(next)) => future::Loop::Continue(next),
+                        Ok(None) => future::Loop::Break(()),
+                        Err(e) => {
+                            error!(
+                                "Failed to find next run for task '{}': {}",
+                                task.description, e
+                            );
+                            future::Loop::Break(())
+                        }
+                    }
+                })
+        }))
+    }
 }
 
-fn parse_hms_field(field: String) -> Result<Duration, SchedulerError> {
+pub fn parse_hms_field(field: String) -> Result<Duration, SchedulerError> {
     let field_parts: Vec<String> = field.split(' ').map(|s| s.to_owned()).collect();
     let mut duration: u64 = 0;
     if field_parts.is_empty() {
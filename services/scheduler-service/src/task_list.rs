@@ -19,8 +19,9 @@
 //!
 
 use crate::error::SchedulerError;
+use crate::history::History;
 use crate::scheduler::SchedulerHandle;
-use crate::task::Task;
+use crate::task::{Task, TaskContext};
 use chrono::{DateTime, Utc};
 use juniper::GraphQLObject;
 use log::{error, info};
@@ -108,10 +109,25 @@ impl TaskList {
     }
 
     // Schedules the tasks contained in this task list
-    pub fn schedule_tasks(&self, app_service_url: &str) -> Result<SchedulerHandle, SchedulerError> {
+    pub fn schedule_tasks(
+        &self,
+        app_service_url: &str,
+        history: Arc<History>,
+    ) -> Result<SchedulerHandle, SchedulerError> {
         let (stopper, receiver) = channel::<()>();
-        let service_url = app_service_url.to_owned();
-        let tasks = self.tasks.to_vec();
+        let context = TaskContext {
+            service_url: app_service_url.to_owned(),
+            task_list: self.filename.to_owned(),
+            history,
+            tasks: Arc::new(self.tasks.to_vec()),
+        };
+        // Dependent tasks are started by the tasks they depend on
+        let tasks: Vec<Task> = self
+            .tasks
+            .iter()
+            .filter(|task| task.depends_on.is_none())
+            .cloned()
+            .collect();
         let thread_handle = thread::spawn(move || {
             let mut runner = Runtime::new().unwrap_or_else(|e| {
                 error!("Failed to create timer runtime: {}", e);
@@ -121,7 +137,7 @@ impl TaskList {
             runner.spawn(lazy(move || {
                 for task in tasks {
                     info!("Scheduling task '{}'", &task.app.name);
-                    tokio::spawn(task.schedule(service_url.clone()));
+                    tokio::spawn(task.schedule(context.clone()));
                 }
                 Ok(())
             }));
@@ -283,9 +299,53 @@ pub fn get_mode_task_lists(mode_path: &str) -> Result<Vec<TaskList>, SchedulerEr
 pub fn validate_task_list(path: &str) -> Result<(), SchedulerError> {
     let task_path = Path::new(path);
     let task_list = TaskList::from_path(task_path)?;
-    for task in task_list.tasks {
-        let _ = task.get_duration()?;
-        let _ = task.get_period()?;
+    for task in &task_list.tasks {
+        task.validate()?;
+    }
+    validate_dependencies(&task_list.tasks)
+}
+
+// Make sure each dependency refers to exactly one other task, and that following them
+// always leads back to a task with its own start time
+fn validate_dependencies(tasks: &[Task]) -> Result<(), SchedulerError> {
+    for task in tasks {
+        if let Some(dependency) = &task.depends_on {
+            match tasks
+                .iter()
+                .filter(|t| &t.description == dependency)
+                .count()
+            {
+                0 => {
+                    return Err(SchedulerError::TaskParseError {
+                        err: format!("Dependency '{}' not found", dependency),
+                        description: task.description.to_owned(),
+                    })
+                }
+                1 => {}
+                _ => {
+                    return Err(SchedulerError::TaskParseError {
+                        err: format!("Dependency '{}' matches more than one task", dependency),
+                        description: task.description.to_owned(),
+                    })
+                }
+            }
+        }
+
+        let mut current = task;
+        let mut steps = 0;
+        while let Some(dependency) = &current.depends_on {
+            steps += 1;
+            if steps > tasks.len() {
+                return Err(SchedulerError::TaskParseError {
+                    err: "Circular dependency found".to_owned(),
+                    description: task.description.to_owned(),
+                });
+            }
+            match tasks.iter().find(|t| &t.description == dependency) {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
     }
     Ok(())
 }
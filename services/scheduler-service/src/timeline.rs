@@ -0,0 +1,273 @@
+/*
+ * Copyright (C) 2019 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//!
+//! Preview of when the tasks in a mode would run
+//!
+
+use crate::error::SchedulerError;
+use crate::history::format_time;
+use crate::task::{parse_hms_field, Task};
+use crate::task_list::get_mode_task_lists;
+use chrono::{DateTime, Utc};
+use juniper::GraphQLObject;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Most runs included in a single timeline
+pub const MAX_TIMELINE_ENTRIES: usize = 10000;
+
+// A single run of a task
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct TimelineEntry {
+    // UTC time of the run
+    pub time: String,
+    // Name of the task list containing the task
+    pub task_list: String,
+    // Description of the task
+    pub description: String,
+    // Name of the app which would be started
+    pub app: String,
+    // Arguments the app would be started with
+    pub args: Option<Vec<String>>,
+    // Whether another run of the same app starts too close to this one
+    pub overlaps: bool,
+}
+
+// A task which doesn't run within the timeline
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct OutsideEntry {
+    // Name of the task list containing the task
+    pub task_list: String,
+    // Description of the task
+    pub description: String,
+    // Name of the app which would be started
+    pub app: String,
+    // UTC time of the task's next run after the timeline, if it has one
+    pub next_run: Option<String>,
+}
+
+// Every run of a mode's tasks between two times, as if the mode were activated at the start
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct Timeline {
+    // UTC start time of the timeline
+    pub start: String,
+    // UTC end time of the timeline
+    pub end: String,
+    // Runs within the timeline, in order
+    pub entries: Vec<TimelineEntry>,
+    // Tasks which don't run within the timeline
+    pub outside_horizon: Vec<OutsideEntry>,
+    // Whether runs were left out because the timeline was too long
+    pub truncated: bool,
+}
+
+struct Run {
+    time: DateTime<Utc>,
+    task_list: String,
+    task: Task,
+}
+
+// Times a task runs within the timeline, and the time of its next run after it
+type TaskRuns = (Vec<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+// Times a task with its own start time runs between the start and end times
+fn task_runs(
+    task: &Task,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<TaskRuns, SchedulerError> {
+    let mut runs = vec![];
+
+    let mut next = if let Some(delay) = &task.delay {
+        let delay = chrono::Duration::from_std(parse_hms_field(delay.to_owned())?)
+            .map_err(|e| SchedulerError::GenericError { err: e.to_string() })?;
+        Some(start + delay)
+    } else {
+        task.next_run(start)?
+    };
+
+    let period = match (&task.delay, task.get_period()?) {
+        (Some(_), Some(period)) => Some(
+            chrono::Duration::from_std(period)
+                .map_err(|e| SchedulerError::GenericError { err: e.to_string() })?,
+        ),
+        _ => None,
+    };
+
+    while let Some(time) = next {
+        // Go one past the limit, so the timeline knows it was truncated
+        if time > end || runs.len() > MAX_TIMELINE_ENTRIES {
+            break;
+        }
+        runs.push(time);
+        next = match period {
+            Some(period) => Some(time + period),
+            None if task.delay.is_some() => None,
+            None => task.next_run(time)?,
+        };
+    }
+
+    Ok((runs, next))
+}
+
+// Add the runs of the tasks which depend on the given task. Dependent tasks are assumed to run
+// as soon as the task they depend on succeeds
+fn add_dependents(
+    runs: &mut Vec<Run>,
+    tasks: &[Task],
+    task_list: &str,
+    task: &Task,
+    times: &[DateTime<Utc>],
+    depth: usize,
+) {
+    if depth > tasks.len() {
+        return;
+    }
+
+    for dependent in tasks
+        .iter()
+        .filter(|t| t.depends_on.as_deref() == Some(task.description.as_str()))
+    {
+        for time in times {
+            runs.push(Run {
+                time: *time,
+                task_list: task_list.to_owned(),
+                task: dependent.clone(),
+            });
+        }
+        add_dependents(runs, tasks, task_list, dependent, times, depth + 1);
+    }
+}
+
+// Build the timeline of a mode's tasks from now until the end of the horizon.
+// Runs of the same app are flagged as overlapping if one starts before an earlier run's app
+// timeout has passed, or less than `window` after it if the earlier run has no timeout
+pub fn get_timeline(
+    scheduler_dir: &str,
+    mode: &str,
+    horizon: &str,
+    window: &str,
+) -> Result<Timeline, SchedulerError> {
+    let mode = mode.to_lowercase();
+    let mode_path = format!("{}/{}", scheduler_dir, mode);
+    if !Path::new(&mode_path).is_dir() {
+        return Err(SchedulerError::GenericError {
+            err: format!("Mode '{}' not found", mode),
+        });
+    }
+
+    let to_chrono = |field: &str| -> Result<chrono::Duration, SchedulerError> {
+        chrono::Duration::from_std(parse_hms_field(field.to_owned())?)
+            .map_err(|e| SchedulerError::GenericError { err: e.to_string() })
+    };
+    let horizon = to_chrono(horizon)?;
+    let window = to_chrono(window)?;
+
+    let start = Utc::now();
+    let end = start + horizon;
+
+    let mut runs = vec![];
+    let mut outside_horizon = vec![];
+
+    for list in get_mode_task_lists(&mode_path)? {
+        for task in list.tasks.iter().filter(|task| task.depends_on.is_none()) {
+            let (times, next) = task_runs(task, start, end)?;
+
+            if times.is_empty() {
+                outside_horizon.push(OutsideEntry {
+                    task_list: list.filename.to_owned(),
+                    description: task.description.to_owned(),
+                    app: task.app.name.to_owned(),
+                    next_run: next.map(format_time),
+                });
+            }
+
+            for time in &times {
+                runs.push(Run {
+                    time: *time,
+                    task_list: list.filename.to_owned(),
+                    task: task.clone(),
+                });
+            }
+            add_dependents(&mut runs, &list.tasks, &list.filename, task, &times, 0);
+        }
+    }
+
+    // Runs at the same time stay in the order they were added, except that dependent tasks
+    // always come after the tasks they depend on
+    runs.sort_by(|a, b| {
+        a.time.cmp(&b.time).then(
+            a.task
+                .depends_on
+                .is_some()
+                .cmp(&b.task.depends_on.is_some()),
+        )
+    });
+
+    let truncated = runs.len() > MAX_TIMELINE_ENTRIES;
+    runs.truncate(MAX_TIMELINE_ENTRIES);
+
+    // How long each run is expected to keep its app busy for
+    let windows = runs
+        .iter()
+        .map(|run| match run.task.app.get_timeout()? {
+            Some(timeout) => chrono::Duration::from_std(timeout)
+                .map_err(|e| SchedulerError::GenericError { err: e.to_string() }),
+            None => Ok(window),
+        })
+        .collect::<Result<Vec<_>, SchedulerError>>()?;
+
+    let mut overlaps = vec![false; runs.len()];
+    // Earlier runs of each app whose window is still open
+    let mut open_runs: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, run) in runs.iter().enumerate() {
+        let open = open_runs.entry(run.task.app.name.as_str()).or_default();
+        open.retain(|&earlier| run.time - runs[earlier].time < windows[earlier]);
+        for &earlier in open.iter() {
+            let earlier_run = &runs[earlier];
+            // A dependent task only starts once the task it depends on has finished
+            let dependent = run.task_list == earlier_run.task_list
+                && run.task.depends_on.as_deref() == Some(earlier_run.task.description.as_str());
+            if !dependent {
+                overlaps[earlier] = true;
+                overlaps[index] = true;
+            }
+        }
+        open.push(index);
+    }
+
+    let entries = runs
+        .iter()
+        .zip(overlaps)
+        .map(|(run, overlaps)| TimelineEntry {
+            time: format_time(run.time),
+            task_list: run.task_list.to_owned(),
+            description: run.task.description.to_owned(),
+            app: run.task.app.name.to_owned(),
+            args: run.task.app.args.clone(),
+            overlaps,
+        })
+        .collect();
+
+    Ok(Timeline {
+        start: format_time(start),
+        end: format_time(end),
+        entries,
+        outside_horizon,
+        truncated,
+    })
+}
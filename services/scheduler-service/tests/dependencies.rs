@@ -0,0 +1,159 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+mod util;
+
+use serde_json::json;
+use std::thread;
+use std::time::Duration;
+use util::SchedulerFixture;
+use utils::testing::{ServiceListener, ServiceResponder};
+
+// Stands in for both the app service and a service answering precondition queries
+#[derive(Clone)]
+struct PowerResponder;
+impl ServiceResponder for PowerResponder {
+    fn respond(&self, body: &str) -> String {
+        if body.contains("startApp") {
+            json!({"data": {"startApp": {"success": true, "errors": "", "pid": 1234}}})
+        } else {
+            json!({"data": {"power": {"voltage": 6.9}}})
+        }
+        .to_string()
+    }
+}
+
+fn start_query(name: &str) -> String {
+    format!(
+        r#"{{"query":"mutation {{ startApp(name: \"{}\") {{ success, errors, pid }} }}"}}"#,
+        name
+    )
+}
+
+#[test]
+fn run_dependent_task() {
+    let listener = ServiceListener::spawn_with_responder("127.0.0.1", 9038, PowerResponder);
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8038);
+
+    fixture.create_mode("init");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "second-task",
+                "depends_on": "first-task",
+                "app": {
+                    "name": "second-app"
+                }
+            },
+            {
+                "description": "first-task",
+                "delay": "0s",
+                "app": {
+                    "name": "first-app"
+                }
+            }
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    fixture.import_task_list("imaging", &schedule_path, "init");
+    fixture.activate_mode("init");
+
+    thread::sleep(Duration::from_millis(500));
+
+    // The dependent task only runs once the first task has started successfully
+    assert_eq!(listener.get_request(), Some(start_query("first-app")));
+    assert_eq!(listener.get_request(), Some(start_query("second-app")));
+    assert_eq!(listener.get_request(), None);
+}
+
+#[test]
+fn precondition_retry_and_skip() {
+    let listener = ServiceListener::spawn_with_responder("127.0.0.1", 9039, PowerResponder);
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8039);
+
+    fixture.create_mode("init");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "first-task",
+                "delay": "0s",
+                "precondition": {
+                    "service": "app-service",
+                    "query": "{ power { voltage } }",
+                    "field": "power.voltage",
+                    "operator": ">",
+                    "value": "7.2"
+                },
+                "retry": {
+                    "count": 1,
+                    "delay": "1s"
+                },
+                "app": {
+                    "name": "first-app"
+                }
+            },
+            {
+                "description": "second-task",
+                "depends_on": "first-task",
+                "app": {
+                    "name": "second-app"
+                }
+            }
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    fixture.import_task_list("imaging", &schedule_path, "init");
+    fixture.activate_mode("init");
+
+    thread::sleep(Duration::from_millis(1500));
+
+    let query = r#"{"query":"{ power { voltage } }"}"#;
+
+    // Neither app is started, since the precondition never holds
+    assert_eq!(listener.get_request(), Some(query.to_owned()));
+    assert_eq!(listener.get_request(), Some(query.to_owned()));
+    assert_eq!(listener.get_request(), None);
+
+    assert_eq!(
+        fixture.query(r#"{ executionHistory { description, attempt, success, errors } }"#),
+        json!({
+            "data": {
+                "executionHistory": [
+                    {
+                        "description": "first-task",
+                        "attempt": 1,
+                        "success": false,
+                        "errors": "Precondition not met: power.voltage is 6.9, expected > 7.2"
+                    },
+                    {
+                        "description": "first-task",
+                        "attempt": 2,
+                        "success": false,
+                        "errors": "Precondition not met: power.voltage is 6.9, expected > 7.2"
+                    },
+                    {
+                        "description": "second-task",
+                        "attempt": 0,
+                        "success": false,
+                        "errors": "Dependency 'first-task' did not succeed"
+                    }
+                ]
+            }
+        })
+    );
+}
@@ -0,0 +1,115 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+mod util;
+
+use serde_json::json;
+use std::thread;
+use std::time::Duration;
+use util::{BasicAppResponder, SchedulerFixture};
+use utils::testing::ServiceListener;
+
+#[test]
+fn history_records_executions() {
+    let _listener = ServiceListener::spawn_with_responder("127.0.0.1", 9036, BasicAppResponder);
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8036);
+
+    fixture.create_mode("init");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "basic-task",
+                "delay": "0s",
+                "app": {
+                    "name": "basic-app"
+                }
+            }
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    fixture.import_task_list("imaging", &schedule_path, "init");
+    fixture.activate_mode("init");
+
+    // Wait for the task to run
+    thread::sleep(Duration::from_millis(500));
+
+    let query = r#"{ executionHistory { taskList, description, app, success, errors, pid } }"#;
+    let expected = json!({
+        "data": {
+            "executionHistory": [
+                {
+                    "taskList": "imaging",
+                    "description": "basic-task",
+                    "app": "basic-app",
+                    "success": true,
+                    "errors": "",
+                    "pid": 1234
+                }
+            ]
+        }
+    });
+    assert_eq!(fixture.query(query), expected);
+
+    // The history should survive a restart of the service
+    fixture.activate_safe();
+    fixture.restart();
+    assert_eq!(fixture.query(query), expected);
+}
+
+#[test]
+fn history_records_failures() {
+    let _listener = ServiceListener::spawn("127.0.0.1", 9037);
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8037);
+
+    fixture.create_mode("init");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "basic-task",
+                "delay": "0s",
+                "period": "1s",
+                "app": {
+                    "name": "basic-app"
+                }
+            }
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    fixture.import_task_list("imaging", &schedule_path, "init");
+    fixture.activate_mode("init");
+
+    // Wait for the task to run twice
+    thread::sleep(Duration::from_millis(1500));
+
+    let query = r#"{ executionHistory(taskList: "imaging", limit: 1) { description, success, errors, pid } }"#;
+    assert_eq!(
+        fixture.query(query),
+        json!({
+            "data": {
+                "executionHistory": [
+                    {
+                        "description": "basic-task",
+                        "success": false,
+                        "errors": "Scheduler query failed: Error parsing response as JSON: missing field `data` at line 1 column 2",
+                        "pid": null
+                    }
+                ]
+            }
+        })
+    );
+}
@@ -109,7 +109,7 @@ fn import_raw_run_delay() {
     // Wait for the service to restart the scheduler
     thread::sleep(Duration::from_millis(100));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
 
     // Check if the task actually ran
     assert_eq!(listener.get_request(), Some(query.to_owned()))
@@ -150,11 +150,11 @@ fn import_raw_run_two_tasks() {
     thread::sleep(Duration::from_millis(1100));
 
     // Check if first task ran
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
     assert_eq!(listener.get_request(), Some(query.to_owned()));
 
     // Check if second app ran in order
-    let query = r#"{"query":"mutation { startApp(name: \"other-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"other-app\") { success, errors, pid } }"}"#;
     assert_eq!(listener.get_request(), Some(query.to_owned()));
 }
 
@@ -193,7 +193,7 @@ fn import_raw_run_onetime_future() {
     // Wait for the service to restart the scheduler
     thread::sleep(Duration::from_millis(3000));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
 
     // Check if the task actually ran
     assert_eq!(listener.get_request(), Some(query.to_owned()))
@@ -228,7 +228,7 @@ fn import_raw_run_recurring_no_delay() {
     // Wait for the service to restart the scheduler
     thread::sleep(Duration::from_millis(1100));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
 
     // Check if the task was run only twice
     assert_eq!(listener.get_request(), Some(query.to_owned()));
@@ -305,7 +305,7 @@ fn import_raw_run_delay_duplicate() {
     // Wait for the service to restart the scheduler
     thread::sleep(Duration::from_millis(100));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
 
     // Check if the task actually ran
     assert_eq!(listener.get_request(), Some(query.to_owned()));
@@ -119,7 +119,7 @@ fn validate_no_time_specification() {
         json!({
             "data" : {
                 "importTaskList": {
-                    "errors": "Failed to parse task \'first-task\': No delay or time defined",
+                    "errors": "Failed to parse task \'first-task\': No delay, time or cron defined",
                     "success": false
                 }
             }
@@ -314,3 +314,199 @@ fn validate_time_and_delay() {
         })
     );
 }
+
+#[test]
+fn validate_bad_cron() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8033);
+
+    fixture.create_mode("operational");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "first-task",
+                "cron": "0 24 * * *",
+                "app": {
+                    "name": "app-name"
+                },
+            },
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    assert_eq!(
+        fixture.import_task_list("first", &schedule_path, "operational"),
+        json!({
+            "data" : {
+                "importTaskList": {
+                    "errors": "Failed to parse task \'first-task\': Failed to parse cron field \'0 24 * * *\': Value out of range in \'24\', expected 0-23",
+                    "success": false
+                }
+            }
+        })
+    );
+}
+
+#[test]
+fn validate_cron_and_period() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8034);
+
+    fixture.create_mode("operational");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "first-task",
+                "cron": "0 2 * * *",
+                "period": "1h",
+                "app": {
+                    "name": "app-name"
+                },
+            },
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    assert_eq!(
+        fixture.import_task_list("first", &schedule_path, "operational"),
+        json!({
+            "data" : {
+                "importTaskList": {
+                    "errors": "Failed to parse task \'first-task\': Cron defined with delay, time or period",
+                    "success": false
+                }
+            }
+        })
+    );
+}
+
+#[test]
+fn validate_missing_dependency() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8040);
+
+    fixture.create_mode("operational");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "first-task",
+                "depends_on": "other-task",
+                "app": {
+                    "name": "app-name"
+                },
+            },
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    assert_eq!(
+        fixture.import_task_list("first", &schedule_path, "operational"),
+        json!({
+            "data" : {
+                "importTaskList": {
+                    "errors": "Failed to parse task \'first-task\': Dependency \'other-task\' not found",
+                    "success": false
+                }
+            }
+        })
+    );
+}
+
+#[test]
+fn validate_circular_dependency() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8041);
+
+    fixture.create_mode("operational");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "first-task",
+                "depends_on": "second-task",
+                "app": {
+                    "name": "app-name"
+                },
+            },
+            {
+                "description": "second-task",
+                "depends_on": "first-task",
+                "app": {
+                    "name": "app-name"
+                },
+            },
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    assert_eq!(
+        fixture.import_task_list("first", &schedule_path, "operational"),
+        json!({
+            "data" : {
+                "importTaskList": {
+                    "errors": "Failed to parse task \'first-task\': Circular dependency found",
+                    "success": false
+                }
+            }
+        })
+    );
+}
+
+#[test]
+fn validate_zero_timeout() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8042);
+
+    fixture.create_mode("operational");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "first-task",
+                "delay": "10s",
+                "app": {
+                    "name": "app-name",
+                    "timeout": "0s"
+                },
+            },
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    assert_eq!(
+        fixture.import_task_list("first", &schedule_path, "operational"),
+        json!({
+            "data" : {
+                "importTaskList": {
+                    "errors": "Failed to parse hms field \'0s\': Timeout must be at least one second",
+                    "success": false
+                }
+            }
+        })
+    );
+}
+
+#[test]
+fn validate_large_timeout() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8048);
+
+    fixture.create_mode("operational");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "first-task",
+                "delay": "10s",
+                "app": {
+                    "name": "app-name",
+                    "timeout": "600000h"
+                },
+            },
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    assert_eq!(
+        fixture.import_task_list("first", &schedule_path, "operational"),
+        json!({
+            "data" : {
+                "importTaskList": {
+                    "errors": "Failed to parse hms field \'600000h\': Timeout must be at most 2147483647 seconds",
+                    "success": false
+                }
+            }
+        })
+    );
+}
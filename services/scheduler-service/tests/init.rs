@@ -59,7 +59,7 @@ fn run_init_single_no_delay() {
     // Wait for the service to restart the scheduler
     thread::sleep(Duration::from_millis(100));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
 
     // Check if the task actually ran
     assert_eq!(listener.get_request(), Some(query.to_owned()))
@@ -94,7 +94,7 @@ fn run_init_single_with_delay() {
     // Wait for service to run the task
     thread::sleep(Duration::from_millis(1000));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
 
     // Check if the task actually ran
     assert_eq!(listener.get_request(), Some(query.to_owned()))
@@ -133,11 +133,11 @@ fn run_init_two_tasks() {
     thread::sleep(Duration::from_millis(1100));
 
     // Check if first task ran
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
     assert_eq!(listener.get_request(), Some(query.to_owned()));
 
     // Check if second app ran in order
-    let query = r#"{"query":"mutation { startApp(name: \"other-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"other-app\") { success, errors, pid } }"}"#;
     assert_eq!(listener.get_request(), Some(query.to_owned()));
 }
 
@@ -167,7 +167,7 @@ fn run_init_single_args() {
     // Wait for service to restart scheduler and run task
     thread::sleep(Duration::from_millis(100));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\", args: [\"-l\",\"-h\"]) { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\", args: [\"-l\",\"-h\"]) { success, errors, pid } }"}"#;
 
     // Check if the task actually ran
     assert_eq!(listener.get_request(), Some(query.to_owned()))
@@ -199,7 +199,39 @@ fn run_init_single_custom_task_list() {
     // Wait for service to restart scheduler and run task
     thread::sleep(Duration::from_millis(100));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\", config: \"path/to/custom.toml\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\", config: \"path/to/custom.toml\") { success, errors, pid } }"}"#;
+
+    // Check if the task actually ran
+    assert_eq!(listener.get_request(), Some(query.to_owned()))
+}
+
+#[test]
+fn run_init_single_timeout() {
+    let listener = ServiceListener::spawn("127.0.0.1", 9026);
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8026);
+    fixture.create_mode("init");
+
+    // Create some schedule with an init task
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "basic-task",
+                "delay": "0s",
+                "app": {
+                    "name": "basic-app",
+                    "timeout": "1m 30s"
+                }
+            }
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    fixture.import_task_list("imaging", &schedule_path, "init");
+    fixture.activate_mode("init");
+
+    // Wait for service to restart scheduler and run task
+    thread::sleep(Duration::from_millis(100));
+
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\", timeout: 90) { success, errors, pid } }"}"#;
 
     // Check if the task actually ran
     assert_eq!(listener.get_request(), Some(query.to_owned()))
@@ -246,11 +278,11 @@ fn run_init_two_schedules_one_mode() {
     thread::sleep(Duration::from_millis(1100));
 
     // Check if the task ran
-    let query = r#"{"query":"mutation { startApp(name: \"first-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"first-app\") { success, errors, pid } }"}"#;
     assert_eq!(listener.get_request(), Some(query.to_owned()));
 
     // Check if the task ran
-    let query = r#"{"query":"mutation { startApp(name: \"second-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"second-app\") { success, errors, pid } }"}"#;
     assert_eq!(listener.get_request(), Some(query.to_owned()))
 }
 
@@ -296,7 +328,7 @@ fn run_init_two_modes() {
     thread::sleep(Duration::from_millis(100));
 
     // Check if the task ran
-    let query = r#"{"query":"mutation { startApp(name: \"first-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"first-app\") { success, errors, pid } }"}"#;
     assert_eq!(listener.get_request(), Some(query.to_owned()));
 
     // Activate second schedule, wait for task to run
@@ -304,7 +336,7 @@ fn run_init_two_modes() {
     thread::sleep(Duration::from_millis(100));
 
     // Check if the task ran
-    let query = r#"{"query":"mutation { startApp(name: \"second-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"second-app\") { success, errors, pid } }"}"#;
     assert_eq!(listener.get_request(), Some(query.to_owned()))
 }
 
@@ -354,7 +386,7 @@ fn run_init_two_modes_check_stop() {
     thread::sleep(Duration::from_millis(100));
 
     // Check if the task ran
-    let query = r#"{"query":"mutation { startApp(name: \"second-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"second-app\") { success, errors, pid } }"}"#;
     assert_eq!(listener.get_request(), Some(query.to_owned()));
 
     // Give the scheduler time to run (or not) delayed task from first schedule
@@ -396,7 +428,7 @@ fn run_init_after_import() {
     thread::sleep(Duration::from_millis(1000));
 
     // Check if the task ran
-    let query = r#"{"query":"mutation { startApp(name: \"first-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"first-app\") { success, errors, pid } }"}"#;
     assert_eq!(listener.get_request(), Some(query.to_owned()));
 }
 
@@ -462,7 +494,7 @@ fn run_init_import_twice() {
     // Wait for the service to restart the scheduler
     thread::sleep(Duration::from_millis(100));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
 
     // Check if the task actually ran
     assert_eq!(listener.get_request(), Some(query.to_owned()));
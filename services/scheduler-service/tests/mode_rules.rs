@@ -0,0 +1,71 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+mod util;
+
+use serde_json::json;
+use std::thread;
+use std::time::Duration;
+use util::SchedulerFixture;
+use utils::testing::{ServiceListener, ServiceResponder};
+
+#[derive(Clone)]
+struct PowerResponder;
+impl ServiceResponder for PowerResponder {
+    fn respond(&self, _body: &str) -> String {
+        json!({"data": {"power": {"voltage": 6.9}}}).to_string()
+    }
+}
+
+// The app service's listener also answers the rule's query
+static RULES: &str = r#"
+mode_check_interval = "1s"
+mode_hold_down = "1h"
+
+[[scheduler-service.mode_rules]]
+name = "low-voltage"
+mode = "safe"
+
+[scheduler-service.mode_rules.condition]
+service = "app-service"
+query = "{ power { voltage } }"
+field = "power.voltage"
+operator = "<"
+value = "7.0"
+"#;
+
+fn active_mode(fixture: &SchedulerFixture) -> serde_json::Value {
+    fixture.query("{ activeMode { name } }")["data"]["activeMode"]["name"].clone()
+}
+
+#[test]
+fn rule_activates_mode() {
+    let _listener = ServiceListener::spawn_with_responder("127.0.0.1", 9042, PowerResponder);
+    let fixture = SchedulerFixture::spawn_with_config("127.0.0.1", 8042, RULES);
+
+    fixture.create_mode("operational");
+    fixture.activate_mode("operational");
+    assert_eq!(active_mode(&fixture), json!("operational"));
+
+    // Wait for the rule to be checked
+    thread::sleep(Duration::from_millis(1500));
+    assert_eq!(active_mode(&fixture), json!("safe"));
+
+    // The hold down time stops the rule from changing the mode again straight away
+    fixture.activate_mode("operational");
+    thread::sleep(Duration::from_millis(1500));
+    assert_eq!(active_mode(&fixture), json!("operational"));
+}
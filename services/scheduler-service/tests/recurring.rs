@@ -16,6 +16,7 @@
 
 mod util;
 
+use chrono::Utc;
 use serde_json::json;
 use std::thread;
 use std::time::Duration;
@@ -49,7 +50,7 @@ fn run_recurring_no_delay() {
     // Wait for the service to restart the scheduler
     thread::sleep(Duration::from_millis(1100));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
 
     // Check if the task was run only twice
     assert_eq!(listener.get_request(), Some(query.to_owned()));
@@ -84,10 +85,46 @@ fn run_recurring_delay() {
     // Wait for the service to restart the scheduler
     thread::sleep(Duration::from_millis(2100));
 
-    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors } }"}"#;
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
 
     // Check if the task was run only twice
     assert_eq!(listener.get_request(), Some(query.to_owned()));
     assert_eq!(listener.get_request(), Some(query.to_owned()));
     assert_eq!(listener.get_request(), None)
 }
+
+#[test]
+fn run_recurring_past_start() {
+    let listener = ServiceListener::spawn("127.0.0.1", 9035);
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8035);
+
+    fixture.create_mode("init");
+
+    // Start the recurrence in the past, so that the next run falls on a period boundary
+    // rather than when the task list was activated
+    let start = Utc::now() - chrono::Duration::seconds(9);
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "basic-task",
+                "time": start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                "period": "2s",
+                "app": {
+                    "name": "basic-app"
+                }
+            }
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    fixture.import_task_list("imaging", &schedule_path, "init");
+    fixture.activate_mode("init");
+
+    // Wait for the service to restart the scheduler
+    thread::sleep(Duration::from_millis(4100));
+
+    let query = r#"{"query":"mutation { startApp(name: \"basic-app\") { success, errors, pid } }"}"#;
+
+    // Check the task has run at least once per period since the mode was activated
+    assert_eq!(listener.get_request(), Some(query.to_owned()));
+    assert_eq!(listener.get_request(), Some(query.to_owned()));
+}
@@ -0,0 +1,311 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+mod util;
+
+use serde_json::json;
+use util::SchedulerFixture;
+
+#[test]
+fn timeline_lists_runs() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8043);
+
+    fixture.create_mode("operational");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "beacon",
+                "delay": "10m",
+                "period": "20m",
+                "app": {
+                    "name": "beacon-app",
+                    "args": ["-v"]
+                }
+            },
+            {
+                "description": "downlink",
+                "depends_on": "beacon",
+                "app": {
+                    "name": "downlink-app"
+                }
+            },
+            {
+                "description": "deploy",
+                "delay": "3h",
+                "app": {
+                    "name": "deploy-app"
+                }
+            }
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    fixture.import_task_list("comms", &schedule_path, "operational");
+
+    let query = r#"{ timeline(mode: "operational", horizon: "1h") {
+            entries { taskList, description, app, args, overlaps },
+            outsideHorizon { taskList, description, app },
+            truncated
+        } }"#;
+    let beacon = json!({
+        "taskList": "comms",
+        "description": "beacon",
+        "app": "beacon-app",
+        "args": ["-v"],
+        "overlaps": false
+    });
+    let downlink = json!({
+        "taskList": "comms",
+        "description": "downlink",
+        "app": "downlink-app",
+        "args": null,
+        "overlaps": false
+    });
+    assert_eq!(
+        fixture.query(query),
+        json!({
+            "data": {
+                "timeline": {
+                    "entries": [beacon, downlink, beacon, downlink, beacon, downlink],
+                    "outsideHorizon": [
+                        {
+                            "taskList": "comms",
+                            "description": "deploy",
+                            "app": "deploy-app"
+                        }
+                    ],
+                    "truncated": false
+                }
+            }
+        })
+    );
+
+    // Times should be in order and within the horizon
+    let result = fixture.query(
+        r#"{ timeline(mode: "operational", horizon: "1h") { start, end, entries { time } } }"#,
+    );
+    let timeline = &result["data"]["timeline"];
+    let mut previous = timeline["start"].as_str().unwrap().to_owned();
+    for entry in timeline["entries"].as_array().unwrap() {
+        let time = entry["time"].as_str().unwrap().to_owned();
+        assert!(time >= previous);
+        previous = time;
+    }
+    assert!(previous <= timeline["end"].as_str().unwrap().to_owned());
+}
+
+#[test]
+fn timeline_flags_overlaps() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8044);
+
+    fixture.create_mode("operational");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "first-capture",
+                "delay": "1m",
+                "app": {
+                    "name": "camera-app"
+                }
+            },
+            {
+                "description": "second-capture",
+                "delay": "1m 30s",
+                "app": {
+                    "name": "camera-app"
+                }
+            },
+            {
+                "description": "housekeeping",
+                "delay": "1m 10s",
+                "app": {
+                    "name": "housekeeping-app"
+                }
+            },
+            {
+                "description": "late-capture",
+                "delay": "2h",
+                "app": {
+                    "name": "camera-app"
+                }
+            }
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    fixture.import_task_list("imaging", &schedule_path, "operational");
+
+    let query = r#"{ timeline(mode: "operational", horizon: "1h", window: "1m") {
+            entries { description, overlaps },
+            outsideHorizon { description }
+        } }"#;
+    assert_eq!(
+        fixture.query(query),
+        json!({
+            "data": {
+                "timeline": {
+                    "entries": [
+                        { "description": "first-capture", "overlaps": true },
+                        { "description": "housekeeping", "overlaps": false },
+                        { "description": "second-capture", "overlaps": true }
+                    ],
+                    "outsideHorizon": [
+                        { "description": "late-capture" }
+                    ]
+                }
+            }
+        })
+    );
+}
+
+#[test]
+fn timeline_overlaps_all_open_runs() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8046);
+
+    fixture.create_mode("operational");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "capture",
+                "delay": "1m",
+                "app": {
+                    "name": "camera-app"
+                }
+            },
+            {
+                "description": "process",
+                "depends_on": "capture",
+                "app": {
+                    "name": "camera-app"
+                }
+            },
+            {
+                "description": "retry-capture",
+                "delay": "1m 30s",
+                "app": {
+                    "name": "camera-app"
+                }
+            }
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    fixture.import_task_list("imaging", &schedule_path, "operational");
+
+    // The dependent run doesn't overlap the run it depends on, but the later run overlaps both
+    let query = r#"{ timeline(mode: "operational", horizon: "1h", window: "1m") {
+            entries { description, overlaps }
+        } }"#;
+    assert_eq!(
+        fixture.query(query),
+        json!({
+            "data": {
+                "timeline": {
+                    "entries": [
+                        { "description": "capture", "overlaps": true },
+                        { "description": "process", "overlaps": true },
+                        { "description": "retry-capture", "overlaps": true }
+                    ]
+                }
+            }
+        })
+    );
+}
+
+#[test]
+fn timeline_overlaps_use_timeout() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8047);
+
+    fixture.create_mode("operational");
+
+    let schedule = json!({
+        "tasks": [
+            {
+                "description": "long-capture",
+                "delay": "1m",
+                "app": {
+                    "name": "camera-app",
+                    "timeout": "5m"
+                }
+            },
+            {
+                "description": "quick-capture",
+                "delay": "2m",
+                "app": {
+                    "name": "camera-app",
+                    "timeout": "30s"
+                }
+            },
+            {
+                "description": "next-capture",
+                "delay": "4m",
+                "app": {
+                    "name": "camera-app"
+                }
+            },
+            {
+                "description": "ping",
+                "delay": "1m",
+                "app": {
+                    "name": "ping-app",
+                    "timeout": "10s"
+                }
+            },
+            {
+                "description": "ping-again",
+                "delay": "1m 30s",
+                "app": {
+                    "name": "ping-app"
+                }
+            }
+        ]
+    });
+    let schedule_path = fixture.create_task_list(Some(schedule.to_string()));
+    fixture.import_task_list("imaging", &schedule_path, "operational");
+
+    // A run's app timeout is used in place of the window, so the last capture overlaps the first
+    // even though the capture between them has already timed out
+    let query = r#"{ timeline(mode: "operational", horizon: "1h", window: "1m") {
+            entries { description, overlaps }
+        } }"#;
+    assert_eq!(
+        fixture.query(query),
+        json!({
+            "data": {
+                "timeline": {
+                    "entries": [
+                        { "description": "long-capture", "overlaps": true },
+                        { "description": "ping", "overlaps": false },
+                        { "description": "ping-again", "overlaps": false },
+                        { "description": "quick-capture", "overlaps": true },
+                        { "description": "next-capture", "overlaps": true }
+                    ]
+                }
+            }
+        })
+    );
+}
+
+#[test]
+fn timeline_bad_mode() {
+    let fixture = SchedulerFixture::spawn("127.0.0.1", 8045);
+
+    let result = fixture.query(r#"{ timeline(mode: "missing", horizon: "1h") { truncated } }"#);
+    assert_eq!(
+        result["errors"][0]["message"],
+        "Scheduler error encountered: Mode 'missing' not found"
+    );
+}
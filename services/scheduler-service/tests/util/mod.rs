@@ -33,6 +33,7 @@ impl ServiceResponder for BasicAppResponder {
                 "startApp": {
                     "success": true,
                     "errors": "",
+                    "pid": 1234,
                 }
             }
         })
@@ -53,6 +54,11 @@ pub struct SchedulerFixture {
 #[allow(dead_code)]
 impl SchedulerFixture {
     pub fn spawn(ip: &str, port: u16) -> SchedulerFixture {
+        SchedulerFixture::spawn_with_config(ip, port, "")
+    }
+
+    // Spawns the service with extra settings added to its section of the config file
+    pub fn spawn_with_config(ip: &str, port: u16, extra_config: &str) -> SchedulerFixture {
         let schedules_dir = TempDir::new().unwrap();
         let schedules_dir_path = schedules_dir.path().to_str().unwrap();
 
@@ -63,10 +69,12 @@ impl SchedulerFixture {
         port = {}
         [scheduler-service]
         schedules_dir = "{}"
+        {}
         "#,
             ip,
             (port + 1000),
             schedules_dir_path,
+            extra_config,
         );
 
         let mut scheduler_service = TestService::new("scheduler-service", ip, port);
@@ -0,0 +1,376 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use kubos_service::Config;
+use kubos_telemetry_db::{Alarm, Database, Value, ValueType};
+use log::{error, info, warn};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Alarm state of a telemetry parameter
+#[derive(GraphQLEnum, Clone, Copy, Debug, PartialEq)]
+pub enum AlarmState {
+    Nominal,
+    YellowLow,
+    YellowHigh,
+    RedLow,
+    RedHigh,
+}
+
+impl AlarmState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlarmState::Nominal => "nominal",
+            AlarmState::YellowLow => "yellow_low",
+            AlarmState::YellowHigh => "yellow_high",
+            AlarmState::RedLow => "red_low",
+            AlarmState::RedHigh => "red_high",
+        }
+    }
+
+    pub fn from_name(state: &str) -> Option<Self> {
+        match state {
+            "nominal" => Some(AlarmState::Nominal),
+            "yellow_low" => Some(AlarmState::YellowLow),
+            "yellow_high" => Some(AlarmState::YellowHigh),
+            "red_low" => Some(AlarmState::RedLow),
+            "red_high" => Some(AlarmState::RedHigh),
+            _ => None,
+        }
+    }
+
+    fn severity(self) -> Severity {
+        match self {
+            AlarmState::Nominal => Severity::Nominal,
+            AlarmState::YellowLow | AlarmState::YellowHigh => Severity::Yellow,
+            AlarmState::RedLow | AlarmState::RedHigh => Severity::Red,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+enum Severity {
+    Nominal,
+    Yellow,
+    Red,
+}
+
+fn default_severity() -> Severity {
+    Severity::Red
+}
+
+fn default_persistence() -> u32 {
+    1
+}
+
+// Query sent to another service when a parameter enters an alarm state
+#[derive(Clone, Debug, Deserialize)]
+pub struct HookConfig {
+    service: String,
+    query: String,
+    #[serde(default = "default_severity")]
+    severity: Severity,
+}
+
+// Limits for a single parameter, as read from the service's config
+#[derive(Clone, Debug, Deserialize)]
+pub struct LimitConfig {
+    subsystem: String,
+    parameter: String,
+    red_low: Option<f64>,
+    yellow_low: Option<f64>,
+    yellow_high: Option<f64>,
+    red_high: Option<f64>,
+    #[serde(default = "default_persistence")]
+    persistence: u32,
+    hook: Option<HookConfig>,
+}
+
+// Current state of a single parameter's limits
+#[derive(Clone, Debug)]
+pub struct LimitStatus {
+    pub subsystem: String,
+    pub parameter: String,
+    pub red_low: Option<f64>,
+    pub yellow_low: Option<f64>,
+    pub yellow_high: Option<f64>,
+    pub red_high: Option<f64>,
+    pub persistence: u32,
+    pub state: AlarmState,
+    pub last_value: Option<f64>,
+}
+
+struct Limit {
+    config: LimitConfig,
+    // URL of the service to send the hook's query to
+    hook_url: Option<String>,
+    state: AlarmState,
+    // State which the latest values have been in, and for how many entries in a row
+    pending: AlarmState,
+    count: u32,
+    last_value: Option<f64>,
+}
+
+impl Limit {
+    fn evaluate(&self, value: f64) -> AlarmState {
+        let below = |limit: Option<f64>| limit.map_or(false, |limit| value < limit);
+        let above = |limit: Option<f64>| limit.map_or(false, |limit| value > limit);
+
+        if below(self.config.red_low) {
+            AlarmState::RedLow
+        } else if above(self.config.red_high) {
+            AlarmState::RedHigh
+        } else if below(self.config.yellow_low) {
+            AlarmState::YellowLow
+        } else if above(self.config.yellow_high) {
+            AlarmState::YellowHigh
+        } else {
+            AlarmState::Nominal
+        }
+    }
+
+    // Returns the previous state if the value causes the parameter's state to change
+    fn update(&mut self, value: f64) -> Option<AlarmState> {
+        self.last_value = Some(value);
+
+        let state = self.evaluate(value);
+        if state == self.state {
+            self.pending = state;
+            self.count = 0;
+            return None;
+        }
+
+        if state == self.pending {
+            self.count += 1;
+        } else {
+            self.pending = state;
+            self.count = 1;
+        }
+
+        if self.count < self.config.persistence {
+            return None;
+        }
+
+        let previous = self.state;
+        self.state = state;
+        self.count = 0;
+        Some(previous)
+    }
+}
+
+pub struct Limits {
+    // Keyed by subsystem and parameter
+    limits: Mutex<HashMap<(String, String), Limit>>,
+}
+
+impl Limits {
+    pub fn new(configs: Vec<LimitConfig>) -> Result<Self, String> {
+        let mut limits = HashMap::new();
+
+        for config in configs {
+            let ordered = [
+                config.red_low,
+                config.yellow_low,
+                config.yellow_high,
+                config.red_high,
+            ]
+            .iter()
+            .filter_map(|limit| *limit)
+            .collect::<Vec<f64>>()
+            .windows(2)
+            .all(|pair| pair[0] <= pair[1]);
+            if !ordered {
+                return Err(format!(
+                    "Limits for {}/{} must be ordered red_low <= yellow_low <= yellow_high <= red_high",
+                    config.subsystem, config.parameter
+                ));
+            }
+
+            if config.persistence == 0 {
+                return Err(format!(
+                    "Persistence for {}/{} must be at least 1",
+                    config.subsystem, config.parameter
+                ));
+            }
+
+            let hook_url = match &config.hook {
+                Some(hook) if hook.severity == Severity::Nominal => {
+                    return Err(format!(
+                        "Hook severity for {}/{} must be yellow or red",
+                        config.subsystem, config.parameter
+                    ));
+                }
+                Some(hook) => Some(
+                    Config::new(&hook.service)
+                        .ok()
+                        .and_then(|service| service.hosturl())
+                        .ok_or_else(|| {
+                            format!("Failed to load URL of hook service {}", hook.service)
+                        })?,
+                ),
+                None => None,
+            };
+
+            let key = (config.subsystem.clone(), config.parameter.clone());
+            if limits.contains_key(&key) {
+                return Err(format!(
+                    "Duplicate limits for {}/{}",
+                    config.subsystem, config.parameter
+                ));
+            }
+
+            limits.insert(
+                key,
+                Limit {
+                    config,
+                    hook_url,
+                    state: AlarmState::Nominal,
+                    pending: AlarmState::Nominal,
+                    count: 0,
+                    last_value: None,
+                },
+            );
+        }
+
+        Ok(Limits {
+            limits: Mutex::new(limits),
+        })
+    }
+
+    /// Check a newly inserted entry against its parameter's limits, recording any change
+    /// in the parameter's alarm state
+    pub fn check(
+        &self,
+        db: &Database,
+        timestamp: f64,
+        subsystem: &str,
+        parameter: &str,
+        value: &str,
+        value_type: Option<ValueType>,
+    ) {
+        let mut limits = match self.limits.lock() {
+            Ok(limits) => limits,
+            Err(err) => {
+                error!("limits - Failed to get lock on limits: {}", err);
+                return;
+            }
+        };
+
+        let limit = match limits.get_mut(&(subsystem.to_owned(), parameter.to_owned())) {
+            Some(limit) => limit,
+            None => return,
+        };
+
+        let value = match Value::parse(value, value_type) {
+            Ok(Value::Integer(integer)) => integer as f64,
+            Ok(Value::Real(real)) => real,
+            _ => {
+                warn!(
+                    "limits - Ignoring non-numeric value for {}/{}",
+                    subsystem, parameter
+                );
+                return;
+            }
+        };
+
+        let previous = match limit.update(value) {
+            Some(previous) => previous,
+            None => return,
+        };
+
+        info!(
+            "{}/{} changed from {} to {} ({})",
+            subsystem,
+            parameter,
+            previous.as_str(),
+            limit.state.as_str(),
+            value
+        );
+
+        if let Err(err) = db.insert_alarm(&Alarm {
+            timestamp,
+            subsystem: subsystem.to_owned(),
+            parameter: parameter.to_owned(),
+            previous_state: previous.as_str().to_owned(),
+            state: limit.state.as_str().to_owned(),
+            value,
+        }) {
+            error!("limits - Failed to record alarm: {}", err);
+        }
+
+        if let (Some(hook), Some(url)) = (&limit.config.hook, &limit.hook_url) {
+            // Only trigger the hook when the alarm first becomes severe enough
+            if limit.state.severity() >= hook.severity && previous.severity() < hook.severity {
+                let query = hook.query.clone();
+                let url = url.clone();
+                thread::spawn(move || send_hook(&query, &url));
+            }
+        }
+    }
+
+    pub fn status(&self) -> Result<Vec<LimitStatus>, String> {
+        let limits = self
+            .limits
+            .lock()
+            .map_err(|err| format!("Failed to get lock on limits: {}", err))?;
+
+        let mut status: Vec<LimitStatus> = limits
+            .values()
+            .map(|limit| LimitStatus {
+                subsystem: limit.config.subsystem.clone(),
+                parameter: limit.config.parameter.clone(),
+                red_low: limit.config.red_low,
+                yellow_low: limit.config.yellow_low,
+                yellow_high: limit.config.yellow_high,
+                red_high: limit.config.red_high,
+                persistence: limit.config.persistence,
+                state: limit.state,
+                last_value: limit.last_value,
+            })
+            .collect();
+        status.sort_by(|a, b| (&a.subsystem, &a.parameter).cmp(&(&b.subsystem, &b.parameter)));
+
+        Ok(status)
+    }
+}
+
+fn send_hook(query: &str, url: &str) {
+    info!("Sending alarm hook to {}: {}", url, query);
+
+    let client = match Client::builder().timeout(Duration::from_secs(5)).build() {
+        Ok(client) => client,
+        Err(err) => {
+            error!("limits - Failed to build hook client: {}", err);
+            return;
+        }
+    };
+
+    let mut map = HashMap::new();
+    map.insert("query", query);
+
+    match client.post(&format!("http://{}", url)).json(&map).send() {
+        Ok(mut response) => info!(
+            "Alarm hook response: {}",
+            response.text().unwrap_or_default()
+        ),
+        Err(err) => error!("limits - Failed to send alarm hook: {}", err),
+    }
+}
@@ -36,6 +36,95 @@
 //! service's IP address, and `port` specifies the port on which the service will be
 //! listening for UDP packets.
 //!
+//! # Retention
+//!
+//! Rules limiting how much telemetry is kept may optionally be added to the configuration:
+//!
+//! ```
+//! [telemetry-service.retention]
+//! interval = 60
+//!
+//! [[telemetry-service.retention.rules]]
+//! subsystem = "eps"
+//! max_age = 604800
+//!
+//! [[telemetry-service.retention.rules]]
+//! subsystem = "gps"
+//! parameter = "altitude"
+//! max_rows = 10000
+//! downsample_after = 86400
+//! downsample_interval = 60
+//! ```
+//!
+//! The service enforces each rule every `interval` seconds (60 by default). A rule applies to
+//! the entries of the given `subsystem` and `parameter`, or to all subsystems or parameters if
+//! either is omitted, and may contain any of the following limits:
+//!
+//! - `max_age` - Entries older than this many seconds are deleted
+//! - `max_rows` - Only the newest `max_rows` entries are kept
+//! - `downsample_after` and `downsample_interval` - Entries older than `downsample_after` seconds
+//!   are replaced with their average over each `downsample_interval` seconds. Entries whose values
+//!   aren't numeric are left as they are
+//!
+//! The `retention` query reports the number of entries each rule has pruned.
+//!
+//! # Limits
+//!
+//! Limits may optionally be configured for any telemetry parameter:
+//!
+//! ```
+//! [[telemetry-service.limits]]
+//! subsystem = "eps"
+//! parameter = "voltage"
+//! red_low = 3.0
+//! yellow_low = 3.3
+//! yellow_high = 4.1
+//! red_high = 4.3
+//! persistence = 3
+//!
+//! [telemetry-service.limits.hook]
+//! service = "scheduler-service"
+//! query = "mutation { safeMode { success, errors } }"
+//! severity = "red"
+//! ```
+//!
+//! Every entry inserted for the parameter is checked against its limits, any of which may be
+//! omitted. The parameter's alarm state only changes once `persistence` consecutive entries
+//! (1 by default) agree on the new state. Each change of state is recorded in the database's
+//! `alarms` table, and can be fetched with the `alarms` query.
+//!
+//! The optional `hook` specifies a GraphQL request which is sent to the given service when the
+//! parameter enters an alarm state of at least the given `severity` (`yellow` or `red`, which is
+//! the default). The service's address is read from its own section of the config file.
+//!
+//! # Subscriptions
+//!
+//! Rather than polling the `telemetry` query, listeners may register a UDP address with the
+//! `subscribe` mutation. Every entry inserted afterwards which matches the subscription's
+//! optional `subsystem` and `parameter`/`parameters` filters is sent to that address as a
+//! JSON object, in the same format accepted by the direct UDP port:
+//!
+//! ```
+//! {"timestamp":1554236400.5,"subsystem":"eps","parameter":"voltage","value":"3.7","type":"real"}
+//! ```
+//!
+//! Each subscription is leased for five minutes. Listeners should renew their subscriptions
+//! with the `renewSubscription` mutation before the lease runs out, otherwise they are removed.
+//! Subscriptions are also removed with the `unsubscribe` mutation, when the service restarts, or
+//! if sending to their listener fails five times in a row.
+//! The `subscriptions` query lists the currently registered listeners, along with the number of
+//! seconds left on each of their leases.
+//!
+//! At most 16 subscriptions may be registered at once, and at most four for any one host.
+//! The total and the lease time (in seconds) can be changed with the `max_subscriptions` and
+//! `subscription_lease` options:
+//!
+//! ```
+//! [telemetry-service]
+//! max_subscriptions = 32
+//! subscription_lease = 600
+//! ```
+//!
 //! # Starting the Service
 //!
 //! The service should be started automatically by its init script, but may also be started manually:
@@ -58,14 +147,43 @@
 //!   timestamp: Integer!
 //!   subsystem: String!
 //!   parameter: String!
-//!   value: Float!
+//!   value: String!
+//!   valueType: ValueType!
+//! }
+//!
+//! enum AlarmState {
+//!   NOMINAL
+//!   YELLOW_LOW
+//!   YELLOW_HIGH
+//!   RED_LOW
+//!   RED_HIGH
+//! }
+//!
+//! enum ExportFormat {
+//!   JSON
+//!   CBOR
+//! }
+//!
+//! enum ValueType {
+//!   INTEGER
+//!   REAL
+//!   TEXT
+//!   BLOB
 //! }
 //!
 //! query ping: "pong"
 //! query telemetry(timestampGe: Integer, timestampLe: Integer, subsystem: String, parameter: String, parameters: [String]): Entry
-//! query routedTelemetry(timestampGe: Integer, timestampLe: Integer, subsystem: String, parameter: String, parameters: [String], output: String!, compress: Boolean = true): String!
+//! query routedTelemetry(timestampGe: Integer, timestampLe: Integer, subsystem: String, parameter: String, parameters: [String], output: String!, compress: Boolean = true, format: ExportFormat = JSON): String!
+//! query aggregate(timestampGe: Integer, timestampLe: Integer, subsystem: String, parameter: String, parameters: [String], bucket: Float!, function: AggregateFunction!): [{ subsystem: String!, parameter: String!, timestamps: [Float!]!, values: [Float!]! }]
+//! query alarms(timestampGe: Float, timestampLe: Float, subsystem: String, parameter: String, limit: Integer): [{ timestamp: Float!, subsystem: String!, parameter: String!, previousState: AlarmState!, state: AlarmState!, value: Float! }]
+//! query limits: [{ subsystem: String!, parameter: String!, redLow: Float, yellowLow: Float, yellowHigh: Float, redHigh: Float, persistence: Integer!, state: AlarmState!, lastValue: Float }]
+//! query subscriptions: [{ id: Integer!, address: String!, subsystem: String, parameters: [String!], expiresIn: Float! }]
+//! query retention: [{ subsystem: String, parameter: String, maxAge: Float, maxRows: Integer, downsampleAfter: Float, downsampleInterval: Float, rowsPruned: Integer!, lastRun: Float }]
 //!
-//! mutation insert(timestamp: Integer, subsystem: String!, parameter: String!, value: String!):{ success: Boolean!, errors: String! }
+//! mutation insert(timestamp: Integer, subsystem: String!, parameter: String!, value: String!, valueType: ValueType):{ success: Boolean!, errors: String! }
+//! mutation subscribe(address: String!, subsystem: String, parameter: String, parameters: [String]):{ success: Boolean!, errors: String!, id: Integer }
+//! mutation unsubscribe(id: Integer!):{ success: Boolean!, errors: String! }
+//! mutation renewSubscription(id: Integer!):{ success: Boolean!, errors: String! }
 //! ```
 //!
 //! # Example Queries
@@ -168,6 +286,50 @@
 //! }
 //! ```
 //!
+//! ## Repeat the previous query, but write the output in the compact binary format
+//! ```graphql
+//! {
+//!   routedTelemetry(limit: 10, timestampGe: 1008, output: "/home/system/recent_telem", format: CBOR)
+//! }
+//! ```
+//!
+//! ## Get the hourly average of the eps voltage between the timestamps 1000000 and 1604800
+//! ```graphql
+//! {
+//!   aggregate(subsystem: "eps", parameter: "voltage", timestampGe: 1000000, timestampLe: 1604800, bucket: 3600, function: AVG) {
+//!     subsystem,
+//!     parameter,
+//!     timestamps,
+//!     values
+//!   }
+//! }
+//! ```
+//!
+//! ## Fetch the ten most recent alarm state changes of the eps subsystem
+//! ```graphql
+//! {
+//!   alarms(subsystem: "eps", limit: 10) {
+//!     timestamp,
+//!     parameter,
+//!     previousState,
+//!     state,
+//!     value
+//!   }
+//! }
+//! ```
+//!
+//! ## Check how many entries have been removed by the retention rules
+//! ```graphql
+//! {
+//!   retention {
+//!     subsystem,
+//!     parameter,
+//!     rowsPruned,
+//!     lastRun
+//!   }
+//! }
+//! ```
+//!
 //! # Example Mutations
 //!
 //! ## Insert a new entry, allowing the service to generate the timestamp
@@ -180,6 +342,16 @@
 //! }
 //! ```
 //!
+//! ## Insert a new binary entry. Blob values are given as hex strings
+//! ```graphql
+//! mutation {
+//!     insert(subsystem: "obc", parameter: "status", value: "01ff3a", valueType: BLOB) {
+//!         success,
+//!         errors
+//!     }
+//! }
+//! ```
+//!
 //! ## Insert a new entry with a custom timestamp
 //! ```graphql
 //! mutation {
@@ -191,6 +363,17 @@
 //!
 //! ```
 //!
+//! ## Send all new entries of the eps subsystem to a ground tool listening on port 9000
+//! ```graphql
+//! mutation {
+//!     subscribe(address: "192.168.0.1:9000", subsystem: "eps") {
+//!         success,
+//!         errors,
+//!         id
+//!     }
+//! }
+//! ```
+//!
 //! ## Delete all entries from the EPS subsystem occuring before timestamp 1003
 //! ```graphql
 //! mutation {
@@ -205,13 +388,20 @@
 #[macro_use]
 extern crate juniper;
 
+mod limits;
+mod retention;
 mod schema;
+mod subscriptions;
 mod udp;
 
+use crate::limits::{LimitConfig, Limits};
+use crate::retention::{Retention, RetentionConfig};
 use crate::schema::{MutationRoot, QueryRoot, Subsystem};
+use crate::subscriptions::{Subscriptions, DEFAULT_MAX_SUBSCRIPTIONS, DEFAULT_SUBSCRIPTION_LEASE};
 use kubos_service::{Config, Logger, Service};
 use kubos_telemetry_db::Database;
 use log::error;
+use std::time::Duration;
 
 fn main() {
     Logger::init("kubos-telemetry-service").unwrap();
@@ -261,9 +451,78 @@ fn main() {
         format!("{}:{}", host_ip, port)
     });
 
+    let retention = config.get("retention").map(|retention| {
+        let retention: RetentionConfig = retention
+            .try_into()
+            .map_err(|err| {
+                error!("Failed to parse 'retention' config value: {}", err);
+                "Failed to parse 'retention' config value"
+            })
+            .unwrap();
+
+        Retention::new(retention)
+            .map_err(|err| {
+                error!("{}", err);
+                "Failed to parse 'retention' config value"
+            })
+            .unwrap()
+    });
+
+    let limits = config
+        .get("limits")
+        .map(|limits| {
+            limits
+                .try_into::<Vec<LimitConfig>>()
+                .map_err(|err| {
+                    error!("Failed to parse 'limits' config value: {}", err);
+                    "Failed to parse 'limits' config value"
+                })
+                .unwrap()
+        })
+        .unwrap_or_default();
+    let limits = Limits::new(limits)
+        .map_err(|err| {
+            error!("{}", err);
+            "Failed to parse 'limits' config value"
+        })
+        .unwrap();
+
+    let max_subscriptions = config
+        .get("max_subscriptions")
+        .map(|max| {
+            max.try_into::<usize>()
+                .map_err(|err| {
+                    error!("Failed to parse 'max_subscriptions' config value: {}", err);
+                    "Failed to parse 'max_subscriptions' config value"
+                })
+                .unwrap()
+        })
+        .unwrap_or(DEFAULT_MAX_SUBSCRIPTIONS);
+
+    let subscription_lease = config
+        .get("subscription_lease")
+        .map(|lease| {
+            lease
+                .try_into::<u64>()
+                .map_err(|err| {
+                    error!("Failed to parse 'subscription_lease' config value: {}", err);
+                    "Failed to parse 'subscription_lease' config value"
+                })
+                .unwrap()
+        })
+        .unwrap_or(DEFAULT_SUBSCRIPTION_LEASE);
+
+    let subscriptions =
+        Subscriptions::new(max_subscriptions, Duration::from_secs(subscription_lease))
+            .map_err(|err| {
+                error!("{}", err);
+                err
+            })
+            .unwrap();
+
     Service::new(
         config,
-        Subsystem::new(db, direct_udp),
+        Subsystem::new(db, direct_udp, retention, limits, subscriptions),
         QueryRoot,
         MutationRoot,
     )
@@ -0,0 +1,143 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use kubos_telemetry_db::{systime, Database, Downsample, RetentionRule};
+use log::{error, info};
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Retention rule, as read from the service's config
+#[derive(Clone, Debug, Deserialize)]
+pub struct RuleConfig {
+    subsystem: Option<String>,
+    parameter: Option<String>,
+    max_age: Option<f64>,
+    max_rows: Option<i64>,
+    downsample_after: Option<f64>,
+    downsample_interval: Option<f64>,
+}
+
+// Retention settings, as read from the `retention` table of the service's config
+#[derive(Clone, Debug, Deserialize)]
+pub struct RetentionConfig {
+    // Seconds between each enforcement of the rules
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+}
+
+fn default_interval() -> u64 {
+    60
+}
+
+// Current state of a single retention rule
+#[derive(Clone, Debug)]
+pub struct RuleStatus {
+    pub rule: RetentionRule,
+    pub rows_pruned: u64,
+    pub last_run: Option<f64>,
+}
+
+pub struct Retention {
+    interval: Duration,
+    status: Arc<Mutex<Vec<RuleStatus>>>,
+}
+
+impl Retention {
+    pub fn new(config: RetentionConfig) -> Result<Self, String> {
+        let mut status = vec![];
+        for rule in config.rules {
+            let downsample = match (rule.downsample_after, rule.downsample_interval) {
+                (Some(after), Some(interval)) if interval > 0.0 => {
+                    Some(Downsample { after, interval })
+                }
+                (None, None) => None,
+                _ => {
+                    return Err(format!(
+                        "Invalid downsampling for rule {:?}. 'downsample_after' and a positive \
+                         'downsample_interval' must both be given",
+                        rule
+                    ))
+                }
+            };
+
+            status.push(RuleStatus {
+                rule: RetentionRule {
+                    subsystem: rule.subsystem,
+                    parameter: rule.parameter,
+                    max_age: rule.max_age,
+                    max_rows: rule.max_rows,
+                    downsample,
+                },
+                rows_pruned: 0,
+                last_run: None,
+            });
+        }
+
+        Ok(Retention {
+            interval: Duration::from_secs(config.interval),
+            status: Arc::new(Mutex::new(status)),
+        })
+    }
+
+    pub fn status(&self) -> Arc<Mutex<Vec<RuleStatus>>> {
+        self.status.clone()
+    }
+
+    pub fn start(&self, db: Arc<Mutex<Database>>) {
+        info!(
+            "Enforcing telemetry retention rules every {} seconds",
+            self.interval.as_secs()
+        );
+
+        loop {
+            thread::sleep(self.interval);
+
+            if let Err(err) = self.enforce(&db) {
+                error!("retention - Failed to enforce rules: {}", err);
+            }
+        }
+    }
+
+    fn enforce(&self, db: &Arc<Mutex<Database>>) -> Result<(), String> {
+        let mut status = self
+            .status
+            .lock()
+            .map_err(|err| format!("Failed to get lock on status: {}", err))?;
+
+        for rule in status.iter_mut() {
+            let now = systime();
+
+            let result = db
+                .lock()
+                .map_err(|err| format!("Failed to get lock on database: {}", err))?
+                .apply_retention(&rule.rule, now);
+
+            match result {
+                Ok(pruned) => {
+                    rule.rows_pruned += pruned as u64;
+                    rule.last_run = Some(now);
+                }
+                Err(err) => error!("retention - Failed to apply rule {:?}: {}", rule.rule, err),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -14,6 +14,9 @@
 // limitations under the License.
 //
 
+use crate::limits::*;
+use crate::retention::*;
+use crate::subscriptions::*;
 use crate::udp::*;
 use diesel::prelude::*;
 use flate2::write::GzEncoder;
@@ -32,18 +35,75 @@ type Context = kubos_service::Context<Subsystem>;
 #[derive(Clone)]
 pub struct Subsystem {
     pub database: Arc<Mutex<kubos_telemetry_db::Database>>,
+    pub retention: Arc<Mutex<Vec<RuleStatus>>>,
+    pub limits: Arc<Limits>,
+    pub subscriptions: Arc<Subscriptions>,
 }
 
 impl Subsystem {
-    pub fn new(database: kubos_telemetry_db::Database, direct_udp: Option<String>) -> Self {
+    pub fn new(
+        database: kubos_telemetry_db::Database,
+        direct_udp: Option<String>,
+        retention: Option<Retention>,
+        limits: Limits,
+        subscriptions: Subscriptions,
+    ) -> Self {
         let db = Arc::new(Mutex::new(database));
+        let limits = Arc::new(limits);
+        let subscriptions = Arc::new(subscriptions);
 
         if let Some(udp_url) = direct_udp {
-            let udp = DirectUdp::new(db.clone());
+            let udp = DirectUdp::new(db.clone(), limits.clone(), subscriptions.clone());
             spawn(move || udp.start(udp_url.to_owned()));
         }
 
-        Subsystem { database: db }
+        let status = match retention {
+            Some(retention) => {
+                let status = retention.status();
+                let retention_db = db.clone();
+                spawn(move || retention.start(retention_db));
+                status
+            }
+            None => Arc::new(Mutex::new(vec![])),
+        };
+
+        Subsystem {
+            database: db,
+            retention: status,
+            limits,
+            subscriptions,
+        }
+    }
+}
+
+/// Type of a telemetry value
+#[derive(GraphQLEnum, Clone, Copy)]
+enum ValueType {
+    Integer,
+    Real,
+    Text,
+    Blob,
+}
+
+impl From<ValueType> for kubos_telemetry_db::ValueType {
+    fn from(value_type: ValueType) -> Self {
+        match value_type {
+            ValueType::Integer => kubos_telemetry_db::ValueType::Integer,
+            ValueType::Real => kubos_telemetry_db::ValueType::Real,
+            ValueType::Text => kubos_telemetry_db::ValueType::Text,
+            ValueType::Blob => kubos_telemetry_db::ValueType::Blob,
+        }
+    }
+}
+
+impl From<kubos_telemetry_db::ValueType> for ValueType {
+    fn from(value_type: kubos_telemetry_db::ValueType) -> Self {
+        match value_type {
+            kubos_telemetry_db::ValueType::Integer => ValueType::Integer,
+            kubos_telemetry_db::ValueType::Real => ValueType::Real,
+            kubos_telemetry_db::ValueType::Text => ValueType::Text,
+            kubos_telemetry_db::ValueType::Blob => ValueType::Blob,
+        }
     }
 }
 
@@ -68,8 +128,237 @@ graphql_object!(Entry: () |&self| {
     field value() -> &String as "Telemetry value" {
         &self.0.value
     }
+
+    field value_type() -> ValueType as "Type of the telemetry value" {
+        self.0.value_type().into()
+    }
 });
 
+#[derive(GraphQLObject)]
+struct RetentionStatus {
+    subsystem: Option<String>,
+    parameter: Option<String>,
+    max_age: Option<f64>,
+    max_rows: Option<i32>,
+    downsample_after: Option<f64>,
+    downsample_interval: Option<f64>,
+    rows_pruned: i32,
+    last_run: Option<f64>,
+}
+
+/// Function used to combine the entries within each aggregation bucket
+#[derive(GraphQLEnum, Clone, Copy)]
+enum AggregateFunction {
+    Min,
+    Max,
+    Avg,
+    Count,
+    Last,
+}
+
+impl From<AggregateFunction> for kubos_telemetry_db::Aggregate {
+    fn from(function: AggregateFunction) -> Self {
+        match function {
+            AggregateFunction::Min => kubos_telemetry_db::Aggregate::Min,
+            AggregateFunction::Max => kubos_telemetry_db::Aggregate::Max,
+            AggregateFunction::Avg => kubos_telemetry_db::Aggregate::Avg,
+            AggregateFunction::Count => kubos_telemetry_db::Aggregate::Count,
+            AggregateFunction::Last => kubos_telemetry_db::Aggregate::Last,
+        }
+    }
+}
+
+/// File format used by the `routedTelemetry` query
+#[derive(GraphQLEnum, Clone, Copy, PartialEq)]
+enum ExportFormat {
+    Json,
+    Cbor,
+}
+
+fn export_entry(entry: &kubos_telemetry_db::Entry) -> telemetry_export::Entry {
+    use kubos_telemetry_db::Value as DbValue;
+    use telemetry_export::Value;
+
+    telemetry_export::Entry {
+        timestamp: entry.timestamp,
+        subsystem: entry.subsystem.clone(),
+        parameter: entry.parameter.clone(),
+        value: match entry.typed_value() {
+            DbValue::Integer(value) => Value::Integer(value),
+            DbValue::Real(value) => Value::Real(value),
+            DbValue::Text(value) => Value::Text(value),
+            DbValue::Blob(value) => Value::Blob(value),
+        },
+    }
+}
+
+/// Aggregated values of a single telemetry parameter
+#[derive(GraphQLObject)]
+struct Series {
+    subsystem: String,
+    parameter: String,
+    timestamps: Vec<f64>,
+    values: Vec<f64>,
+}
+
+fn aggregate_db(
+    database: &Arc<Mutex<kubos_telemetry_db::Database>>,
+    timestamp_ge: Option<f64>,
+    timestamp_le: Option<f64>,
+    subsystem: Option<String>,
+    parameters: Option<Vec<String>>,
+    bucket: f64,
+    function: AggregateFunction,
+) -> FieldResult<Vec<Series>> {
+    if !bucket.is_finite() || bucket <= 0.0 {
+        return Err(FieldError::new(
+            "The `bucket` width must be positive and finite",
+            Value::null(),
+        ));
+    }
+
+    let entries = database
+        .lock()
+        .map_err(|err| {
+            log::error!("Failed to get lock on database: {:?}", err);
+            err
+        })?
+        .aggregate(
+            subsystem.as_deref(),
+            parameters.as_deref(),
+            timestamp_ge,
+            timestamp_le,
+            bucket,
+            function.into(),
+        )
+        .map_err(|err| {
+            log::error!("Failed to aggregate database entries: {:?}", err);
+            err
+        })?;
+
+    // Entries are ordered by subsystem and parameter, so each series is contiguous
+    let mut series: Vec<Series> = Vec::new();
+    for entry in entries {
+        match series.last_mut() {
+            Some(last)
+                if last.subsystem == entry.subsystem && last.parameter == entry.parameter =>
+            {
+                last.timestamps.push(entry.timestamp);
+                last.values.push(entry.value);
+            }
+            _ => series.push(Series {
+                subsystem: entry.subsystem,
+                parameter: entry.parameter,
+                timestamps: vec![entry.timestamp],
+                values: vec![entry.value],
+            }),
+        }
+    }
+
+    Ok(series)
+}
+
+/// A change in the alarm state of a telemetry parameter
+#[derive(GraphQLObject)]
+struct AlarmEntry {
+    timestamp: f64,
+    subsystem: String,
+    parameter: String,
+    previous_state: AlarmState,
+    state: AlarmState,
+    value: f64,
+}
+
+/// Limits of a telemetry parameter, and its current alarm state
+#[derive(GraphQLObject)]
+struct LimitEntry {
+    subsystem: String,
+    parameter: String,
+    red_low: Option<f64>,
+    yellow_low: Option<f64>,
+    yellow_high: Option<f64>,
+    red_high: Option<f64>,
+    persistence: i32,
+    state: AlarmState,
+    last_value: Option<f64>,
+}
+
+/// A listener registered for new telemetry entries
+#[derive(GraphQLObject)]
+struct SubscriptionEntry {
+    id: i32,
+    address: String,
+    subsystem: Option<String>,
+    parameters: Option<Vec<String>>,
+    expires_in: f64,
+}
+
+fn query_alarms(
+    database: &Arc<Mutex<kubos_telemetry_db::Database>>,
+    timestamp_ge: Option<f64>,
+    timestamp_le: Option<f64>,
+    subsystem: Option<String>,
+    parameter: Option<String>,
+    limit: Option<i32>,
+) -> FieldResult<Vec<AlarmEntry>> {
+    use kubos_telemetry_db::alarms;
+    use kubos_telemetry_db::alarms::dsl;
+
+    let mut query = alarms::table.into_boxed::<<SqliteConnection as Connection>::Backend>();
+
+    if let Some(sub) = subsystem {
+        query = query.filter(dsl::subsystem.eq(sub));
+    }
+
+    if let Some(param) = parameter {
+        query = query.filter(dsl::parameter.eq(param));
+    }
+
+    if let Some(time_ge) = timestamp_ge {
+        query = query.filter(dsl::timestamp.ge(time_ge));
+    }
+
+    if let Some(time_le) = timestamp_le {
+        query = query.filter(dsl::timestamp.le(time_le));
+    }
+
+    if let Some(l) = limit {
+        query = query.limit(l.into());
+    }
+
+    query = query.order(dsl::timestamp.desc());
+
+    let alarms = query
+        .load::<kubos_telemetry_db::Alarm>(
+            &database
+                .lock()
+                .map_err(|err| {
+                    log::error!("Failed to get lock on database: {:?}", err);
+                    err
+                })?
+                .connection,
+        )
+        .map_err(|err| {
+            log::error!("Failed to load alarms: {:?}", err);
+            err
+        })?;
+
+    // States are only ever written by the service, so unknown names shouldn't occur
+    let state = |name: &str| AlarmState::from_name(name).unwrap_or(AlarmState::Nominal);
+
+    Ok(alarms
+        .into_iter()
+        .map(|alarm| AlarmEntry {
+            timestamp: alarm.timestamp,
+            previous_state: state(&alarm.previous_state),
+            state: state(&alarm.state),
+            subsystem: alarm.subsystem,
+            parameter: alarm.parameter,
+            value: alarm.value,
+        })
+        .collect())
+}
+
 fn query_db(
     database: &Arc<Mutex<kubos_telemetry_db::Database>>,
     timestamp_ge: Option<f64>,
@@ -174,6 +463,7 @@ graphql_object!(QueryRoot: Context |&self| {
         limit: Option<i32>,
         output: String,
         compress = true: bool,
+        format: Option<ExportFormat>,
     ) -> FieldResult<String>
         as "Telemetry entries in database"
     {
@@ -187,7 +477,13 @@ graphql_object!(QueryRoot: Context |&self| {
             query_db(&executor.context().subsystem().database, timestamp_ge, timestamp_le, subsystem, parameters, limit)?
         };
 
-        let entries = serde_json::to_vec(&entries)?;
+        let entries = match format.unwrap_or(ExportFormat::Json) {
+            ExportFormat::Json => serde_json::to_vec(&entries)?,
+            ExportFormat::Cbor => {
+                let entries: Vec<telemetry_export::Entry> = entries.iter().map(|entry| export_entry(&entry.0)).collect();
+                telemetry_export::encode(&entries)?
+            }
+        };
 
         let output_str = output.clone();
         let output_path = Path::new(&output_str);
@@ -220,6 +516,101 @@ graphql_object!(QueryRoot: Context |&self| {
             Ok(output)
         }
     }
+
+    field aggregate(
+        &executor,
+        timestamp_ge: Option<f64>,
+        timestamp_le: Option<f64>,
+        subsystem: Option<String>,
+        parameter: Option<String>,
+        parameters: Option<Vec<String>>,
+        bucket: f64,
+        function: AggregateFunction,
+    ) -> FieldResult<Vec<Series>>
+        as "Telemetry entries combined over fixed-width time buckets"
+    {
+        if parameter.is_some() && parameters.is_some() {
+            return Err(FieldError::new("The `parameter` and `parameters` input fields are mutually exclusive", Value::null()));
+        }
+
+        if let Some(param) = parameter {
+            aggregate_db(&executor.context().subsystem().database, timestamp_ge, timestamp_le, subsystem, Some(vec!(param)), bucket, function)
+        } else {
+            aggregate_db(&executor.context().subsystem().database, timestamp_ge, timestamp_le, subsystem, parameters, bucket, function)
+        }
+    }
+
+    field alarms(
+        &executor,
+        timestamp_ge: Option<f64>,
+        timestamp_le: Option<f64>,
+        subsystem: Option<String>,
+        parameter: Option<String>,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<AlarmEntry>>
+        as "Changes in the alarm states of telemetry parameters"
+    {
+        query_alarms(&executor.context().subsystem().database, timestamp_ge, timestamp_le, subsystem, parameter, limit)
+    }
+
+    field limits(&executor) -> FieldResult<Vec<LimitEntry>>
+        as "Configured telemetry limits and the current alarm state of each parameter"
+    {
+        let status = executor.context().subsystem().limits.status().map_err(|err| {
+            log::error!("limits - {}", err);
+            FieldError::new(err, Value::null())
+        })?;
+
+        Ok(status.into_iter().map(|status| LimitEntry {
+            subsystem: status.subsystem,
+            parameter: status.parameter,
+            red_low: status.red_low,
+            yellow_low: status.yellow_low,
+            yellow_high: status.yellow_high,
+            red_high: status.red_high,
+            persistence: status.persistence as i32,
+            state: status.state,
+            last_value: status.last_value,
+        }).collect())
+    }
+
+    field subscriptions(&executor) -> FieldResult<Vec<SubscriptionEntry>>
+        as "Listeners currently registered for new telemetry entries"
+    {
+        let subscriptions = executor.context().subsystem().subscriptions.list().map_err(|err| {
+            log::error!("subscriptions - {}", err);
+            FieldError::new(err, Value::null())
+        })?;
+
+        Ok(subscriptions.into_iter().map(|sub| SubscriptionEntry {
+            id: sub.id,
+            address: sub.address.to_string(),
+            expires_in: sub.expires_in().as_secs_f64(),
+            subsystem: sub.subsystem,
+            parameters: sub.parameters,
+        }).collect())
+    }
+
+    field retention(&executor) -> FieldResult<Vec<RetentionStatus>>
+        as "Retention rules and the number of entries each has pruned"
+    {
+        let status = executor.context().subsystem().retention.lock().map_err(|err| {
+            log::error!("retention - Failed to get lock on status: {:?}", err);
+            err
+        })?;
+
+        Ok(status.iter().map(|status| RetentionStatus {
+            subsystem: status.rule.subsystem.clone(),
+            parameter: status.rule.parameter.clone(),
+            max_age: status.rule.max_age,
+            // GraphQL integers are 32-bit, so larger counts are reported as the largest one
+            max_rows: status.rule.max_rows.map(|rows| rows.min(i32::MAX as i64) as i32),
+            downsample_after: status.rule.downsample.as_ref().map(|downsample| downsample.after),
+            downsample_interval: status.rule.downsample.as_ref().map(|downsample| downsample.interval),
+            rows_pruned: status.rows_pruned.min(i32::MAX as u64) as i32,
+            last_run: status.last_run,
+        }).collect())
+    }
 });
 
 pub struct MutationRoot;
@@ -230,6 +621,13 @@ struct InsertResponse {
     errors: String,
 }
 
+#[derive(GraphQLObject)]
+struct SubscribeResponse {
+    success: bool,
+    errors: String,
+    id: Option<i32>,
+}
+
 #[derive(GraphQLObject)]
 struct DeleteResponse {
     success: bool,
@@ -243,22 +641,24 @@ struct InsertEntry {
     subsystem: String,
     parameter: String,
     value: String,
+    value_type: Option<ValueType>,
 }
 
 graphql_object!(MutationRoot: Context | &self | {
-    field insert(&executor, timestamp: Option<f64>, subsystem: String, parameter: String, value: String) -> FieldResult<InsertResponse> {
-        let result = match timestamp {
-            Some(time) => executor.context().subsystem().database.lock().map_err(|err| {
-                    log::error!("insert - Failed to get lock on database: {:?}", err);
-                    err
-                })?
-            .insert(time, &subsystem, &parameter, &value),
-            None => executor.context().subsystem().database.lock().map_err(|err| {
-                    log::error!("insert - Failed to get lock on database: {:?}", err);
-                    err
-                })?
-            .insert_systime(&subsystem, &parameter, &value),
-        };
+    field insert(&executor, timestamp: Option<f64>, subsystem: String, parameter: String, value: String, value_type: Option<ValueType>) -> FieldResult<InsertResponse> {
+        let value_type: Option<kubos_telemetry_db::ValueType> = value_type.map(Into::into);
+        let timestamp = timestamp.unwrap_or_else(kubos_telemetry_db::systime);
+
+        let db = executor.context().subsystem().database.lock().map_err(|err| {
+            log::error!("insert - Failed to get lock on database: {:?}", err);
+            err
+        })?;
+
+        let result = db.insert(timestamp, &subsystem, &parameter, &value, value_type);
+        if result.is_ok() {
+            executor.context().subsystem().limits.check(&db, timestamp, &subsystem, &parameter, &value, value_type);
+            executor.context().subsystem().subscriptions.publish(timestamp, &subsystem, &parameter, &value, value_type);
+        }
 
         Ok(InsertResponse {
             success: result.is_ok(),
@@ -275,25 +675,44 @@ graphql_object!(MutationRoot: Context | &self | {
         entries: Vec<InsertEntry>
     ) -> FieldResult<InsertResponse>
     {
-        let time = time::now_utc().to_timespec();
-        let systime = time.sec as f64 + (f64::from(time.nsec) / 1_000_000_000.0);
+        let systime = kubos_telemetry_db::systime();
 
         let mut new_entries: Vec<kubos_telemetry_db::Entry> = Vec::new();
         for entry in entries {
             let ts = entry.timestamp.or(timestamp).unwrap_or(systime);
 
-            new_entries.push(kubos_telemetry_db::Entry {
-                timestamp: ts,
-                subsystem: entry.subsystem,
-                parameter: entry.parameter,
-                value: entry.value,
-            });
+            match kubos_telemetry_db::Entry::new(
+                ts,
+                &entry.subsystem,
+                &entry.parameter,
+                &entry.value,
+                entry.value_type.map(Into::into),
+            ) {
+                Ok(new_entry) => new_entries.push(new_entry),
+                Err(err) => return Ok(InsertResponse {
+                    success: false,
+                    errors: format!("{}", err),
+                }),
+            }
         }
 
-        let result = executor.context().subsystem().database.lock().map_err(|err| {
+        let db = executor.context().subsystem().database.lock().map_err(|err| {
             log::error!("insert_bulk - Failed to get lock on database: {:?}", err);
             err
-        })?.insert_bulk(new_entries);
+        })?;
+
+        let checks: Vec<_> = new_entries
+            .iter()
+            .map(|entry| (entry.timestamp, entry.subsystem.clone(), entry.parameter.clone(), entry.value.clone(), entry.value_type()))
+            .collect();
+
+        let result = db.insert_bulk(new_entries);
+        if result.is_ok() {
+            for (timestamp, subsystem, parameter, value, value_type) in checks {
+                executor.context().subsystem().limits.check(&db, timestamp, &subsystem, &parameter, &value, Some(value_type));
+                executor.context().subsystem().subscriptions.publish(timestamp, &subsystem, &parameter, &value, Some(value_type));
+            }
+        }
 
         Ok(InsertResponse {
             success: result.is_ok(),
@@ -304,6 +723,60 @@ graphql_object!(MutationRoot: Context | &self | {
         })
     }
 
+    field subscribe(
+        &executor,
+        address: String,
+        subsystem: Option<String>,
+        parameter: Option<String>,
+        parameters: Option<Vec<String>>,
+    ) -> FieldResult<SubscribeResponse>
+    {
+        if parameter.is_some() && parameters.is_some() {
+            return Err(FieldError::new("The `parameter` and `parameters` input fields are mutually exclusive", Value::null()));
+        }
+
+        let parameters = parameter.map(|param| vec!(param)).or(parameters);
+
+        match executor.context().subsystem().subscriptions.subscribe(&address, subsystem, parameters) {
+            Ok(id) => Ok(SubscribeResponse {
+                success: true,
+                errors: "".to_owned(),
+                id: Some(id),
+            }),
+            Err(err) => Ok(SubscribeResponse {
+                success: false,
+                errors: err,
+                id: None,
+            }),
+        }
+    }
+
+    field unsubscribe(&executor, id: i32) -> FieldResult<InsertResponse> {
+        let result = executor.context().subsystem().subscriptions.unsubscribe(id);
+
+        Ok(InsertResponse {
+            success: result == Ok(true),
+            errors: match result {
+                Ok(true) => "".to_owned(),
+                Ok(false) => format!("No subscription with ID {}", id),
+                Err(err) => err,
+            },
+        })
+    }
+
+    field renew_subscription(&executor, id: i32) -> FieldResult<InsertResponse> {
+        let result = executor.context().subsystem().subscriptions.renew(id);
+
+        Ok(InsertResponse {
+            success: result == Ok(true),
+            errors: match result {
+                Ok(true) => "".to_owned(),
+                Ok(false) => format!("No subscription with ID {}", id),
+                Err(err) => err,
+            },
+        })
+    }
+
     field delete(
         &executor,
         timestamp_ge: Option<f64>,
@@ -0,0 +1,303 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use kubos_telemetry_db::{Value, ValueType};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of subscriptions which may be registered at once, if not configured
+pub const DEFAULT_MAX_SUBSCRIPTIONS: usize = 16;
+/// Number of subscriptions which may be registered for the same host at once
+pub const MAX_SUBSCRIPTIONS_PER_HOST: usize = 4;
+/// How long a subscription lasts without being renewed (in seconds), if not configured
+pub const DEFAULT_SUBSCRIPTION_LEASE: u64 = 300;
+/// Number of times in a row that sending to a listener may fail before its subscription is removed
+pub const MAX_SEND_FAILURES: u32 = 5;
+
+// Message sent to listeners for each new entry. Matches the format accepted by the direct UDP port
+#[derive(Debug, Serialize)]
+struct DataPoint<'a> {
+    timestamp: f64,
+    subsystem: &'a str,
+    parameter: &'a str,
+    value: &'a str,
+    #[serde(rename = "type")]
+    value_type: ValueType,
+}
+
+// A listener which has registered for new telemetry entries
+#[derive(Clone, Debug)]
+pub struct Subscription {
+    pub id: i32,
+    pub address: SocketAddr,
+    pub subsystem: Option<String>,
+    pub parameters: Option<Vec<String>>,
+    // Number of times in a row that sending an entry to the listener has failed
+    failures: u32,
+    // When the subscription will be removed, unless it's renewed first
+    expires: Instant,
+}
+
+impl Subscription {
+    /// Time left before the subscription expires
+    pub fn expires_in(&self) -> Duration {
+        self.expires.saturating_duration_since(Instant::now())
+    }
+
+    fn matches(&self, subsystem: &str, parameter: &str) -> bool {
+        self.subsystem.as_ref().map_or(true, |sub| sub == subsystem)
+            && self
+                .parameters
+                .as_ref()
+                .map_or(true, |params| params.iter().any(|param| param == parameter))
+    }
+}
+
+struct Listeners {
+    next_id: i32,
+    subscriptions: Vec<Subscription>,
+}
+
+impl Listeners {
+    // Remove the subscriptions whose leases have run out
+    fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.subscriptions.retain(|sub| {
+            if sub.expires <= now {
+                info!("Subscription {} for {} expired", sub.id, sub.address);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+pub struct Subscriptions {
+    socket: UdpSocket,
+    listeners: Mutex<Listeners>,
+    max_subscriptions: usize,
+    lease: Duration,
+}
+
+impl Subscriptions {
+    pub fn new(max_subscriptions: usize, lease: Duration) -> Result<Self, String> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .map_err(|err| format!("Failed to bind subscription socket: {}", err))?;
+
+        Ok(Subscriptions {
+            socket,
+            listeners: Mutex::new(Listeners {
+                next_id: 1,
+                subscriptions: vec![],
+            }),
+            max_subscriptions,
+            lease,
+        })
+    }
+
+    /// Register a listener, returning the ID of its subscription.
+    /// The subscription expires after the lease time unless it's renewed
+    pub fn subscribe(
+        &self,
+        address: &str,
+        subsystem: Option<String>,
+        parameters: Option<Vec<String>>,
+    ) -> Result<i32, String> {
+        let address = address
+            .parse::<SocketAddr>()
+            .map_err(|err| format!("Failed to parse address {}: {}", address, err))?;
+
+        let mut listeners = self
+            .listeners
+            .lock()
+            .map_err(|err| format!("Failed to get lock on subscriptions: {}", err))?;
+
+        listeners.remove_expired();
+
+        if listeners.subscriptions.len() >= self.max_subscriptions {
+            return Err(format!(
+                "Maximum number of subscriptions ({}) reached",
+                self.max_subscriptions
+            ));
+        }
+
+        // Stop the service being used to flood a single host with entries
+        let host_count = listeners
+            .subscriptions
+            .iter()
+            .filter(|sub| sub.address.ip() == address.ip())
+            .count();
+        if host_count >= MAX_SUBSCRIPTIONS_PER_HOST {
+            return Err(format!(
+                "Maximum number of subscriptions for {} ({}) reached",
+                address.ip(),
+                MAX_SUBSCRIPTIONS_PER_HOST
+            ));
+        }
+
+        let id = listeners.next_id;
+        listeners.next_id += 1;
+
+        info!("Subscription {} added for {}", id, address);
+
+        listeners.subscriptions.push(Subscription {
+            id,
+            address,
+            subsystem,
+            parameters,
+            failures: 0,
+            expires: Instant::now() + self.lease,
+        });
+
+        Ok(id)
+    }
+
+    /// Extend a subscription's lease, returning whether it existed
+    pub fn renew(&self, id: i32) -> Result<bool, String> {
+        let mut listeners = self
+            .listeners
+            .lock()
+            .map_err(|err| format!("Failed to get lock on subscriptions: {}", err))?;
+
+        listeners.remove_expired();
+
+        match listeners.subscriptions.iter_mut().find(|sub| sub.id == id) {
+            Some(sub) => {
+                sub.expires = Instant::now() + self.lease;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Remove a subscription, returning whether it existed
+    pub fn unsubscribe(&self, id: i32) -> Result<bool, String> {
+        let mut listeners = self
+            .listeners
+            .lock()
+            .map_err(|err| format!("Failed to get lock on subscriptions: {}", err))?;
+
+        let count = listeners.subscriptions.len();
+        listeners.subscriptions.retain(|sub| sub.id != id);
+
+        if listeners.subscriptions.len() == count {
+            return Ok(false);
+        }
+
+        info!("Subscription {} removed", id);
+        Ok(true)
+    }
+
+    pub fn list(&self) -> Result<Vec<Subscription>, String> {
+        let mut listeners = self
+            .listeners
+            .lock()
+            .map_err(|err| format!("Failed to get lock on subscriptions: {}", err))?;
+
+        listeners.remove_expired();
+
+        Ok(listeners.subscriptions.clone())
+    }
+
+    /// Send a newly inserted entry to all of the listeners whose filters it matches.
+    /// Expired subscriptions, and listeners which can't be sent to several times in a row,
+    /// are unsubscribed
+    pub fn publish(
+        &self,
+        timestamp: f64,
+        subsystem: &str,
+        parameter: &str,
+        value: &str,
+        value_type: Option<ValueType>,
+    ) {
+        let mut listeners = match self.listeners.lock() {
+            Ok(listeners) => listeners,
+            Err(err) => {
+                error!(
+                    "subscriptions - Failed to get lock on subscriptions: {}",
+                    err
+                );
+                return;
+            }
+        };
+
+        listeners.remove_expired();
+
+        if !listeners
+            .subscriptions
+            .iter()
+            .any(|sub| sub.matches(subsystem, parameter))
+        {
+            return;
+        }
+
+        // Listeners always get the entry's type, even if it was inferred
+        let value_type = match Value::parse(value, value_type) {
+            Ok(value) => value.value_type(),
+            Err(err) => {
+                error!("subscriptions - Failed to parse entry: {}", err);
+                return;
+            }
+        };
+
+        let data = match serde_json::to_vec(&DataPoint {
+            timestamp,
+            subsystem,
+            parameter,
+            value,
+            value_type,
+        }) {
+            Ok(data) => data,
+            Err(err) => {
+                error!("subscriptions - Failed to serialize entry: {}", err);
+                return;
+            }
+        };
+
+        for sub in listeners
+            .subscriptions
+            .iter_mut()
+            .filter(|sub| sub.matches(subsystem, parameter))
+        {
+            match self.socket.send_to(&data, sub.address) {
+                Ok(_) => sub.failures = 0,
+                Err(err) => {
+                    error!(
+                        "subscriptions - Failed to send entry to {}: {}",
+                        sub.address, err
+                    );
+                    sub.failures += 1;
+                }
+            }
+        }
+
+        listeners.subscriptions.retain(|sub| {
+            if sub.failures >= MAX_SEND_FAILURES {
+                warn!(
+                    "Subscription {} removed after {} failed sends to {}",
+                    sub.id, sub.failures, sub.address
+                );
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
@@ -14,7 +14,9 @@
 // limitations under the License.
 //
 
-use kubos_telemetry_db::Database;
+use crate::limits::Limits;
+use crate::subscriptions::Subscriptions;
+use kubos_telemetry_db::{systime, Database, ValueType};
 use log::{error, info};
 use serde::Deserialize;
 use std::net::{SocketAddr, UdpSocket};
@@ -22,6 +24,8 @@ use std::sync::{Arc, Mutex};
 
 pub struct DirectUdp {
     db: Arc<Mutex<Database>>,
+    limits: Arc<Limits>,
+    subscriptions: Arc<Subscriptions>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,11 +34,21 @@ struct DataPoint {
     subsystem: String,
     parameter: String,
     value: String,
+    #[serde(rename = "type")]
+    value_type: Option<ValueType>,
 }
 
 impl DirectUdp {
-    pub fn new(db: Arc<Mutex<Database>>) -> Self {
-        DirectUdp { db }
+    pub fn new(
+        db: Arc<Mutex<Database>>,
+        limits: Arc<Limits>,
+        subscriptions: Arc<Subscriptions>,
+    ) -> Self {
+        DirectUdp {
+            db,
+            limits,
+            subscriptions,
+        }
     }
 
     pub fn start(&self, url: String) {
@@ -83,31 +97,41 @@ impl DirectUdp {
     }
 
     fn process(&self, message: &DataPoint) -> Result<(), String> {
-        if let Some(time) = message.timestamp {
-            self.db
-                .lock()
-                .map_err(|err| {
-                    error!("udp - Failed to get lock on database: {}", err);
-                    format!("{}", err)
-                })?
-                .insert(time, &message.subsystem, &message.parameter, &message.value)
-                .map_err(|err| {
-                    error!("udp - Failed to get lock on database: {}", err);
-                    format!("{}", err)
-                })?;
-        } else {
-            self.db
-                .lock()
-                .map_err(|err| {
-                    error!("udp - Failed to get lock on database: {}", err);
-                    format!("{}", err)
-                })?
-                .insert_systime(&message.subsystem, &message.parameter, &message.value)
-                .map_err(|err| {
-                    error!("udp - Failed to get lock on database: {}", err);
-                    format!("{}", err)
-                })?;
-        }
+        let timestamp = message.timestamp.unwrap_or_else(systime);
+
+        let db = self.db.lock().map_err(|err| {
+            error!("udp - Failed to get lock on database: {}", err);
+            format!("{}", err)
+        })?;
+
+        db.insert(
+            timestamp,
+            &message.subsystem,
+            &message.parameter,
+            &message.value,
+            message.value_type,
+        )
+        .map_err(|err| {
+            error!("udp - Failed to insert entry: {}", err);
+            format!("{}", err)
+        })?;
+
+        self.limits.check(
+            &db,
+            timestamp,
+            &message.subsystem,
+            &message.parameter,
+            &message.value,
+            message.value_type,
+        );
+
+        self.subscriptions.publish(
+            timestamp,
+            &message.subsystem,
+            &message.parameter,
+            &message.value,
+            message.value_type,
+        );
 
         Ok(())
     }
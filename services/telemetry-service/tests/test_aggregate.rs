@@ -0,0 +1,173 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+mod utils;
+
+use crate::utils::*;
+use serde_json::json;
+use tempfile::TempDir;
+
+static SQL: &str = r"
+insert into telemetry values(1000, 'eps', 'voltage', '3.3');
+insert into telemetry values(1001, 'mcu', 'voltage', '3.4');
+insert into telemetry values(1002, 'gps', 'voltage', '3.2');
+insert into telemetry values(1003, 'eps', 'current', '3.1');
+insert into telemetry values(1004, 'mcu', 'current', '3.0');
+insert into telemetry values(1005, 'gps', 'current', '2.9');
+insert into telemetry values(1006, 'eps', 'voltage', '2.8');
+insert into telemetry values(1007, 'eps', 'mode', 'safe');
+insert into telemetry values(1012, 'eps', 'voltage', '2.6');
+insert into telemetry values(1014, 'eps', 'current', '2.5');
+insert into telemetry values(1018, 'eps', 'voltage', '2.4');
+";
+
+#[test]
+fn test_aggregate() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8130;
+    let udp = 8140;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), Some(SQL));
+
+    let res = do_query(
+        Some(port),
+        r#"{
+            aggregate(subsystem: "eps", bucket: 10, function: MAX) {
+                subsystem,
+                parameter,
+                timestamps,
+                values
+            }
+        }"#,
+    );
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "aggregate": [
+                    {
+                        "subsystem": "eps",
+                        "parameter": "current",
+                        "timestamps": [1000.0, 1010.0],
+                        "values": [3.1, 2.5]
+                    },
+                    {
+                        "subsystem": "eps",
+                        "parameter": "voltage",
+                        "timestamps": [1000.0, 1010.0],
+                        "values": [3.3, 2.6]
+                    }
+                ]
+            }
+        })
+    );
+
+    let res = do_query(
+        Some(port),
+        r#"{
+            aggregate(subsystem: "eps", parameter: "voltage", bucket: 10, function: LAST) {
+                timestamps,
+                values
+            }
+        }"#,
+    );
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "aggregate": [
+                    {
+                        "timestamps": [1000.0, 1010.0],
+                        "values": [2.8, 2.4]
+                    }
+                ]
+            }
+        })
+    );
+
+    let res = do_query(
+        Some(port),
+        r#"{
+            aggregate(parameters: ["voltage", "mode"], timestampLe: 1010, bucket: 100, function: COUNT) {
+                subsystem,
+                parameter,
+                values
+            }
+        }"#,
+    );
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "aggregate": [
+                    {
+                        "subsystem": "eps",
+                        "parameter": "mode",
+                        "values": [1.0]
+                    },
+                    {
+                        "subsystem": "eps",
+                        "parameter": "voltage",
+                        "values": [2.0]
+                    },
+                    {
+                        "subsystem": "gps",
+                        "parameter": "voltage",
+                        "values": [1.0]
+                    },
+                    {
+                        "subsystem": "mcu",
+                        "parameter": "voltage",
+                        "values": [1.0]
+                    }
+                ]
+            }
+        })
+    );
+}
+
+#[test]
+fn test_aggregate_bad_bucket() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8131;
+    let udp = 8141;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), Some(SQL));
+
+    let res = do_query(
+        Some(port),
+        r#"{ aggregate(bucket: 0, function: AVG) { values } }"#,
+    );
+    assert_eq!(
+        res["errors"][0]["message"],
+        json!("The `bucket` width must be positive and finite")
+    );
+
+    let res = do_query(
+        Some(port),
+        r#"{ aggregate(bucket: 1e400, function: AVG) { values } }"#,
+    );
+    assert_eq!(
+        res["errors"][0]["message"],
+        json!("The `bucket` width must be positive and finite")
+    );
+}
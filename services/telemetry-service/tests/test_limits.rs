@@ -0,0 +1,170 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+mod utils;
+
+use crate::utils::*;
+use serde_json::{json, ser};
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+// The hook sends a request back to the telemetry service, so that we can tell when it fires
+static CONFIG: &str = r#"
+[[telemetry-service.limits]]
+subsystem = "eps"
+parameter = "voltage"
+red_low = 3.0
+yellow_low = 3.3
+yellow_high = 4.1
+red_high = 4.3
+persistence = 2
+
+[telemetry-service.limits.hook]
+service = "telemetry-service"
+query = "mutation { insert(subsystem: \"hook\", parameter: \"fired\", value: \"1\") { success } }"
+"#;
+
+fn insert(port: u16, timestamp: u32, value: &str) {
+    let mutation = format!(
+        r#"mutation {{
+            insert(timestamp: {}, subsystem: "eps", parameter: "voltage", value: "{}") {{
+                success
+            }}
+        }}"#,
+        timestamp, value
+    );
+    let res = do_query(Some(port), &mutation);
+    assert_eq!(res, json!({"data": {"insert": {"success": true}}}));
+}
+
+#[test]
+fn test_limits() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8135;
+    let udp = 8145;
+
+    let _fixture =
+        TelemetryServiceFixture::setup_with_config(db, Some(port), Some(udp), None, CONFIG);
+
+    // A single reading outside of the limits isn't enough to change the state
+    insert(port, 1000, "3.2");
+    insert(port, 1001, "3.5");
+    insert(port, 1002, "3.2");
+    insert(port, 1003, "3.1");
+
+    let mutation = r#"mutation {
+            insertBulk(entries: [
+                { timestamp: 1004, subsystem: "eps", parameter: "voltage", value: "2.9" },
+                { timestamp: 1005, subsystem: "eps", parameter: "current", value: "2.9" },
+            ]) {
+                success
+            }
+        }"#;
+    let res = do_query(Some(port), mutation);
+    assert_eq!(res, json!({"data": {"insertBulk": {"success": true}}}));
+
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let entry = json!({
+        "timestamp": 1006,
+        "subsystem": "eps",
+        "parameter": "voltage",
+        "value": "2.8"
+    });
+    socket
+        .send_to(&ser::to_vec(&entry).unwrap(), format!("0.0.0.0:{}", udp))
+        .unwrap();
+
+    // Give the service time to process the UDP message and send the hook
+    thread::sleep(Duration::from_secs(1));
+
+    let res = do_query(
+        Some(port),
+        "{alarms{timestamp,subsystem,parameter,previousState,state,value}}",
+    );
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "alarms": [
+                    {
+                        "timestamp": 1006.0,
+                        "subsystem": "eps",
+                        "parameter": "voltage",
+                        "previousState": "YELLOW_LOW",
+                        "state": "RED_LOW",
+                        "value": 2.8
+                    },
+                    {
+                        "timestamp": 1003.0,
+                        "subsystem": "eps",
+                        "parameter": "voltage",
+                        "previousState": "NOMINAL",
+                        "state": "YELLOW_LOW",
+                        "value": 3.1
+                    }
+                ]
+            }
+        })
+    );
+
+    let res = do_query(
+        Some(port),
+        "{limits{subsystem,parameter,redLow,yellowLow,yellowHigh,redHigh,persistence,state,lastValue}}",
+    );
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "limits": [
+                    {
+                        "subsystem": "eps",
+                        "parameter": "voltage",
+                        "redLow": 3.0,
+                        "yellowLow": 3.3,
+                        "yellowHigh": 4.1,
+                        "redHigh": 4.3,
+                        "persistence": 2,
+                        "state": "RED_LOW",
+                        "lastValue": 2.8
+                    }
+                ]
+            }
+        })
+    );
+
+    let res = do_query(
+        Some(port),
+        r#"{telemetry(subsystem: "hook") { parameter, value }}"#,
+    );
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "telemetry": [
+                    {
+                        "parameter": "fired",
+                        "value": "1"
+                    }
+                ]
+            }
+        })
+    );
+}
@@ -0,0 +1,210 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+mod utils;
+
+use crate::utils::*;
+use serde_json::json;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+static SQL: &str = r"
+insert into telemetry values(1000, 'eps', 'voltage', '3.3');
+insert into telemetry values(1001, 'mcu', 'voltage', '3.4');
+insert into telemetry values(1002, 'gps', 'voltage', '3.2');
+insert into telemetry values(1003, 'eps', 'current', '3.1');
+insert into telemetry values(1004, 'mcu', 'voltage', '3.0');
+insert into telemetry values(1005, 'gps', 'current', '2.9');
+insert into telemetry values(1006, 'eps', 'voltage', '2.8');
+insert into telemetry values(1007, 'mcu', 'voltage', '2.6');
+insert into telemetry values(1008, 'gps', 'voltage', '2.6');
+insert into telemetry values(1009, 'mcu', 'mode', 'safe');
+insert into telemetry values(1010, 'mcu', 'voltage', '2.4');
+";
+
+static CONFIG: &str = r#"
+[telemetry-service.retention]
+interval = 1
+
+[[telemetry-service.retention.rules]]
+subsystem = "eps"
+max_age = 86400
+
+[[telemetry-service.retention.rules]]
+subsystem = "gps"
+max_rows = 2
+
+[[telemetry-service.retention.rules]]
+subsystem = "mcu"
+downsample_after = 86400
+downsample_interval = 60
+"#;
+
+#[test]
+fn test_retention() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8118;
+    let udp = 8128;
+
+    let _fixture =
+        TelemetryServiceFixture::setup_with_config(db, Some(port), Some(udp), Some(SQL), CONFIG);
+
+    // Give the rules a chance to be enforced
+    thread::sleep(Duration::from_millis(2500));
+
+    let res = do_query(
+        Some(port),
+        "{retention{subsystem,parameter,maxAge,maxRows,downsampleAfter,downsampleInterval,rowsPruned}}",
+    );
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "retention": [
+                    {
+                        "subsystem": "eps",
+                        "parameter": null,
+                        "maxAge": 86400.0,
+                        "maxRows": null,
+                        "downsampleAfter": null,
+                        "downsampleInterval": null,
+                        "rowsPruned": 3
+                    },
+                    {
+                        "subsystem": "gps",
+                        "parameter": null,
+                        "maxAge": null,
+                        "maxRows": 2,
+                        "downsampleAfter": null,
+                        "downsampleInterval": null,
+                        "rowsPruned": 1
+                    },
+                    {
+                        "subsystem": "mcu",
+                        "parameter": null,
+                        "maxAge": null,
+                        "maxRows": null,
+                        "downsampleAfter": 86400.0,
+                        "downsampleInterval": 60.0,
+                        "rowsPruned": 3
+                    }
+                ]
+            }
+        })
+    );
+
+    let res = do_query(
+        Some(port),
+        "{telemetry{timestamp,subsystem,parameter,value}}",
+    );
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "telemetry": [
+                    {
+                        "timestamp": 1009.0,
+                        "subsystem": "mcu",
+                        "parameter": "mode",
+                        "value": "safe"
+                    },
+                    {
+                        "timestamp": 1008.0,
+                        "subsystem": "gps",
+                        "parameter": "voltage",
+                        "value": "2.6"
+                    },
+                    {
+                        "timestamp": 1005.0,
+                        "subsystem": "gps",
+                        "parameter": "current",
+                        "value": "2.9"
+                    },
+                    {
+                        "timestamp": 960.0,
+                        "subsystem": "mcu",
+                        "parameter": "voltage",
+                        "value": "2.85"
+                    }
+                ]
+            }
+        })
+    );
+}
+
+#[test]
+fn test_retention_none() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8119;
+    let udp = 8129;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), Some(SQL));
+
+    let res = do_query(Some(port), "{retention{subsystem,rowsPruned}}");
+    assert_eq!(res, json!({ "data": { "retention": [] } }));
+}
+
+#[test]
+fn test_retention_downsample_non_numeric_at_bucket_start() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8156;
+    let udp = 8157;
+
+    let sql = r"
+insert into telemetry values(960, 'mcu', 'voltage', 'n/a');
+insert into telemetry values(1001, 'mcu', 'voltage', '3.4');
+insert into telemetry values(1004, 'mcu', 'voltage', '3.0');
+";
+
+    let _fixture =
+        TelemetryServiceFixture::setup_with_config(db, Some(port), Some(udp), Some(sql), CONFIG);
+
+    // Give the rules a chance to be enforced
+    thread::sleep(Duration::from_millis(2500));
+
+    let res = do_query(Some(port), "{retention{subsystem,rowsPruned}}");
+    assert_eq!(res["data"]["retention"][2]["rowsPruned"], json!(2));
+
+    let res = do_query(
+        Some(port),
+        "{telemetry{timestamp,subsystem,parameter,value}}",
+    );
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "telemetry": [
+                    {
+                        "timestamp": 960.0,
+                        "subsystem": "mcu",
+                        "parameter": "voltage",
+                        "value": "3.2"
+                    }
+                ]
+            }
+        })
+    );
+}
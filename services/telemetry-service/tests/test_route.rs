@@ -332,3 +332,41 @@ fn test_route_conflict() {
         })
     );
 }
+
+#[test]
+fn test_route_cbor() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8138;
+    let udp = 8148;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), Some(SQL));
+
+    let output_dir = TempDir::new().unwrap();
+    let output_path = output_dir.path().join("output");
+
+    let query = format!(
+        r#"{{
+        routedTelemetry(subsystem: "eps", output: "{}", compress: false, format: CBOR)
+    }}"#,
+        output_path.to_str().unwrap()
+    );
+
+    do_query(Some(port), &query);
+
+    let contents = fs::read(output_path).unwrap();
+    let entries = telemetry_export::decode(&contents).unwrap();
+
+    assert_eq!(
+        serde_json::to_value(&entries).unwrap(),
+        json!([
+            {"timestamp":1004.0,"subsystem":"eps","parameter":"voltage","value":"3.6","type":"real"},
+            {"timestamp":1003.0,"subsystem":"eps","parameter":"current","value":"3.5","type":"real"},
+            {"timestamp":1002.0,"subsystem":"eps","parameter":"voltage","value":"3.2","type":"real"},
+            {"timestamp":1001.0,"subsystem":"eps","parameter":"current","value":"3.4","type":"real"},
+            {"timestamp":1000.0,"subsystem":"eps","parameter":"voltage","value":"3.3","type":"real"}
+        ])
+    );
+}
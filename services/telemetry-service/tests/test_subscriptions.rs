@@ -0,0 +1,362 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+mod utils;
+
+use crate::utils::*;
+use serde_json::{json, ser};
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn recv(listener: &UdpSocket) -> Option<serde_json::Value> {
+    let mut buf = [0; 4096];
+    listener
+        .recv(&mut buf)
+        .ok()
+        .map(|size| serde_json::from_slice(&buf[0..size]).unwrap())
+}
+
+#[test]
+fn test_subscriptions() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8136;
+    let udp = 8146;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), None);
+
+    let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+    listener
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+
+    let mutation = format!(
+        r#"mutation {{
+            subscribe(address: "{}", subsystem: "eps", parameters: ["voltage", "current"]) {{
+                success,
+                errors,
+                id
+            }}
+        }}"#,
+        listener.local_addr().unwrap()
+    );
+    let res = do_query(Some(port), &mutation);
+    assert_eq!(
+        res,
+        json!({"data": {"subscribe": {"success": true, "errors": "", "id": 1}}})
+    );
+
+    let res = do_query(
+        Some(port),
+        "{subscriptions{id,address,subsystem,parameters}}",
+    );
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "subscriptions": [
+                    {
+                        "id": 1,
+                        "address": listener.local_addr().unwrap().to_string(),
+                        "subsystem": "eps",
+                        "parameters": ["voltage", "current"]
+                    }
+                ]
+            }
+        })
+    );
+
+    let mutation = r#"mutation {
+            insertBulk(entries: [
+                { timestamp: 1000, subsystem: "eps", parameter: "voltage", value: "3.3" },
+                { timestamp: 1000, subsystem: "eps", parameter: "mode", value: "safe" },
+                { timestamp: 1000, subsystem: "gps", parameter: "voltage", value: "3.1" },
+            ]) {
+                success
+            }
+        }"#;
+    let res = do_query(Some(port), mutation);
+    assert_eq!(res, json!({"data": {"insertBulk": {"success": true}}}));
+
+    assert_eq!(
+        recv(&listener),
+        Some(json!({
+            "timestamp": 1000.0,
+            "subsystem": "eps",
+            "parameter": "voltage",
+            "value": "3.3",
+            "type": "real"
+        }))
+    );
+
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let entry = json!({
+        "timestamp": 1001,
+        "subsystem": "eps",
+        "parameter": "current",
+        "value": "-2"
+    });
+    socket
+        .send_to(&ser::to_vec(&entry).unwrap(), format!("0.0.0.0:{}", udp))
+        .unwrap();
+
+    assert_eq!(
+        recv(&listener),
+        Some(json!({
+            "timestamp": 1001.0,
+            "subsystem": "eps",
+            "parameter": "current",
+            "value": "-2",
+            "type": "integer"
+        }))
+    );
+
+    let res = do_query(
+        Some(port),
+        "mutation { unsubscribe(id: 1) { success, errors } }",
+    );
+    assert_eq!(
+        res,
+        json!({"data": {"unsubscribe": {"success": true, "errors": ""}}})
+    );
+
+    let mutation = r#"mutation {
+            insert(timestamp: 1002, subsystem: "eps", parameter: "voltage", value: "3.4") {
+                success
+            }
+        }"#;
+    let res = do_query(Some(port), mutation);
+    assert_eq!(res, json!({"data": {"insert": {"success": true}}}));
+
+    assert_eq!(recv(&listener), None);
+
+    let res = do_query(
+        Some(port),
+        "mutation { unsubscribe(id: 1) { success, errors } }",
+    );
+    assert_eq!(
+        res,
+        json!({"data": {"unsubscribe": {
+            "success": false,
+            "errors": "No subscription with ID 1"
+        }}})
+    );
+}
+
+#[test]
+fn test_subscribe_bad_address() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8137;
+    let udp = 8147;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), None);
+
+    let res = do_query(
+        Some(port),
+        r#"mutation { subscribe(address: "ground") { success, errors, id } }"#,
+    );
+    assert_eq!(
+        res,
+        json!({"data": {"subscribe": {
+            "success": false,
+            "errors": "Failed to parse address ground: invalid socket address syntax",
+            "id": null
+        }}})
+    );
+}
+
+#[test]
+fn test_subscribe_max() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8139;
+    let udp = 8149;
+
+    let _fixture = TelemetryServiceFixture::setup_with_config(
+        db,
+        Some(port),
+        Some(udp),
+        None,
+        "max_subscriptions = 1",
+    );
+
+    let mutation = r#"mutation { subscribe(address: "127.0.0.1:9000") { success, errors, id } }"#;
+    let res = do_query(Some(port), mutation);
+    assert_eq!(
+        res,
+        json!({"data": {"subscribe": {"success": true, "errors": "", "id": 1}}})
+    );
+
+    let res = do_query(Some(port), mutation);
+    assert_eq!(
+        res,
+        json!({"data": {"subscribe": {
+            "success": false,
+            "errors": "Maximum number of subscriptions (1) reached",
+            "id": null
+        }}})
+    );
+}
+
+#[test]
+fn test_subscription_removed_after_failures() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8150;
+    let udp = 8151;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), None);
+
+    // The service's socket isn't allowed to broadcast, so every send to this address fails
+    let res = do_query(
+        Some(port),
+        r#"mutation { subscribe(address: "255.255.255.255:9000") { success, errors, id } }"#,
+    );
+    assert_eq!(
+        res,
+        json!({"data": {"subscribe": {"success": true, "errors": "", "id": 1}}})
+    );
+
+    for timestamp in 1000..1005 {
+        let mutation = format!(
+            r#"mutation {{
+                insert(timestamp: {}, subsystem: "eps", parameter: "voltage", value: "3.3") {{
+                    success
+                }}
+            }}"#,
+            timestamp
+        );
+        let res = do_query(Some(port), &mutation);
+        assert_eq!(res, json!({"data": {"insert": {"success": true}}}));
+    }
+
+    let res = do_query(Some(port), "{subscriptions{id}}");
+    assert_eq!(res, json!({"data": {"subscriptions": []}}));
+}
+
+#[test]
+fn test_subscribe_max_per_host() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8152;
+    let udp = 8153;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), None);
+
+    for listener_port in 9000..9004 {
+        let mutation = format!(
+            r#"mutation {{ subscribe(address: "127.0.0.1:{}") {{ success }} }}"#,
+            listener_port
+        );
+        let res = do_query(Some(port), &mutation);
+        assert_eq!(res, json!({"data": {"subscribe": {"success": true}}}));
+    }
+
+    let res = do_query(
+        Some(port),
+        r#"mutation { subscribe(address: "127.0.0.1:9004") { success, errors, id } }"#,
+    );
+    assert_eq!(
+        res,
+        json!({"data": {"subscribe": {
+            "success": false,
+            "errors": "Maximum number of subscriptions for 127.0.0.1 (4) reached",
+            "id": null
+        }}})
+    );
+
+    // Other hosts can still subscribe
+    let res = do_query(
+        Some(port),
+        r#"mutation { subscribe(address: "127.0.0.2:9000") { success, errors, id } }"#,
+    );
+    assert_eq!(
+        res,
+        json!({"data": {"subscribe": {"success": true, "errors": "", "id": 5}}})
+    );
+}
+
+#[test]
+fn test_subscription_lease() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8154;
+    let udp = 8155;
+
+    let _fixture = TelemetryServiceFixture::setup_with_config(
+        db,
+        Some(port),
+        Some(udp),
+        None,
+        "subscription_lease = 2",
+    );
+
+    let res = do_query(
+        Some(port),
+        r#"mutation { subscribe(address: "127.0.0.1:9000") { success, errors, id } }"#,
+    );
+    assert_eq!(
+        res,
+        json!({"data": {"subscribe": {"success": true, "errors": "", "id": 1}}})
+    );
+
+    // Renewing part of the way through the lease keeps the subscription past its original expiry
+    thread::sleep(Duration::from_millis(1500));
+    let res = do_query(
+        Some(port),
+        "mutation { renewSubscription(id: 1) { success, errors } }",
+    );
+    assert_eq!(
+        res,
+        json!({"data": {"renewSubscription": {"success": true, "errors": ""}}})
+    );
+
+    thread::sleep(Duration::from_millis(1000));
+    let res = do_query(Some(port), "{subscriptions{id}}");
+    assert_eq!(res, json!({"data": {"subscriptions": [{"id": 1}]}}));
+
+    // Once the lease runs out, the subscription is removed and can no longer be renewed
+    thread::sleep(Duration::from_millis(1500));
+    let res = do_query(Some(port), "{subscriptions{id}}");
+    assert_eq!(res, json!({"data": {"subscriptions": []}}));
+
+    let res = do_query(
+        Some(port),
+        "mutation { renewSubscription(id: 1) { success, errors } }",
+    );
+    assert_eq!(
+        res,
+        json!({"data": {"renewSubscription": {
+            "success": false,
+            "errors": "No subscription with ID 1"
+        }}})
+    );
+}
@@ -0,0 +1,178 @@
+//
+// Copyright (C) 2019 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+mod utils;
+
+use crate::utils::*;
+use serde_json::{json, ser};
+use std::net::UdpSocket;
+use std::time::Duration;
+use tempfile::TempDir;
+
+// Entries written before values were typed
+static SQL: &str = r"
+insert into telemetry values(1000, 'eps', 'voltage', '3.3');
+insert into telemetry values(1001, 'eps', 'count', '12');
+insert into telemetry values(1002, 'eps', 'mode', 'safe');
+";
+
+#[test]
+fn test_value_type_inferred() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8132;
+    let udp = 8142;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), Some(SQL));
+
+    let mutation = r#"mutation {
+            insert(timestamp: 1003, subsystem: "eps", parameter: "current", value: "-7") {
+                success,
+                errors
+            }
+        }"#;
+    let res = do_query(Some(port), mutation);
+    assert_eq!(
+        res,
+        json!({"data": {"insert": {"success": true, "errors": ""}}})
+    );
+
+    let res = do_query(Some(port), "{telemetry{timestamp,value,valueType}}");
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "telemetry": [
+                    {"timestamp": 1003.0, "value": "-7", "valueType": "INTEGER"},
+                    {"timestamp": 1002.0, "value": "safe", "valueType": "TEXT"},
+                    {"timestamp": 1001.0, "value": "12", "valueType": "INTEGER"},
+                    {"timestamp": 1000.0, "value": "3.3", "valueType": "REAL"},
+                ]
+            }
+        })
+    );
+}
+
+#[test]
+fn test_value_type_explicit() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8133;
+    let udp = 8143;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), None);
+
+    let mutation = r#"mutation {
+            insertBulk(entries: [
+                { timestamp: 1000, subsystem: "obc", parameter: "status", value: "01FF3a", valueType: BLOB },
+                { timestamp: 1001, subsystem: "obc", parameter: "version", value: "12", valueType: TEXT },
+                { timestamp: 1002, subsystem: "obc", parameter: "temperature", value: "20", valueType: REAL },
+            ]) {
+                success,
+                errors
+            }
+        }"#;
+    let res = do_query(Some(port), mutation);
+    assert_eq!(
+        res,
+        json!({"data": {"insertBulk": {"success": true, "errors": ""}}})
+    );
+
+    let mutation = r#"mutation {
+            insert(subsystem: "obc", parameter: "uptime", value: "forever", valueType: INTEGER) {
+                success,
+                errors
+            }
+        }"#;
+    let res = do_query(Some(port), mutation);
+    assert_eq!(
+        res,
+        json!({"data": {"insert": {
+            "success": false,
+            "errors": "Unable to convert 'forever' to integer"
+        }}})
+    );
+
+    let res = do_query(Some(port), "{telemetry{parameter,value,valueType}}");
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "telemetry": [
+                    {"parameter": "temperature", "value": "20", "valueType": "REAL"},
+                    {"parameter": "version", "value": "12", "valueType": "TEXT"},
+                    {"parameter": "status", "value": "01ff3a", "valueType": "BLOB"},
+                ]
+            }
+        })
+    );
+}
+
+#[test]
+fn test_value_type_udp() {
+    let db_dir = TempDir::new().unwrap();
+    let db_path = db_dir.path().join("test.db");
+
+    let db = db_path.to_str().unwrap();
+    let port = 8134;
+    let udp = 8144;
+
+    let _fixture = TelemetryServiceFixture::setup(db, Some(port), Some(udp), None);
+
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let service = format!("0.0.0.0:{}", udp);
+
+    let entries = json!([
+        {
+            "timestamp": 1000,
+            "subsystem": "eps",
+            "parameter": "voltage",
+            "value": "3",
+            "type": "real"
+        },
+        {
+            "timestamp": 1001,
+            "subsystem": "eps",
+            "parameter": "voltage",
+            "value": "3.4"
+        }
+    ]);
+
+    socket
+        .send_to(&ser::to_vec(&entries).unwrap(), &service)
+        .unwrap();
+
+    // Give the service time to process the messages, since we're not actually waiting
+    // for a response
+    ::std::thread::sleep(Duration::from_secs(1));
+
+    let res = do_query(Some(port), "{telemetry{timestamp,value,valueType}}");
+    assert_eq!(
+        res,
+        json!({
+            "data": {
+                "telemetry": [
+                    {"timestamp": 1001.0, "value": "3.4", "valueType": "REAL"},
+                    {"timestamp": 1000.0, "value": "3", "valueType": "REAL"},
+                ]
+            }
+        })
+    );
+}
@@ -94,6 +94,16 @@ impl TelemetryServiceFixture {
         service_port: Option<u16>,
         udp_port: Option<u16>,
         sql: Option<&str>,
+    ) -> Self {
+        Self::setup_with_config(db, service_port, udp_port, sql, "")
+    }
+
+    pub fn setup_with_config(
+        db: &str,
+        service_port: Option<u16>,
+        udp_port: Option<u16>,
+        sql: Option<&str>,
+        extra_config: &str,
     ) -> Self {
         let service_port = service_port.unwrap_or(8111);
         let udp_port = udp_port.unwrap_or(8112);
@@ -111,12 +121,14 @@ impl TelemetryServiceFixture {
             [telemetry-service]
             database = "{}"
             direct_port = {}
-            
+
+            {}
+
             [telemetry-service.addr]
             ip = "127.0.0.1"
             port = {}
             "#,
-            db, udp_port, service_port
+            db, udp_port, extra_config, service_port
         );
 
         let mut config_file = File::create(config_path.clone()).unwrap();
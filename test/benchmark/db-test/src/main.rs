@@ -101,7 +101,7 @@ impl DbTest {
 
             let start = PreciseTime::now();
             if db
-                .insert(timestamp, "db-test", "parameter", "value")
+                .insert(timestamp, "db-test", "parameter", "value", None)
                 .is_ok()
             {
                 times.push(start.to(PreciseTime::now()).num_microseconds().unwrap());
@@ -131,12 +131,10 @@ impl DbTest {
         for _ in 0..self.iterations {
             let timestamp: f64 = thread_rng().gen_range(0.0, 100_000_000_000_000_000.0);
 
-            entries.push(Entry {
-                timestamp,
-                subsystem: "db-test".to_string(),
-                parameter: "parameter".to_string(),
-                value: "value".to_string(),
-            });
+            entries.push(
+                Entry::new(timestamp, "db-test", "parameter", "value", None)
+                    .expect("Failed to create entry"),
+            );
         }
 
         let start = PreciseTime::now();
@@ -133,6 +133,7 @@ pub fn download(
         1,
         None,
         (chunk_size as usize) * 64,
+        None,
     );
     let f_protocol =
         FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
@@ -141,7 +142,7 @@ pub fn download(
 
     // Send our file request to the remote addr and verify that it's
     // going to be able to send it
-    f_protocol.send_import(channel, source_path)?;
+    f_protocol.send_import(channel, source_path, None)?;
 
     // Wait for the request reply.
     // Note/TODO: We don't use a timeout here because we don't know how long it will
@@ -136,6 +136,7 @@ pub fn upload(
         1,
         None,
         (chunk_size as usize) * 64,
+        None,
     );
     let f_protocol =
         FileProtocol::new(&format!("{}:{}", host_ip, host_port), remote_addr, f_config);
@@ -149,7 +150,7 @@ pub fn upload(
     f_protocol.send_metadata(channel, &hash, num_chunks)?;
 
     // send export command for file
-    f_protocol.send_export(channel, &hash, target_path, mode)?;
+    f_protocol.send_export(channel, &hash, target_path, mode, None)?;
 
     // start the engine to send the file data chunks
     f_protocol.message_engine(